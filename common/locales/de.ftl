@@ -0,0 +1,31 @@
+# German translations of user-facing messages, keyed by message id. Messages without
+# translation are left in English.
+
+## Errors of the sql_runner
+
+error-init = Fehler beim Initialisieren der Datenbank: { $message }
+error-query = Fehler beim Ausführen der Abfrage: { $message }
+error-timeout = Die Abfrage hat das Zeitlimit überschritten und wurde abgebrochen.
+error-denied = Die Abfrage ist durch die Regel „{ $rule }“ nicht erlaubt.
+error-seed-data = Fehler beim Laden der Beispieldaten: { $message }
+error-budget = Das Ausführungsbudget dieser Aufgabe ist aufgebraucht.
+
+## Postgres errors by SQLSTATE, `name` is the first quoted name of the original message
+
+pg-21000 = Eine als Ausdruck verwendete Unterabfrage hat mehr als eine Zeile geliefert.
+pg-22012 = Division durch null.
+pg-22P02 = Ungültige Eingabe für den Datentyp: „{ $name }“.
+pg-23502 = Ein Nullwert in der Spalte „{ $name }“ verletzt die NOT-NULL-Bedingung.
+pg-23503 = Die Einfügung oder Änderung verletzt den Fremdschlüssel „{ $name }“.
+pg-23505 = Ein doppelter Schlüsselwert verletzt die Eindeutigkeitsbedingung „{ $name }“.
+pg-25006 = Die Anweisung kann nicht ausgeführt werden, die Datenbank ist schreibgeschützt.
+pg-42501 = Keine Berechtigung für diese Anweisung.
+pg-42601 = Syntaxfehler bei „{ $name }“.
+pg-42702 = Der Spaltenverweis „{ $name }“ ist mehrdeutig.
+pg-42703 = Die Spalte „{ $name }“ existiert nicht.
+pg-42803 = Die Spalte „{ $name }“ muss in der GROUP-BY-Klausel stehen oder in einer Aggregatfunktion verwendet werden.
+pg-42P01 = Die Tabelle oder Sicht „{ $name }“ existiert nicht.
+
+## Errors of the persistence_proxy
+
+cooldown = Abgaben für diese Aufgabe sind begrenzt, bitte in { $seconds } Sekunden erneut versuchen.
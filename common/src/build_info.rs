@@ -0,0 +1,30 @@
+//! Helpers for build scripts recording build metadata, read back by [`crate::version_info`].
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Emits `GIT_COMMIT` and `BUILD_TIMESTAMP` as compile time environment variables.
+/// `GIT_COMMIT` can be set explicitly for builds without a git checkout, e.g. in docker.
+pub fn emit() {
+    let git_commit = std::env::var("GIT_COMMIT").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|commit| commit.trim().to_string())
+    });
+    let build_timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    println!(
+        "cargo:rustc-env=GIT_COMMIT={}",
+        git_commit.unwrap_or_else(|| "unknown".to_string())
+    );
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={build_timestamp}");
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+}
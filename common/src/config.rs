@@ -0,0 +1,128 @@
+use serde::de::DeserializeOwned;
+use std::fmt::{Display, Formatter};
+use url::Url;
+
+/// Every problem found in the configuration of a service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Reads the configuration from environment variables, naming the variable in errors.
+pub fn from_env<T: DeserializeOwned>() -> Result<T, ConfigError> {
+    envy::from_env().map_err(|err| ConfigError {
+        problems: vec![match err {
+            envy::Error::MissingValue(field) => {
+                format!("{} is required but not set", field.to_uppercase())
+            }
+            envy::Error::Custom(message) => message,
+        }],
+    })
+}
+
+/// Collects problems of a deserialized configuration, so all of them are reported at once.
+#[derive(Debug, Default)]
+pub struct ConfigValidator {
+    problems: Vec<String>,
+}
+
+impl ConfigValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `problem` unless `valid`.
+    pub fn check(&mut self, valid: bool, problem: impl FnOnce() -> String) -> &mut Self {
+        if !valid {
+            self.problems.push(problem());
+        }
+        self
+    }
+
+    /// Requires an absolute `http` or `https` url.
+    pub fn url(&mut self, name: &str, value: &str) -> &mut Self {
+        match Url::parse(value) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {}
+            Ok(url) => self.problems.push(format!(
+                "{name} must be an http or https url, got `{}`",
+                url.scheme()
+            )),
+            Err(err) => self
+                .problems
+                .push(format!("{name} is not a valid url ({err}): `{value}`")),
+        }
+        self
+    }
+
+    pub fn optional_url(&mut self, name: &str, value: Option<&str>) -> &mut Self {
+        if let Some(value) = value {
+            self.url(name, value);
+        }
+        self
+    }
+
+    /// Records the error of parsing the option, if any.
+    pub fn parses<T, E: Display>(&mut self, name: &str, result: Option<Result<T, E>>) -> &mut Self {
+        if let Some(Err(err)) = result {
+            self.problems.push(format!("{name} is invalid: {err}"));
+        }
+        self
+    }
+
+    pub fn port(&mut self, name: &str, value: u16) -> &mut Self {
+        self.check(value != 0, || format!("{name} must be between 1 and 65535"))
+    }
+
+    pub fn positive(&mut self, name: &str, value: u64) -> &mut Self {
+        self.check(value > 0, || format!("{name} must be greater than 0"))
+    }
+
+    /// Requires either all or none of the options to be set.
+    pub fn all_or_none(&mut self, options: &[(&str, bool)]) -> &mut Self {
+        let set: Vec<&str> = options
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name)
+            .collect();
+        let missing: Vec<&str> = options
+            .iter()
+            .filter(|(_, set)| !*set)
+            .map(|(name, _)| *name)
+            .collect();
+        self.check(set.is_empty() || missing.is_empty(), || {
+            format!(
+                "{} must be set together with {}",
+                missing.join(", "),
+                set.join(", ")
+            )
+        })
+    }
+
+    /// Requires `dependency` to be set if `option` is.
+    pub fn requires(&mut self, option: (&str, bool), dependency: (&str, bool)) -> &mut Self {
+        self.check(!option.1 || dependency.1, || {
+            format!("{} requires {} to be set", option.0, dependency.0)
+        })
+    }
+
+    pub fn finish(&mut self) -> Result<(), ConfigError> {
+        match self.problems.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError {
+                problems: std::mem::take(&mut self.problems),
+            }),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Fault injection for resilience tests, only compiled with the `fault-injection` feature.
+//!
+//! Configured through the environment:
+//! - `FAULT_TARGETS`: comma separated call targets to disturb, e.g. `runner,upstream,llm`
+//! - `FAULT_DELAY_MS`: delay added to every call of a target
+//! - `FAULT_FAILURE_RATE`: fraction (0.0 - 1.0) of calls failing after the delay
+
+use crate::random::random;
+use std::fmt::{Display, Formatter};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+struct FaultConfig {
+    targets: Vec<String>,
+    delay: Duration,
+    failure_rate: f64,
+}
+
+static CONFIG: LazyLock<FaultConfig> = LazyLock::new(|| FaultConfig {
+    targets: std::env::var("FAULT_TARGETS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|target| target.trim().to_string())
+        .filter(|target| !target.is_empty())
+        .collect(),
+    delay: Duration::from_millis(
+        std::env::var("FAULT_DELAY_MS")
+            .ok()
+            .and_then(|delay| delay.parse().ok())
+            .unwrap_or_default(),
+    ),
+    failure_rate: std::env::var("FAULT_FAILURE_RATE")
+        .ok()
+        .and_then(|rate| rate.parse().ok())
+        .unwrap_or_default(),
+});
+
+#[derive(Debug)]
+pub struct InjectedFault(pub &'static str);
+
+impl Display for InjectedFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "injected fault for `{}`", self.0)
+    }
+}
+
+impl std::error::Error for InjectedFault {}
+
+/// Delays and possibly fails a call to `target` according to the configuration.
+pub async fn inject(target: &'static str) -> Result<(), InjectedFault> {
+    let config = &*CONFIG;
+    if !config.targets.iter().any(|t| t == target) {
+        return Ok(());
+    }
+    if !config.delay.is_zero() {
+        tokio::time::sleep(config.delay).await;
+    }
+    if random() < config.failure_rate {
+        return Err(InjectedFault(target));
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use std::sync::LazyLock;
+use unic_langid::LanguageIdentifier;
+
+/// Translations of the user-facing messages, which are English in the services.
+const TRANSLATIONS: &[(Language, &str)] = &[(Language::German, include_str!("../locales/de.ftl"))];
+
+static BUNDLES: LazyLock<Vec<(Language, FluentBundle<FluentResource>)>> = LazyLock::new(|| {
+    TRANSLATIONS
+        .iter()
+        .map(|(language, source)| {
+            let resource =
+                FluentResource::try_new(source.to_string()).expect("translations are valid fluent");
+            let mut bundle = FluentBundle::new_concurrent(vec![language.identifier()]);
+            // the messages are plain text, not shown next to text of another direction
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .expect("translations have unique message ids");
+            (*language, bundle)
+        })
+        .collect()
+});
+
+/// Language of user-facing messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+}
+
+impl Language {
+    pub fn tag(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+        }
+    }
+
+    fn identifier(self) -> LanguageIdentifier {
+        self.tag().parse().expect("tags are valid")
+    }
+
+    /// Supported language of a tag like `de-AT`, regardless of region and script.
+    pub fn from_tag(tag: &str) -> Option<Language> {
+        let identifier: LanguageIdentifier = tag.trim().parse().ok()?;
+        [Language::English, Language::German]
+            .into_iter()
+            .find(|language| language.tag() == identifier.language.as_str())
+    }
+
+    /// The requested language if supported, otherwise the first supported one of an
+    /// `Accept-Language` header by quality, English if there is none.
+    pub fn negotiate(requested: Option<&str>, accept_language: Option<&str>) -> Language {
+        requested
+            .and_then(Language::from_tag)
+            .or_else(|| {
+                let mut accepted: Vec<(f32, &str)> = accept_language?
+                    .split(',')
+                    .filter_map(|range| {
+                        let mut parts = range.split(';');
+                        let tag = parts.next()?.trim();
+                        let quality = parts
+                            .find_map(|param| param.trim().strip_prefix("q="))
+                            .map_or(Some(1.0), |quality| quality.trim().parse().ok())?;
+                        (quality > 0.0).then_some((quality, tag))
+                    })
+                    .collect();
+                // stable, so ranges of equal quality keep their order
+                accepted.sort_by(|a, b| b.0.total_cmp(&a.0));
+                accepted
+                    .into_iter()
+                    .find_map(|(_, tag)| Language::from_tag(tag))
+            })
+            .unwrap_or_default()
+    }
+
+    /// Translation of the message with the arguments, `None` for English and for messages
+    /// which are not translated or lack an argument.
+    pub fn translate(self, id: &str, args: &[(&str, &str)]) -> Option<String> {
+        let (_, bundle) = BUNDLES.iter().find(|(language, _)| *language == self)?;
+        let pattern = bundle.get_message(id)?.value()?;
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, *value);
+        }
+        let mut errors = vec![];
+        let message = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+        errors.is_empty().then(|| message.into_owned())
+    }
+}
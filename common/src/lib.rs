@@ -1 +1,19 @@
+pub mod build_info;
+pub mod config;
+#[cfg(feature = "fault-injection")]
+pub mod fault;
+#[cfg(feature = "i18n")]
+pub mod i18n;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod models;
+pub mod pagination;
+#[cfg(any(feature = "fault-injection", feature = "retry"))]
+mod random;
+pub mod render;
+#[cfg(feature = "retry")]
+pub mod retry;
+pub mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod version;
@@ -1,15 +1,87 @@
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize, Serializer};
 use utoipa::ToSchema;
 
+/// Dates and timestamps are serialised as strings, which are deserialised as text as JSON does
+/// not tell them apart. Text spelling a date only equals it after the explicit
+/// [`SqlValue::parse_date_text`].
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, PartialOrd)]
 #[serde(untagged)]
 pub enum SqlValue {
+    /// SQL `NULL`, serialised as `null` and equal to itself so result sets with outer joins
+    /// compare like rows with `IS NOT DISTINCT FROM`
+    Null,
     Bool(bool),
     Int(i64),
     Float(f64),
+    /// Serialised as `YYYY-MM-DD`
+    #[schema(value_type = String, format = Date)]
+    #[serde(skip_deserializing)]
+    Date(#[serde(serialize_with = "serialize_date")] NaiveDate),
+    /// Serialised as `YYYY-MM-DDTHH:MM:SS` with optional fraction, timestamps with time zone
+    /// are converted to UTC
+    #[schema(value_type = String, format = DateTime)]
+    #[serde(skip_deserializing)]
+    Timestamp(#[serde(serialize_with = "serialize_timestamp")] NaiveDateTime),
     Text(String),
 }
 
+impl SqlValue {
+    /// Text spelling a timestamp or date, e.g. `2024-1-2`, as such, other values as they are.
+    pub fn parse_date_text(self) -> SqlValue {
+        let SqlValue::Text(text) = &self else {
+            return self;
+        };
+        if let Some(timestamp) = parse_timestamp(text) {
+            SqlValue::Timestamp(timestamp)
+        } else if let Some(date) = parse_date(text) {
+            SqlValue::Date(date)
+        } else {
+            self
+        }
+    }
+}
+
+/// Parses `YYYY-MM-DD` or `YYYY/MM/DD`, month and day may lack leading zeros.
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim();
+    ["%Y-%m-%d", "%Y/%m/%d"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+}
+
+/// Parses ISO 8601 timestamps separated by `T` or a space, those with an offset are
+/// converted to UTC.
+pub fn parse_timestamp(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(text) {
+        return Some(timestamp.naive_utc());
+    }
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f%#z",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| match DateTime::parse_from_str(text, format) {
+        Ok(timestamp) => Some(timestamp.naive_utc()),
+        Err(_) => NaiveDateTime::parse_from_str(text, format).ok(),
+    })
+}
+
+fn serialize_date<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&date.format("%Y-%m-%d"))
+}
+
+fn serialize_timestamp<S: Serializer>(
+    timestamp: &NaiveDateTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&timestamp.format("%Y-%m-%dT%H:%M:%S%.f"))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, PartialOrd)]
 pub struct ResultSet {
     pub columns: Vec<String>,
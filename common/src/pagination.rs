@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Upper bound of `per_page`, larger values are clamped.
+pub const MAX_PER_PAGE: u64 = 500;
+
+fn get_default_page() -> u64 {
+    1
+}
+
+fn get_default_per_page() -> u64 {
+    50
+}
+
+/// Query parameters of paginated list endpoints.
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// Page number, starting at 1
+    #[serde(default = "get_default_page")]
+    pub page: u64,
+    /// Items per page, at most 500
+    #[serde(default = "get_default_per_page")]
+    pub per_page: u64,
+    /// Field to sort by, prefixed with `-` for descending order
+    pub sort: Option<String>,
+    /// Only return items containing this text, case-insensitive
+    pub filter: Option<String>,
+}
+
+impl Default for PageParams {
+    fn default() -> Self {
+        PageParams {
+            page: get_default_page(),
+            per_page: get_default_per_page(),
+            sort: None,
+            filter: None,
+        }
+    }
+}
+
+/// Sort field and direction parsed from [`PageParams::sort`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort<'a> {
+    pub field: &'a str,
+    pub descending: bool,
+}
+
+impl PageParams {
+    pub fn page(&self) -> u64 {
+        self.page.max(1)
+    }
+
+    pub fn per_page(&self) -> u64 {
+        self.per_page.clamp(1, MAX_PER_PAGE)
+    }
+
+    /// Number of items before the requested page.
+    pub fn offset(&self) -> u64 {
+        (self.page() - 1).saturating_mul(self.per_page())
+    }
+
+    pub fn sort(&self) -> Option<Sort<'_>> {
+        let sort = self.sort.as_deref()?.trim();
+        let (field, descending) = match sort.strip_prefix('-') {
+            Some(field) => (field, true),
+            None => (sort.strip_prefix('+').unwrap_or(sort), false),
+        };
+        (!field.is_empty()).then_some(Sort { field, descending })
+    }
+
+    /// Whether the text matches the filter, always true without a filter.
+    pub fn matches(&self, text: &str) -> bool {
+        self.filter
+            .as_deref()
+            .is_none_or(|filter| text.to_lowercase().contains(&filter.to_lowercase()))
+    }
+
+    /// Lowercase `LIKE` pattern of text containing the filter, whose `%`, `_` and `\` are
+    /// escaped with `\`.
+    pub fn like_pattern(&self) -> Option<String> {
+        let filter = self.filter.as_deref()?.to_lowercase();
+        let mut pattern = String::from("%");
+        for c in filter.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('%');
+        Some(pattern)
+    }
+
+    /// Requested page of items which are already filtered and sorted.
+    pub fn paginate<T>(&self, items: Vec<T>) -> Page<T> {
+        let total = items.len() as u64;
+        let items = items
+            .into_iter()
+            .skip(self.offset().try_into().unwrap_or(usize::MAX))
+            .take(self.per_page() as usize)
+            .collect();
+        Page::new(items, self, total)
+    }
+}
+
+/// One page of a list endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub per_page: u64,
+    /// Number of items on all pages
+    pub total: u64,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, params: &PageParams, total: u64) -> Self {
+        Page {
+            items,
+            page: params.page(),
+            per_page: params.per_page(),
+            total,
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            page: self.page,
+            per_page: self.per_page,
+            total: self.total,
+        }
+    }
+}
+
+/// Query parameters of list endpoints paginated by cursor, whose pages stay consistent while
+/// items are added.
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CursorParams {
+    /// `next_cursor` of the previous page, omitted for the first page
+    pub cursor: Option<i64>,
+    /// Items per page, at most 500
+    #[serde(default = "get_default_per_page")]
+    pub per_page: u64,
+}
+
+impl CursorParams {
+    pub fn per_page(&self) -> u64 {
+        self.per_page.clamp(1, MAX_PER_PAGE)
+    }
+
+    /// Items to fetch, one more than a page to tell whether there is a next one.
+    pub fn limit(&self) -> u64 {
+        self.per_page() + 1
+    }
+}
+
+/// One page of a list endpoint paginated by cursor.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    /// Cursor of the next page, `null` on the last one
+    pub next_cursor: Option<i64>,
+}
+
+impl<T> CursorPage<T> {
+    /// Page of the items fetched with [`CursorParams::limit`], the cursor of the next page is
+    /// the one of its last item.
+    pub fn new(mut items: Vec<T>, params: &CursorParams, cursor: impl Fn(&T) -> i64) -> Self {
+        let has_next = items.len() as u64 > params.per_page();
+        items.truncate(params.per_page() as usize);
+        CursorPage {
+            next_cursor: items.last().filter(|_| has_next).map(cursor),
+            items,
+        }
+    }
+}
@@ -0,0 +1,7 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// Uniformly distributed value in `[0, 1)`, good enough for sampling faults and jitter.
+pub fn random() -> f64 {
+    (RandomState::new().hash_one(0u8) >> 11) as f64 / (1u64 << 53) as f64
+}
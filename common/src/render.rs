@@ -0,0 +1,142 @@
+use crate::models::{ResultSet, SqlValue};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Marks truncated cells and omitted rows.
+pub const TRUNCATION_MARKER: &str = "…";
+
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TableFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+/// Limits applied while rendering, so large result sets stay readable.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct RenderOptions {
+    #[serde(default = "get_default_max_rows")]
+    pub max_rows: usize,
+    #[serde(default = "get_default_max_cell_chars")]
+    pub max_cell_chars: usize,
+}
+
+fn get_default_max_rows() -> usize {
+    20
+}
+
+fn get_default_max_cell_chars() -> usize {
+    60
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            max_rows: get_default_max_rows(),
+            max_cell_chars: get_default_max_cell_chars(),
+        }
+    }
+}
+
+/// Renders the result set as a table, cells longer than `max_cell_chars` are cut and rows
+/// beyond `max_rows` are omitted and summarised with the truncation marker.
+pub fn render_result_set(
+    result_set: &ResultSet,
+    format: TableFormat,
+    options: &RenderOptions,
+) -> String {
+    let omitted = result_set.rows.len().saturating_sub(options.max_rows);
+    let rows = result_set.rows.iter().take(options.max_rows).map(|row| {
+        row.iter()
+            .map(|value| truncate(&display_value(value), options.max_cell_chars))
+            .collect::<Vec<_>>()
+    });
+    let columns = result_set
+        .columns
+        .iter()
+        .map(|column| truncate(column, options.max_cell_chars));
+    match format {
+        TableFormat::Markdown => {
+            let mut table = markdown_row(columns.collect());
+            table.push_str(&markdown_row(vec![
+                "---".to_string();
+                result_set.columns.len()
+            ]));
+            rows.for_each(|row| table.push_str(&markdown_row(row)));
+            if omitted > 0 {
+                table.push_str(&format!(
+                    "{TRUNCATION_MARKER} {omitted} more row{}\n",
+                    plural(omitted)
+                ));
+            }
+            table
+        }
+        TableFormat::Html => {
+            let mut table = "<table>\n<thead>".to_string();
+            table.push_str(&html_row("th", columns.collect()));
+            table.push_str("</thead>\n<tbody>\n");
+            rows.for_each(|row| table.push_str(&html_row("td", row)));
+            if omitted > 0 {
+                table.push_str(&format!(
+                    "<tr><td colspan=\"{}\">{TRUNCATION_MARKER} {omitted} more row{}</td></tr>\n",
+                    result_set.columns.len().max(1),
+                    plural(omitted)
+                ));
+            }
+            table.push_str("</tbody>\n</table>\n");
+            table
+        }
+    }
+}
+
+fn display_value(value: &SqlValue) -> String {
+    match value {
+        SqlValue::Null => "NULL".to_string(),
+        SqlValue::Bool(value) => value.to_string(),
+        SqlValue::Int(value) => value.to_string(),
+        SqlValue::Float(value) => value.to_string(),
+        SqlValue::Date(value) => value.to_string(),
+        SqlValue::Timestamp(value) => value.to_string(),
+        SqlValue::Text(value) => value.clone(),
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}{TRUNCATION_MARKER}", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+fn markdown_row(cells: Vec<String>) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| {
+            cell.replace('\\', "\\\\")
+                .replace('|', "\\|")
+                .replace(['\r', '\n'], " ")
+        })
+        .collect();
+    format!("| {} |\n", cells.join(" | "))
+}
+
+fn html_row(tag: &str, cells: Vec<String>) -> String {
+    let cells: String = cells
+        .iter()
+        .map(|cell| format!("<{tag}>{}</{tag}>", escape_html(cell)))
+        .collect();
+    format!("<tr>{cells}</tr>\n")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,63 @@
+//! Retries of calls failing transiently, e.g. with a rate limit or an overloaded server, only
+//! compiled with the `retry` feature.
+
+use crate::random::random;
+use std::time::Duration;
+
+/// Longest delay between two attempts unless the initial delay is longer.
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Exponential backoff with full jitter, the n-th retry waits a random time of up to
+/// `initial_delay * 2^(n-1)`, capped at `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    /// Attempts including the first one, `1` disables retries
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(max_attempts: u32, initial_delay: Duration) -> Self {
+        Backoff {
+            max_attempts,
+            initial_delay,
+            max_delay: MAX_DELAY.max(initial_delay),
+        }
+    }
+
+    /// Upper bound of the delay before the retry, counted from 1.
+    pub fn max_delay_before(&self, retry: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay)
+    }
+
+    /// Calls until the call succeeds, fails with an error which is not transient or the
+    /// attempts are used up, the result of the last call is returned.
+    pub async fn retry<T, E, F>(
+        &self,
+        mut call: impl FnMut() -> F,
+        is_transient: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(err) if attempt < self.max_attempts && is_transient(&err) => {
+                    tokio::time::sleep(self.max_delay_before(attempt).mul_f64(random())).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether a response with the HTTP status may succeed when retried: rate limits and server
+/// errors.
+pub fn is_transient_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
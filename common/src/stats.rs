@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Requests older than this are not part of the recent error rate.
+pub const RECENT_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+const BUCKET: Duration = Duration::from_secs(60);
+
+/// Requests and errors of the last [`RECENT_WINDOW`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct RecentErrors {
+    pub window_secs: u64,
+    pub requests: u64,
+    pub errors: u64,
+    /// Errors divided by requests, `0` without requests
+    pub error_rate: f64,
+}
+
+/// Counts requests and errors in one minute buckets covering the recent window.
+#[derive(Debug)]
+pub struct ErrorRate {
+    started: Instant,
+    buckets: Mutex<VecDeque<(u64, u64, u64)>>,
+}
+
+impl Default for ErrorRate {
+    fn default() -> Self {
+        ErrorRate {
+            started: Instant::now(),
+            buckets: Default::default(),
+        }
+    }
+}
+
+impl ErrorRate {
+    pub fn record(&self, error: bool) {
+        let bucket = self.current_bucket();
+        let mut buckets = self.buckets.lock().unwrap();
+        match buckets.back_mut() {
+            Some((index, requests, errors)) if *index == bucket => {
+                *requests += 1;
+                *errors += error as u64;
+            }
+            _ => buckets.push_back((bucket, 1, error as u64)),
+        }
+        Self::prune(&mut buckets, bucket);
+    }
+
+    pub fn recent(&self) -> RecentErrors {
+        let bucket = self.current_bucket();
+        let mut buckets = self.buckets.lock().unwrap();
+        Self::prune(&mut buckets, bucket);
+        let (requests, errors) = buckets.iter().fold((0, 0), |(requests, errors), bucket| {
+            (requests + bucket.1, errors + bucket.2)
+        });
+        RecentErrors {
+            window_secs: RECENT_WINDOW.as_secs(),
+            requests,
+            errors,
+            error_rate: if requests == 0 {
+                0.0
+            } else {
+                errors as f64 / requests as f64
+            },
+        }
+    }
+
+    fn current_bucket(&self) -> u64 {
+        self.started.elapsed().as_secs() / BUCKET.as_secs()
+    }
+
+    fn prune(buckets: &mut VecDeque<(u64, u64, u64)>, current: u64) {
+        let buckets_in_window = RECENT_WINDOW.as_secs() / BUCKET.as_secs();
+        while buckets
+            .front()
+            .is_some_and(|(index, _, _)| index + buckets_in_window <= current)
+        {
+            buckets.pop_front();
+        }
+    }
+}
+
+/// Response of the sql_runner `/api/v1/stats` route.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct RunnerStats {
+    pub timeouts: u64,
+    /// Environments waiting for a free creation slot
+    #[serde(default)]
+    pub queued_environments: usize,
+    /// Environments which are currently initialised
+    #[serde(default)]
+    pub initialising_environments: usize,
+    /// Executed queries and the ones failing
+    #[serde(default)]
+    pub recent: RecentErrors,
+    #[serde(default)]
+    pub connection_pools: ConnectionPoolStats,
+}
+
+/// Connection pools of the sql_runner to its environments.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct ConnectionPoolStats {
+    pub open: usize,
+    /// Pools kept open at most, 0 if unlimited
+    pub max: usize,
+    /// Pools closed to make room for a new one
+    pub evicted: u64,
+    /// Pools closed after being idle for the idle timeout
+    pub expired: u64,
+}
+
+/// Response of the sql_feedback `/api/v1/stats` route.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct FeedbackStats {
+    /// Requests to the llm which are currently waiting for a response
+    pub llm_in_flight: usize,
+    pub llm_requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Feedback requests and the ones failing
+    pub recent: RecentErrors,
+}
@@ -0,0 +1,163 @@
+//! Builders and proptest strategies of the shared models for the tests of the services, only
+//! compiled with the `testing` feature.
+
+use crate::models::{ResultSet, SqlResult, SqlValue};
+use chrono::{Duration, NaiveDate};
+use proptest::prelude::*;
+
+/// Builds a result set row by row.
+#[derive(Debug, Clone)]
+pub struct ResultSetBuilder {
+    result_set: ResultSet,
+}
+
+impl ResultSetBuilder {
+    pub fn new(columns: &[&str]) -> Self {
+        ResultSetBuilder {
+            result_set: ResultSet {
+                columns: columns.iter().map(|column| column.to_string()).collect(),
+                rows: vec![],
+            },
+        }
+    }
+
+    /// Adds a row, which needs a value per column.
+    pub fn row(mut self, values: Vec<SqlValue>) -> Self {
+        assert_eq!(
+            values.len(),
+            self.result_set.columns.len(),
+            "rows need a value per column"
+        );
+        self.result_set.rows.push(values);
+        self
+    }
+
+    pub fn build(self) -> ResultSet {
+        self.result_set
+    }
+}
+
+pub fn text(value: &str) -> SqlValue {
+    SqlValue::Text(value.to_string())
+}
+
+/// Panics for days which do not exist.
+pub fn date(year: i32, month: u32, day: u32) -> SqlValue {
+    SqlValue::Date(NaiveDate::from_ymd_opt(year, month, day).expect("the date exists"))
+}
+
+/// Type of the values of a generated column.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ValueKind {
+    Bool,
+    Int,
+    Float,
+    Date,
+    Timestamp,
+    Text,
+}
+
+impl ValueKind {
+    pub const ALL: [ValueKind; 6] = [
+        ValueKind::Bool,
+        ValueKind::Int,
+        ValueKind::Float,
+        ValueKind::Date,
+        ValueKind::Timestamp,
+        ValueKind::Text,
+    ];
+
+    /// Values of the kind from small ranges, so that generated rows share values. Floats are
+    /// finite as `NaN` has no order.
+    pub fn values(self) -> BoxedStrategy<SqlValue> {
+        let epoch = NaiveDate::from_ymd_opt(1900, 1, 1).expect("the date exists");
+        let dates = (0i64..73_000).prop_map(move |days| epoch + Duration::days(days));
+        match self {
+            ValueKind::Bool => any::<bool>().prop_map(SqlValue::Bool).boxed(),
+            ValueKind::Int => (-100i64..100).prop_map(SqlValue::Int).boxed(),
+            ValueKind::Float => (-400i64..400)
+                .prop_map(|quarters| SqlValue::Float(quarters as f64 / 4.0))
+                .boxed(),
+            ValueKind::Date => dates.prop_map(SqlValue::Date).boxed(),
+            ValueKind::Timestamp => (dates, 0i64..86_400_000)
+                .prop_map(|(date, millis)| {
+                    SqlValue::Timestamp(
+                        date.and_time(Default::default()) + Duration::milliseconds(millis),
+                    )
+                })
+                .boxed(),
+            ValueKind::Text => "[a-zA-Z ]{0,8}".prop_map(SqlValue::Text).boxed(),
+        }
+    }
+
+    /// Values of the kind or `NULL`.
+    pub fn nullable_values(self) -> BoxedStrategy<SqlValue> {
+        prop_oneof![1 => Just(SqlValue::Null), 5 => self.values()].boxed()
+    }
+}
+
+impl Arbitrary for ValueKind {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<ValueKind>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop::sample::select(&ValueKind::ALL[..]).boxed()
+    }
+}
+
+impl Arbitrary for SqlValue {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<SqlValue>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        any::<ValueKind>()
+            .prop_flat_map(ValueKind::nullable_values)
+            .boxed()
+    }
+}
+
+/// Result sets with the columns, whose values are of the column's kind or `NULL`.
+pub fn result_set_with(columns: &[(&str, ValueKind)], max_rows: usize) -> BoxedStrategy<ResultSet> {
+    let names: Vec<String> = columns.iter().map(|(name, _)| name.to_string()).collect();
+    let row: Vec<_> = columns
+        .iter()
+        .map(|(_, kind)| kind.nullable_values())
+        .collect();
+    prop::collection::vec(row, 0..=max_rows)
+        .prop_map(move |rows| ResultSet {
+            columns: names.clone(),
+            rows,
+        })
+        .boxed()
+}
+
+/// Up to 4 columns named `c0`, `c1`, … of any kind and up to 8 rows.
+impl Arbitrary for ResultSet {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<ResultSet>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop::collection::vec(any::<ValueKind>(), 0..=4)
+            .prop_flat_map(|kinds| {
+                let names: Vec<String> =
+                    (0..kinds.len()).map(|index| format!("c{index}")).collect();
+                let columns: Vec<(&str, ValueKind)> =
+                    names.iter().map(String::as_str).zip(kinds).collect();
+                result_set_with(&columns, 8)
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for SqlResult {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<SqlResult>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            3 => any::<ResultSet>().prop_map(SqlResult::Ok),
+            1 => "[a-z ]{1,20}".prop_map(SqlResult::Error),
+        ]
+        .boxed()
+    }
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Version of the sql_runner HTTP contract, increased on incompatible changes.
+pub const RUNNER_API_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct VersionInfo {
+    pub version: String,
+    pub api_version: u32,
+    #[serde(default)]
+    pub git_commit: String,
+    /// Unix timestamp of the build
+    #[serde(default)]
+    pub build_timestamp: u64,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+/// Response of the `/ping` healthcheck route.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct PingResponse {
+    #[serde(flatten)]
+    pub version: VersionInfo,
+    pub uptime_secs: u64,
+}
+
+/// Starts measuring the uptime, should be called once at startup.
+pub fn mark_started() {
+    LazyLock::force(&STARTED);
+}
+
+/// Time since [`mark_started`] was called.
+pub fn uptime() -> Duration {
+    STARTED.elapsed()
+}
+
+/// Builds the [`VersionInfo`] of the calling crate, which has to run
+/// [`crate::build_info::emit`] in its build script.
+#[macro_export]
+macro_rules! version_info {
+    ($api_version:expr, $features:expr) => {
+        $crate::version::VersionInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            api_version: $api_version,
+            git_commit: env!("GIT_COMMIT").to_string(),
+            build_timestamp: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+            features: $features
+                .iter()
+                .map(|feature: &&str| feature.to_string())
+                .collect(),
+        }
+    };
+}
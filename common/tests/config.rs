@@ -0,0 +1,39 @@
+use common::config::ConfigValidator;
+
+#[test]
+fn valid_configuration_passes() {
+    let result = ConfigValidator::new()
+        .url("RUNNER_URL", "http://localhost:8080")
+        .optional_url("CALLBACK_URL", None)
+        .port("PORT", 8080)
+        .positive("TIMEOUT", 5)
+        .all_or_none(&[("USER", true), ("PASSWORD", true)])
+        .requires(("TLS_CERT", false), ("TLS_KEY", false))
+        .finish();
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn all_problems_are_reported() {
+    let error = ConfigValidator::new()
+        .url("RUNNER_URL", "localhost:8080")
+        .url("LLM_URL", "not a url")
+        .port("PORT", 0)
+        .parses("SCHEDULE", Some("x".parse::<u32>()))
+        .all_or_none(&[("USER", true), ("PASSWORD", false)])
+        .requires(("TLS_CERT", true), ("TLS_KEY", false))
+        .finish()
+        .unwrap_err();
+    assert_eq!(error.problems.len(), 6);
+    assert_eq!(
+        error.problems[0],
+        "RUNNER_URL must be an http or https url, got `localhost`"
+    );
+    assert_eq!(error.problems[4], "PASSWORD must be set together with USER");
+    assert_eq!(error.problems[5], "TLS_CERT requires TLS_KEY to be set");
+    assert!(
+        error
+            .to_string()
+            .starts_with("invalid configuration:\n  - RUNNER_URL")
+    );
+}
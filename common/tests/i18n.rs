@@ -0,0 +1,42 @@
+#![cfg(feature = "i18n")]
+
+use common::i18n::Language;
+
+#[test]
+fn tags_match_regardless_of_region() {
+    assert_eq!(Language::from_tag("de-AT"), Some(Language::German));
+    assert_eq!(Language::from_tag("en"), Some(Language::English));
+    assert_eq!(Language::from_tag("fr"), None);
+    assert_eq!(Language::German.tag(), "de");
+}
+
+#[test]
+fn requested_language_is_preferred_over_the_header() {
+    assert_eq!(
+        Language::negotiate(Some("de"), Some("en")),
+        Language::German
+    );
+    assert_eq!(
+        Language::negotiate(Some("fr"), Some("fr-FR, de;q=0.5, en;q=0.4")),
+        Language::German
+    );
+    assert_eq!(
+        Language::negotiate(None, Some("de;q=0, en-GB;q=0.8")),
+        Language::English
+    );
+    assert_eq!(Language::negotiate(None, None), Language::English);
+}
+
+#[test]
+fn messages_are_translated_with_arguments() {
+    assert_eq!(
+        Language::German.translate("pg-42703", &[("name", "x")]),
+        Some("Die Spalte „x“ existiert nicht.".to_string())
+    );
+    assert_eq!(Language::German.translate("pg-42703", &[]), None);
+    assert_eq!(Language::German.translate("pg-00000", &[]), None);
+    assert_eq!(
+        Language::English.translate("pg-42703", &[("name", "x")]),
+        None
+    );
+}
@@ -0,0 +1,97 @@
+use chrono::NaiveDate;
+use common::models::{SqlValue, parse_date, parse_timestamp};
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn dates_are_parsed_leniently() {
+    assert_eq!(parse_date("2024-01-02"), Some(date(2024, 1, 2)));
+    assert_eq!(parse_date("2024-1-2"), Some(date(2024, 1, 2)));
+    assert_eq!(parse_date(" 2024/01/02 "), Some(date(2024, 1, 2)));
+    assert_eq!(parse_date("02.01.2024"), None);
+    assert_eq!(parse_date("2024-02-30"), None);
+}
+
+#[test]
+fn timestamps_are_parsed_in_utc() {
+    let expected = date(2024, 1, 2).and_hms_opt(3, 4, 5).unwrap();
+    assert_eq!(parse_timestamp("2024-01-02T03:04:05"), Some(expected));
+    assert_eq!(parse_timestamp("2024-01-02 03:04:05"), Some(expected));
+    assert_eq!(parse_timestamp("2024-01-02T05:04:05+02:00"), Some(expected));
+    assert_eq!(parse_timestamp("2024-01-02 05:04:05+02"), Some(expected));
+    assert_eq!(
+        parse_timestamp("2024-01-02 03:04:05.250"),
+        date(2024, 1, 2).and_hms_milli_opt(3, 4, 5, 250)
+    );
+    assert_eq!(parse_timestamp("yesterday"), None);
+}
+
+#[test]
+fn text_equals_typed_values_only_once_parsed() {
+    let day = SqlValue::Date(date(2024, 1, 2));
+    let text = SqlValue::Text("2024-1-2".to_string());
+    assert_ne!(day, text);
+    assert_eq!(text.parse_date_text(), day);
+    assert_ne!(
+        SqlValue::Text("2024-01-03".to_string()).parse_date_text(),
+        day
+    );
+
+    let timestamp = SqlValue::Timestamp(date(2024, 1, 2).and_hms_opt(3, 4, 5).unwrap());
+    assert_eq!(
+        SqlValue::Text("2024-01-02 03:04:05".to_string()).parse_date_text(),
+        timestamp
+    );
+    assert_ne!(timestamp, day);
+    assert_eq!(
+        SqlValue::Text("not a date".to_string()).parse_date_text(),
+        SqlValue::Text("not a date".to_string())
+    );
+    assert_eq!(
+        SqlValue::Int(20240102).parse_date_text(),
+        SqlValue::Int(20240102)
+    );
+}
+
+#[test]
+fn dates_and_timestamps_are_serialised_as_iso_strings_and_read_as_text() {
+    let values = vec![
+        SqlValue::Date(date(2024, 1, 2)),
+        SqlValue::Timestamp(date(2024, 1, 2).and_hms_milli_opt(3, 4, 5, 500).unwrap()),
+        SqlValue::Text("not a date".to_string()),
+        SqlValue::Int(3),
+    ];
+    let json = serde_json::to_string(&values).unwrap();
+    assert_eq!(
+        json,
+        r#"["2024-01-02","2024-01-02T03:04:05.500","not a date",3]"#
+    );
+    let parsed: Vec<SqlValue> = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        parsed,
+        vec![
+            SqlValue::Text("2024-01-02".to_string()),
+            SqlValue::Text("2024-01-02T03:04:05.500".to_string()),
+            SqlValue::Text("not a date".to_string()),
+            SqlValue::Int(3),
+        ]
+    );
+    assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+}
+
+#[test]
+fn null_is_serialised_as_null_and_equal_to_itself() {
+    let row = vec![SqlValue::Int(1), SqlValue::Null];
+    let json = serde_json::to_string(&row).unwrap();
+    assert_eq!(json, "[1,null]");
+    assert_eq!(serde_json::from_str::<Vec<SqlValue>>(&json).unwrap(), row);
+    assert_eq!(SqlValue::Null, SqlValue::Null);
+    assert_ne!(SqlValue::Null, SqlValue::Text("NULL".to_string()));
+    assert_ne!(SqlValue::Null, SqlValue::Int(0));
+
+    let mut rows = [vec![SqlValue::Int(2)], vec![SqlValue::Null]];
+    rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(rows[0], vec![SqlValue::Null]);
+}
@@ -0,0 +1,18 @@
+use common::pagination::PageParams;
+
+fn filtered(filter: &str) -> PageParams {
+    PageParams {
+        filter: Some(filter.to_string()),
+        ..PageParams::default()
+    }
+}
+
+#[test]
+fn like_patterns_escape_wildcards() {
+    assert_eq!(PageParams::default().like_pattern(), None);
+    assert_eq!(filtered("Task").like_pattern().unwrap(), "%task%");
+    assert_eq!(
+        filtered(r"50%_a\b").like_pattern().unwrap(),
+        r"%50\%\_a\\b%"
+    );
+}
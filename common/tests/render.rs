@@ -0,0 +1,47 @@
+use common::models::{ResultSet, SqlValue};
+use common::render::{RenderOptions, TableFormat, render_result_set};
+
+fn result_set(rows: usize) -> ResultSet {
+    ResultSet {
+        columns: vec!["name".to_string(), "score".to_string()],
+        rows: (0..rows)
+            .map(|row| {
+                vec![
+                    SqlValue::Text(format!("a|b <{row}>")),
+                    SqlValue::Int(row as i64),
+                ]
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn markdown_escapes_pipes_and_marks_omitted_rows() {
+    let options = RenderOptions {
+        max_rows: 2,
+        max_cell_chars: 60,
+    };
+    assert_eq!(
+        render_result_set(&result_set(3), TableFormat::Markdown, &options),
+        "| name | score |\n| --- | --- |\n| a\\|b <0> | 0 |\n| a\\|b <1> | 1 |\n… 1 more row\n"
+    );
+}
+
+#[test]
+fn html_escapes_cells() {
+    assert_eq!(
+        render_result_set(&result_set(1), TableFormat::Html, &RenderOptions::default()),
+        "<table>\n<thead><tr><th>name</th><th>score</th></tr>\n</thead>\n<tbody>\n\
+         <tr><td>a|b &lt;0&gt;</td><td>0</td></tr>\n</tbody>\n</table>\n"
+    );
+}
+
+#[test]
+fn long_cells_are_truncated() {
+    let options = RenderOptions {
+        max_rows: 5,
+        max_cell_chars: 3,
+    };
+    let rendered = render_result_set(&result_set(1), TableFormat::Markdown, &options);
+    assert_eq!(rendered.lines().nth(2), Some("| a\\|b… | 0 |"));
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "retry")]
+
+use common::retry::{Backoff, is_transient_status};
+use std::cell::Cell;
+use std::time::Duration;
+
+#[test]
+fn delays_grow_exponentially_up_to_the_cap() {
+    let backoff = Backoff::new(5, Duration::from_secs(1));
+    assert_eq!(backoff.max_delay_before(1), Duration::from_secs(1));
+    assert_eq!(backoff.max_delay_before(3), Duration::from_secs(4));
+    assert_eq!(backoff.max_delay_before(5), Duration::from_secs(10));
+    assert_eq!(backoff.max_delay_before(100), backoff.max_delay);
+}
+
+#[test]
+fn rate_limits_and_server_errors_are_transient() {
+    assert!(is_transient_status(429));
+    assert!(is_transient_status(503));
+    assert!(!is_transient_status(400));
+    assert!(!is_transient_status(200));
+}
+
+#[tokio::test]
+async fn transient_errors_are_retried_until_the_attempts_are_used_up() {
+    let backoff = Backoff::new(3, Duration::ZERO);
+    let calls = Cell::new(0);
+    let result: Result<(), u16> = backoff
+        .retry(
+            || async {
+                calls.set(calls.get() + 1);
+                Err(503)
+            },
+            |status| is_transient_status(*status),
+        )
+        .await;
+    assert_eq!((result, calls.get()), (Err(503), 3));
+
+    calls.set(0);
+    let result = backoff
+        .retry(
+            || async {
+                calls.set(calls.get() + 1);
+                if calls.get() < 2 {
+                    Err(429)
+                } else {
+                    Ok(calls.get())
+                }
+            },
+            |status| is_transient_status(*status),
+        )
+        .await;
+    assert_eq!(result, Ok(2));
+
+    calls.set(0);
+    let result: Result<(), u16> = backoff
+        .retry(
+            || async {
+                calls.set(calls.get() + 1);
+                Err(404)
+            },
+            |status| is_transient_status(*status),
+        )
+        .await;
+    assert_eq!((result, calls.get()), (Err(404), 1));
+}
@@ -0,0 +1,15 @@
+use common::stats::{ErrorRate, RECENT_WINDOW};
+
+#[test]
+fn error_rate_counts_recent_requests() {
+    let rate = ErrorRate::default();
+    assert_eq!(rate.recent().error_rate, 0.0);
+    rate.record(false);
+    rate.record(true);
+    rate.record(false);
+    rate.record(false);
+    let recent = rate.recent();
+    assert_eq!(recent.window_secs, RECENT_WINDOW.as_secs());
+    assert_eq!((recent.requests, recent.errors), (4, 1));
+    assert_eq!(recent.error_rate, 0.25);
+}
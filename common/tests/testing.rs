@@ -0,0 +1,68 @@
+#![cfg(feature = "testing")]
+
+use common::models::{ResultSet, SqlResult, SqlValue};
+use common::testing::{ResultSetBuilder, ValueKind, date, result_set_with, text};
+use proptest::prelude::*;
+
+#[test]
+fn result_sets_are_built_row_by_row() {
+    let result_set = ResultSetBuilder::new(&["name", "born"])
+        .row(vec![text("Ada"), date(1815, 12, 10)])
+        .row(vec![text("Grace"), SqlValue::Null])
+        .build();
+    assert_eq!(result_set.columns, vec!["name", "born"]);
+    assert_eq!(result_set.rows[1], vec![text("Grace"), SqlValue::Null]);
+}
+
+#[test]
+#[should_panic(expected = "rows need a value per column")]
+fn rows_need_a_value_per_column() {
+    ResultSetBuilder::new(&["name", "born"]).row(vec![text("Ada")]);
+}
+
+proptest! {
+    #[test]
+    fn values_have_the_kind_of_their_column(
+        result_set in result_set_with(&[("id", ValueKind::Int), ("name", ValueKind::Text)], 5)
+    ) {
+        prop_assert!(result_set.rows.len() <= 5);
+        for row in &result_set.rows {
+            prop_assert!(matches!(row[0], SqlValue::Int(_) | SqlValue::Null));
+            prop_assert!(matches!(row[1], SqlValue::Text(_) | SqlValue::Null));
+        }
+    }
+
+    #[test]
+    fn generated_values_are_totally_ordered(a in any::<SqlValue>(), b in any::<SqlValue>()) {
+        prop_assert!(a.partial_cmp(&b).is_some());
+    }
+
+    #[test]
+    fn sorting_rows_is_idempotent_and_ignores_their_order(
+        (result_set, shuffled) in any::<ResultSet>().prop_flat_map(|result_set| {
+            let rows = Just(result_set.rows.clone()).prop_shuffle();
+            (Just(result_set), rows)
+        })
+    ) {
+        let sort = |mut rows: Vec<Vec<SqlValue>>| {
+            rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            rows
+        };
+        let sorted = sort(result_set.rows);
+        prop_assert_eq!(&sort(sorted.clone()), &sorted);
+        prop_assert_eq!(&sort(shuffled), &sorted);
+    }
+
+    #[test]
+    fn results_survive_serialisation(result in any::<SqlResult>()) {
+        // dates and timestamps are read back as text, which is serialised the same way
+        let json = serde_json::to_string(&result).unwrap();
+        let parsed = serde_json::from_str::<SqlResult>(&json).unwrap();
+        prop_assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+
+    #[test]
+    fn every_kind_is_generated(kind in any::<ValueKind>()) {
+        prop_assert!(ValueKind::ALL.contains(&kind));
+    }
+}
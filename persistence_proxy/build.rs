@@ -0,0 +1,3 @@
+fn main() {
+    common::build_info::emit();
+}
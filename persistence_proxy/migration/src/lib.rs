@@ -1,12 +1,28 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20261017_000001_add_quota;
+mod m20261017_000002_create_job_run;
+mod m20261017_000003_create_tenant;
+mod m20261017_000004_create_task;
+mod m20261017_000005_create_review;
+mod m20261017_000006_add_review_appeal;
+mod m20261017_000007_add_task_deadline;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261017_000001_add_quota::Migration),
+            Box::new(m20261017_000002_create_job_run::Migration),
+            Box::new(m20261017_000003_create_tenant::Migration),
+            Box::new(m20261017_000004_create_task::Migration),
+            Box::new(m20261017_000005_create_review::Migration),
+            Box::new(m20261017_000006_add_review_appeal::Migration),
+            Box::new(m20261017_000007_add_task_deadline::Migration),
+        ]
     }
 }
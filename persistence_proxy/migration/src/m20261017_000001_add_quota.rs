@@ -0,0 +1,75 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // existing logs keep `NULL` instead of the time of the migration, which would count
+        // them as analyses of that day
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Log::Table)
+                    .add_column(timestamp_with_time_zone_null(Log::CreatedAt))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Log::Table)
+                    .modify_column(
+                        ColumnDef::new(Log::CreatedAt).default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Consumer::Table)
+                    .add_column(integer_null(Consumer::DailyLimit))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Consumer::Table)
+                    .drop_column(Consumer::DailyLimit)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Log::Table)
+                    .drop_column(Log::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Consumer {
+    Table,
+    DailyLimit,
+}
+
+#[derive(DeriveIden)]
+enum Log {
+    Table,
+    CreatedAt,
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(JobRun::Table)
+                    .if_not_exists()
+                    .col(pk_auto(JobRun::Id))
+                    .col(string(JobRun::Job))
+                    .col(timestamp_with_time_zone(JobRun::StartedAt))
+                    .col(timestamp_with_time_zone(JobRun::FinishedAt))
+                    .col(boolean(JobRun::Success))
+                    .col(text_null(JobRun::Message))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(JobRun::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum JobRun {
+    Table,
+    Id,
+    Job,
+    StartedAt,
+    FinishedAt,
+    Success,
+    Message,
+}
@@ -0,0 +1,78 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Tenant::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Tenant::Id))
+                    .col(string(Tenant::Name))
+                    .col(string_null(Tenant::UpstreamUrl))
+                    .col(string_null(Tenant::SqlRunnerUrl))
+                    .col(string_null(Tenant::Model))
+                    .col(integer_null(Tenant::DailyLimit))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Consumer::Table)
+                    .add_column(integer_null(Consumer::TenantId))
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_consumer_tenant")
+                            .from_tbl(Consumer::Table)
+                            .from_col(Consumer::TenantId)
+                            .to_tbl(Tenant::Table)
+                            .to_col(Tenant::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Consumer::Table)
+                    .drop_foreign_key(Alias::new("fk_consumer_tenant"))
+                    .drop_column(Consumer::TenantId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Tenant::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Tenant {
+    Table,
+    Id,
+    Name,
+    UpstreamUrl,
+    SqlRunnerUrl,
+    Model,
+    DailyLimit,
+}
+
+#[derive(DeriveIden)]
+enum Consumer {
+    Table,
+    TenantId,
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Task::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Task::Id))
+                    .col(integer(Task::ConsumerId))
+                    .col(string(Task::TaskId))
+                    .col(json(Task::Comparison))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_task_consumer")
+                            .from(Task::Table, Task::ConsumerId)
+                            .to(Consumer::Table, Consumer::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_task_consumer_task_id")
+                    .table(Task::Table)
+                    .col(Task::ConsumerId)
+                    .col(Task::TaskId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Task::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+#[allow(clippy::enum_variant_names)]
+enum Task {
+    Table,
+    Id,
+    ConsumerId,
+    TaskId,
+    Comparison,
+}
+
+#[derive(DeriveIden)]
+enum Consumer {
+    Table,
+    Id,
+}
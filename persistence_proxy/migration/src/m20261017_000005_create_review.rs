@@ -0,0 +1,93 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Review::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Review::Id))
+                    .col(integer(Review::ConsumerId))
+                    .col(integer_null(Review::LogId))
+                    .col(string(Review::SubmissionKey))
+                    .col(string_null(Review::TaskId))
+                    .col(string_null(Review::UserId))
+                    .col(text(Review::Submission))
+                    .col(string(Review::Reason))
+                    .col(string(Review::Status))
+                    .col(json(Review::Result))
+                    .col(boolean_null(Review::OverrideCorrect))
+                    .col(text_null(Review::OverrideFeedback))
+                    .col(timestamp_with_time_zone(Review::CreatedAt))
+                    .col(timestamp_with_time_zone_null(Review::ReviewedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_review_consumer")
+                            .from(Review::Table, Review::ConsumerId)
+                            .to(Consumer::Table, Consumer::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_review_log")
+                            .from(Review::Table, Review::LogId)
+                            .to(Log::Table, Log::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_review_consumer_submission_key")
+                    .table(Review::Table)
+                    .col(Review::ConsumerId)
+                    .col(Review::SubmissionKey)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Review::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Review {
+    Table,
+    Id,
+    ConsumerId,
+    LogId,
+    SubmissionKey,
+    TaskId,
+    UserId,
+    Submission,
+    Reason,
+    Status,
+    Result,
+    OverrideCorrect,
+    OverrideFeedback,
+    CreatedAt,
+    ReviewedAt,
+}
+
+#[derive(DeriveIden)]
+enum Consumer {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Log {
+    Table,
+    Id,
+}
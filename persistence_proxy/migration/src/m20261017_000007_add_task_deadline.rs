@@ -0,0 +1,40 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Task::Table)
+                    .modify_column(json_null(Task::Comparison))
+                    .add_column(timestamp_with_time_zone_null(Task::Deadline))
+                    .add_column(boolean(Task::ReviewOnlyAfterDeadline).default(true))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Task::Table)
+                    .drop_column(Task::ReviewOnlyAfterDeadline)
+                    .drop_column(Task::Deadline)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Task {
+    Table,
+    Comparison,
+    Deadline,
+    ReviewOnlyAfterDeadline,
+}
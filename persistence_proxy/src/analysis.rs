@@ -0,0 +1,172 @@
+//! Stored analyses, so instructors can review past requests and the results returned.
+
+use crate::AppState;
+use crate::auth::AuthExtractor;
+use crate::db::prelude::{Consumer, Log};
+use crate::db::{consumer, log as db_log};
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, FixedOffset};
+use common::pagination::{CursorPage, CursorParams};
+use log::{error, warn};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+/// Analyses with a result which is not correct.
+const HAS_INCORRECT_RESULT: &str = r#"EXISTS (SELECT 1 FROM json_array_elements("log"."response") AS result WHERE (result->>'correct')::boolean IS NOT TRUE)"#;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AnalysisLogEntry {
+    /// `analysis_id` of the results
+    pub id: i32,
+    pub consumer_id: i32,
+    /// `null` for analyses stored before their time was recorded
+    pub created_at: Option<String>,
+    /// Analysis request as received
+    pub request: Value,
+    /// Results returned for the request
+    pub response: Value,
+}
+
+impl From<db_log::Model> for AnalysisLogEntry {
+    fn from(log: db_log::Model) -> Self {
+        AnalysisLogEntry {
+            id: log.id,
+            consumer_id: log.consumer_id,
+            created_at: log.created_at.map(|created_at| created_at.to_rfc3339()),
+            request: log.request,
+            response: log.response,
+        }
+    }
+}
+
+/// Consumers whose analyses the authenticated one may read: itself and the other consumers of
+/// its tenant.
+async fn readable_consumers(
+    db: &DatabaseConnection,
+    auth: &AuthExtractor,
+) -> Result<Vec<i32>, DbErr> {
+    let Some(tenant) = &auth.tenant else {
+        return Ok(vec![auth.consumer_id]);
+    };
+    Consumer::find()
+        .select_only()
+        .column(consumer::Column::Id)
+        .filter(consumer::Column::TenantId.eq(tenant.id))
+        .into_tuple()
+        .all(db)
+        .await
+}
+
+fn parse_timestamp(
+    name: &str,
+    timestamp: Option<&str>,
+) -> Result<Option<DateTime<FixedOffset>>, StatusCode> {
+    timestamp
+        .map(DateTime::parse_from_rfc3339)
+        .transpose()
+        .map_err(|err| {
+            warn!("invalid `{name}` of analyses: {err}");
+            StatusCode::BAD_REQUEST
+        })
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnalysisQuery {
+    /// Only return analyses of this consumer, the authenticated one or another of its tenant
+    pub consumer_id: Option<i32>,
+    /// Only return analyses of this task
+    pub task_id: Option<String>,
+    /// RFC 3339 timestamp, only return analyses created at or after it
+    pub from: Option<String>,
+    /// RFC 3339 timestamp, only return analyses created before it
+    pub to: Option<String>,
+    /// Only return analyses whose results are all correct, or with an incorrect one if false
+    pub correct: Option<bool>,
+}
+
+#[utoipa::path(get, path = "/api/v1/analyses", params(CursorParams, AnalysisQuery), responses((status = OK, body = CursorPage<AnalysisLogEntry>), (status = UNAUTHORIZED), (status = BAD_REQUEST), (status = FORBIDDEN)), description = "List the stored analyses of the consumer and the other consumers of its tenant, newest first")]
+pub async fn list_analyses(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Query(params): Query<CursorParams>,
+    Query(analysis_query): Query<AnalysisQuery>,
+) -> Result<Json<CursorPage<AnalysisLogEntry>>, StatusCode> {
+    let from = parse_timestamp("from", analysis_query.from.as_deref())?;
+    let to = parse_timestamp("to", analysis_query.to.as_deref())?;
+    let mut consumers = readable_consumers(&state.db, &auth).await.map_err(|err| {
+        error!("failed to load consumers of the tenant: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if let Some(consumer_id) = analysis_query.consumer_id {
+        if !consumers.contains(&consumer_id) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        consumers = vec![consumer_id];
+    }
+
+    let mut query = Log::find().filter(db_log::Column::ConsumerId.is_in(consumers));
+    if let Some(task_id) = analysis_query.task_id {
+        query = query.filter(Expr::cust_with_values(
+            r#""log"."request"->>'task_id' = $1"#,
+            [task_id],
+        ));
+    }
+    if let Some(from) = from {
+        query = query.filter(db_log::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = to {
+        query = query.filter(db_log::Column::CreatedAt.lt(to));
+    }
+    query = match analysis_query.correct {
+        Some(true) => query.filter(Expr::cust(HAS_INCORRECT_RESULT).not()),
+        Some(false) => query.filter(Expr::cust(HAS_INCORRECT_RESULT)),
+        None => query,
+    };
+    if let Some(cursor) = params.cursor {
+        query = query.filter(db_log::Column::Id.lt(cursor));
+    }
+    let logs = query
+        .order_by_desc(db_log::Column::Id)
+        .limit(params.limit())
+        .all(&state.db)
+        .await
+        .map_err(|err| {
+            error!("failed to list analyses: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let entries = logs.into_iter().map(AnalysisLogEntry::from).collect();
+    Ok(Json(CursorPage::new(entries, &params, |entry| {
+        entry.id.into()
+    })))
+}
+
+#[utoipa::path(get, path = "/api/v1/analyses/{id}", params(("id" = i32, Path, description = "`analysis_id` of the results")), responses((status = OK, body = AnalysisLogEntry), (status = UNAUTHORIZED), (status = NOT_FOUND)), description = "Get a stored analysis of the consumer or another consumer of its tenant")]
+pub async fn get_analysis(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<AnalysisLogEntry>, StatusCode> {
+    let result = async {
+        let consumers = readable_consumers(&state.db, &auth).await?;
+        Log::find_by_id(id)
+            .filter(db_log::Column::ConsumerId.is_in(consumers))
+            .one(&state.db)
+            .await
+    }
+    .await;
+    result
+        .map_err(|err| {
+            error!("failed to load analysis {id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map(|log| Json(log.into()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
@@ -1,32 +1,120 @@
-use crate::AppState;
 use crate::auth::AuthExtractor;
+use crate::cooldown::{CooldownResponse, cooldown_response};
 use crate::db::log as db_log;
-use crate::model::{AnalysisRequest, AnalysisResults, Results, SqlResult};
-use crate::runner::{RunResponse, RunnerInterface};
+use crate::deadline;
+use crate::model::{
+    AggregateVerdict, AnalyseResponse, AnalysisRequest, AnalysisResult, AnalysisResults,
+    DryRunResponse, Priority, Results, SqlResult, validate_results,
+};
+use crate::quota::{QuotaQuery, QuotaResponse, used_today};
+use crate::review;
+use crate::runner::{BudgetExceeded, RunContext, RunResponse, RunnerInterface};
+use crate::task;
+use crate::{API_VERSION, AppState, ENABLED_FEATURES};
 use axum::Json;
-use axum::extract::State;
-use axum::http::StatusCode;
-use futures::future::join_all;
-use log::{error, warn};
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use common::i18n::Language;
+use common::retry::is_transient_status;
+use common::version::{PingResponse, VersionInfo};
+use futures::future::{Either, join_all, select};
+use log::{debug, error, info, warn};
 use sea_orm::{ActiveModelTrait, NotSet, Set};
+use serde::Serialize;
+use serde_json::Value;
+use std::pin::pin;
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::time::sleep;
+use utoipa::ToSchema;
 
-#[utoipa::path(post, path = "/api/v1/analyse", request_body = AnalysisRequest, responses((status = OK, body = AnalysisResults), (status = UNAUTHORIZED), (status = BAD_REQUEST), (status = BAD_GATEWAY)), description = "Analyze SQL submission")]
+#[utoipa::path(post, path = "/api/v1/analyse", request_body = AnalysisRequest, responses((status = OK, body = AnalyseResponse), (status = UNAUTHORIZED), (status = BAD_REQUEST), (status = TOO_MANY_REQUESTS, body = CooldownResponse), (status = BAD_GATEWAY, body = UpstreamErrorResponse)), description = "Analyze SQL submission")]
 pub async fn analyse(
     auth: AuthExtractor,
     state: State<AppState>,
+    headers: HeaderMap,
     body: Json<AnalysisRequest>,
-) -> Result<Json<AnalysisResults>, StatusCode> {
+) -> Result<Json<AnalyseResponse>, Response> {
+    // errors shown to students follow the feedback language, else the browser's preference
+    let language = Language::negotiate(
+        body.feedback_language.as_deref(),
+        headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok()),
+    );
+    // malformed requests must not use up the cooldown
+    body.validate().map_err(|err| {
+        warn!("invalid analysis request: {err}");
+        StatusCode::BAD_REQUEST.into_response()
+    })?;
+    let cooldown = match (&state.cooldown, &body.user_id, body.dry_run) {
+        (Some(cooldown), Some(user_id), false) => Some((cooldown.clone(), user_id.clone())),
+        _ => None,
+    };
+    if let Some((cooldown, user_id)) = &cooldown {
+        cooldown
+            .try_attempt(auth.consumer_id, user_id, body.task_id.as_deref())
+            .await
+            .map_err(|remaining| cooldown_response(remaining, language))?;
+    }
+    let consumer_id = auth.consumer_id;
+    let task_id = body.task_id.clone();
+    let recent_errors = state.recent_errors.clone();
+    let result = analyse_request(auth, state, body, language).await;
+    let server_error = result
+        .as_ref()
+        .is_err_and(|response| response.status().is_server_error());
+    recent_errors.record(server_error);
+    // the student is not to blame for failures of the proxy, the runner or the upstream
+    if let (Some((cooldown, user_id)), true) = (&cooldown, server_error) {
+        cooldown
+            .release(consumer_id, user_id, task_id.as_deref())
+            .await;
+    }
+    result
+}
+
+async fn analyse_request(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    body: Json<AnalysisRequest>,
+    language: Language,
+) -> Result<Json<AnalyseResponse>, Response> {
+    if let Some(daily_limit) = auth.daily_limit {
+        let used = used_today(&state.db, auth.consumer_id)
+            .await
+            .map_err(|err| {
+                error!("failed to count analyses: {err}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            })?;
+        if used >= daily_limit.max(0) as u64 {
+            return Err(StatusCode::TOO_MANY_REQUESTS.into_response());
+        }
+    }
+
+    let tenant = auth.tenant.as_ref();
     let mut upstream_request = body.0.clone();
-    if let Some(runner_interface) = &state.runner_interface {
+    upstream_request.model = tenant.and_then(|tenant| tenant.model.clone());
+    let run_context = RunContext {
+        consumer_id: auth.consumer_id,
+        task_id: upstream_request.task_id.clone(),
+        user_id: upstream_request.user_id.clone(),
+        metadata: upstream_request.metadata.clone(),
+        language,
+    };
+    if let Some(runner_interface) = &state.runner_interface(tenant) {
         if upstream_request.solution_results.is_none() {
             upstream_request.solution_results = Some(
                 generate_results(
                     &upstream_request.db_schema,
                     &upstream_request.solutions,
                     runner_interface,
+                    &run_context,
                 )
-                .await,
+                .await
+                .map_err(IntoResponse::into_response)?,
             )
         }
         if upstream_request.submission_results.is_none() {
@@ -35,28 +123,132 @@ pub async fn analyse(
                     &upstream_request.db_schema,
                     &upstream_request.submissions,
                     runner_interface,
+                    &run_context,
                 )
-                .await,
+                .await
+                .map_err(IntoResponse::into_response)?,
             )
         }
     }
 
-    let response = upstream_proxy(upstream_request, &state)
+    let mut review_only = false;
+    if let Some(task_id) = &upstream_request.task_id {
+        let stored = task::find(&state.db, auth.consumer_id, task_id)
+            .await
+            .map_err(|err| {
+                error!("failed to load task {task_id}: {err}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            })?;
+        if let Some(stored) = stored {
+            review_only = deadline::is_review_only(
+                stored.review_only_after_deadline,
+                stored.deadline,
+                Utc::now(),
+            );
+            upstream_request.comparison = stored.comparison;
+        }
+    }
+    // review-only grading needs the runner's verdict, which uses its default comparison if the
+    // task has no spec
+    let compare = upstream_request.comparison.is_some() || review_only;
+    let mut matched_solutions = vec![None; body.submissions.len()];
+    if let (true, Some(runner_interface)) = (compare, &state.runner_interface(tenant)) {
+        let matches = solution_matches(
+            &upstream_request,
+            upstream_request.comparison.as_ref(),
+            runner_interface,
+            &run_context,
+        )
+        .await;
+        matched_solutions = matches.iter().map(|matched| matched.flatten()).collect();
+        upstream_request.submission_matches = Some(
+            matches
+                .iter()
+                .map(|matched| matched.map(|index| index.is_some()))
+                .collect(),
+        );
+    }
+
+    upstream_request.truncate_results(&state.config.result_limits());
+
+    if upstream_request.dry_run {
+        upstream_request.redact();
+        return Ok(Json(AnalyseResponse::DryRun(Box::new(DryRunResponse {
+            upstream_request,
+        }))));
+    }
+
+    let submission_errors = upstream_request.submission_errors();
+    let submission_keys = (0..body.submissions.len())
+        .map(|index| body.submission_key(index))
+        .collect::<Vec<_>>();
+    let reviewed = review::reviewed_results(&state.db, auth.consumer_id, &submission_keys)
         .await
-        .map_err(|e| {
-            warn!("error from upstream: {}", e);
-            StatusCode::BAD_GATEWAY
-        })
-        .map(Json)?;
+        .map_err(|err| {
+            error!("failed to load reviews: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+    let mut response = if submission_keys.iter().all(|key| reviewed.contains_key(key)) {
+        debug!("every submission was reviewed, skipping the upstream");
+        submission_keys
+            .iter()
+            .map(|key| reviewed[key].clone())
+            .collect()
+    } else if review_only {
+        debug!("the deadline of the task passed, grading in review-only mode");
+        review_only_results(&upstream_request)
+    } else {
+        upstream_proxy(upstream_request, &state, state.upstream_url(tenant))
+            .await
+            .map_err(|e| {
+                warn!("error from upstream: {}", e);
+                match e.downcast_ref::<ProxyError>() {
+                    Some(ProxyError::InvalidResponse(diagnostics)) => (
+                        StatusCode::BAD_GATEWAY,
+                        Json(UpstreamErrorResponse {
+                            error: "the upstream response violates the analysis contract"
+                                .to_string(),
+                            diagnostics: diagnostics.clone(),
+                        }),
+                    )
+                        .into_response(),
+                    _ => StatusCode::BAD_GATEWAY.into_response(),
+                }
+            })?
+    };
+    for ((result, error), key) in response
+        .iter_mut()
+        .zip(submission_errors)
+        .zip(&submission_keys)
+    {
+        if let Some(reviewed) = reviewed.get(key) {
+            *result = reviewed.clone();
+        }
+        result.execution_error = error;
+        result.needs_review |= !result.reviewed
+            && matches!(
+                (result.confidence, state.config.min_feedback_confidence),
+                (Some(confidence), Some(min)) if confidence < min
+            );
+    }
+    let needs_review = response.iter().filter(|result| result.needs_review).count();
+    if needs_review > 0 {
+        info!(
+            "{needs_review} of {} results of consumer {} need review",
+            response.len(),
+            auth.consumer_id
+        );
+    }
 
-    db_log::ActiveModel {
+    let log = db_log::ActiveModel {
         id: NotSet,
         consumer_id: Set(auth.consumer_id),
+        created_at: NotSet,
         request: match serde_json::to_value(&body.0) {
             Ok(res) => Set(res),
             Err(_) => NotSet,
         },
-        response: match serde_json::to_value(&response.0) {
+        response: match serde_json::to_value(&response) {
             Ok(res) => Set(res),
             Err(_) => NotSet,
         },
@@ -65,54 +257,283 @@ pub async fn analyse(
     .await
     .map_err(|err| {
         error!("failed to store {err}");
-        StatusCode::INTERNAL_SERVER_ERROR
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })?;
+    review::enqueue(
+        &state.db,
+        auth.consumer_id,
+        log.id,
+        &body,
+        &submission_keys,
+        &response,
+    )
+    .await
+    .map_err(|err| {
+        error!("failed to queue reviews: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
     })?;
+    for result in &mut response {
+        result.analysis_id = Some(log.id);
+    }
+
+    let aggregate = body.aggregate.then(|| {
+        AggregateVerdict::new(
+            &response,
+            matched_solutions,
+            body.submission_weights.as_deref(),
+        )
+    });
+    Ok(Json(match (body.0.metadata, aggregate) {
+        (None, None) => AnalyseResponse::Results(response),
+        (metadata, aggregate) => AnalyseResponse::Annotated {
+            results: response,
+            metadata,
+            aggregate,
+        },
+    }))
+}
+
+#[utoipa::path(get, path = "/api/v1/quota", params(QuotaQuery), responses((status = OK, body = QuotaResponse), (status = UNAUTHORIZED)), description = "Get the quota usage of the calling consumer and the cooldown of one of its users")]
+pub async fn quota(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Query(quota_query): Query<QuotaQuery>,
+) -> Result<Json<QuotaResponse>, StatusCode> {
+    let used_today = used_today(&state.db, auth.consumer_id)
+        .await
+        .map_err(|err| {
+            error!("failed to count analyses: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let daily_limit = auth.daily_limit.map(|limit| limit.max(0) as u64);
+    let cooldown_remaining = match (&state.cooldown, &quota_query.user_id) {
+        (Some(cooldown), Some(user_id)) => {
+            cooldown
+                .remaining(auth.consumer_id, user_id, quota_query.task_id.as_deref())
+                .await
+        }
+        _ => None,
+    };
+    Ok(Json(QuotaResponse {
+        daily_limit,
+        used_today,
+        remaining_today: daily_limit.map(|limit| limit.saturating_sub(used_today)),
+        interactive_slots_available: state.upstream_semaphore.available_permits(),
+        batch_slots_available: state.upstream_batch_semaphore.available_permits(),
+        cooldown_secs: state
+            .cooldown
+            .as_ref()
+            .map(|cooldown| cooldown.period().as_secs()),
+        cooldown_retry_after_secs: cooldown_remaining
+            .map(|remaining| remaining.as_secs_f64().ceil() as u64),
+    }))
+}
+
+#[utoipa::path(get, path = "/api/v1/version", responses((status = OK, body = VersionInfo)), description = "Get version and build information")]
+pub async fn version() -> Json<VersionInfo> {
+    Json(common::version_info!(API_VERSION, ENABLED_FEATURES))
+}
 
-    Ok(response)
+#[utoipa::path(get, path = "/ping", responses((status = OK, body = PingResponse)), description = "Lightweight liveness check without touching any dependency")]
+pub async fn ping() -> Json<PingResponse> {
+    Json(PingResponse {
+        version: common::version_info!(API_VERSION, ENABLED_FEATURES),
+        uptime_secs: common::version::uptime().as_secs(),
+    })
 }
 
+/// Results of the queries, fails with `TOO_MANY_REQUESTS` once the runner reports the
+/// execution budget of the task as exhausted.
 async fn generate_results(
     db_schema: &str,
     queries: &[String],
     runner_interface: &Arc<RunnerInterface>,
-) -> Results {
-    join_all(
-        queries
-            .iter()
-            .map(|query| runner_interface.run(db_schema.to_string(), query.clone())),
-    )
+    context: &RunContext,
+) -> Result<Results, StatusCode> {
+    let results = match runner_interface
+        .batch_run(db_schema.to_string(), queries, context)
+        .await
+    {
+        Ok(results) => results,
+        Err(err) if err.is::<BudgetExceeded>() => {
+            warn!("execution budget exhausted for task {:?}", context.task_id);
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        }
+        Err(err) => {
+            error!("error while contacting sql runner: {err}");
+            return Ok(vec![None; queries.len()]);
+        }
+    };
+    Ok(results
+        .into_iter()
+        .map(|r| {
+            Some(match r {
+                RunResponse::Success(s) => SqlResult::Ok(s.result_set),
+                RunResponse::Error(e) => SqlResult::Error(format!("Error: {}", e.error)),
+            })
+        })
+        .collect())
+}
+
+/// Index of the solution each submission matches, `Some(None)` if it matches none and `None`
+/// if the runner could not decide.
+async fn solution_matches(
+    request: &AnalysisRequest,
+    comparison: Option<&Value>,
+    runner_interface: &Arc<RunnerInterface>,
+    context: &RunContext,
+) -> Vec<Option<Option<usize>>> {
+    join_all(request.submissions.iter().map(|submission| {
+        runner_interface.matching_solution(
+            request.db_schema.clone(),
+            &request.solutions,
+            submission.clone(),
+            comparison,
+            context,
+        )
+    }))
     .await
     .into_iter()
     .map(|r| {
-        match r {
-            Ok(i) => Some(i),
-            Err(err) => {
-                error!("error while contacting sql runner: {err}");
-                None
-            }
-        }
-        .map(|r| match r {
-            RunResponse::Success(s) => SqlResult::Ok(s.result_set),
-            RunResponse::Error(e) => SqlResult::Error(format!("Error: {}", e.error)),
-        })
+        r.inspect_err(|err| error!("error while comparing submission: {err}"))
+            .ok()
     })
     .collect()
 }
 
+/// Correctness of every submission as decided by the runner, without feedback. Submissions
+/// the runner could not decide need review.
+fn review_only_results(request: &AnalysisRequest) -> AnalysisResults {
+    let matches = request.submission_matches.clone().unwrap_or_default();
+    (0..request.submissions.len())
+        .map(|index| {
+            let matched = matches.get(index).copied().flatten();
+            AnalysisResult {
+                correct: matched == Some(true),
+                needs_review: matched.is_none(),
+                review_only: true,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
 async fn upstream_proxy(
     mut body: AnalysisRequest,
     state: &AppState,
+    upstream_url: &str,
 ) -> Result<AnalysisResults, anyhow::Error> {
     body.redact();
-    let _permit = state.upstream_semaphore.acquire().await?;
+    let hedge_delay = state
+        .config
+        .upstream_hedge_percentile
+        .and_then(|percentile| state.upstream_latency.percentile(percentile));
+
+    let first = pin!(send_upstream_with_retries(&body, state, upstream_url));
+    let Some(hedge_delay) = hedge_delay else {
+        return first.await;
+    };
+    let first = match select(first, pin!(sleep(hedge_delay))).await {
+        Either::Left((res, _)) => return res,
+        Either::Right((_, first)) => first,
+    };
+
+    debug!("upstream did not respond within {hedge_delay:?}, sending hedged request");
+    let second = pin!(send_upstream_with_retries(&body, state, upstream_url));
+    match select(first, second).await {
+        Either::Left((Ok(res), _)) | Either::Right((Ok(res), _)) => Ok(res),
+        Either::Left((Err(err), other)) | Either::Right((Err(err), other)) => {
+            warn!("hedged upstream request failed: {err}");
+            other.await
+        }
+    }
+}
+
+/// Sends the request again after rate limits, server and connection errors.
+async fn send_upstream_with_retries(
+    body: &AnalysisRequest,
+    state: &AppState,
+    upstream_url: &str,
+) -> Result<AnalysisResults, anyhow::Error> {
+    state
+        .config
+        .upstream_backoff()
+        .retry(
+            || send_upstream(body, state, upstream_url),
+            |err: &anyhow::Error| {
+                let transient = match err.downcast_ref::<ProxyError>() {
+                    Some(ProxyError::UpstreamError(status, _)) => {
+                        is_transient_status(status.as_u16())
+                    }
+                    Some(ProxyError::InvalidResponse(_)) => false,
+                    None => err
+                        .downcast_ref::<reqwest::Error>()
+                        .is_some_and(|err| err.is_connect() || err.is_timeout()),
+                };
+                if transient {
+                    warn!("transient upstream error, retrying: {err}");
+                }
+                transient
+            },
+        )
+        .await
+}
+
+async fn send_upstream(
+    body: &AnalysisRequest,
+    state: &AppState,
+    upstream_url: &str,
+) -> Result<AnalysisResults, anyhow::Error> {
+    let semaphore = match body.priority {
+        Priority::Interactive => &state.upstream_semaphore,
+        Priority::Batch => &state.upstream_batch_semaphore,
+    };
+    let _permit = semaphore.acquire().await?;
+    let start = Instant::now();
+    let result = request_upstream(body, state, upstream_url).await;
+    let outcome = match &result {
+        Ok(_) => "ok",
+        Err(err) => match err.downcast_ref::<ProxyError>() {
+            Some(ProxyError::UpstreamError(..)) => "error_status",
+            Some(ProxyError::InvalidResponse(_)) => "invalid_response",
+            None => "unreachable",
+        },
+    };
+    metrics::counter!("proxy_upstream_requests_total", "outcome" => outcome).increment(1);
+    metrics::histogram!("proxy_upstream_request_duration_seconds", "outcome" => outcome)
+        .record(start.elapsed().as_secs_f64());
+    result
+}
+
+async fn request_upstream(
+    body: &AnalysisRequest,
+    state: &AppState,
+    upstream_url: &str,
+) -> Result<AnalysisResults, anyhow::Error> {
+    #[cfg(feature = "fault-injection")]
+    common::fault::inject("upstream").await?;
+    let start = Instant::now();
     let res = reqwest::Client::new()
-        .post(&state.config.upstream_url)
-        .json(&body)
+        .post(upstream_url)
+        .json(body)
         .send()
         .await?;
 
     match res.error_for_status_ref() {
-        Ok(_) => Ok(res.json().await?),
+        Ok(_) => {
+            let results: AnalysisResults =
+                serde_json::from_slice(&res.bytes().await?).map_err(|err| {
+                    ProxyError::InvalidResponse(vec![format!("malformed body: {err}")])
+                })?;
+            state.upstream_latency.record(start.elapsed());
+            validate_results(
+                &results,
+                body.submissions.len(),
+                state.config.max_feedback_length,
+            )
+            .map_err(ProxyError::InvalidResponse)?;
+            Ok(results)
+        }
         Err(_) => Err(ProxyError::UpstreamError(res.status(), res.text().await?).into()),
     }
 }
@@ -121,4 +542,13 @@ async fn upstream_proxy(
 pub enum ProxyError {
     #[error("unexpected code {0}: {1}")]
     UpstreamError(StatusCode, String),
+    #[error("invalid upstream response: {}", .0.join("; "))]
+    InvalidResponse(Vec<String>),
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UpstreamErrorResponse {
+    pub error: String,
+    /// Every violation of the contract found in the upstream response
+    pub diagnostics: Vec<String>,
 }
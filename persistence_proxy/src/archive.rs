@@ -0,0 +1,90 @@
+use anyhow::anyhow;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Url};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Minimal client for S3-compatible object storage, only supporting path-style uploads.
+#[derive(Debug)]
+pub struct ObjectStorage {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl ObjectStorage {
+    pub fn new(
+        endpoint: Url,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+    ) -> Self {
+        ObjectStorage {
+            client: Client::new(),
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+        }
+    }
+
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), anyhow::Error> {
+        let url = self.endpoint.join(&format!("{}/{}", self.bucket, key))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            _ => return Err(anyhow!("object storage endpoint has no host")),
+        };
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "PUT\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}",
+            url.path()
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut signing_key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date.as_str(), &self.region, "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+
+        self.client
+            .put(url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                    self.access_key
+                ),
+            )
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
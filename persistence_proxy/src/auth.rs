@@ -1,13 +1,16 @@
 use crate::AppState;
 use crate::db::consumer::Column::TokenHash;
-use crate::db::prelude::Consumer;
+use crate::db::prelude::{Consumer, Tenant};
+use crate::db::tenant;
 use axum::extract::{FromRef, FromRequestParts};
 use axum::http::StatusCode;
 use axum::http::request::Parts;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 
 pub struct AuthExtractor {
     pub consumer_id: i32,
+    pub daily_limit: Option<i32>,
+    pub tenant: Option<tenant::Model>,
 }
 
 impl<S: Send + Sync> FromRequestParts<S> for AuthExtractor
@@ -23,19 +26,27 @@ where
             .and_then(|h| h.to_str().ok())
             .and_then(|h| h.split(" ").nth(1))
             .ok_or(StatusCode::UNAUTHORIZED)?;
-        let hashed_token = blake3::hash(token.as_bytes()).to_hex().to_string();
-
-        let state_ref = AppState::from_ref(state);
-
-        let participant = Consumer::find()
-            .filter(TokenHash.contains(hashed_token))
-            .one(&state_ref.db)
+        authenticate(&AppState::from_ref(state).db, token)
             .await
-            .ok()
-            .flatten()
-            .ok_or(StatusCode::UNAUTHORIZED)?;
-        Ok(AuthExtractor {
-            consumer_id: participant.id,
-        })
+            .ok_or(StatusCode::UNAUTHORIZED)
     }
 }
+
+/// Consumer of the bearer token, if any.
+pub async fn authenticate(db: &DatabaseConnection, token: &str) -> Option<AuthExtractor> {
+    let hashed_token = blake3::hash(token.as_bytes()).to_hex().to_string();
+    let (participant, tenant) = Consumer::find()
+        .find_also_related(Tenant)
+        .filter(TokenHash.contains(hashed_token))
+        .one(db)
+        .await
+        .ok()
+        .flatten()?;
+    Some(AuthExtractor {
+        consumer_id: participant.id,
+        daily_limit: participant
+            .daily_limit
+            .or(tenant.as_ref().and_then(|tenant| tenant.daily_limit)),
+        tenant,
+    })
+}
@@ -0,0 +1,204 @@
+//! Replays a workload against a deployed stack and reports latency percentiles and error rates.
+//!
+//! Configured through `LOADTEST_*` environment variables, see [`Config`]. The workload is a
+//! JSONL file with one `{"kind": "run" | "compare" | "analyse", "body": {...}}` entry per
+//! line; without a file a synthetic run/compare/analyse mix is generated.
+
+use futures::{StreamExt, stream};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+fn get_default_requests() -> usize {
+    100
+}
+
+fn get_default_concurrency() -> usize {
+    10
+}
+
+#[derive(Deserialize, Debug)]
+struct Config {
+    /// Base url of sql_runner, e.g. `http://localhost:8081`
+    runner_url: Option<String>,
+    /// Base url of persistence_proxy, e.g. `http://localhost:8080`
+    proxy_url: Option<String>,
+    /// Consumer token used for analyse requests
+    token: Option<String>,
+    workload: Option<String>,
+    #[serde(default = "get_default_requests")]
+    requests: usize,
+    #[serde(default = "get_default_concurrency")]
+    concurrency: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Run,
+    Compare,
+    Analyse,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Request {
+    kind: Kind,
+    body: Value,
+}
+
+const ENVIRONMENT: &str = "CREATE TABLE loadtest (id INT PRIMARY KEY, name TEXT, score INT);
+INSERT INTO loadtest SELECT i, 'name ' || i, i % 17 FROM generate_series(1, 1000) AS i;";
+
+fn synthetic_workload(config: &Config) -> Vec<Request> {
+    let mut kinds = vec![];
+    if config.runner_url.is_some() {
+        kinds.extend([Kind::Run, Kind::Compare]);
+    }
+    if config.proxy_url.is_some() && config.token.is_some() {
+        kinds.push(Kind::Analyse);
+    }
+    if kinds.is_empty() {
+        return vec![];
+    }
+    (0..config.requests)
+        .map(|i| {
+            let kind = kinds[i % kinds.len()];
+            let query = format!("SELECT name, score FROM loadtest WHERE score = {}", i % 17);
+            let body = match kind {
+                Kind::Run => json!({"environment": ENVIRONMENT, "query": query}),
+                Kind::Compare => json!({
+                    "environment": ENVIRONMENT,
+                    "solution": query,
+                    "submission": "SELECT name, score FROM loadtest WHERE score = 3",
+                    "row_normalisation": "SortRows",
+                }),
+                Kind::Analyse => json!({
+                    "sql_environment": "PostgreSQL",
+                    "db_schema": ENVIRONMENT,
+                    "task": "Select the names and scores with the given score",
+                    "solutions": [query],
+                    "submissions": ["SELECT name, score FROM loadtest WHERE score = 3"],
+                    "priority": "batch",
+                }),
+            };
+            Request { kind, body }
+        })
+        .collect()
+}
+
+fn load_workload(path: &str, requests: usize) -> Result<Vec<Request>, anyhow::Error> {
+    let recorded = std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Request>, _>>()?;
+    Ok(recorded.iter().cycle().take(requests).cloned().collect())
+}
+
+async fn send(
+    client: &reqwest::Client,
+    config: &Config,
+    request: &Request,
+) -> Result<(), anyhow::Error> {
+    let (base_url, path) = match request.kind {
+        Kind::Run => (&config.runner_url, "/api/v1/run"),
+        Kind::Compare => (&config.runner_url, "/api/v1/compare"),
+        Kind::Analyse => (&config.proxy_url, "/api/v1/analyse"),
+    };
+    let base_url = base_url
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("no url configured for {:?}", request.kind))?;
+    let mut builder = client.post(format!("{base_url}{path}")).json(&request.body);
+    if let (Kind::Analyse, Some(token)) = (request.kind, &config.token) {
+        builder = builder.bearer_auth(token);
+    }
+    let response: Value = builder.send().await?.error_for_status()?.json().await?;
+    // The runner reports query errors with a 200 response
+    if let Some(error) = response.get("error") {
+        return Err(anyhow::anyhow!("{error}"));
+    }
+    Ok(())
+}
+
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    sorted[((sorted.len() - 1) as f64 * percentile).round() as usize]
+}
+
+async fn run() -> Result<(), anyhow::Error> {
+    let config = envy::prefixed("LOADTEST_").from_env::<Config>()?;
+    let workload = match &config.workload {
+        Some(path) => load_workload(path, config.requests)?,
+        None => synthetic_workload(&config),
+    };
+    if workload.is_empty() {
+        return Err(anyhow::anyhow!(
+            "empty workload, configure LOADTEST_RUNNER_URL and/or LOADTEST_PROXY_URL with LOADTEST_TOKEN"
+        ));
+    }
+
+    let client = reqwest::Client::new();
+    let start = Instant::now();
+    let results = stream::iter(workload.iter())
+        .map(|request| {
+            let (client, config) = (&client, &config);
+            async move {
+                let request_start = Instant::now();
+                let result = send(client, config, request).await;
+                (request.kind, request_start.elapsed(), result)
+            }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    let total = start.elapsed();
+
+    let mut by_kind: BTreeMap<Kind, (Vec<Duration>, usize)> = BTreeMap::new();
+    for (kind, latency, result) in results {
+        let entry = by_kind.entry(kind).or_default();
+        entry.0.push(latency);
+        if let Err(err) = result {
+            entry.1 += 1;
+            log::debug!("{kind:?} request failed: {err}");
+        }
+    }
+
+    println!(
+        "{} requests in {:.2}s ({:.1} req/s)",
+        workload.len(),
+        total.as_secs_f64(),
+        workload.len() as f64 / total.as_secs_f64()
+    );
+    println!(
+        "{:<8} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "kind", "count", "errors", "p50 ms", "p90 ms", "p99 ms", "max ms"
+    );
+    for (kind, (mut latencies, errors)) in by_kind {
+        latencies.sort();
+        println!(
+            "{:<8} {:>8} {:>7.1}% {:>10} {:>10} {:>10} {:>10}",
+            format!("{kind:?}").to_lowercase(),
+            latencies.len(),
+            errors as f64 * 100.0 / latencies.len() as f64,
+            percentile(&latencies, 0.5).as_millis(),
+            percentile(&latencies, 0.9).as_millis(),
+            percentile(&latencies, 0.99).as_millis(),
+            latencies.last().copied().unwrap_or_default().as_millis(),
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    if let Err(err) = rt.block_on(run()) {
+        eprintln!("{err}");
+        exit(1)
+    }
+}
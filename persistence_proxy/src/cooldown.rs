@@ -0,0 +1,159 @@
+use crate::shared_store::SharedStore;
+use axum::Json;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use common::i18n::Language;
+use log::error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Entries are pruned once the map grows beyond this size.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+type AttemptKey = (i32, String, Option<String>);
+
+/// Minimum time between analyses of the same task by the same user of a consumer, tracked
+/// per replica unless a shared store is configured.
+#[derive(Debug)]
+pub struct Cooldown {
+    period: Duration,
+    last_attempts: Mutex<HashMap<AttemptKey, Instant>>,
+    shared_store: Option<SharedStore>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CooldownResponse {
+    pub error: String,
+    /// Seconds until the next analysis of the task is accepted
+    pub retry_after_secs: u64,
+}
+
+impl Cooldown {
+    pub fn new(period: Duration) -> Self {
+        Cooldown {
+            period,
+            last_attempts: Default::default(),
+            shared_store: None,
+        }
+    }
+
+    /// Tracks the attempts in the store shared by all replicas.
+    pub fn with_shared_store(self, shared_store: SharedStore) -> Self {
+        Cooldown {
+            shared_store: Some(shared_store),
+            ..self
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Time until the next attempt of the user for the task is accepted, `None` if it would be
+    /// accepted now or the shared store fails.
+    pub async fn remaining(
+        &self,
+        consumer_id: i32,
+        user_id: &str,
+        task_id: Option<&str>,
+    ) -> Option<Duration> {
+        if let Some(shared_store) = &self.shared_store {
+            let key = shared_key(consumer_id, user_id, task_id);
+            return shared_store.remaining(&key).await.unwrap_or_else(|err| {
+                error!("failed to read the cooldown from the shared store: {err}");
+                None
+            });
+        }
+        let last_attempts = self.last_attempts.lock().unwrap();
+        let elapsed = last_attempts.get(&(
+            consumer_id,
+            user_id.to_string(),
+            task_id.map(str::to_string),
+        ))?;
+        self.period
+            .checked_sub(elapsed.elapsed())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Records an attempt, or returns the remaining time if the previous attempt of the user
+    /// for the task is too recent. Attempts are accepted if the shared store fails.
+    pub async fn try_attempt(
+        &self,
+        consumer_id: i32,
+        user_id: &str,
+        task_id: Option<&str>,
+    ) -> Result<(), Duration> {
+        if let Some(shared_store) = &self.shared_store {
+            let key = shared_key(consumer_id, user_id, task_id);
+            return match shared_store.set_if_absent(&key, self.period).await {
+                Ok(None) => Ok(()),
+                Ok(Some(remaining)) => Err(remaining),
+                Err(err) => {
+                    error!("failed to check the cooldown in the shared store: {err}");
+                    Ok(())
+                }
+            };
+        }
+        let now = Instant::now();
+        let mut last_attempts = self.last_attempts.lock().unwrap();
+        let key = (
+            consumer_id,
+            user_id.to_string(),
+            task_id.map(str::to_string),
+        );
+        let elapsed = last_attempts.get(&key).map(|last| now - *last);
+        if let Some(elapsed) = elapsed.filter(|elapsed| *elapsed < self.period) {
+            return Err(self.period - elapsed);
+        }
+        if last_attempts.len() >= PRUNE_THRESHOLD {
+            last_attempts.retain(|_, last| now - *last < self.period);
+        }
+        last_attempts.insert(key, now);
+        Ok(())
+    }
+
+    /// Forgets the attempt of the user for the task, so an analysis which failed on our side
+    /// does not delay the next one.
+    pub async fn release(&self, consumer_id: i32, user_id: &str, task_id: Option<&str>) {
+        if let Some(shared_store) = &self.shared_store {
+            let key = shared_key(consumer_id, user_id, task_id);
+            if let Err(err) = shared_store.remove(&key).await {
+                error!("failed to release the cooldown in the shared store: {err}");
+            }
+            return;
+        }
+        self.last_attempts.lock().unwrap().remove(&(
+            consumer_id,
+            user_id.to_string(),
+            task_id.map(str::to_string),
+        ));
+    }
+}
+
+fn shared_key(consumer_id: i32, user_id: &str, task_id: Option<&str>) -> String {
+    let key = serde_json::to_string(&(consumer_id, user_id, task_id))
+        .expect("attempt keys are serialisable");
+    format!("cooldown:{}", blake3::hash(key.as_bytes()).to_hex())
+}
+
+/// `429` response telling the client when to retry.
+pub fn cooldown_response(remaining: Duration, language: Language) -> Response {
+    let retry_after_secs = remaining.as_secs_f64().ceil() as u64;
+    let error = language
+        .translate("cooldown", &[("seconds", &retry_after_secs.to_string())])
+        .unwrap_or_else(|| {
+            format!("submissions for this task are limited, retry in {retry_after_secs} seconds")
+        });
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after_secs.to_string())],
+        Json(CooldownResponse {
+            error,
+            retry_after_secs,
+        }),
+    )
+        .into_response()
+}
@@ -9,12 +9,26 @@ pub struct Model {
     pub id: i32,
     pub name: String,
     pub token_hash: String,
+    pub daily_limit: Option<i32>,
+    pub tenant_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::log::Entity")]
     Log,
+    #[sea_orm(has_many = "super::review::Entity")]
+    Review,
+    #[sea_orm(has_many = "super::task::Entity")]
+    Task,
+    #[sea_orm(
+        belongs_to = "super::tenant::Entity",
+        from = "Column::TenantId",
+        to = "super::tenant::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Tenant,
 }
 
 impl Related<super::log::Entity> for Entity {
@@ -23,4 +37,22 @@ impl Related<super::log::Entity> for Entity {
     }
 }
 
+impl Related<super::review::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Review.def()
+    }
+}
+
+impl Related<super::task::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Task.def()
+    }
+}
+
+impl Related<super::tenant::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tenant.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -10,6 +10,7 @@ pub struct Model {
     pub consumer_id: i32,
     pub request: Json,
     pub response: Json,
+    pub created_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -22,6 +23,8 @@ pub enum Relation {
         on_delete = "NoAction"
     )]
     Consumer,
+    #[sea_orm(has_many = "super::review::Entity")]
+    Review,
 }
 
 impl Related<super::consumer::Entity> for Entity {
@@ -30,4 +33,10 @@ impl Related<super::consumer::Entity> for Entity {
     }
 }
 
+impl Related<super::review::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Review.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
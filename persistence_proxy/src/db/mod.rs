@@ -3,4 +3,8 @@
 pub mod prelude;
 
 pub mod consumer;
+pub mod job_run;
 pub mod log;
+pub mod review;
+pub mod task;
+pub mod tenant;
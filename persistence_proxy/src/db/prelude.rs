@@ -1,4 +1,8 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.0
 
 pub use super::consumer::Entity as Consumer;
+pub use super::job_run::Entity as JobRun;
 pub use super::log::Entity as Log;
+pub use super::review::Entity as Review;
+pub use super::task::Entity as Task;
+pub use super::tenant::Entity as Tenant;
@@ -0,0 +1,11 @@
+use chrono::{DateTime, FixedOffset, Utc};
+
+/// Whether analyses of a task are graded in review-only mode at `now`, i.e. its deadline
+/// passed and the mode is enabled for it.
+pub fn is_review_only(
+    review_only_after_deadline: bool,
+    deadline: Option<DateTime<FixedOffset>>,
+    now: DateTime<Utc>,
+) -> bool {
+    review_only_after_deadline && deadline.is_some_and(|deadline| deadline <= now)
+}
@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const MIN_SAMPLES: usize = 20;
+
+/// Sliding window of recent upstream latencies used to decide when to hedge a request.
+#[derive(Debug)]
+pub struct LatencyTracker {
+    capacity: usize,
+    samples: Mutex<VecDeque<Duration>>,
+}
+
+impl LatencyTracker {
+    pub fn new(capacity: usize) -> Self {
+        LatencyTracker {
+            capacity,
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    /// Latency below which the given fraction (0.0 - 1.0) of recent requests completed.
+    /// Returns `None` until enough samples were recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut samples = self
+            .samples
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        if samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        samples.sort();
+        let index = ((samples.len() - 1) as f64 * percentile.clamp(0.0, 1.0)).round() as usize;
+        Some(samples[index])
+    }
+}
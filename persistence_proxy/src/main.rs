@@ -1,24 +1,65 @@
+mod analysis;
 mod api;
+mod archive;
 mod auth;
+mod cooldown;
 #[allow(unused_imports)]
 mod db;
+mod deadline;
+mod hedge;
 mod model;
+mod overview;
+#[cfg(feature = "queue")]
+mod queue;
+mod quota;
+mod readiness;
+mod review;
 mod runner;
+mod scheduler;
+mod shared_store;
+mod task;
+mod tenant;
+mod truncation;
 
 use crate::api::*;
+use crate::archive::ObjectStorage;
+use crate::cooldown::Cooldown;
+use crate::hedge::LatencyTracker;
 use crate::runner::RunnerInterface;
+use crate::shared_store::SharedStore;
+use crate::truncation::ResultLimits;
+use axum::middleware;
+use common::config::{ConfigError, ConfigValidator};
+use common::retry::Backoff;
+use common::stats::ErrorRate;
 use env_logger::Env;
 use log::{LevelFilter, error, info};
+use reqwest::header::HeaderValue;
 use sea_orm::{ConnectOptions, Database, DatabaseConnection};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::process::exit;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use tokio::sync::Semaphore;
 use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 use utoipa_redoc::{Redoc, Servable};
 
+/// Version of the analysis HTTP API, increased on incompatible changes.
+pub const API_VERSION: u32 = 1;
+
+/// Cargo features this binary was built with.
+pub const ENABLED_FEATURES: &[&str] = &[
+    #[cfg(feature = "fault-injection")]
+    "fault-injection",
+    #[cfg(feature = "loadtest")]
+    "loadtest",
+    #[cfg(feature = "queue")]
+    "queue",
+];
+
 fn get_default_port() -> u16 {
     8080
 }
@@ -27,22 +68,250 @@ fn get_default_max_concurrent() -> usize {
     5
 }
 
+fn get_default_batch_max_concurrent() -> usize {
+    2
+}
+
+fn get_default_runner_check_interval() -> u64 {
+    60
+}
+
+fn get_default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+#[cfg(feature = "queue")]
+fn get_default_nats_subject() -> String {
+    "assa.analyse".to_string()
+}
+
+#[cfg(feature = "queue")]
+fn get_default_nats_queue_group() -> String {
+    "persistence_proxy".to_string()
+}
+
+#[cfg(feature = "queue")]
+fn get_default_nats_concurrency() -> usize {
+    4
+}
+
+fn get_default_redis_key_prefix() -> String {
+    "assa:".to_string()
+}
+
+fn get_default_max_feedback_length() -> usize {
+    10_000
+}
+
+fn get_default_upstream_latency_samples() -> usize {
+    200
+}
+
+fn get_default_upstream_max_attempts() -> u32 {
+    3
+}
+
+fn get_default_upstream_retry_delay_ms() -> u64 {
+    500
+}
+
 #[derive(Deserialize, Debug)]
 struct Config {
     database_url: String,
     upstream_url: String,
     #[serde(default = "get_default_max_concurrent")]
     upstream_max_concurrent: usize,
+    #[serde(default = "get_default_batch_max_concurrent")]
+    upstream_batch_max_concurrent: usize,
+    upstream_hedge_percentile: Option<f64>,
+    #[serde(default = "get_default_upstream_latency_samples")]
+    upstream_latency_samples: usize,
+    /// Attempts of an upstream request failing with a rate limit, server or connection error,
+    /// retried with exponential backoff
+    #[serde(default = "get_default_upstream_max_attempts")]
+    upstream_max_attempts: u32,
+    /// Longest delay before the first retry of an upstream request, doubled for every further
+    /// one
+    #[serde(default = "get_default_upstream_retry_delay_ms")]
+    upstream_retry_delay_ms: u64,
     #[serde(default = "get_default_port")]
     port: u16,
     sql_runner_url: Option<String>,
+    /// API key sent to the runners, if they require one
+    sql_runner_api_key: Option<String>,
+    /// Seconds between checks of the sql_runner contract
+    #[serde(default = "get_default_runner_check_interval")]
+    runner_check_interval: u64,
+    job_schedule: Option<String>,
+    /// Logs stored before their time was recorded are not deleted
+    log_retention_days: Option<i64>,
+    /// Logs stored before their time was recorded are not archived
+    log_archive_after_days: Option<i64>,
+    s3_endpoint: Option<String>,
+    s3_bucket: Option<String>,
+    #[serde(default = "get_default_s3_region")]
+    s3_region: String,
+    s3_access_key: Option<String>,
+    s3_secret_key: Option<String>,
+    /// Minimum seconds between analyses of the same task by the same user, analyses failing
+    /// with a server error do not count
+    analysis_cooldown_secs: Option<u64>,
+    /// Maximum number of characters of a single feedback returned by the upstream
+    #[serde(default = "get_default_max_feedback_length")]
+    max_feedback_length: usize,
+    /// Results the upstream is less confident in are flagged for review
+    min_feedback_confidence: Option<f64>,
+    /// Rows of a result set sent upstream, larger ones keep their first and last rows
+    result_max_rows: Option<usize>,
+    /// Cells of a result set sent upstream
+    result_max_cells: Option<usize>,
+    /// Bytes of a result set sent upstream, serialised as JSON
+    result_max_bytes: Option<usize>,
+    /// Redis keeping the rate limiting state shared by all replicas, per replica without it
+    redis_url: Option<String>,
+    /// Prefix of all keys stored in Redis
+    #[serde(default = "get_default_redis_key_prefix")]
+    redis_key_prefix: String,
+    /// NATS server analysis requests are consumed from, requires the `queue` feature
+    nats_url: Option<String>,
+    /// Subject analysis requests are published to
+    #[cfg(feature = "queue")]
+    #[serde(default = "get_default_nats_subject")]
+    nats_subject: String,
+    /// Queue group of the replicas, each request is handled by one of them
+    #[cfg(feature = "queue")]
+    #[serde(default = "get_default_nats_queue_group")]
+    nats_queue_group: String,
+    /// Subject results are published to if the request has no reply subject
+    #[cfg(feature = "queue")]
+    nats_reply_subject: Option<String>,
+    /// Analysis requests from NATS handled at the same time
+    #[cfg(feature = "queue")]
+    #[serde(default = "get_default_nats_concurrency")]
+    nats_concurrency: usize,
+}
+
+impl Config {
+    /// Problems envy cannot detect, reported together before exiting.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let s3_endpoint = ("S3_ENDPOINT", self.s3_endpoint.is_some());
+        ConfigValidator::new()
+            .port("PORT", self.port)
+            .url("UPSTREAM_URL", &self.upstream_url)
+            .optional_url("SQL_RUNNER_URL", self.sql_runner_url.as_deref())
+            .parses(
+                "SQL_RUNNER_API_KEY",
+                self.sql_runner_api_key
+                    .as_deref()
+                    .map(|key| HeaderValue::from_str(&format!("Bearer {key}"))),
+            )
+            .optional_url("S3_ENDPOINT", self.s3_endpoint.as_deref())
+            .positive(
+                "UPSTREAM_MAX_CONCURRENT",
+                self.upstream_max_concurrent as u64,
+            )
+            .positive(
+                "UPSTREAM_BATCH_MAX_CONCURRENT",
+                self.upstream_batch_max_concurrent as u64,
+            )
+            .positive("UPSTREAM_MAX_ATTEMPTS", self.upstream_max_attempts as u64)
+            .check(
+                self.upstream_hedge_percentile
+                    .is_none_or(|percentile| percentile > 0.0 && percentile < 1.0),
+                || "UPSTREAM_HEDGE_PERCENTILE must be a fraction between 0 and 1".to_string(),
+            )
+            .requires(
+                (
+                    "UPSTREAM_HEDGE_PERCENTILE",
+                    self.upstream_hedge_percentile.is_some(),
+                ),
+                (
+                    "a positive UPSTREAM_LATENCY_SAMPLES",
+                    self.upstream_latency_samples > 0,
+                ),
+            )
+            .requires(
+                ("SQL_RUNNER_URL", self.sql_runner_url.is_some()),
+                (
+                    "a positive RUNNER_CHECK_INTERVAL",
+                    self.runner_check_interval > 0,
+                ),
+            )
+            .all_or_none(&[
+                s3_endpoint,
+                ("S3_BUCKET", self.s3_bucket.is_some()),
+                ("S3_ACCESS_KEY", self.s3_access_key.is_some()),
+                ("S3_SECRET_KEY", self.s3_secret_key.is_some()),
+            ])
+            .requires(
+                (
+                    "LOG_ARCHIVE_AFTER_DAYS",
+                    self.log_archive_after_days.is_some(),
+                ),
+                s3_endpoint,
+            )
+            .parses(
+                "JOB_SCHEDULE",
+                self.job_schedule.as_deref().map(scheduler::parse_schedule),
+            )
+            .positive("MAX_FEEDBACK_LENGTH", self.max_feedback_length as u64)
+            .check(
+                self.min_feedback_confidence
+                    .is_none_or(|confidence| (0.0..=1.0).contains(&confidence)),
+                || "MIN_FEEDBACK_CONFIDENCE must be between 0 and 1".to_string(),
+            )
+            .check(
+                [
+                    self.result_max_rows,
+                    self.result_max_cells,
+                    self.result_max_bytes,
+                ]
+                .iter()
+                .all(|limit| limit.is_none_or(|limit| limit > 0)),
+                || {
+                    "RESULT_MAX_ROWS, RESULT_MAX_CELLS and RESULT_MAX_BYTES must be positive"
+                        .to_string()
+                },
+            )
+            .parses(
+                "REDIS_URL",
+                self.redis_url.as_deref().map(redis::Client::open),
+            )
+            .check(self.nats_url.is_none() || cfg!(feature = "queue"), || {
+                "NATS_URL requires building with the `queue` feature".to_string()
+            })
+            .finish()
+    }
+
+    fn upstream_backoff(&self) -> Backoff {
+        Backoff::new(
+            self.upstream_max_attempts,
+            Duration::from_millis(self.upstream_retry_delay_ms),
+        )
+    }
+
+    fn result_limits(&self) -> ResultLimits {
+        ResultLimits {
+            max_rows: self.result_max_rows,
+            max_cells: self.result_max_cells,
+            max_bytes: self.result_max_bytes,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct AppState {
     db: DatabaseConnection,
     upstream_semaphore: Arc<Semaphore>,
+    upstream_batch_semaphore: Arc<Semaphore>,
+    upstream_latency: Arc<LatencyTracker>,
     runner_interface: Option<Arc<RunnerInterface>>,
+    tenant_runners: Arc<Mutex<HashMap<String, Arc<RunnerInterface>>>>,
+    runner_error: Arc<RwLock<Option<String>>>,
+    object_storage: Option<Arc<ObjectStorage>>,
+    cooldown: Option<Arc<Cooldown>>,
+    shared_store: Option<SharedStore>,
+    recent_errors: Arc<ErrorRate>,
     config: Arc<Config>,
 }
 
@@ -51,34 +320,103 @@ struct AppState {
 struct ApiDoc;
 
 async fn run() -> Result<(), anyhow::Error> {
+    common::version::mark_started();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    let config = envy::from_env::<Config>()?;
+    let config = common::config::from_env::<Config>()?;
+    config.validate()?;
+    let metrics = common::metrics::install_recorder();
 
     let mut opt = ConnectOptions::new(&config.database_url);
     opt.sqlx_logging_level(LevelFilter::Debug);
 
     let db = Database::connect(opt).await?;
+    let shared_store = match &config.redis_url {
+        Some(url) => Some(SharedStore::connect(url, config.redis_key_prefix.clone()).await?),
+        None => None,
+    };
 
     let (router, api) = OpenApiRouter::with_openapi(ApiDoc::openapi())
         .routes(routes!(analyse))
+        .routes(routes!(quota))
+        .routes(routes!(readiness::readyz))
+        .routes(routes!(version))
+        .routes(routes!(ping))
+        .routes(routes!(overview::overview))
+        .routes(routes!(task::get_comparison, task::put_comparison))
+        .routes(routes!(task::get_deadline, task::put_deadline))
+        .routes(routes!(task::list_tasks))
+        .routes(routes!(review::list_reviews))
+        .routes(routes!(review::get_review, review::resolve_review))
+        .routes(routes!(review::appeal))
+        .routes(routes!(analysis::list_analyses))
+        .routes(routes!(analysis::get_analysis))
         .split_for_parts();
 
-    info!("Starting on port {}", config.port);
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.port)).await?;
+    let jobs = match &config.job_schedule {
+        Some(schedule) => scheduler::parse_schedule(schedule)?,
+        None => vec![],
+    };
+
+    let state = AppState {
+        db,
+        upstream_semaphore: Arc::new(Semaphore::new(config.upstream_max_concurrent)),
+        upstream_batch_semaphore: Arc::new(Semaphore::new(config.upstream_batch_max_concurrent)),
+        upstream_latency: Arc::new(LatencyTracker::new(config.upstream_latency_samples)),
+        runner_interface: config.sql_runner_url.as_ref().map(|url| {
+            Arc::new(RunnerInterface::new(
+                url.parse().expect("failed to parse SQL_RUNNER_URL"),
+                config.sql_runner_api_key.as_deref(),
+            ))
+        }),
+        tenant_runners: Default::default(),
+        runner_error: Default::default(),
+        object_storage: match (
+            &config.s3_endpoint,
+            &config.s3_bucket,
+            &config.s3_access_key,
+            &config.s3_secret_key,
+        ) {
+            (Some(endpoint), Some(bucket), Some(access_key), Some(secret_key)) => {
+                Some(Arc::new(ObjectStorage::new(
+                    endpoint.parse().expect("failed to parse S3_ENDPOINT"),
+                    bucket.clone(),
+                    config.s3_region.clone(),
+                    access_key.clone(),
+                    secret_key.clone(),
+                )))
+            }
+            _ => None,
+        },
+        cooldown: config.analysis_cooldown_secs.map(|secs| {
+            let cooldown = Cooldown::new(Duration::from_secs(secs));
+            Arc::new(match &shared_store {
+                Some(shared_store) => cooldown.with_shared_store(shared_store.clone()),
+                None => cooldown,
+            })
+        }),
+        shared_store,
+        recent_errors: Default::default(),
+        config: Arc::new(config),
+    };
+    scheduler::start(jobs, state.clone());
+    #[cfg(feature = "queue")]
+    if let Some(url) = &state.config.nats_url {
+        queue::start(state.clone(), url).await?;
+    }
+    readiness::start_runner_check(
+        state.clone(),
+        Duration::from_secs(state.config.runner_check_interval),
+    );
+
+    info!("Starting on port {}", state.config.port);
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", state.config.port)).await?;
     axum::serve(
         listener,
         router
             .merge(Redoc::with_url("/redoc", api))
-            .with_state(AppState {
-                db,
-                upstream_semaphore: Arc::new(Semaphore::new(config.upstream_max_concurrent)),
-                runner_interface: config.sql_runner_url.as_ref().map(|url| {
-                    Arc::new(RunnerInterface::new(
-                        url.parse().expect("failed to parse SQL_RUNNER_URL"),
-                    ))
-                }),
-                config: Arc::new(config),
-            }),
+            .merge(common::metrics::router(metrics))
+            .layer(middleware::from_fn(common::metrics::track_requests))
+            .with_state(state),
     )
     .await?;
 
@@ -1,5 +1,7 @@
+use crate::truncation::{ResultLimits, ResultTruncation};
 pub use common::models::{Results, SqlResult};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use utoipa::ToSchema;
 
 #[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
@@ -14,19 +16,261 @@ pub struct AnalysisRequest {
     pub task_id: Option<String>,
     pub user_id: Option<String>,
     pub feedback_language: Option<String>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Execute the queries but skip the upstream call and logging
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Opaque object, e.g. course, assignment and attempt, which is ignored by the analysis
+    /// but stored with it, forwarded to the runner's audit log and echoed in the response
+    pub metadata: Option<Value>,
+    /// Add a verdict over all submissions to the response
+    #[serde(default)]
+    pub aggregate: bool,
+    /// Weight of each submission in the score of the aggregate verdict, equal if not given
+    pub submission_weights: Option<Vec<f64>>,
+    /// Model requested from the upstream, set by the proxy from the tenant configuration
+    #[serde(default, skip_deserializing)]
+    pub model: Option<String>,
+    /// Comparison spec stored for `task_id`, set by the proxy
+    #[serde(default, skip_deserializing)]
+    pub comparison: Option<Value>,
+    /// Whether each submission matches any solution under `comparison`, `null` if the runner
+    /// could not decide, set by the proxy
+    #[serde(default, skip_deserializing)]
+    pub submission_matches: Option<Vec<Option<bool>>>,
+    /// Result sets which exceeded the configured limits and were truncated, set by the proxy
+    #[serde(default, skip_deserializing)]
+    pub result_truncation: Option<ResultTruncation>,
+}
+
+/// Interactive requests (students checking a submission) and batch requests (regrades)
+/// are queued separately so that batch jobs cannot starve interactive use.
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    #[default]
+    Interactive,
+    Batch,
 }
 
 impl AnalysisRequest {
+    /// Execution error of every submission, `None` for submissions that ran or were not run.
+    pub fn submission_errors(&self) -> Vec<Option<String>> {
+        (0..self.submissions.len())
+            .map(|index| {
+                match self
+                    .submission_results
+                    .as_ref()
+                    .and_then(|results| results.get(index))
+                {
+                    Some(Some(SqlResult::Error(error))) => Some(error.clone()),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Identifies the analysis of a submission, requests with the same environment, schema,
+    /// task and solutions get the same key for the same submission.
+    pub fn submission_key(&self, index: usize) -> String {
+        let content = serde_json::to_vec(&(
+            &self.sql_environment,
+            &self.db_schema,
+            &self.task,
+            &self.solutions,
+            &self.submissions[index],
+        ))
+        .unwrap_or_default();
+        blake3::hash(&content).to_hex().to_string()
+    }
+
+    /// Truncates the results to the limits and notes which ones were truncated.
+    pub fn truncate_results(&mut self, limits: &ResultLimits) {
+        let truncate = |results: &mut Option<Results>| {
+            results
+                .as_mut()
+                .map(|results| limits.truncate_results(results))
+                .unwrap_or_default()
+        };
+        let solutions = truncate(&mut self.solution_results);
+        let submissions = truncate(&mut self.submission_results);
+        self.result_truncation = solutions
+            .iter()
+            .chain(&submissions)
+            .any(Option::is_some)
+            .then_some(ResultTruncation {
+                solutions,
+                submissions,
+            });
+    }
+
     pub fn redact(&mut self) {
         self.task_id.take();
         self.user_id.take();
     }
+
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.solutions.is_empty() {
+            return Err("at least one solution is required");
+        }
+        if self.submissions.is_empty() {
+            return Err("at least one submission is required");
+        }
+        let lengths_match = |results: &Option<Results>, queries: &[String]| {
+            results
+                .as_ref()
+                .is_none_or(|results| results.len() == queries.len())
+        };
+        if !lengths_match(&self.solution_results, &self.solutions) {
+            return Err("solution_results must contain one entry per solution");
+        }
+        if !lengths_match(&self.submission_results, &self.submissions) {
+            return Err("submission_results must contain one entry per submission");
+        }
+        if let Some(weights) = &self.submission_weights {
+            if weights.len() != self.submissions.len() {
+                return Err("submission_weights must contain one weight per submission");
+            }
+            if weights
+                .iter()
+                .any(|weight| !weight.is_finite() || *weight < 0.0)
+                || weights.iter().sum::<f64>() <= 0.0
+            {
+                return Err("submission_weights must not be negative and not all 0");
+            }
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
 pub struct AnalysisResult {
     pub correct: bool,
     pub feedback: String,
+    /// Error of executing the submission, set by the proxy from the submission results
+    #[serde(default)]
+    pub execution_error: Option<String>,
+    /// Confidence of the upstream in the feedback between 0 and 1, if it reports one
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    /// The upstream abstained or was not confident enough, an instructor should review the
+    /// submission before the feedback is shown
+    #[serde(default)]
+    pub needs_review: bool,
+    /// The result was reviewed by an instructor, whose decision overrides the upstream
+    #[serde(default)]
+    pub reviewed: bool,
+    /// Graded after the deadline of the task, only the correctness decided by the runner is
+    /// reported and the feedback is withheld
+    #[serde(default)]
+    pub review_only: bool,
+    /// Id of the stored analysis, which appeals refer to, set by the proxy
+    #[serde(default, skip_deserializing)]
+    pub analysis_id: Option<i32>,
 }
 
 pub type AnalysisResults = Vec<AnalysisResult>;
+
+/// Checks the upstream contract: a non-empty list with one result per submission, bounded
+/// feedback and confidences between 0 and 1. Returns every violation found.
+pub fn validate_results(
+    results: &AnalysisResults,
+    submissions: usize,
+    max_feedback_length: usize,
+) -> Result<(), Vec<String>> {
+    let mut diagnostics = Vec::new();
+    if results.is_empty() {
+        diagnostics.push("no results returned".to_string());
+    } else if results.len() != submissions {
+        diagnostics.push(format!(
+            "expected {submissions} results, one per submission, got {}",
+            results.len()
+        ));
+    }
+    for (index, result) in results.iter().enumerate() {
+        let length = result.feedback.chars().count();
+        if length > max_feedback_length {
+            diagnostics.push(format!(
+                "feedback of result {index} has {length} characters, at most {max_feedback_length} are allowed"
+            ));
+        }
+        if let Some(confidence) = result
+            .confidence
+            .filter(|confidence| !(0.0..=1.0).contains(confidence))
+        {
+            diagnostics.push(format!(
+                "confidence of result {index} is {confidence}, it must be between 0 and 1"
+            ));
+        }
+    }
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Verdict over all submissions of a request, so clients grading them together don't have to
+/// combine the results themselves.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AggregateVerdict {
+    /// Whether every submission is correct
+    pub correct: bool,
+    /// Weighted share of the correct submissions between 0 and 1
+    pub score: f64,
+    /// Index of the solution each submission matches according to the runner, `null` if it
+    /// matches none or was not compared
+    pub matched_solutions: Vec<Option<usize>>,
+    /// Some result needs review, so the verdict may still change
+    pub needs_review: bool,
+}
+
+impl AggregateVerdict {
+    /// A submission counts as correct if it matches a solution, unless an instructor reviewed
+    /// it, otherwise the verdict of its result applies.
+    pub fn new(
+        results: &AnalysisResults,
+        matched_solutions: Vec<Option<usize>>,
+        weights: Option<&[f64]>,
+    ) -> Self {
+        let correct: Vec<bool> = results
+            .iter()
+            .zip(&matched_solutions)
+            .map(|(result, matched)| result.correct || (!result.reviewed && matched.is_some()))
+            .collect();
+        let weight = |index: usize| weights.map_or(1.0, |weights| weights[index]);
+        let total: f64 = (0..correct.len()).map(weight).sum();
+        let achieved: f64 = (0..correct.len())
+            .filter(|index| correct[*index])
+            .map(weight)
+            .sum();
+        AggregateVerdict {
+            correct: correct.iter().all(|correct| *correct),
+            score: if total > 0.0 { achieved / total } else { 0.0 },
+            matched_solutions,
+            needs_review: results.iter().any(|result| result.needs_review),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DryRunResponse {
+    /// The request that would have been sent upstream, including the computed results
+    pub upstream_request: AnalysisRequest,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum AnalyseResponse {
+    Results(AnalysisResults),
+    /// Results of a request with metadata, which is echoed, or asking for the aggregate
+    Annotated {
+        results: AnalysisResults,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        metadata: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        aggregate: Option<AggregateVerdict>,
+    },
+    DryRun(Box<DryRunResponse>),
+}
@@ -0,0 +1,139 @@
+use crate::auth::AuthExtractor;
+use crate::readiness::readiness_errors;
+use crate::{API_VERSION, AppState, ENABLED_FEATURES};
+use axum::Json;
+use axum::extract::State;
+use common::stats::{FeedbackStats, RecentErrors, RunnerStats};
+use common::version::PingResponse;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Time to wait for each service before reporting it as unreachable.
+const SERVICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProxyOverview {
+    pub ping: PingResponse,
+    pub ready: bool,
+    pub errors: Vec<String>,
+    /// Interactive upstream requests currently running
+    pub interactive_in_flight: usize,
+    /// Batch upstream requests currently running
+    pub batch_in_flight: usize,
+    /// Analyses and the ones failing with a server error
+    pub recent: RecentErrors,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ServiceOverview<T> {
+    pub reachable: bool,
+    pub ping: Option<PingResponse>,
+    pub stats: Option<T>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OverviewResponse {
+    pub proxy: ProxyOverview,
+    /// Missing if no sql_runner is configured
+    pub sql_runner: Option<ServiceOverview<RunnerStats>>,
+    pub sql_feedback: ServiceOverview<FeedbackStats>,
+}
+
+#[utoipa::path(get, path = "/api/v1/overview", responses((status = OK, body = OverviewResponse), (status = UNAUTHORIZED)), description = "Get health, load, error rates and llm usage of all services for an operations dashboard")]
+pub async fn overview(_auth: AuthExtractor, state: State<AppState>) -> Json<OverviewResponse> {
+    let client = Client::builder()
+        .timeout(SERVICE_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let runner_url = state
+        .runner_interface
+        .as_ref()
+        .map(|runner| runner.run_url().clone());
+    let feedback_url = state.config.upstream_url.parse::<Url>().ok();
+    let (errors, sql_runner, sql_feedback) = tokio::join!(
+        readiness_errors(&state),
+        async {
+            match &runner_url {
+                Some(url) => Some(service_overview(&client, url).await),
+                None => None,
+            }
+        },
+        async {
+            match &feedback_url {
+                Some(url) => service_overview(&client, url).await,
+                None => unreachable_service("UPSTREAM_URL is not a valid url".to_string()),
+            }
+        },
+    );
+
+    let config = &state.config;
+    Json(OverviewResponse {
+        proxy: ProxyOverview {
+            ping: PingResponse {
+                version: common::version_info!(API_VERSION, ENABLED_FEATURES),
+                uptime_secs: common::version::uptime().as_secs(),
+            },
+            ready: errors.is_empty(),
+            errors,
+            interactive_in_flight: config
+                .upstream_max_concurrent
+                .saturating_sub(state.upstream_semaphore.available_permits()),
+            batch_in_flight: config
+                .upstream_batch_max_concurrent
+                .saturating_sub(state.upstream_batch_semaphore.available_permits()),
+            recent: state.recent_errors.recent(),
+        },
+        sql_runner,
+        sql_feedback,
+    })
+}
+
+/// Fetches `/ping` and `/api/v1/stats` of the service serving the api route `url`.
+async fn service_overview<T: DeserializeOwned>(client: &Client, url: &Url) -> ServiceOverview<T> {
+    let (ping, stats) = tokio::join!(
+        fetch::<PingResponse>(client, url, "/ping"),
+        fetch::<T>(client, url, "stats")
+    );
+    match (ping, stats) {
+        (Ok(ping), Ok(stats)) => ServiceOverview {
+            reachable: true,
+            ping: Some(ping),
+            stats: Some(stats),
+            error: None,
+        },
+        (Ok(ping), Err(err)) => ServiceOverview {
+            reachable: true,
+            ping: Some(ping),
+            stats: None,
+            error: Some(err.to_string()),
+        },
+        (Err(err), _) => unreachable_service(err.to_string()),
+    }
+}
+
+fn unreachable_service<T>(error: String) -> ServiceOverview<T> {
+    ServiceOverview {
+        reachable: false,
+        ping: None,
+        stats: None,
+        error: Some(error),
+    }
+}
+
+async fn fetch<T: DeserializeOwned>(
+    client: &Client,
+    url: &Url,
+    path: &str,
+) -> Result<T, anyhow::Error> {
+    Ok(client
+        .get(url.join(path)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
@@ -0,0 +1,114 @@
+//! Accepts analysis requests from NATS, for batch grading pipelines without HTTP. Requests
+//! are handled like `POST /api/v1/analyse`, including cooldown, quota and logging, and
+//! authenticated by the `Authorization` header of the message.
+
+use crate::AppState;
+use crate::api::analyse;
+use crate::auth::authenticate;
+use crate::model::AnalysisRequest;
+use async_nats::{Client, HeaderMap, Message};
+use axum::Json;
+use axum::extract::State;
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::StreamExt;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Header of a request which is copied to its reply, to correlate them on a reply subject.
+const CORRELATION_ID: &str = "Correlation-Id";
+
+/// Published to the reply subject of the request, or the configured one.
+#[derive(Debug, Serialize)]
+struct QueueReply {
+    /// HTTP status the request would have been answered with
+    status: u16,
+    /// Body of the HTTP response, `null` if it had none
+    body: Value,
+}
+
+/// Subscribes to the configured subject in the configured queue group, so every request is
+/// handled by a single replica.
+pub async fn start(state: AppState, url: &str) -> Result<(), anyhow::Error> {
+    let config = &state.config;
+    let client = async_nats::connect(url).await?;
+    let subscriber = client
+        .queue_subscribe(config.nats_subject.clone(), config.nats_queue_group.clone())
+        .await?;
+    info!("Consuming analysis requests from {}", config.nats_subject);
+    let concurrency = config.nats_concurrency.max(1);
+    tokio::spawn(subscriber.for_each_concurrent(concurrency, move |message| {
+        handle(state.clone(), client.clone(), message)
+    }));
+    Ok(())
+}
+
+async fn handle(state: AppState, client: Client, message: Message) {
+    let header = |name: &str| {
+        message
+            .headers
+            .as_ref()
+            .and_then(|headers| headers.get(name))
+            .map(|value| value.as_str().to_string())
+    };
+    let token =
+        header("Authorization").and_then(|value| value.split(' ').nth(1).map(str::to_string));
+    let auth = match &token {
+        Some(token) => authenticate(&state.db, token).await,
+        None => None,
+    };
+    let response = match (
+        auth,
+        serde_json::from_slice::<AnalysisRequest>(&message.payload),
+    ) {
+        (None, _) => StatusCode::UNAUTHORIZED.into_response(),
+        (_, Err(err)) => {
+            warn!("invalid analysis request from {}: {err}", message.subject);
+            StatusCode::BAD_REQUEST.into_response()
+        }
+        (Some(auth), Ok(request)) => {
+            let mut request_headers = axum::http::HeaderMap::new();
+            if let Some(accept_language) =
+                header("Accept-Language").and_then(|value| HeaderValue::from_str(&value).ok())
+            {
+                request_headers.insert(ACCEPT_LANGUAGE, accept_language);
+            }
+            analyse(auth, State(state.clone()), request_headers, Json(request))
+                .await
+                .into_response()
+        }
+    };
+
+    let Some(subject) = message
+        .reply
+        .as_ref()
+        .map(ToString::to_string)
+        .or_else(|| state.config.nats_reply_subject.clone())
+    else {
+        warn!("no reply subject for analysis request, dropping the result");
+        return;
+    };
+    let mut headers = HeaderMap::new();
+    if let Some(correlation_id) = header(CORRELATION_ID) {
+        headers.insert(CORRELATION_ID, correlation_id);
+    }
+    let reply = serde_json::to_vec(&reply(response).await).expect("replies are serialisable");
+    if let Err(err) = client
+        .publish_with_headers(subject, headers, reply.into())
+        .await
+    {
+        error!("failed to publish analysis result: {err}");
+    }
+}
+
+async fn reply(response: Response) -> QueueReply {
+    let status = response.status().as_u16();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .ok()
+        .and_then(|body| serde_json::from_slice(&body).ok())
+        .unwrap_or(Value::Null);
+    QueueReply { status, body }
+}
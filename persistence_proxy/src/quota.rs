@@ -0,0 +1,53 @@
+use crate::db::log::Column as LogColumn;
+use crate::db::prelude::Log;
+use chrono::Utc;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QuotaQuery {
+    /// Report the cooldown of this user, for `task_id` or analyses without a task
+    pub user_id: Option<String>,
+    pub task_id: Option<String>,
+}
+
+/// Analyses are counted instead of llm tokens: the upstream does not report the tokens an
+/// analysis took, they are only known to the feedback service and its daily budget.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuotaResponse {
+    /// Maximum number of analyses per day, `null` if unlimited
+    pub daily_limit: Option<u64>,
+    pub used_today: u64,
+    /// Analyses left for today, `null` if unlimited
+    pub remaining_today: Option<u64>,
+    /// Free upstream slots for interactive requests
+    pub interactive_slots_available: usize,
+    /// Free upstream slots for batch requests
+    pub batch_slots_available: usize,
+    /// Minimum seconds between analyses of the same task by the same user, `null` if unlimited
+    pub cooldown_secs: Option<u64>,
+    /// Seconds until the next analysis of the user in the query is accepted, `null` if it is
+    /// accepted now or no user was given
+    pub cooldown_retry_after_secs: Option<u64>,
+}
+
+/// Number of analyses the consumer has stored since midnight (UTC).
+pub async fn used_today(db: &DatabaseConnection, consumer_id: i32) -> Result<u64, DbErr> {
+    Log::find()
+        .filter(LogColumn::ConsumerId.eq(consumer_id))
+        .filter(LogColumn::CreatedAt.gte(start_of_day()))
+        .count(db)
+        .await
+}
+
+fn start_of_day() -> DateTimeWithTimeZone {
+    Utc::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .unwrap_or_default()
+        .and_utc()
+        .fixed_offset()
+}
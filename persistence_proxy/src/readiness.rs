@@ -0,0 +1,78 @@
+use crate::AppState;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use log::{error, info};
+use serde::Serialize;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub errors: Vec<String>,
+}
+
+/// Checks the sql_runner contract on boot and then periodically, storing the outcome for
+/// the readiness route.
+pub fn start_runner_check(state: AppState, interval: Duration) {
+    let Some(runner_interface) = state.runner_interface.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        loop {
+            let result = runner_interface.check_compatibility().await;
+            let error = match result {
+                Ok(()) => None,
+                Err(err) => {
+                    error!("sql_runner is not compatible: {err}");
+                    Some(err.to_string())
+                }
+            };
+            {
+                let mut runner_error = state.runner_error.write().unwrap();
+                if runner_error.is_some() && error.is_none() {
+                    info!("sql_runner is compatible again");
+                }
+                *runner_error = error;
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+#[utoipa::path(get, path = "/readyz", responses((status = OK, body = ReadinessResponse), (status = SERVICE_UNAVAILABLE, body = ReadinessResponse)), description = "Check whether the proxy is ready to serve requests")]
+pub async fn readyz(state: State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let errors = readiness_errors(&state).await;
+    let status = if errors.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(ReadinessResponse {
+            ready: errors.is_empty(),
+            errors,
+        }),
+    )
+}
+
+/// Reasons why the proxy cannot serve requests, empty if it is ready.
+pub async fn readiness_errors(state: &AppState) -> Vec<String> {
+    let mut errors = vec![];
+    if let Err(err) = state.db.ping().await {
+        errors.push(format!("database is not reachable: {err}"));
+    }
+    let shared_store = match &state.shared_store {
+        Some(shared_store) => shared_store.ping().await,
+        None => Ok(()),
+    };
+    if let Err(err) = shared_store {
+        errors.push(format!("redis is not reachable: {err}"));
+    }
+    if let Some(err) = state.runner_error.read().unwrap().clone() {
+        errors.push(err);
+    }
+    errors
+}
@@ -0,0 +1,406 @@
+//! Review queue of analyses the upstream was not confident in or students appealed against.
+//! Instructors resolve them by confirming or overriding the result, which is returned for
+//! identical submissions from then on instead of asking the upstream again.
+
+use crate::AppState;
+use crate::auth::AuthExtractor;
+use crate::db::prelude::{Log, Review};
+use crate::db::review;
+use crate::model::{AnalysisRequest, AnalysisResult, AnalysisResults};
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::Utc;
+use common::pagination::{Page, PageParams};
+use log::{error, warn};
+use sea_orm::sea_query::{Expr, Func, LikeExpr};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, NotSet, Order,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, TryIntoModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+
+/// Why an analysis was queued for review.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewReason {
+    /// The upstream abstained or was not confident enough
+    NeedsReview,
+    /// The student disagrees with the result
+    Appeal,
+}
+
+impl ReviewReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            ReviewReason::NeedsReview => "needs_review",
+            ReviewReason::Appeal => "appeal",
+        }
+    }
+}
+
+/// Maximum number of characters of the reason of an appeal.
+const MAX_APPEAL_REASON_LENGTH: usize = 4_000;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    Pending,
+    Resolved,
+}
+
+impl ReviewStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Resolved => "resolved",
+        }
+    }
+}
+
+/// Result of a resolved review, the stored upstream result with the overrides applied.
+fn reviewed_result(review: &review::Model) -> Option<AnalysisResult> {
+    let mut result = serde_json::from_value::<AnalysisResult>(review.result.clone()).ok()?;
+    if let Some(correct) = review.override_correct {
+        result.correct = correct;
+    }
+    if let Some(feedback) = &review.override_feedback {
+        result.feedback = feedback.clone();
+    }
+    result.needs_review = false;
+    result.reviewed = true;
+    Some(result)
+}
+
+/// Reviewed results of the consumer by submission key, the latest review wins.
+pub async fn reviewed_results(
+    db: &DatabaseConnection,
+    consumer_id: i32,
+    submission_keys: &[String],
+) -> Result<HashMap<String, AnalysisResult>, DbErr> {
+    let reviews = Review::find()
+        .filter(review::Column::ConsumerId.eq(consumer_id))
+        .filter(review::Column::Status.eq(ReviewStatus::Resolved.as_str()))
+        .filter(review::Column::SubmissionKey.is_in(submission_keys.iter().cloned()))
+        .order_by_asc(review::Column::ReviewedAt)
+        .all(db)
+        .await?;
+    Ok(reviews
+        .iter()
+        .filter_map(|review| Some((review.submission_key.clone(), reviewed_result(review)?)))
+        .collect())
+}
+
+async fn pending(
+    db: &DatabaseConnection,
+    consumer_id: i32,
+    submission_key: &str,
+) -> Result<Option<review::Model>, DbErr> {
+    Review::find()
+        .filter(review::Column::ConsumerId.eq(consumer_id))
+        .filter(review::Column::SubmissionKey.eq(submission_key))
+        .filter(review::Column::Status.eq(ReviewStatus::Pending.as_str()))
+        .one(db)
+        .await
+}
+
+/// Pending review of the submission with the given index of the analysis.
+fn new_review(
+    consumer_id: i32,
+    log_id: i32,
+    request: &AnalysisRequest,
+    index: usize,
+    result: &AnalysisResult,
+    reason: ReviewReason,
+) -> review::ActiveModel {
+    review::ActiveModel {
+        id: NotSet,
+        consumer_id: Set(consumer_id),
+        log_id: Set(Some(log_id)),
+        submission_key: Set(request.submission_key(index)),
+        task_id: Set(request.task_id.clone()),
+        user_id: Set(request.user_id.clone()),
+        submission: Set(request.submissions[index].clone()),
+        reason: Set(reason.as_str().to_string()),
+        status: Set(ReviewStatus::Pending.as_str().to_string()),
+        result: Set(serde_json::to_value(result).unwrap_or_default()),
+        override_correct: Set(None),
+        override_feedback: Set(None),
+        created_at: Set(Utc::now().fixed_offset()),
+        reviewed_at: Set(None),
+        appeal_reason: Set(None),
+    }
+}
+
+/// Queues every result needing review, unless the submission is already waiting for one.
+pub async fn enqueue(
+    db: &DatabaseConnection,
+    consumer_id: i32,
+    log_id: i32,
+    request: &AnalysisRequest,
+    submission_keys: &[String],
+    results: &AnalysisResults,
+) -> Result<(), DbErr> {
+    for (index, (key, result)) in submission_keys.iter().zip(results).enumerate() {
+        if !result.needs_review || result.reviewed || pending(db, consumer_id, key).await?.is_some()
+        {
+            continue;
+        }
+        new_review(
+            consumer_id,
+            log_id,
+            request,
+            index,
+            result,
+            ReviewReason::NeedsReview,
+        )
+        .insert(db)
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReviewResponse {
+    pub id: i32,
+    /// Analysis the review was queued by, unless the log was deleted by the retention job
+    pub log_id: Option<i32>,
+    pub task_id: Option<String>,
+    pub user_id: Option<String>,
+    pub submission: String,
+    pub reason: String,
+    pub status: String,
+    /// Result returned by the upstream
+    pub result: AnalysisResult,
+    pub override_correct: Option<bool>,
+    pub override_feedback: Option<String>,
+    pub created_at: String,
+    pub reviewed_at: Option<String>,
+    /// Reason given by the student, if they appealed
+    pub appeal_reason: Option<String>,
+}
+
+impl TryFrom<review::Model> for ReviewResponse {
+    type Error = serde_json::Error;
+
+    fn try_from(review: review::Model) -> Result<Self, Self::Error> {
+        Ok(ReviewResponse {
+            id: review.id,
+            log_id: review.log_id,
+            task_id: review.task_id,
+            user_id: review.user_id,
+            submission: review.submission,
+            reason: review.reason,
+            status: review.status,
+            result: serde_json::from_value(review.result)?,
+            override_correct: review.override_correct,
+            override_feedback: review.override_feedback,
+            created_at: review.created_at.to_rfc3339(),
+            reviewed_at: review.reviewed_at.map(|at| at.to_rfc3339()),
+            appeal_reason: review.appeal_reason,
+        })
+    }
+}
+
+fn response(review: review::Model) -> Result<ReviewResponse, StatusCode> {
+    let id = review.id;
+    ReviewResponse::try_from(review).map_err(|err| {
+        error!("stored result of review {id} is invalid: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn find(
+    db: &DatabaseConnection,
+    consumer_id: i32,
+    id: i32,
+) -> Result<review::Model, StatusCode> {
+    Review::find_by_id(id)
+        .filter(review::Column::ConsumerId.eq(consumer_id))
+        .one(db)
+        .await
+        .map_err(|err| {
+            error!("failed to load review {id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReviewQuery {
+    /// Only return reviews with this status
+    pub status: Option<ReviewStatus>,
+}
+
+#[utoipa::path(get, path = "/api/v1/reviews", params(PageParams, ReviewQuery), responses((status = OK, body = Page<ReviewResponse>), (status = UNAUTHORIZED)), description = "List the reviews of the consumer, filtered by `task_id` and sorted by `created_at`, oldest first by default")]
+pub async fn list_reviews(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Query(params): Query<PageParams>,
+    Query(review_query): Query<ReviewQuery>,
+) -> Result<Json<Page<ReviewResponse>>, StatusCode> {
+    let mut query = Review::find().filter(review::Column::ConsumerId.eq(auth.consumer_id));
+    if let Some(status) = review_query.status {
+        query = query.filter(review::Column::Status.eq(status.as_str()));
+    }
+    if let Some(pattern) = params.like_pattern() {
+        query = query.filter(
+            Expr::expr(Func::lower(Expr::col(review::Column::TaskId)))
+                .like(LikeExpr::new(pattern).escape('\\')),
+        );
+    }
+    let order = match params.sort() {
+        Some(sort) if sort.descending => Order::Desc,
+        _ => Order::Asc,
+    };
+    let result = async {
+        let total = query.clone().count(&state.db).await?;
+        let reviews = query
+            .order_by(review::Column::CreatedAt, order)
+            .offset(params.offset())
+            .limit(params.per_page())
+            .all(&state.db)
+            .await?;
+        Ok::<_, DbErr>((reviews, total))
+    }
+    .await;
+    let (reviews, total) = result.map_err(|err| {
+        error!("failed to list reviews: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let reviews = reviews
+        .into_iter()
+        .map(response)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Json(Page::new(reviews, &params, total)))
+}
+
+#[utoipa::path(get, path = "/api/v1/reviews/{id}", params(("id" = i32, Path, description = "Id of the review")), responses((status = OK, body = ReviewResponse), (status = UNAUTHORIZED), (status = NOT_FOUND)), description = "Get a review of the consumer")]
+pub async fn get_review(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<ReviewResponse>, StatusCode> {
+    response(find(&state.db, auth.consumer_id, id).await?).map(Json)
+}
+
+/// Decision of an instructor, fields which are not set keep the upstream result.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ReviewDecision {
+    pub correct: Option<bool>,
+    pub feedback: Option<String>,
+}
+
+#[utoipa::path(put, path = "/api/v1/reviews/{id}", params(("id" = i32, Path, description = "Id of the review")), request_body = ReviewDecision, responses((status = OK, body = ReviewResponse), (status = UNAUTHORIZED), (status = BAD_REQUEST), (status = NOT_FOUND)), description = "Resolve a review, the decision is returned for identical submissions from then on")]
+pub async fn resolve_review(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Path(id): Path<i32>,
+    Json(decision): Json<ReviewDecision>,
+) -> Result<Json<ReviewResponse>, StatusCode> {
+    if decision
+        .feedback
+        .as_ref()
+        .is_some_and(|feedback| feedback.chars().count() > state.config.max_feedback_length)
+    {
+        warn!("feedback of review {id} exceeds MAX_FEEDBACK_LENGTH");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut review: review::ActiveModel = find(&state.db, auth.consumer_id, id).await?.into();
+    review.status = Set(ReviewStatus::Resolved.as_str().to_string());
+    review.override_correct = Set(decision.correct);
+    review.override_feedback = Set(decision.feedback);
+    review.reviewed_at = Set(Some(Utc::now().fixed_offset()));
+    let review = review.update(&state.db).await.map_err(|err| {
+        error!("failed to resolve review {id}: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    response(review).map(Json)
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct AppealRequest {
+    /// Pseudonym of the student, which must match the `user_id` of the analysis
+    pub user_id: String,
+    pub reason: String,
+    /// Index of the submission of the analysis the appeal is about
+    #[serde(default)]
+    pub submission: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AppealResponse {
+    /// Review the appeal was added to
+    pub review_id: i32,
+}
+
+#[utoipa::path(post, path = "/api/v1/analyses/{log_id}/appeal", params(("log_id" = i32, Path, description = "`analysis_id` of the appealed results")), request_body = AppealRequest, responses((status = CREATED, body = AppealResponse), (status = UNAUTHORIZED), (status = BAD_REQUEST), (status = FORBIDDEN), (status = NOT_FOUND)), description = "Appeal against the result of a submission, which queues it for review by an instructor")]
+pub async fn appeal(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Path(log_id): Path<i32>,
+    Json(appeal): Json<AppealRequest>,
+) -> Result<(StatusCode, Json<AppealResponse>), StatusCode> {
+    let length = appeal.reason.trim().chars().count();
+    if length == 0 || length > MAX_APPEAL_REASON_LENGTH {
+        warn!("appeal against analysis {log_id} has a reason of {length} characters");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let log = Log::find_by_id(log_id)
+        .one(&state.db)
+        .await
+        .map_err(|err| {
+            error!("failed to load analysis {log_id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .filter(|log| log.consumer_id == auth.consumer_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let (request, results) = match (
+        serde_json::from_value::<AnalysisRequest>(log.request),
+        serde_json::from_value::<AnalysisResults>(log.response),
+    ) {
+        (Ok(request), Ok(results)) => (request, results),
+        (Err(err), _) | (_, Err(err)) => {
+            error!("stored analysis {log_id} is invalid: {err}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if request.user_id.as_deref() != Some(appeal.user_id.as_str()) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let Some(result) = results.get(appeal.submission) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    let key = request.submission_key(appeal.submission);
+    let review = async {
+        let mut review: review::ActiveModel =
+            match pending(&state.db, auth.consumer_id, &key).await? {
+                Some(review) => review.into(),
+                None => new_review(
+                    auth.consumer_id,
+                    log_id,
+                    &request,
+                    appeal.submission,
+                    result,
+                    ReviewReason::Appeal,
+                ),
+            };
+        review.reason = Set(ReviewReason::Appeal.as_str().to_string());
+        review.appeal_reason = Set(Some(appeal.reason));
+        review.save(&state.db).await?.try_into_model()
+    }
+    .await
+    .map_err(|err: DbErr| {
+        error!("failed to store appeal against analysis {log_id}: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok((
+        StatusCode::CREATED,
+        Json(AppealResponse {
+            review_id: review.id,
+        }),
+    ))
+}
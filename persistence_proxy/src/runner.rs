@@ -1,6 +1,51 @@
+use anyhow::anyhow;
+use common::i18n::Language;
 pub use common::models::ResultSet;
-use reqwest::{Client, Url};
+use common::version::{RUNNER_API_VERSION, VersionInfo};
+use reqwest::header::{ACCEPT_LANGUAGE, AUTHORIZATION, HeaderMap, HeaderValue};
+use reqwest::{Client, RequestBuilder, StatusCode, Url};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// The runner rejected the query because the execution budget of the task is used up.
+#[derive(Debug, Error)]
+#[error("execution budget of the task is exhausted")]
+pub struct BudgetExceeded;
+
+/// Consumer, task and user forwarded to the runner, which accounts execution time per task.
+#[derive(Debug, Clone)]
+pub struct RunContext {
+    pub consumer_id: i32,
+    pub task_id: Option<String>,
+    pub user_id: Option<String>,
+    /// Metadata of the analysis, recorded in the runner's audit log
+    pub metadata: Option<Value>,
+    /// Language of the execution errors
+    pub language: Language,
+}
+
+impl RunContext {
+    fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        let mut request = request
+            .header("X-Caller", self.consumer_id.to_string())
+            .header(ACCEPT_LANGUAGE, self.language.tag());
+        if let Some(task_id) = &self.task_id {
+            request = request.header("X-Task-Id", task_id);
+        }
+        if let Some(user_id) = &self.user_id {
+            request = request.header("X-User-Id", user_id);
+        }
+        request
+    }
+}
+
+fn check_budget(response: reqwest::Response) -> Result<reqwest::Response, anyhow::Error> {
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Err(BudgetExceeded.into());
+    }
+    Ok(response.error_for_status()?)
+}
 
 #[derive(Debug)]
 pub struct RunnerInterface {
@@ -9,36 +54,134 @@ pub struct RunnerInterface {
 }
 
 impl RunnerInterface {
-    pub fn new(run_url: Url) -> Self {
+    /// The API key is sent as bearer token with every request, if the runner requires one.
+    pub fn new(run_url: Url, api_key: Option<&str>) -> Self {
+        let mut headers = HeaderMap::new();
+        if let Some(api_key) = api_key {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {api_key}"))
+                    .expect("SQL_RUNNER_API_KEY is validated at startup"),
+            );
+        }
         RunnerInterface {
-            client: Client::new(),
+            client: Client::builder()
+                .default_headers(headers)
+                .build()
+                .expect("failed to build the runner client"),
             run_url,
         }
     }
 
-    pub async fn run(
+    /// Results of the queries in order, executed by the runner in a single request.
+    pub async fn batch_run(
         &self,
         environment: String,
-        query: String,
-    ) -> Result<RunResponse, anyhow::Error> {
-        Ok(self
+        queries: &[String],
+        context: &RunContext,
+    ) -> Result<Vec<RunResponse>, anyhow::Error> {
+        #[cfg(feature = "fault-injection")]
+        common::fault::inject("runner").await?;
+        let response = context
+            .apply(self.client.post(self.run_url.join("batch_run")?))
+            .json(&BatchRunRequest {
+                environment,
+                queries: queries.to_vec(),
+                metadata: context.metadata.clone(),
+            })
+            .send()
+            .await?;
+        match check_budget(response)?.json().await? {
+            BatchRunResponse::Success { results } if results.len() == queries.len() => Ok(results),
+            BatchRunResponse::Success { results } => Err(anyhow!(
+                "runner returned {} results for {} queries",
+                results.len(),
+                queries.len()
+            )),
+            // e.g. the environment is initialising, which applies to every query
+            BatchRunResponse::Error(err) => Ok(vec![RunResponse::Error(err); queries.len()]),
+        }
+    }
+
+    /// Index of the first solution the submission matches under the comparison spec, or the
+    /// runner's default comparison without one, `None` if it matches none.
+    pub async fn matching_solution(
+        &self,
+        environment: String,
+        solutions: &[String],
+        submission: String,
+        comparison: Option<&Value>,
+        context: &RunContext,
+    ) -> Result<Option<usize>, anyhow::Error> {
+        #[cfg(feature = "fault-injection")]
+        common::fault::inject("runner").await?;
+        let response = context
+            .apply(self.client.post(self.run_url.join("batch_compare")?))
+            .json(&BatchCompareRequest {
+                environment,
+                solutions: solutions
+                    .iter()
+                    .map(|query| BatchCompareSolution {
+                        query: query.clone(),
+                        comparison: comparison.cloned(),
+                    })
+                    .collect(),
+                submission,
+                stop_on_first_match: true,
+            })
+            .send()
+            .await?;
+        let response: BatchCompareResponse = check_budget(response)?.json().await?;
+        match response {
+            BatchCompareResponse::Success { solutions, errors } => {
+                if let Some(index) = solutions
+                    .iter()
+                    .position(BatchCompareSolutionResponse::accepted)
+                {
+                    return Ok(Some(index));
+                }
+                // without a single comparison the runner could not decide
+                match errors.first() {
+                    Some(err) if solutions.iter().all(|solution| solution.error.is_some()) => {
+                        Err(anyhow!("{}: {}", err.location, err.error))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            BatchCompareResponse::Error(err) => Err(anyhow!("{}: {}", err.location, err.error)),
+        }
+    }
+
+    /// Url of the run route, other routes of the runner are resolved relative to it.
+    pub fn run_url(&self) -> &Url {
+        &self.run_url
+    }
+
+    /// Verifies that the runner speaks the same API version as this proxy.
+    pub async fn check_compatibility(&self) -> Result<(), anyhow::Error> {
+        let version: VersionInfo = self
             .client
-            .post(self.run_url.clone())
-            .json(&RunRequest { environment, query })
+            .get(self.run_url.join("version")?)
             .send()
             .await?
-            .error_for_status()?
+            .error_for_status()
+            .map_err(|err| {
+                anyhow!("failed to fetch sql_runner version, it might be too old: {err}")
+            })?
             .json()
-            .await?)
+            .await?;
+        if version.api_version != RUNNER_API_VERSION {
+            return Err(anyhow!(
+                "sql_runner {} implements api version {}, but version {} is required",
+                version.version,
+                version.api_version,
+                RUNNER_API_VERSION
+            ));
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct RunRequest {
-    pub environment: String,
-    pub query: String,
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct RunSuccessResponse {
     pub result_set: ResultSet,
@@ -57,3 +200,69 @@ pub enum RunResponse {
     Success(RunSuccessResponse),
     Error(RunSuccessErrorResponse),
 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchRunRequest {
+    pub environment: String,
+    pub queries: Vec<String>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum BatchRunResponse {
+    Success { results: Vec<RunResponse> },
+    Error(RunSuccessErrorResponse),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchCompareSolution {
+    pub query: String,
+    /// The runner's default comparison if omitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchCompareRequest {
+    pub environment: String,
+    pub solutions: Vec<BatchCompareSolution>,
+    pub submission: String,
+    /// Only whether any solution matches is of interest
+    pub stop_on_first_match: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchCompareSolutionResponse {
+    pub eq: bool,
+    /// Index into the errors of the response
+    pub error: Option<usize>,
+    /// Set if the task requires column names
+    pub column_names: Option<ColumnNameCheck>,
+}
+
+impl BatchCompareSolutionResponse {
+    /// Equal values under the required column names, if any.
+    fn accepted(&self) -> bool {
+        self.eq
+            && self
+                .column_names
+                .as_ref()
+                .is_none_or(|column_names| column_names.matches)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColumnNameCheck {
+    pub matches: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum BatchCompareResponse {
+    Success {
+        solutions: Vec<BatchCompareSolutionResponse>,
+        errors: Vec<RunSuccessErrorResponse>,
+    },
+    Error(RunSuccessErrorResponse),
+}
@@ -0,0 +1,189 @@
+use crate::AppState;
+use crate::db::job_run;
+use crate::db::log::Column as LogColumn;
+use crate::db::prelude::Log;
+use anyhow::anyhow;
+use chrono::{Duration, Utc};
+use cron::Schedule;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use log::{error, info};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, NotSet, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use serde_json::json;
+use std::io::Write;
+use std::str::FromStr;
+
+const ARCHIVE_BATCH_SIZE: u64 = 5000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JobKind {
+    RetentionCleanup,
+    LogArchive,
+}
+
+impl JobKind {
+    fn name(&self) -> &'static str {
+        match self {
+            JobKind::RetentionCleanup => "retention_cleanup",
+            JobKind::LogArchive => "log_archive",
+        }
+    }
+}
+
+impl FromStr for JobKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "retention_cleanup" => Ok(JobKind::RetentionCleanup),
+            "log_archive" => Ok(JobKind::LogArchive),
+            _ => Err(anyhow!("unknown job `{s}`")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduledJob {
+    kind: JobKind,
+    schedule: Schedule,
+}
+
+/// Parses a job schedule of the form `job=cron expression;job=cron expression`,
+/// e.g. `retention_cleanup=0 0 3 * * *`.
+pub fn parse_schedule(config: &str) -> Result<Vec<ScheduledJob>, anyhow::Error> {
+    config
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (job, expression) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected `job=cron expression`, got `{entry}`"))?;
+            Ok(ScheduledJob {
+                kind: job.trim().parse()?,
+                schedule: Schedule::from_str(expression.trim())
+                    .map_err(|err| anyhow!("invalid schedule for `{job}`: {err}"))?,
+            })
+        })
+        .collect()
+}
+
+/// Spawns one task per scheduled job which runs the job at every scheduled time and
+/// records each run in the `job_run` table.
+pub fn start(jobs: Vec<ScheduledJob>, state: AppState) {
+    for job in jobs {
+        let state = state.clone();
+        tokio::spawn(async move {
+            for next in job.schedule.upcoming(Utc) {
+                let Ok(delay) = (next - Utc::now()).to_std() else {
+                    continue;
+                };
+                tokio::time::sleep(delay).await;
+                run(job.kind, &state).await;
+            }
+        });
+    }
+}
+
+async fn run(kind: JobKind, state: &AppState) {
+    info!("running job {}", kind.name());
+    let started_at = Utc::now();
+    let result = match kind {
+        JobKind::RetentionCleanup => retention_cleanup(state).await,
+        JobKind::LogArchive => log_archive(state).await,
+    };
+    if let Err(err) = &result {
+        error!("job {} failed: {err}", kind.name());
+    }
+
+    let history = job_run::ActiveModel {
+        id: NotSet,
+        job: Set(kind.name().to_string()),
+        started_at: Set(started_at.fixed_offset()),
+        finished_at: Set(Utc::now().fixed_offset()),
+        success: Set(result.is_ok()),
+        message: Set(Some(match result {
+            Ok(message) => message,
+            Err(err) => err.to_string(),
+        })),
+    }
+    .insert(&state.db)
+    .await;
+    if let Err(err) = history {
+        error!("failed to store run of job {}: {err}", kind.name());
+    }
+}
+
+async fn retention_cleanup(state: &AppState) -> Result<String, anyhow::Error> {
+    let Some(retention_days) = state.config.log_retention_days else {
+        return Ok("LOG_RETENTION_DAYS is not configured, nothing to do".to_string());
+    };
+    let deleted = Log::delete_many()
+        .filter(LogColumn::CreatedAt.lt(Utc::now() - Duration::days(retention_days)))
+        .exec(&state.db)
+        .await?
+        .rows_affected;
+    Ok(format!(
+        "deleted {deleted} logs older than {retention_days} days"
+    ))
+}
+
+/// Uploads logs older than `LOG_ARCHIVE_AFTER_DAYS` as gzipped JSONL to object storage and
+/// deletes them afterwards, one batch per object.
+async fn log_archive(state: &AppState) -> Result<String, anyhow::Error> {
+    let (Some(storage), Some(archive_after_days)) =
+        (&state.object_storage, state.config.log_archive_after_days)
+    else {
+        return Ok(
+            "object storage or LOG_ARCHIVE_AFTER_DAYS is not configured, nothing to do".to_string(),
+        );
+    };
+    let cutoff = Utc::now() - Duration::days(archive_after_days);
+    let mut archived = 0;
+
+    loop {
+        let logs = Log::find()
+            .filter(LogColumn::CreatedAt.lt(cutoff))
+            .order_by_asc(LogColumn::Id)
+            .limit(ARCHIVE_BATCH_SIZE)
+            .all(&state.db)
+            .await?;
+        let (Some(first), Some(last)) = (logs.first(), logs.last()) else {
+            break;
+        };
+        let key = format!(
+            "logs/{}-{}-{}.jsonl.gz",
+            Utc::now().format("%Y%m%d%H%M%S"),
+            first.id,
+            last.id
+        );
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        for log in &logs {
+            serde_json::to_writer(
+                &mut encoder,
+                &json!({
+                    "id": log.id,
+                    "consumer_id": log.consumer_id,
+                    "created_at": log.created_at,
+                    "request": log.request,
+                    "response": log.response,
+                }),
+            )?;
+            encoder.write_all(b"\n")?;
+        }
+        storage.put(&key, encoder.finish()?).await?;
+
+        Log::delete_many()
+            .filter(LogColumn::Id.is_in(logs.iter().map(|log| log.id)))
+            .exec(&state.db)
+            .await?;
+        archived += logs.len();
+    }
+
+    Ok(format!(
+        "archived {archived} logs older than {archive_after_days} days"
+    ))
+}
@@ -0,0 +1,73 @@
+use redis::RedisError;
+use redis::aio::ConnectionManager;
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+
+/// State shared by all replicas of the proxy, kept in Redis under keys starting with
+/// `prefix`.
+#[derive(Clone)]
+pub struct SharedStore {
+    connection: ConnectionManager,
+    prefix: String,
+}
+
+impl Debug for SharedStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedStore")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SharedStore {
+    pub async fn connect(url: &str, prefix: String) -> Result<Self, RedisError> {
+        let connection = ConnectionManager::new(redis::Client::open(url)?).await?;
+        Ok(SharedStore { connection, prefix })
+    }
+
+    /// Sets the key for `ttl` unless it exists, in which case its remaining time is returned.
+    pub async fn set_if_absent(
+        &self,
+        key: &str,
+        ttl: Duration,
+    ) -> Result<Option<Duration>, RedisError> {
+        let key = format!("{}{key}", self.prefix);
+        let (set, remaining_ms): (Option<String>, i64) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .cmd("PTTL")
+            .arg(&key)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(set
+            .is_none()
+            .then(|| Duration::from_millis(remaining_ms.max(0) as u64)))
+    }
+
+    /// Remaining time of the key, `None` if it does not exist.
+    pub async fn remaining(&self, key: &str) -> Result<Option<Duration>, RedisError> {
+        let remaining_ms: i64 = redis::cmd("PTTL")
+            .arg(format!("{}{key}", self.prefix))
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok((remaining_ms > 0).then(|| Duration::from_millis(remaining_ms as u64)))
+    }
+
+    pub async fn remove(&self, key: &str) -> Result<(), RedisError> {
+        redis::cmd("DEL")
+            .arg(format!("{}{key}", self.prefix))
+            .query_async(&mut self.connection.clone())
+            .await
+    }
+
+    pub async fn ping(&self) -> Result<(), RedisError> {
+        redis::cmd("PING")
+            .query_async(&mut self.connection.clone())
+            .await
+    }
+}
@@ -0,0 +1,251 @@
+use crate::AppState;
+use crate::auth::AuthExtractor;
+use crate::db::prelude::Task;
+use crate::db::task;
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::DateTime;
+use common::pagination::{Page, PageParams};
+use log::{error, warn};
+use sea_orm::sea_query::{Expr, Func, LikeExpr};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, NotSet, Order,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Comparison spec stored for a task of the consumer, if any.
+pub async fn comparison_spec(
+    db: &DatabaseConnection,
+    consumer_id: i32,
+    task_id: &str,
+) -> Result<Option<Value>, DbErr> {
+    Ok(find(db, consumer_id, task_id)
+        .await?
+        .and_then(|task| task.comparison))
+}
+
+pub async fn find(
+    db: &DatabaseConnection,
+    consumer_id: i32,
+    task_id: &str,
+) -> Result<Option<task::Model>, DbErr> {
+    Task::find()
+        .filter(task::Column::ConsumerId.eq(consumer_id))
+        .filter(task::Column::TaskId.eq(task_id))
+        .one(db)
+        .await
+}
+
+/// Checks the shape of a comparison spec, the steps themselves are validated by the runner.
+fn validate(spec: &Value) -> Result<(), &'static str> {
+    let steps = spec
+        .as_array()
+        .ok_or("comparison spec must be a list of steps")?;
+    if steps
+        .iter()
+        .any(|step| step.get("step").and_then(Value::as_str).is_none())
+    {
+        return Err("every comparison step needs a `step` name");
+    }
+    Ok(())
+}
+
+#[utoipa::path(get, path = "/api/v1/tasks/{task_id}/comparison", params(("task_id" = String, Path, description = "Task id used in analysis requests")), responses((status = OK, body = Value), (status = UNAUTHORIZED), (status = NOT_FOUND)), description = "Get the comparison spec stored for a task")]
+pub async fn get_comparison(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    comparison_spec(&state.db, auth.consumer_id, &task_id)
+        .await
+        .map_err(|err| {
+            error!("failed to load task {task_id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[utoipa::path(put, path = "/api/v1/tasks/{task_id}/comparison", params(("task_id" = String, Path, description = "Task id used in analysis requests")), request_body = Value, responses((status = NO_CONTENT), (status = UNAUTHORIZED), (status = BAD_REQUEST)), description = "Store the comparison spec applied to analyses of a task")]
+pub async fn put_comparison(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Path(task_id): Path<String>,
+    Json(spec): Json<Value>,
+) -> Result<StatusCode, StatusCode> {
+    validate(&spec).map_err(|err| {
+        warn!("invalid comparison spec for task {task_id}: {err}");
+        StatusCode::BAD_REQUEST
+    })?;
+    let existing = find(&state.db, auth.consumer_id, &task_id)
+        .await
+        .map_err(|err| {
+            error!("failed to load task {task_id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let result = match existing {
+        Some(task) => {
+            let mut task: task::ActiveModel = task.into();
+            task.comparison = Set(Some(spec));
+            task.update(&state.db).await
+        }
+        None => {
+            task::ActiveModel {
+                id: NotSet,
+                consumer_id: Set(auth.consumer_id),
+                task_id: Set(task_id.clone()),
+                comparison: Set(Some(spec)),
+                deadline: Set(None),
+                review_only_after_deadline: Set(get_default_review_only()),
+            }
+            .insert(&state.db)
+            .await
+        }
+    };
+    result.map_err(|err| {
+        error!("failed to store task {task_id}: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn get_default_review_only() -> bool {
+    true
+}
+
+/// Deadline of a task, after which analyses only report correctness. Feedback, which could
+/// reveal the solution, is withheld so it can't be copied into late submissions.
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct TaskDeadline {
+    /// RFC 3339 timestamp, `null` removes the deadline
+    pub deadline: Option<String>,
+    /// Grade analyses after the deadline in review-only mode, otherwise the deadline is only
+    /// recorded
+    #[serde(default = "get_default_review_only")]
+    pub review_only: bool,
+}
+
+#[utoipa::path(get, path = "/api/v1/tasks/{task_id}/deadline", params(("task_id" = String, Path, description = "Task id used in analysis requests")), responses((status = OK, body = TaskDeadline), (status = UNAUTHORIZED), (status = NOT_FOUND)), description = "Get the deadline of a task")]
+pub async fn get_deadline(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<Json<TaskDeadline>, StatusCode> {
+    find(&state.db, auth.consumer_id, &task_id)
+        .await
+        .map_err(|err| {
+            error!("failed to load task {task_id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map(|task| {
+            Json(TaskDeadline {
+                deadline: task.deadline.map(|deadline| deadline.to_rfc3339()),
+                review_only: task.review_only_after_deadline,
+            })
+        })
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[utoipa::path(put, path = "/api/v1/tasks/{task_id}/deadline", params(("task_id" = String, Path, description = "Task id used in analysis requests")), request_body = TaskDeadline, responses((status = NO_CONTENT), (status = UNAUTHORIZED), (status = BAD_REQUEST)), description = "Store the deadline of a task, after which analyses are graded in review-only mode")]
+pub async fn put_deadline(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Path(task_id): Path<String>,
+    Json(body): Json<TaskDeadline>,
+) -> Result<StatusCode, StatusCode> {
+    let deadline = body
+        .deadline
+        .as_deref()
+        .map(DateTime::parse_from_rfc3339)
+        .transpose()
+        .map_err(|err| {
+            warn!("invalid deadline for task {task_id}: {err}");
+            StatusCode::BAD_REQUEST
+        })?;
+    let existing = find(&state.db, auth.consumer_id, &task_id)
+        .await
+        .map_err(|err| {
+            error!("failed to load task {task_id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let result = match existing {
+        Some(task) => {
+            let mut task: task::ActiveModel = task.into();
+            task.deadline = Set(deadline);
+            task.review_only_after_deadline = Set(body.review_only);
+            task.update(&state.db).await
+        }
+        None => {
+            task::ActiveModel {
+                id: NotSet,
+                consumer_id: Set(auth.consumer_id),
+                task_id: Set(task_id.clone()),
+                comparison: Set(None),
+                deadline: Set(deadline),
+                review_only_after_deadline: Set(body.review_only),
+            }
+            .insert(&state.db)
+            .await
+        }
+    };
+    result.map_err(|err| {
+        error!("failed to store task {task_id}: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TaskResponse {
+    pub task_id: String,
+    pub comparison: Option<Value>,
+    /// RFC 3339 timestamp of the deadline, if any
+    pub deadline: Option<String>,
+    pub review_only_after_deadline: bool,
+}
+
+#[utoipa::path(get, path = "/api/v1/tasks", params(PageParams), responses((status = OK, body = Page<TaskResponse>), (status = UNAUTHORIZED)), description = "List the tasks of the consumer, filtered and sorted by `task_id`")]
+pub async fn list_tasks(
+    auth: AuthExtractor,
+    state: State<AppState>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<TaskResponse>>, StatusCode> {
+    let mut query = Task::find().filter(task::Column::ConsumerId.eq(auth.consumer_id));
+    if let Some(pattern) = params.like_pattern() {
+        query = query.filter(
+            Expr::expr(Func::lower(Expr::col(task::Column::TaskId)))
+                .like(LikeExpr::new(pattern).escape('\\')),
+        );
+    }
+    let order = match params.sort() {
+        Some(sort) if sort.descending => Order::Desc,
+        _ => Order::Asc,
+    };
+    let result = async {
+        let total = query.clone().count(&state.db).await?;
+        let tasks = query
+            .order_by(task::Column::TaskId, order)
+            .offset(params.offset())
+            .limit(params.per_page())
+            .all(&state.db)
+            .await?;
+        Ok::<_, DbErr>((tasks, total))
+    }
+    .await;
+    let (tasks, total) = result.map_err(|err| {
+        error!("failed to list tasks: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(Page::new(tasks, &params, total).map(|task| {
+        TaskResponse {
+            task_id: task.task_id,
+            comparison: task.comparison,
+            deadline: task.deadline.map(|deadline| deadline.to_rfc3339()),
+            review_only_after_deadline: task.review_only_after_deadline,
+        }
+    })))
+}
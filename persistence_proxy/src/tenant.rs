@@ -0,0 +1,39 @@
+use crate::AppState;
+use crate::db::tenant;
+use crate::runner::RunnerInterface;
+use log::error;
+use std::sync::Arc;
+
+impl AppState {
+    /// Runner used for the tenant, falling back to the globally configured runner.
+    pub fn runner_interface(&self, tenant: Option<&tenant::Model>) -> Option<Arc<RunnerInterface>> {
+        let Some(url) = tenant.and_then(|tenant| tenant.sql_runner_url.as_ref()) else {
+            return self.runner_interface.clone();
+        };
+        let mut runners = self.tenant_runners.lock().unwrap();
+        if let Some(runner) = runners.get(url) {
+            return Some(runner.clone());
+        }
+        match url.parse() {
+            Ok(parsed) => {
+                let runner = Arc::new(RunnerInterface::new(
+                    parsed,
+                    self.config.sql_runner_api_key.as_deref(),
+                ));
+                runners.insert(url.clone(), runner.clone());
+                Some(runner)
+            }
+            Err(err) => {
+                error!("invalid sql_runner_url `{url}` configured for tenant: {err}");
+                None
+            }
+        }
+    }
+
+    /// Upstream used for the tenant, falling back to the globally configured upstream.
+    pub fn upstream_url<'a>(&'a self, tenant: Option<&'a tenant::Model>) -> &'a str {
+        tenant
+            .and_then(|tenant| tenant.upstream_url.as_deref())
+            .unwrap_or(&self.config.upstream_url)
+    }
+}
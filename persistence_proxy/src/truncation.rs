@@ -0,0 +1,93 @@
+use crate::model::{Results, SqlResult};
+use common::models::ResultSet;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Caps on the size of the result sets embedded in the upstream request, each unlimited if
+/// not set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResultLimits {
+    pub max_rows: Option<usize>,
+    pub max_cells: Option<usize>,
+    /// Serialised size of the column names and rows
+    pub max_bytes: Option<usize>,
+}
+
+/// Rows omitted from a result set sent upstream, only the first `head_rows` and the last
+/// `tail_rows` rows were kept.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Truncation {
+    pub total_rows: usize,
+    pub head_rows: usize,
+    pub tail_rows: usize,
+    pub omitted_rows: usize,
+}
+
+/// Truncations of the solution and submission results, `null` for those sent in full.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ResultTruncation {
+    pub solutions: Vec<Option<Truncation>>,
+    pub submissions: Vec<Option<Truncation>>,
+}
+
+impl ResultLimits {
+    /// Keeps as many rows as fit, taken alternately from the start and the end so the
+    /// upstream sees how the result begins and ends. `None` if the result set fits.
+    pub fn truncate(&self, result_set: &mut ResultSet) -> Option<Truncation> {
+        let total_rows = result_set.rows.len();
+        let mut max_rows = self.max_rows.unwrap_or(usize::MAX).min(total_rows);
+        if let Some(max_cells) = self.max_cells {
+            max_rows = max_rows.min(max_cells / result_set.columns.len().max(1));
+        }
+        let mut budget = self
+            .max_bytes
+            .map(|max_bytes| max_bytes.saturating_sub(serialized_len(&result_set.columns)));
+        let (mut head_rows, mut tail_rows) = (0, 0);
+        while head_rows + tail_rows < max_rows {
+            let from_head = head_rows <= tail_rows;
+            let index = if from_head {
+                head_rows
+            } else {
+                total_rows - 1 - tail_rows
+            };
+            if let Some(budget) = &mut budget {
+                // separating comma
+                let len = serialized_len(&result_set.rows[index]) + 1;
+                if len > *budget {
+                    break;
+                }
+                *budget -= len;
+            }
+            if from_head {
+                head_rows += 1;
+            } else {
+                tail_rows += 1;
+            }
+        }
+        if head_rows + tail_rows == total_rows {
+            return None;
+        }
+        result_set.rows.drain(head_rows..total_rows - tail_rows);
+        Some(Truncation {
+            total_rows,
+            head_rows,
+            tail_rows,
+            omitted_rows: total_rows - head_rows - tail_rows,
+        })
+    }
+
+    /// Truncates every executed result, errors are kept as they are.
+    pub fn truncate_results(&self, results: &mut Results) -> Vec<Option<Truncation>> {
+        results
+            .iter_mut()
+            .map(|result| match result {
+                Some(SqlResult::Ok(result_set)) => self.truncate(result_set),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn serialized_len<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map_or(0, |json| json.len())
+}
@@ -0,0 +1,139 @@
+//! Cooldown of analyses per replica, and in the Redis at `TEST_REDIS_URL` if it is set.
+
+#[path = "../src/cooldown.rs"]
+mod cooldown;
+#[path = "../src/shared_store.rs"]
+mod shared_store;
+
+use axum::http::{StatusCode, header};
+use common::i18n::Language;
+use cooldown::{Cooldown, CooldownResponse, cooldown_response};
+use futures::executor::block_on;
+use shared_store::SharedStore;
+use std::time::Duration;
+
+const PERIOD: Duration = Duration::from_secs(60);
+
+fn attempt(
+    cooldown: &Cooldown,
+    consumer_id: i32,
+    user_id: &str,
+    task_id: Option<&str>,
+) -> Result<(), Duration> {
+    block_on(cooldown.try_attempt(consumer_id, user_id, task_id))
+}
+
+fn assert_waits(result: Result<(), Duration>) {
+    let remaining = result.unwrap_err();
+    assert!(remaining > PERIOD - Duration::from_secs(5) && remaining <= PERIOD);
+}
+
+#[test]
+fn repeated_attempts_wait_for_the_period() {
+    let cooldown = Cooldown::new(PERIOD);
+    assert_eq!(attempt(&cooldown, 1, "user", Some("task")), Ok(()));
+    assert_waits(attempt(&cooldown, 1, "user", Some("task")));
+}
+
+#[test]
+fn attempts_are_tracked_per_consumer_user_and_task() {
+    let cooldown = Cooldown::new(PERIOD);
+    assert_eq!(attempt(&cooldown, 1, "user", Some("task")), Ok(()));
+    assert_eq!(attempt(&cooldown, 2, "user", Some("task")), Ok(()));
+    assert_eq!(attempt(&cooldown, 1, "other", Some("task")), Ok(()));
+    assert_eq!(attempt(&cooldown, 1, "user", Some("other")), Ok(()));
+    assert_eq!(attempt(&cooldown, 1, "user", None), Ok(()));
+    assert_waits(attempt(&cooldown, 1, "user", None));
+}
+
+#[test]
+fn remaining_time_is_reported_without_an_attempt() {
+    let cooldown = Cooldown::new(PERIOD);
+    assert_eq!(cooldown.period(), PERIOD);
+    assert_eq!(block_on(cooldown.remaining(1, "user", Some("task"))), None);
+    assert_eq!(attempt(&cooldown, 1, "user", Some("task")), Ok(()));
+    let remaining = block_on(cooldown.remaining(1, "user", Some("task"))).unwrap();
+    assert!(remaining > PERIOD - Duration::from_secs(5) && remaining <= PERIOD);
+    assert_eq!(block_on(cooldown.remaining(1, "user", None)), None);
+}
+
+#[test]
+fn attempts_after_the_period_are_accepted() {
+    let cooldown = Cooldown::new(Duration::from_millis(10));
+    assert_eq!(attempt(&cooldown, 1, "user", None), Ok(()));
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(block_on(cooldown.remaining(1, "user", None)), None);
+    assert_eq!(attempt(&cooldown, 1, "user", None), Ok(()));
+}
+
+#[test]
+fn released_attempts_do_not_delay_the_next_one() {
+    let cooldown = Cooldown::new(PERIOD);
+    assert_eq!(attempt(&cooldown, 1, "user", Some("task")), Ok(()));
+    assert_eq!(attempt(&cooldown, 1, "user", Some("other")), Ok(()));
+    block_on(cooldown.release(1, "user", Some("task")));
+    assert_eq!(attempt(&cooldown, 1, "user", Some("task")), Ok(()));
+    assert_waits(attempt(&cooldown, 1, "user", Some("task")));
+    assert_waits(attempt(&cooldown, 1, "user", Some("other")));
+}
+
+#[test]
+fn cooldown_responses_round_up_the_remaining_time() {
+    let response = cooldown_response(Duration::from_millis(1500), Language::German);
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    let body = block_on(axum::body::to_bytes(response.into_body(), usize::MAX)).unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["retry_after_secs"], 2);
+    assert!(body["error"].as_str().unwrap().contains("Sekunden"));
+}
+
+#[test]
+fn cooldown_responses_are_documented() {
+    let response = CooldownResponse {
+        error: "retry later".to_string(),
+        retry_after_secs: 1,
+    };
+    assert_eq!(
+        serde_json::to_value(response).unwrap(),
+        serde_json::json!({"error": "retry later", "retry_after_secs": 1})
+    );
+}
+
+#[test]
+fn attempts_are_shared_through_the_store() {
+    let Ok(url) = std::env::var("TEST_REDIS_URL") else {
+        eprintln!("skipped, TEST_REDIS_URL is not set");
+        return;
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let prefix = format!("cooldown-test-{}:", std::process::id());
+        let shared_store = SharedStore::connect(&url, prefix).await.unwrap();
+        shared_store.ping().await.unwrap();
+        let replica = Cooldown::new(PERIOD).with_shared_store(shared_store.clone());
+        let other_replica = Cooldown::new(PERIOD).with_shared_store(shared_store);
+        assert_eq!(replica.try_attempt(1, "user", Some("task")).await, Ok(()));
+        assert_waits(other_replica.try_attempt(1, "user", Some("task")).await);
+        assert_eq!(
+            other_replica.try_attempt(1, "user", Some("other")).await,
+            Ok(())
+        );
+        assert!(
+            other_replica
+                .remaining(1, "user", Some("task"))
+                .await
+                .is_some()
+        );
+        replica.release(1, "user", Some("task")).await;
+        assert_eq!(other_replica.remaining(1, "user", Some("task")).await, None);
+        assert_eq!(
+            other_replica.try_attempt(1, "user", Some("task")).await,
+            Ok(())
+        );
+        assert_waits(replica.try_attempt(1, "user", Some("task")).await);
+    });
+}
@@ -0,0 +1,26 @@
+#[path = "../src/deadline.rs"]
+mod deadline;
+
+use chrono::{DateTime, Duration, Utc};
+use deadline::is_review_only;
+
+#[test]
+fn review_only_after_the_deadline_if_enabled() {
+    let deadline = DateTime::parse_from_rfc3339("2026-07-01T12:00:00+02:00").unwrap();
+    let at = |offset: Duration| deadline.with_timezone(&Utc) + offset;
+
+    assert!(!is_review_only(
+        true,
+        Some(deadline),
+        at(-Duration::seconds(1))
+    ));
+    assert!(is_review_only(true, Some(deadline), at(Duration::zero())));
+    assert!(is_review_only(true, Some(deadline), at(Duration::days(1))));
+
+    assert!(!is_review_only(
+        false,
+        Some(deadline),
+        at(Duration::days(1))
+    ));
+    assert!(!is_review_only(true, None, at(Duration::days(1))));
+}
@@ -0,0 +1,37 @@
+#[path = "../src/hedge.rs"]
+mod hedge;
+
+use hedge::LatencyTracker;
+use std::time::Duration;
+
+fn tracker(capacity: usize, latencies_ms: impl IntoIterator<Item = u64>) -> LatencyTracker {
+    let tracker = LatencyTracker::new(capacity);
+    for latency in latencies_ms {
+        tracker.record(Duration::from_millis(latency));
+    }
+    tracker
+}
+
+#[test]
+fn percentiles_need_enough_samples() {
+    let tracker = tracker(100, 1..20);
+    assert_eq!(tracker.percentile(0.5), None);
+    tracker.record(Duration::from_millis(20));
+    assert_eq!(tracker.percentile(0.5), Some(Duration::from_millis(11)));
+}
+
+#[test]
+fn percentiles_are_taken_from_the_sorted_samples() {
+    let tracker = tracker(100, (1..=21).rev());
+    assert_eq!(tracker.percentile(0.0), Some(Duration::from_millis(1)));
+    assert_eq!(tracker.percentile(0.95), Some(Duration::from_millis(20)));
+    assert_eq!(tracker.percentile(1.0), Some(Duration::from_millis(21)));
+    assert_eq!(tracker.percentile(2.0), Some(Duration::from_millis(21)));
+}
+
+#[test]
+fn only_the_most_recent_samples_are_kept() {
+    let tracker = tracker(20, (1..=20).chain([100; 10]));
+    assert_eq!(tracker.percentile(0.0), Some(Duration::from_millis(11)));
+    assert_eq!(tracker.percentile(0.5), Some(Duration::from_millis(100)));
+}
@@ -0,0 +1,315 @@
+#[path = "../src/model.rs"]
+mod model;
+#[path = "../src/truncation.rs"]
+mod truncation;
+
+use common::models::{ResultSet, SqlValue};
+use model::{
+    AggregateVerdict, AnalyseResponse, AnalysisRequest, AnalysisResult, AnalysisResults,
+    DryRunResponse, Priority, SqlResult, validate_results,
+};
+use serde_json::{Value, json};
+use truncation::ResultLimits;
+
+fn request(extra: Value) -> AnalysisRequest {
+    let mut request = json!({
+        "sql_environment": "CREATE TABLE t (x INT);",
+        "db_schema": "t(x)",
+        "task": "Select every x.",
+        "solutions": ["SELECT x FROM t"],
+        "submissions": ["SELECT * FROM t", "SELECT y FROM t"],
+        "solution_results": null,
+        "submission_results": null,
+        "task_id": "task",
+        "user_id": "user",
+        "feedback_language": null,
+        "metadata": null,
+        "submission_weights": null,
+    });
+    request
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    serde_json::from_value(request).unwrap()
+}
+
+fn result(correct: bool) -> AnalysisResult {
+    AnalysisResult {
+        correct,
+        feedback: "feedback".to_string(),
+        ..Default::default()
+    }
+}
+
+fn result_set(rows: i64) -> ResultSet {
+    ResultSet {
+        columns: vec!["x".to_string(), "y".to_string()],
+        rows: (0..rows)
+            .map(|row| vec![SqlValue::Int(row), SqlValue::Int(row)])
+            .collect(),
+    }
+}
+
+fn first_column(result_set: &ResultSet) -> Vec<i64> {
+    result_set
+        .rows
+        .iter()
+        .map(|row| match row[0] {
+            SqlValue::Int(value) => value,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+#[test]
+fn requests_default_to_interactive_priority() {
+    assert_eq!(request(json!({})).priority, Priority::Interactive);
+    assert_eq!(
+        request(json!({"priority": "batch"})).priority,
+        Priority::Batch
+    );
+}
+
+#[test]
+fn validate_accepts_a_complete_request() {
+    assert_eq!(request(json!({})).validate(), Ok(()));
+    assert_eq!(
+        request(json!({"submission_weights": [1.0, 0.0]})).validate(),
+        Ok(())
+    );
+}
+
+#[test]
+fn validate_rejects_missing_queries_and_mismatched_results() {
+    for (extra, error) in [
+        (
+            json!({"solutions": []}),
+            "at least one solution is required",
+        ),
+        (
+            json!({"submissions": []}),
+            "at least one submission is required",
+        ),
+        (
+            json!({"solution_results": []}),
+            "solution_results must contain one entry per solution",
+        ),
+        (
+            json!({"submission_results": [null]}),
+            "submission_results must contain one entry per submission",
+        ),
+        (
+            json!({"submission_weights": [1.0]}),
+            "submission_weights must contain one weight per submission",
+        ),
+        (
+            json!({"submission_weights": [1.0, -1.0]}),
+            "submission_weights must not be negative and not all 0",
+        ),
+        (
+            json!({"submission_weights": [0.0, 0.0]}),
+            "submission_weights must not be negative and not all 0",
+        ),
+    ] {
+        assert_eq!(request(extra.clone()).validate(), Err(error), "{extra}");
+    }
+}
+
+#[test]
+fn submission_keys_depend_on_the_submission_and_its_context() {
+    let first = request(json!({}));
+    let key = first.submission_key(0);
+    assert_eq!(key.len(), 64);
+    assert_ne!(key, first.submission_key(1));
+
+    // neither the caller nor the options change the analysis
+    let mut other = request(json!({"task_id": "other", "aggregate": true}));
+    other.redact();
+    assert_eq!(other.task_id, None);
+    assert_eq!(other.user_id, None);
+    assert_eq!(other.submission_key(0), key);
+
+    let other = request(json!({"solutions": ["SELECT x FROM t ORDER BY x"]}));
+    assert_ne!(other.submission_key(0), key);
+}
+
+#[test]
+fn submission_errors_are_taken_from_the_submission_results() {
+    let mut request = request(json!({}));
+    assert_eq!(request.submission_errors(), vec![None, None]);
+    request.submission_results = Some(vec![
+        Some(SqlResult::Ok(result_set(1))),
+        Some(SqlResult::Error("column y does not exist".to_string())),
+    ]);
+    assert_eq!(
+        request.submission_errors(),
+        vec![None, Some("column y does not exist".to_string())]
+    );
+}
+
+#[test]
+fn validate_results_accepts_one_bounded_result_per_submission() {
+    let mut results = vec![result(true), result(false)];
+    results[0].confidence = Some(1.0);
+    assert_eq!(validate_results(&results, 2, 8), Ok(()));
+}
+
+#[test]
+fn validate_results_reports_every_violation() {
+    assert_eq!(
+        validate_results(&Vec::new(), 1, 100),
+        Err(vec!["no results returned".to_string()])
+    );
+    let mut results = vec![result(true), result(false)];
+    results[0].confidence = Some(1.5);
+    results[1].feedback = "ä".repeat(5);
+    assert_eq!(
+        validate_results(&results, 3, 4),
+        Err(vec![
+            "expected 3 results, one per submission, got 2".to_string(),
+            "feedback of result 0 has 8 characters, at most 4 are allowed".to_string(),
+            "confidence of result 0 is 1.5, it must be between 0 and 1".to_string(),
+            "feedback of result 1 has 5 characters, at most 4 are allowed".to_string(),
+        ])
+    );
+}
+
+#[test]
+fn aggregate_counts_matched_submissions_as_correct() {
+    let results = vec![result(false), result(false), result(true)];
+    let verdict = AggregateVerdict::new(&results, vec![Some(0), None, None], None);
+    assert!(!verdict.correct);
+    assert_eq!(verdict.score, 2.0 / 3.0);
+    assert_eq!(verdict.matched_solutions, vec![Some(0), None, None]);
+    assert!(!verdict.needs_review);
+
+    let verdict = AggregateVerdict::new(&results, vec![Some(0), Some(1), None], None);
+    assert!(verdict.correct);
+    assert_eq!(verdict.score, 1.0);
+}
+
+#[test]
+fn aggregate_prefers_reviews_and_applies_weights() {
+    let mut results = vec![result(false), result(true), result(false)];
+    results[0].reviewed = true;
+    results[2].needs_review = true;
+    let verdict = AggregateVerdict::new(
+        &results,
+        vec![Some(0), None, Some(0)],
+        Some(&[1.0, 1.0, 2.0]),
+    );
+    assert!(!verdict.correct);
+    assert_eq!(verdict.score, 0.75);
+    assert!(verdict.needs_review);
+
+    assert_eq!(
+        AggregateVerdict::new(&AnalysisResults::new(), Vec::new(), None).score,
+        0.0
+    );
+}
+
+#[test]
+fn truncation_keeps_the_first_and_last_rows() {
+    let limits = ResultLimits {
+        max_rows: Some(5),
+        ..Default::default()
+    };
+    let mut result_set = result_set(10);
+    let truncation = limits.truncate(&mut result_set).unwrap();
+    assert_eq!(first_column(&result_set), vec![0, 1, 2, 8, 9]);
+    assert_eq!(
+        (
+            truncation.total_rows,
+            truncation.head_rows,
+            truncation.tail_rows,
+            truncation.omitted_rows
+        ),
+        (10, 3, 2, 5)
+    );
+}
+
+#[test]
+fn truncation_applies_the_cell_and_byte_limits() {
+    let limits = ResultLimits {
+        max_cells: Some(7),
+        ..Default::default()
+    };
+    let mut rows = result_set(10);
+    limits.truncate(&mut rows).unwrap();
+    assert_eq!(first_column(&rows), vec![0, 1, 9]);
+
+    // the columns take 9 bytes and every row with its comma 6
+    let limits = ResultLimits {
+        max_bytes: Some(9 + 4 * 6),
+        ..Default::default()
+    };
+    let mut rows = result_set(10);
+    limits.truncate(&mut rows).unwrap();
+    assert_eq!(first_column(&rows), vec![0, 1, 8, 9]);
+
+    let limits = ResultLimits {
+        max_bytes: Some(5),
+        ..Default::default()
+    };
+    let mut rows = result_set(2);
+    let truncation = limits.truncate(&mut rows).unwrap();
+    assert!(rows.rows.is_empty());
+    assert_eq!(truncation.omitted_rows, 2);
+}
+
+#[test]
+fn result_sets_within_the_limits_are_kept() {
+    let limits = ResultLimits {
+        max_rows: Some(3),
+        max_cells: Some(6),
+        max_bytes: Some(100),
+    };
+    let mut result_set = result_set(3);
+    assert!(limits.truncate(&mut result_set).is_none());
+    assert_eq!(first_column(&result_set), vec![0, 1, 2]);
+}
+
+#[test]
+fn only_truncated_results_are_noted() {
+    let limits = ResultLimits {
+        max_rows: Some(2),
+        ..Default::default()
+    };
+    let mut request = request(json!({}));
+    request.solution_results = Some(vec![Some(SqlResult::Ok(result_set(2)))]);
+    request.truncate_results(&limits);
+    assert!(request.result_truncation.is_none());
+
+    request.submission_results = Some(vec![
+        Some(SqlResult::Error("syntax error".to_string())),
+        Some(SqlResult::Ok(result_set(4))),
+    ]);
+    request.truncate_results(&limits);
+    let truncation = request.result_truncation.as_ref().unwrap();
+    assert!(truncation.solutions[0].is_none());
+    assert!(truncation.submissions[0].is_none());
+    assert_eq!(truncation.submissions[1].as_ref().unwrap().omitted_rows, 2);
+}
+
+#[test]
+fn responses_serialise_without_wrappers() {
+    let results = vec![result(true)];
+    assert_eq!(
+        serde_json::to_value(AnalyseResponse::Results(results.clone())).unwrap()[0]["correct"],
+        true
+    );
+    let annotated = AnalyseResponse::Annotated {
+        results: results.clone(),
+        metadata: Some(json!({"course": 1})),
+        aggregate: None,
+    };
+    let annotated = serde_json::to_value(annotated).unwrap();
+    assert_eq!(annotated["metadata"], json!({"course": 1}));
+    assert!(annotated.get("aggregate").is_none());
+
+    let dry_run = AnalyseResponse::DryRun(Box::new(DryRunResponse {
+        upstream_request: request(json!({})),
+    }));
+    let dry_run = serde_json::to_value(dry_run).unwrap();
+    assert_eq!(dry_run["upstream_request"]["task_id"], "task");
+}
@@ -0,0 +1,229 @@
+use serde::Serialize;
+use std::ops::Add;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Characters per token of the prompt estimate, a common rule of thumb for English text and
+/// SQL which avoids shipping the tokenizer of every model.
+const CHARS_PER_TOKEN: u64 = 4;
+
+/// Estimated size and worst case cost of a llm request.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema, PartialEq)]
+pub struct CostEstimate {
+    pub prompt_chars: u64,
+    pub prompt_tokens: u64,
+    /// Upper bound of the completion, if `MAX_TOKENS` is configured
+    pub max_completion_tokens: Option<u64>,
+    /// In the currency of the configured token prices, if the prices are configured
+    pub cost: Option<f64>,
+}
+
+impl CostEstimate {
+    /// Prompt tokens and the upper bound of the completion.
+    pub fn max_tokens(&self) -> u64 {
+        self.prompt_tokens + self.max_completion_tokens.unwrap_or_default()
+    }
+}
+
+/// Estimate of a request sending several prompts.
+impl Add for CostEstimate {
+    type Output = CostEstimate;
+
+    fn add(self, other: CostEstimate) -> CostEstimate {
+        CostEstimate {
+            prompt_chars: self.prompt_chars + other.prompt_chars,
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            max_completion_tokens: self
+                .max_completion_tokens
+                .map(|tokens| tokens + other.max_completion_tokens.unwrap_or_default()),
+            cost: self.cost.map(|cost| cost + other.cost.unwrap_or_default()),
+        }
+    }
+}
+
+/// Limit a request was rejected by.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CostLimit {
+    PromptSize,
+    Tokens,
+    Cost,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, ToSchema, PartialEq)]
+pub struct CostRejection {
+    pub limit: CostLimit,
+    pub estimate: CostEstimate,
+}
+
+/// Limits checked before a prompt is sent to the llm, nothing is limited by default.
+#[derive(Debug, Clone, Default)]
+pub struct CostGuard {
+    pub max_prompt_chars: Option<u64>,
+    pub max_completion_tokens: Option<u64>,
+    /// Maximum prompt and completion tokens of a single request
+    pub max_tokens: Option<u64>,
+    /// Prices per million prompt and completion tokens
+    pub token_prices: Option<(f64, f64)>,
+    /// Maximum worst case cost of a single request, requires the token prices and
+    /// `max_completion_tokens`
+    pub max_cost: Option<f64>,
+}
+
+impl CostGuard {
+    pub fn estimate(&self, prompt: &str) -> CostEstimate {
+        let prompt_chars = prompt.chars().count() as u64;
+        let prompt_tokens = prompt_chars.div_ceil(CHARS_PER_TOKEN);
+        CostEstimate {
+            prompt_chars,
+            prompt_tokens,
+            max_completion_tokens: self.max_completion_tokens,
+            cost: self.token_prices.map(|(prompt_price, completion_price)| {
+                (prompt_tokens as f64 * prompt_price
+                    + self.max_completion_tokens.unwrap_or_default() as f64 * completion_price)
+                    / 1_000_000.0
+            }),
+        }
+    }
+
+    /// Estimate of the prompt if it is within the limits.
+    pub fn check(&self, prompt: &str) -> Result<CostEstimate, CostRejection> {
+        let estimate = self.estimate(prompt);
+        let limit = if self
+            .max_prompt_chars
+            .is_some_and(|max| estimate.prompt_chars > max)
+        {
+            Some(CostLimit::PromptSize)
+        } else if self
+            .max_tokens
+            .is_some_and(|max| estimate.max_tokens() > max)
+        {
+            Some(CostLimit::Tokens)
+        } else if matches!((estimate.cost, self.max_cost), (Some(cost), Some(max)) if cost > max) {
+            Some(CostLimit::Cost)
+        } else {
+            None
+        };
+        match limit {
+            Some(limit) => Err(CostRejection { limit, estimate }),
+            None => Ok(estimate),
+        }
+    }
+}
+
+/// Daily limit a request was rejected by.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLimit {
+    Tokens,
+    Cost,
+}
+
+/// The budget of the day does not cover the estimate of a request.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema, PartialEq)]
+pub struct BudgetExhausted {
+    pub limit: BudgetLimit,
+    /// Tokens or cost spent today
+    pub spent: f64,
+    pub budget: f64,
+    pub estimate: CostEstimate,
+    /// Seconds until the budget is reset at midnight UTC
+    pub retry_after_secs: u64,
+}
+
+/// Tokens and cost spent on the day, counted in days since the unix epoch.
+#[derive(Debug, Default)]
+struct Spent {
+    day: u64,
+    tokens: u64,
+    cost: f64,
+}
+
+/// Tokens and cost the llm may use per UTC day, nothing is limited by default. Requests are
+/// checked with their worst case estimate and charged with the tokens the llm reported, so
+/// requests checked at the same time may overrun the budget together.
+#[derive(Debug, Default)]
+pub struct DailyBudget {
+    max_tokens: Option<u64>,
+    max_cost: Option<f64>,
+    token_prices: Option<(f64, f64)>,
+    spent: Mutex<Spent>,
+}
+
+impl DailyBudget {
+    /// The cost budget requires the prices per million prompt and completion tokens.
+    pub fn new(
+        max_tokens: Option<u64>,
+        max_cost: Option<f64>,
+        token_prices: Option<(f64, f64)>,
+    ) -> Self {
+        DailyBudget {
+            max_tokens,
+            max_cost,
+            token_prices,
+            spent: Default::default(),
+        }
+    }
+
+    /// Tokens and cost spent on the day of `now`.
+    fn spent_today<T>(&self, now: SystemTime, f: impl FnOnce(&mut Spent) -> T) -> T {
+        let day = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / DAY.as_secs();
+        let mut spent = self.spent.lock().unwrap();
+        if spent.day != day {
+            *spent = Spent {
+                day,
+                ..Default::default()
+            };
+        }
+        f(&mut spent)
+    }
+
+    /// Rejects the estimate of a request if the rest of the day's budget does not cover it.
+    pub fn check(&self, estimate: CostEstimate, now: SystemTime) -> Result<(), BudgetExhausted> {
+        let (tokens, cost) = self.spent_today(now, |spent| (spent.tokens, spent.cost));
+        let exhausted = |limit, spent, budget| {
+            let since_midnight =
+                now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() % DAY.as_secs();
+            BudgetExhausted {
+                limit,
+                spent,
+                budget,
+                estimate,
+                retry_after_secs: DAY.as_secs() - since_midnight,
+            }
+        };
+        if let Some(max_tokens) = self
+            .max_tokens
+            .filter(|max_tokens| tokens + estimate.max_tokens() > *max_tokens)
+        {
+            return Err(exhausted(
+                BudgetLimit::Tokens,
+                tokens as f64,
+                max_tokens as f64,
+            ));
+        }
+        match (self.max_cost, estimate.cost) {
+            (Some(max_cost), Some(estimated)) if cost + estimated > max_cost => {
+                Err(exhausted(BudgetLimit::Cost, cost, max_cost))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Charges the tokens an llm answer took to the day of `now`.
+    pub fn record(&self, prompt_tokens: u64, completion_tokens: u64, now: SystemTime) {
+        let cost = self
+            .token_prices
+            .map_or(0.0, |(prompt_price, completion_price)| {
+                (prompt_tokens as f64 * prompt_price + completion_tokens as f64 * completion_price)
+                    / 1_000_000.0
+            });
+        self.spent_today(now, |spent| {
+            spent.tokens += prompt_tokens + completion_tokens;
+            spent.cost += cost;
+        });
+    }
+}
@@ -0,0 +1,259 @@
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::fmt::Debug;
+
+/// Version of the Anthropic messages API the requests are written against.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic requires an upper bound of completion tokens, used if `MAX_TOKENS` is not set.
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u64 = 4096;
+
+/// Llm API the feedback is requested from, see `LLM_PROVIDER`.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum LlmProvider {
+    /// OpenAI compatible `/chat/completions` API below `BASE_URL`
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// Azure OpenAI resource at `BASE_URL`, the model is the name of the deployment
+    #[serde(rename = "azure_openai")]
+    AzureOpenAi,
+    /// Native `/api/chat` API of an Ollama server at `BASE_URL`
+    #[serde(rename = "ollama")]
+    Ollama,
+    /// Anthropic `/messages` API below `BASE_URL`, e.g. `https://api.anthropic.com/v1`
+    #[serde(rename = "anthropic")]
+    Anthropic,
+}
+
+/// Format the answer of the llm is asked to have.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnswerFormat {
+    Text,
+    JsonObject,
+    /// JSON object following the schema
+    JsonSchema(Value),
+}
+
+/// Conversation sent to the llm, the messages have a `role` and a `content`.
+#[derive(Debug, Clone)]
+pub struct Completion<'a> {
+    pub model: &'a str,
+    pub messages: &'a [Value],
+    pub max_tokens: Option<u64>,
+    pub format: AnswerFormat,
+}
+
+/// Content of the answer of the llm and the tokens it took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answer {
+    pub content: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Request and response shape of an llm API, sending the requests is up to the caller.
+pub trait LlmClient: Debug + Send + Sync {
+    /// Request asking the llm to continue the conversation.
+    fn request(&self, completion: &Completion) -> RequestBuilder;
+
+    /// Answer of a successful response, `None` if it has no content.
+    fn answer(&self, response: &Value) -> Option<Answer>;
+}
+
+impl LlmProvider {
+    /// Client of the API at `base_url`. The API key is optional for Ollama only, the API
+    /// version is used by Azure OpenAI only.
+    pub fn client(
+        self,
+        base_url: &str,
+        api_key: Option<&str>,
+        api_version: &str,
+    ) -> Box<dyn LlmClient> {
+        let api = Api {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.map(str::to_string),
+        };
+        match self {
+            LlmProvider::OpenAi => Box::new(OpenAi(api)),
+            LlmProvider::AzureOpenAi => Box::new(AzureOpenAi {
+                api,
+                api_version: api_version.to_string(),
+            }),
+            LlmProvider::Ollama => Box::new(Ollama(api)),
+            LlmProvider::Anthropic => Box::new(Anthropic(api)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Api {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Api {
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.client.post(format!("{}{path}", self.base_url))
+    }
+
+    fn api_key(&self) -> &str {
+        self.api_key.as_deref().unwrap_or_default()
+    }
+}
+
+/// Body of a chat completion request, shared by OpenAI and Azure OpenAI.
+fn chat_completion(completion: &Completion) -> Value {
+    let mut request = json!({
+        "model": completion.model,
+        "messages": completion.messages,
+        "temperature": 0,
+    });
+    if let Some(max_tokens) = completion.max_tokens {
+        request["max_tokens"] = max_tokens.into();
+    }
+    match &completion.format {
+        AnswerFormat::Text => {}
+        AnswerFormat::JsonObject => request["response_format"] = json!({"type": "json_object"}),
+        AnswerFormat::JsonSchema(schema) => {
+            request["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": {"name": "feedback", "strict": true, "schema": schema},
+            })
+        }
+    }
+    request
+}
+
+fn chat_completion_answer(response: &Value) -> Option<Answer> {
+    Some(Answer {
+        content: response["choices"][0]["message"]["content"]
+            .as_str()?
+            .to_string(),
+        prompt_tokens: response["usage"]["prompt_tokens"]
+            .as_u64()
+            .unwrap_or_default(),
+        completion_tokens: response["usage"]["completion_tokens"]
+            .as_u64()
+            .unwrap_or_default(),
+    })
+}
+
+#[derive(Debug)]
+struct OpenAi(Api);
+
+impl LlmClient for OpenAi {
+    fn request(&self, completion: &Completion) -> RequestBuilder {
+        self.0
+            .post("/chat/completions")
+            .bearer_auth(self.0.api_key())
+            .json(&chat_completion(completion))
+    }
+
+    fn answer(&self, response: &Value) -> Option<Answer> {
+        chat_completion_answer(response)
+    }
+}
+
+#[derive(Debug)]
+struct AzureOpenAi {
+    api: Api,
+    api_version: String,
+}
+
+impl LlmClient for AzureOpenAi {
+    fn request(&self, completion: &Completion) -> RequestBuilder {
+        self.api
+            .post(&format!(
+                "/openai/deployments/{}/chat/completions",
+                completion.model
+            ))
+            .query(&[("api-version", &self.api_version)])
+            .header("api-key", self.api.api_key())
+            .json(&chat_completion(completion))
+    }
+
+    fn answer(&self, response: &Value) -> Option<Answer> {
+        chat_completion_answer(response)
+    }
+}
+
+#[derive(Debug)]
+struct Ollama(Api);
+
+impl LlmClient for Ollama {
+    fn request(&self, completion: &Completion) -> RequestBuilder {
+        let mut request = json!({
+            "model": completion.model,
+            "messages": completion.messages,
+            "stream": false,
+            "options": {"temperature": 0},
+        });
+        if let Some(max_tokens) = completion.max_tokens {
+            request["options"]["num_predict"] = max_tokens.into();
+        }
+        match &completion.format {
+            AnswerFormat::Text => {}
+            AnswerFormat::JsonObject => request["format"] = "json".into(),
+            AnswerFormat::JsonSchema(schema) => request["format"] = schema.clone(),
+        }
+        let builder = self.0.post("/api/chat");
+        // Ollama itself has no authentication, but it may be behind a proxy requiring it
+        match &self.0.api_key {
+            Some(api_key) => builder.bearer_auth(api_key),
+            None => builder,
+        }
+        .json(&request)
+    }
+
+    fn answer(&self, response: &Value) -> Option<Answer> {
+        Some(Answer {
+            content: response["message"]["content"].as_str()?.to_string(),
+            prompt_tokens: response["prompt_eval_count"].as_u64().unwrap_or_default(),
+            completion_tokens: response["eval_count"].as_u64().unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Anthropic(Api);
+
+impl LlmClient for Anthropic {
+    /// Anthropic has no JSON mode, structured answers rely on the prompt asking for JSON.
+    fn request(&self, completion: &Completion) -> RequestBuilder {
+        self.0
+            .post("/messages")
+            .header("x-api-key", self.0.api_key())
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&json!({
+                "model": completion.model,
+                "messages": completion.messages,
+                "temperature": 0,
+                "max_tokens": completion.max_tokens.unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS),
+            }))
+    }
+
+    fn answer(&self, response: &Value) -> Option<Answer> {
+        let texts: Vec<&str> = response["content"]
+            .as_array()?
+            .iter()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect();
+        if texts.is_empty() {
+            return None;
+        }
+        Some(Answer {
+            content: texts.concat(),
+            prompt_tokens: response["usage"]["input_tokens"]
+                .as_u64()
+                .unwrap_or_default(),
+            completion_tokens: response["usage"]["output_tokens"]
+                .as_u64()
+                .unwrap_or_default(),
+        })
+    }
+}
@@ -1,27 +1,244 @@
+mod cost;
+mod llm;
+mod postprocess;
+mod prompt;
 mod routes;
+mod structured;
+mod usage;
 
+use crate::cost::{CostGuard, DailyBudget};
+use crate::llm::{LlmClient, LlmProvider};
+use crate::postprocess::PostProcessor;
+use crate::usage::Usage;
+use axum::middleware;
+use common::config::{ConfigError, ConfigValidator};
+use common::retry::Backoff;
 use env_logger::Env;
 use log::{error, info};
 use serde::Deserialize;
+use std::path::Path;
 use std::process::exit;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
 use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 use utoipa_redoc::Redoc;
 use utoipa_redoc::Servable;
 
+/// Version of the feedback HTTP API, increased on incompatible changes.
+pub const API_VERSION: u32 = 1;
+
+/// Cargo features this binary was built with.
+pub const ENABLED_FEATURES: &[&str] = &[
+    #[cfg(feature = "fault-injection")]
+    "fault-injection",
+];
+
 fn get_default_port() -> u16 {
     8080
 }
 
+fn get_default_azure_api_version() -> String {
+    "2024-10-21".to_string()
+}
+
+fn get_default_llm_max_concurrent() -> usize {
+    4
+}
+
+fn get_default_llm_max_attempts() -> u32 {
+    3
+}
+
+fn get_default_llm_retry_delay_ms() -> u64 {
+    500
+}
+
+fn get_default_structured_output_retries() -> u32 {
+    2
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Config {
     #[serde(default = "get_default_port")]
     port: u16,
+    /// Api the llm is reached with, one of `openai`, `azure_openai`, `ollama` and `anthropic`
+    #[serde(default)]
+    llm_provider: LlmProvider,
     base_url: String,
-    openai_api_key: String,
+    /// Key of the llm api, optional for Ollama only
+    #[serde(alias = "llm_api_key")]
+    openai_api_key: Option<String>,
+    /// `api-version` of Azure OpenAI requests
+    #[serde(default = "get_default_azure_api_version")]
+    azure_api_version: String,
     model: String,
+    admin_token: Option<String>,
+    postprocessing_rules: Option<String>,
+    /// Llm requests sent at the same time, further ones wait, e.g. for the submissions of a
+    /// request which are asked for one by one
+    #[serde(default = "get_default_llm_max_concurrent")]
+    llm_max_concurrent: usize,
+    /// Attempts of an llm request failing with a rate limit, server or connection error,
+    /// retried with exponential backoff
+    #[serde(default = "get_default_llm_max_attempts")]
+    llm_max_attempts: u32,
+    /// Longest delay before the first retry of an llm request, doubled for every further one
+    #[serde(default = "get_default_llm_retry_delay_ms")]
+    llm_retry_delay_ms: u64,
+    /// Upper bound of completion tokens sent with every llm request
+    max_tokens: Option<u64>,
+    /// Requests whose prompt has more characters are rejected before calling the llm
+    max_prompt_chars: Option<u64>,
+    /// Price per million prompt tokens, used to estimate the cost of a request
+    prompt_token_price: Option<f64>,
+    /// Price per million completion tokens, used to estimate the cost of a request
+    completion_token_price: Option<f64>,
+    /// Requests whose worst case cost is estimated to be higher are rejected before calling
+    /// the llm, requires `MAX_TOKENS` and both token prices
+    max_request_cost: Option<f64>,
+    /// Requests whose prompt and completion tokens are estimated to be more are rejected
+    /// before calling the llm
+    max_request_tokens: Option<u64>,
+    /// Tokens the llm may use per UTC day, requests the rest of the day's budget does not
+    /// cover are answered with `429`
+    daily_token_budget: Option<u64>,
+    /// Cost the llm may cause per UTC day, requires both token prices
+    daily_cost_budget: Option<f64>,
+    /// Asks the llm to answer with a JSON object containing the correctness, feedback, hints,
+    /// its confidence and whether a human should review every submission instead of plain
+    /// feedback
+    #[serde(default)]
+    structured_output: bool,
+    /// Constrains structured answers with a JSON schema instead of only requesting a JSON
+    /// object, for llm APIs supporting `json_schema` response formats
+    #[serde(default)]
+    json_schema: bool,
+    /// Malformed structured answers are sent back to the llm for correction this many times
+    /// before the answer is used as plain feedback, as long as the request limits and the daily
+    /// budget cover the grown conversation
+    #[serde(default = "get_default_structured_output_retries")]
+    structured_output_retries: u32,
+    /// Structured answers with a lower confidence are flagged for review, requires
+    /// `STRUCTURED_OUTPUT`
+    abstain_below_confidence: Option<f64>,
+}
+
+impl Config {
+    /// Problems envy cannot detect, reported together before exiting.
+    fn validate(&self) -> Result<(), ConfigError> {
+        ConfigValidator::new()
+            .port("PORT", self.port)
+            .url("BASE_URL", &self.base_url)
+            .check(!self.model.trim().is_empty(), || {
+                "MODEL must not be empty".to_string()
+            })
+            .check(
+                self.openai_api_key.is_some() || self.llm_provider == LlmProvider::Ollama,
+                || "OPENAI_API_KEY or LLM_API_KEY is required by this LLM_PROVIDER".to_string(),
+            )
+            .check(
+                !self.json_schema || self.llm_provider != LlmProvider::Anthropic,
+                || "JSON_SCHEMA is not supported by the anthropic LLM_PROVIDER".to_string(),
+            )
+            .check(
+                self.postprocessing_rules
+                    .as_deref()
+                    .is_none_or(|path| Path::new(path).is_file()),
+                || "POSTPROCESSING_RULES does not point to a file".to_string(),
+            )
+            .all_or_none(&[
+                ("PROMPT_TOKEN_PRICE", self.prompt_token_price.is_some()),
+                (
+                    "COMPLETION_TOKEN_PRICE",
+                    self.completion_token_price.is_some(),
+                ),
+            ])
+            .requires(
+                ("MAX_REQUEST_COST", self.max_request_cost.is_some()),
+                ("PROMPT_TOKEN_PRICE", self.prompt_token_price.is_some()),
+            )
+            .requires(
+                ("MAX_REQUEST_COST", self.max_request_cost.is_some()),
+                ("MAX_TOKENS", self.max_tokens.is_some()),
+            )
+            .requires(
+                ("DAILY_COST_BUDGET", self.daily_cost_budget.is_some()),
+                ("PROMPT_TOKEN_PRICE", self.prompt_token_price.is_some()),
+            )
+            .requires(
+                (
+                    "ABSTAIN_BELOW_CONFIDENCE",
+                    self.abstain_below_confidence.is_some(),
+                ),
+                ("STRUCTURED_OUTPUT", self.structured_output),
+            )
+            .requires(
+                ("JSON_SCHEMA", self.json_schema),
+                ("STRUCTURED_OUTPUT", self.structured_output),
+            )
+            .check(
+                self.abstain_below_confidence
+                    .is_none_or(|threshold| (0.0..=1.0).contains(&threshold)),
+                || "ABSTAIN_BELOW_CONFIDENCE must be between 0 and 1".to_string(),
+            )
+            .positive("LLM_MAX_CONCURRENT", self.llm_max_concurrent as u64)
+            .positive("LLM_MAX_ATTEMPTS", self.llm_max_attempts as u64)
+            .positive("MAX_TOKENS", self.max_tokens.unwrap_or(1))
+            .positive("MAX_REQUEST_TOKENS", self.max_request_tokens.unwrap_or(1))
+            .positive("DAILY_TOKEN_BUDGET", self.daily_token_budget.unwrap_or(1))
+            .finish()
+    }
+
+    fn cost_guard(&self) -> CostGuard {
+        CostGuard {
+            max_prompt_chars: self.max_prompt_chars,
+            max_completion_tokens: self.max_tokens,
+            max_tokens: self.max_request_tokens,
+            token_prices: self.token_prices(),
+            max_cost: self.max_request_cost,
+        }
+    }
+
+    fn daily_budget(&self) -> DailyBudget {
+        DailyBudget::new(
+            self.daily_token_budget,
+            self.daily_cost_budget,
+            self.token_prices(),
+        )
+    }
+
+    fn llm_backoff(&self) -> Backoff {
+        Backoff::new(
+            self.llm_max_attempts,
+            Duration::from_millis(self.llm_retry_delay_ms),
+        )
+    }
+
+    fn token_prices(&self) -> Option<(f64, f64)> {
+        self.prompt_token_price.zip(self.completion_token_price)
+    }
+
+    fn llm_client(&self) -> Box<dyn LlmClient> {
+        self.llm_provider.client(
+            &self.base_url,
+            self.openai_api_key.as_deref(),
+            &self.azure_api_version,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AppState {
+    config: Arc<Config>,
+    postprocessor: Arc<PostProcessor>,
+    usage: Arc<Usage>,
+    cost_guard: Arc<CostGuard>,
+    daily_budget: Arc<DailyBudget>,
+    llm: Arc<dyn LlmClient>,
+    llm_semaphore: Arc<Semaphore>,
 }
 
 #[derive(OpenApi)]
@@ -29,11 +246,21 @@ pub struct Config {
 struct ApiDoc;
 
 async fn run() -> Result<(), anyhow::Error> {
+    common::version::mark_started();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    let config = envy::from_env::<Config>()?;
+    let config = common::config::from_env::<Config>()?;
+    config.validate()?;
+    let metrics = common::metrics::install_recorder();
+    let postprocessor = match &config.postprocessing_rules {
+        Some(path) => PostProcessor::load(Path::new(path))?,
+        None => PostProcessor::default(),
+    };
 
     let (router, api) = OpenApiRouter::with_openapi(ApiDoc::openapi())
         .routes(routes!(routes::generate_feedback))
+        .routes(routes!(routes::version))
+        .routes(routes!(routes::ping))
+        .routes(routes!(routes::stats))
         .split_for_parts();
 
     info!("Starting on port {}", config.port);
@@ -42,7 +269,17 @@ async fn run() -> Result<(), anyhow::Error> {
         listener,
         router
             .merge(Redoc::with_url("/redoc", api))
-            .with_state(Arc::new(config)),
+            .merge(common::metrics::router(metrics))
+            .layer(middleware::from_fn(common::metrics::track_requests))
+            .with_state(AppState {
+                cost_guard: Arc::new(config.cost_guard()),
+                daily_budget: Arc::new(config.daily_budget()),
+                llm: config.llm_client().into(),
+                llm_semaphore: Arc::new(Semaphore::new(config.llm_max_concurrent)),
+                config: Arc::new(config),
+                postprocessor: Arc::new(postprocessor),
+                usage: Default::default(),
+            }),
     )
     .await?;
 
@@ -0,0 +1,108 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static SCRIPT_BLOCK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<(script|style)\b.*?</(script|style)\s*>").unwrap());
+static HTML_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"</?[A-Za-z][A-Za-z0-9-]*(\s[^<>]*)?/?>|<!--(?s:.*?)-->").unwrap()
+});
+static AUTOLINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<[A-Za-z][A-Za-z0-9+.-]*:[^<>\s]*>").unwrap());
+// destinations may contain balanced parentheses, e.g. `javascript:alert(1)`
+static IMAGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"!\[([^\]]*)\]\((?:[^()]|\([^()]*\))*\)").unwrap());
+static LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]*)\]\((?:[^()]|\([^()]*\))*\)").unwrap());
+static REFERENCE_DEFINITION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^\s{0,3}\[[^\]]+\]:\s+\S+.*$").unwrap());
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReplacementRule {
+    pattern: String,
+    replacement: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RequiredSection {
+    /// Label the section starts with, e.g. `Next step:`
+    label: String,
+    /// Text used when the llm did not produce the section
+    default: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PostProcessingRules {
+    #[serde(default)]
+    replacements: Vec<ReplacementRule>,
+    max_length: Option<usize>,
+    #[serde(default)]
+    required_sections: Vec<RequiredSection>,
+}
+
+/// Rules applied to the llm output before it is returned, loaded from a JSON file.
+#[derive(Debug, Default)]
+pub struct PostProcessor {
+    replacements: Vec<(Regex, String)>,
+    max_length: Option<usize>,
+    required_sections: Vec<RequiredSection>,
+}
+
+impl PostProcessor {
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let rules: PostProcessingRules = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(PostProcessor {
+            replacements: rules
+                .replacements
+                .into_iter()
+                .map(|rule| Ok((Regex::new(&rule.pattern)?, rule.replacement)))
+                .collect::<Result<_, regex::Error>>()?,
+            max_length: rules.max_length,
+            required_sections: rules.required_sections,
+        })
+    }
+
+    pub fn apply(&self, feedback: &str) -> String {
+        let mut feedback = self.clean(feedback);
+        if let Some(max_length) = self.max_length
+            && feedback.chars().count() > max_length
+        {
+            feedback = feedback.chars().take(max_length).collect::<String>();
+            feedback.push('…');
+        }
+        for section in &self.required_sections {
+            if !feedback.contains(&section.label) {
+                feedback.push_str(&format!("\n\n{} {}", section.label, section.default));
+            }
+        }
+        feedback
+    }
+
+    /// Sanitises a hint and applies the replacements, the length and sections only concern
+    /// the feedback.
+    pub fn apply_hint(&self, hint: &str) -> String {
+        self.clean(hint)
+    }
+
+    fn clean(&self, text: &str) -> String {
+        let mut text = sanitise_markdown(text).trim().to_string();
+        for (pattern, replacement) in &self.replacements {
+            text = pattern
+                .replace_all(&text, replacement.as_str())
+                .into_owned();
+        }
+        text
+    }
+}
+
+/// Reduces llm output to a safe markdown subset: HTML, scripts, images and links are removed,
+/// keeping the alt and link texts, as the feedback is rendered directly by the LMS.
+pub fn sanitise_markdown(feedback: &str) -> String {
+    let feedback = SCRIPT_BLOCK.replace_all(feedback, "");
+    let feedback = AUTOLINK.replace_all(&feedback, "");
+    let feedback = HTML_TAG.replace_all(&feedback, "");
+    let feedback = IMAGE.replace_all(&feedback, "$1");
+    let feedback = LINK.replace_all(&feedback, "$1");
+    REFERENCE_DEFINITION.replace_all(&feedback, "").into_owned()
+}
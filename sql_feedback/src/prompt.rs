@@ -0,0 +1,66 @@
+use askama::Template;
+use common::models::{Results, SqlResult};
+use common::render::{RenderOptions, TableFormat, render_result_set};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+#[derive(Template)]
+#[template(path = "prompt.txt")]
+struct PromptTemplate<'a> {
+    request: &'a FeedbackRequest,
+    solution_result: Option<String>,
+    submission_result: Option<String>,
+    structured: bool,
+    /// Every submission is shown and gets its own feedback
+    per_submission: bool,
+    submission_results: Vec<Option<String>>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct FeedbackRequest {
+    pub sql_environment: String,
+    pub db_schema: String,
+    pub task: String,
+    pub solutions: Vec<String>,
+    pub submissions: Vec<String>,
+    pub solution_results: Option<Results>,
+    pub submission_results: Option<Results>,
+    /// Include the rendered prompt in the response, requires the `X-Admin-Token` header
+    #[serde(default)]
+    pub include_prompt: bool,
+    /// Only render the prompt and return it without asking the llm, requires the
+    /// `X-Admin-Token` header
+    #[serde(default)]
+    pub render_only: bool,
+    /// Overrides the configured model
+    pub model: Option<String>,
+}
+
+/// Prompt sent to the llm for the request. If `structured`, it asks for a JSON answer with
+/// the correctness and the confidence of the llm for every submission.
+pub fn render_prompt(request: &FeedbackRequest, structured: bool) -> askama::Result<String> {
+    PromptTemplate {
+        request,
+        solution_result: nth_result(&request.solution_results, 0),
+        submission_result: nth_result(&request.submission_results, 0),
+        structured,
+        per_submission: structured && request.submissions.len() > 1,
+        submission_results: (0..request.submissions.len())
+            .map(|index| nth_result(&request.submission_results, index))
+            .collect(),
+    }
+    .render()
+}
+
+/// Result of the query at the index rendered as a markdown table.
+fn nth_result(results: &Option<Results>, index: usize) -> Option<String> {
+    match results.as_ref()?.get(index)?.as_ref()? {
+        SqlResult::Ok(result_set) => Some(render_result_set(
+            result_set,
+            TableFormat::Markdown,
+            &RenderOptions::default(),
+        )),
+        SqlResult::Error(error) => Some(error.clone()),
+    }
+}
@@ -1,64 +1,313 @@
-use crate::Config;
-use askama::Template;
+use crate::cost::{BudgetExhausted, CostRejection};
+use crate::llm::{AnswerFormat, Completion};
+use crate::prompt::{FeedbackRequest, render_prompt};
+use crate::structured::{StructuredFeedback, parse_feedback, parse_results};
+use crate::{API_VERSION, AppState, Config, ENABLED_FEATURES};
 use axum::Json;
 use axum::extract::State;
-use axum::http::StatusCode;
-use common::models::Results;
-use log::error;
-use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::sync::Arc;
+use axum::http::{HeaderMap, StatusCode};
+use common::retry::is_transient_status;
+use common::stats::FeedbackStats;
+use common::version::{PingResponse, VersionInfo};
+use futures::future::try_join_all;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::ops::Add;
+use std::time::SystemTime;
 use utoipa::ToSchema;
 
-#[derive(Template)]
-#[template(path = "prompt.txt")]
-struct PromptTemplate<'a> {
-    request: &'a FeedbackRequest,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Clone, Deserialize, ToSchema)]
-pub struct FeedbackRequest {
-    pub sql_environment: String,
-    pub db_schema: String,
-    pub task: String,
-    pub solutions: Vec<String>,
-    pub submissions: Vec<String>,
-    pub solution_results: Option<Results>,
-    pub submission_results: Option<Results>,
-}
-
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct FeedbackResponse {
     pub correct: bool,
     pub feedback: String,
+    /// Hints on what to do next, if `STRUCTURED_OUTPUT` is enabled
+    pub hints: Vec<String>,
+    /// Confidence of the llm between 0 and 1, if `STRUCTURED_OUTPUT` is enabled and the llm
+    /// reported it
+    pub confidence: Option<f64>,
+    /// The llm abstained or was not confident enough, an instructor should review the
+    /// submission instead of relying on the feedback
+    pub needs_review: bool,
+    pub prompt: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct FeedbackErrorResponse {
     pub code: u16,
     pub message: &'static str,
+    /// Limit and estimate if the request was rejected for its size or cost
+    pub rejected: Option<CostRejection>,
+    /// Spent and remaining budget if the daily budget does not cover the request
+    pub budget: Option<BudgetExhausted>,
 }
 
-#[utoipa::path(post, path = "/api/v1/feedback", request_body = FeedbackRequest, responses((status = OK, body = FeedbackResponse), (status = UNPROCESSABLE_ENTITY), (status = INTERNAL_SERVER_ERROR)), description = "Gets feedback")]
+type FeedbackErrorResult = (StatusCode, Json<FeedbackErrorResponse>);
+
+#[utoipa::path(post, path = "/api/v1/feedback", request_body = FeedbackRequest, responses((status = OK, body = FeedbackResponse), (status = FORBIDDEN), (status = UNPROCESSABLE_ENTITY, body = FeedbackErrorResponse), (status = TOO_MANY_REQUESTS, body = FeedbackErrorResponse), (status = INTERNAL_SERVER_ERROR)), description = "Gets feedback")]
 #[axum::debug_handler]
 pub async fn generate_feedback(
-    config: State<Arc<Config>>,
+    state: State<AppState>,
+    headers: HeaderMap,
     body: Json<FeedbackRequest>,
-) -> Result<Json<Vec<FeedbackResponse>>, (StatusCode, Json<FeedbackErrorResponse>)> {
-    let prompt = PromptTemplate { request: &body.0 }.render().unwrap();
-
-    let response = reqwest::Client::new()
-        .post(format!("{}/chat/completions", config.base_url))
-        .bearer_auth(&config.openai_api_key)
-        .json(&json!({
-            "model": config.model,
-            "messages": vec![json!({"role": "user", "content": prompt})],
-            "temperature": 0,
+) -> Result<Json<Vec<FeedbackResponse>>, FeedbackErrorResult> {
+    let result = feedback(&state, headers, body).await;
+    state.usage.record_feedback(
+        result
+            .as_ref()
+            .is_err_and(|(status, _)| status.is_server_error()),
+    );
+    result
+}
+
+async fn feedback(
+    state: &AppState,
+    headers: HeaderMap,
+    body: Json<FeedbackRequest>,
+) -> Result<Json<Vec<FeedbackResponse>>, FeedbackErrorResult> {
+    let config = &state.config;
+    if (body.include_prompt || body.render_only) && !is_admin(config, &headers) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(FeedbackErrorResponse {
+                code: 403,
+                message: "a valid admin token is required to include the prompt",
+                rejected: None,
+                budget: None,
+            }),
+        ));
+    }
+
+    let include_prompt = body.include_prompt;
+    // structured answers hold a verdict per submission, plain ones need a prompt each
+    let prompts = if config.structured_output {
+        vec![render_prompt(&body, true).unwrap()]
+    } else {
+        (0..body.submissions.len())
+            .map(|index| render_prompt(&submission_request(&body, index), false).unwrap())
+            .collect()
+    };
+    if body.render_only {
+        return Ok(Json(
+            prompts
+                .into_iter()
+                .map(|prompt| FeedbackResponse {
+                    correct: false,
+                    feedback: String::new(),
+                    hints: vec![],
+                    confidence: None,
+                    needs_review: false,
+                    prompt: Some(prompt),
+                })
+                .collect(),
+        ));
+    }
+
+    let mut estimates = vec![];
+    for prompt in &prompts {
+        let estimate = match state.cost_guard.check(prompt) {
+            Ok(estimate) => estimate,
+            Err(rejection) => {
+                warn!("rejected feedback request before calling the llm: {rejection:?}");
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(FeedbackErrorResponse {
+                        code: 422,
+                        message: "the prompt exceeds the configured size or cost limits",
+                        rejected: Some(rejection),
+                        budget: None,
+                    }),
+                ));
+            }
+        };
+        debug!("estimated llm request: {estimate:?}");
+        estimates.push(estimate);
+    }
+    let estimate = estimates.into_iter().reduce(Add::add);
+    if let Some(Err(exhausted)) =
+        estimate.map(|estimate| state.daily_budget.check(estimate, SystemTime::now()))
+    {
+        warn!("rejected feedback request, the daily budget is exhausted: {exhausted:?}");
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(FeedbackErrorResponse {
+                code: 429,
+                message: "the daily llm budget is exhausted",
+                rejected: None,
+                budget: Some(exhausted),
+            }),
+        ));
+    }
+
+    let model = body.model.as_deref().unwrap_or(&config.model);
+    let results = if config.structured_output {
+        structured_feedback(state, model, &prompts[0], body.submissions.len()).await?
+    } else {
+        try_join_all(prompts.iter().map(|prompt| async move {
+            let messages = [json!({"role": "user", "content": prompt})];
+            complete(state, model, &messages)
+                .await
+                .map(|answer| StructuredFeedback::plain(&answer))
         }))
-        .send()
-        .await
-        .and_then(|response| response.error_for_status());
+        .await?
+    };
+    let needs_review = results.iter().filter(|result| result.needs_review).count();
+    if needs_review > 0 {
+        info!(
+            "llm abstained or was not confident enough for {needs_review} of {} submissions",
+            results.len()
+        );
+    }
+
+    Ok(Json(
+        results
+            .into_iter()
+            .enumerate()
+            .map(|(index, feedback)| FeedbackResponse {
+                correct: feedback.correct,
+                feedback: state.postprocessor.apply(&feedback.feedback),
+                hints: feedback
+                    .hints
+                    .iter()
+                    .map(|hint| state.postprocessor.apply_hint(hint))
+                    .filter(|hint| !hint.is_empty())
+                    .collect(),
+                confidence: feedback.confidence,
+                needs_review: feedback.needs_review,
+                prompt: include_prompt.then(|| prompts.get(index).unwrap_or(&prompts[0]).clone()),
+            })
+            .collect(),
+    ))
+}
+
+/// The request reduced to the submission at the index and its result.
+fn submission_request(request: &FeedbackRequest, index: usize) -> FeedbackRequest {
+    FeedbackRequest {
+        submissions: vec![request.submissions[index].clone()],
+        submission_results: request
+            .submission_results
+            .as_ref()
+            .map(|results| vec![results.get(index).cloned().flatten()]),
+        ..request.clone()
+    }
+}
+
+/// Asks for a structured answer with a verdict per submission, malformed answers are sent
+/// back for correction up to `STRUCTURED_OUTPUT_RETRIES` times if the budget covers it.
+async fn structured_feedback(
+    state: &AppState,
+    model: &str,
+    prompt: &str,
+    submissions: usize,
+) -> Result<Vec<StructuredFeedback>, FeedbackErrorResult> {
+    let config = &state.config;
+    let mut messages = vec![json!({"role": "user", "content": prompt})];
+    let mut answer = complete(state, model, &messages).await?;
+    let mut retries = config.structured_output_retries;
+    let plain = |answer: &str| {
+        let mut feedback = parse_feedback(answer, config.abstain_below_confidence);
+        feedback.needs_review = true;
+        vec![feedback; submissions]
+    };
+    loop {
+        match parse_results(&answer, submissions, config.abstain_below_confidence) {
+            Ok(results) => return Ok(results),
+            Err(problem) if retries > 0 => {
+                retries -= 1;
+                messages.push(json!({"role": "assistant", "content": answer}));
+                messages.push(json!({
+                    "role": "user",
+                    "content": format!(
+                        "Your answer is invalid: {problem}. Return only the JSON object described above."
+                    ),
+                }));
+                if !affordable(state, &messages) {
+                    warn!(
+                        "llm answer is malformed and a correction exceeds the limits or the daily budget, using it as plain feedback: {problem}"
+                    );
+                    return Ok(plain(&answer));
+                }
+                warn!("llm answer is malformed, asking for a correction: {problem}");
+                answer = complete(state, model, &messages).await?;
+            }
+            Err(problem) => {
+                warn!("llm answer is malformed, using it as plain feedback: {problem}");
+                return Ok(plain(&answer));
+            }
+        }
+    }
+}
+
+/// Whether the request limits and the rest of the daily budget cover sending the conversation,
+/// which grows with every correction beyond the estimate the request was admitted with.
+fn affordable(state: &AppState, messages: &[Value]) -> bool {
+    let conversation = messages
+        .iter()
+        .filter_map(|message| message["content"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    state.cost_guard.check(&conversation).is_ok_and(|estimate| {
+        state
+            .daily_budget
+            .check(estimate, SystemTime::now())
+            .is_ok()
+    })
+}
+
+/// Sends the conversation to the llm and returns the content of its answer.
+async fn complete(
+    state: &AppState,
+    model: &str,
+    messages: &[Value],
+) -> Result<String, FeedbackErrorResult> {
+    let config = &state.config;
+    #[cfg(feature = "fault-injection")]
+    if let Err(e) = common::fault::inject("llm").await {
+        error!("error while sending llm request: {e}");
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(FeedbackErrorResponse {
+                code: 500,
+                message: "an error occurred while sending llm request",
+                rejected: None,
+                budget: None,
+            }),
+        ));
+    }
+
+    let completion = completion_request(config, model, messages);
+    let response = config
+        .llm_backoff()
+        .retry(
+            || async {
+                let _permit = state
+                    .llm_semaphore
+                    .acquire()
+                    .await
+                    .expect("llm semaphore is never closed");
+                state.usage.llm_request_started();
+                let response = state
+                    .llm
+                    .request(&completion)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                state.usage.llm_request_finished();
+                response
+            },
+            |e: &reqwest::Error| {
+                let transient = e
+                    .status()
+                    .map_or(e.is_connect() || e.is_timeout(), |status| {
+                        is_transient_status(status.as_u16())
+                    });
+                if transient {
+                    warn!("transient error while sending llm request, retrying: {e}");
+                }
+                transient
+            },
+        )
+        .await;
 
     let response = match response {
         Ok(response) => response,
@@ -69,12 +318,14 @@ pub async fn generate_feedback(
                 Json(FeedbackErrorResponse {
                     code: 500,
                     message: "an error occurred while sending llm request",
+                    rejected: None,
+                    budget: None,
                 }),
             ));
         }
     };
 
-    let body = match response.json::<serde_json::Value>().await {
+    let body = match response.json::<Value>().await {
         Ok(body) => body,
         Err(e) => {
             error!("error while parsing llm response: {e}");
@@ -83,28 +334,103 @@ pub async fn generate_feedback(
                 Json(FeedbackErrorResponse {
                     code: 500,
                     message: "an error occurred while parsing the llm response",
+                    rejected: None,
+                    budget: None,
                 }),
             ));
         }
     };
-    let message = body["choices"][0]["message"]["content"].as_str();
-
-    let message = match message {
-        Some(message) => message,
+    match state.llm.answer(&body) {
+        Some(answer) => {
+            state
+                .usage
+                .record_tokens(answer.prompt_tokens, answer.completion_tokens);
+            state.daily_budget.record(
+                answer.prompt_tokens,
+                answer.completion_tokens,
+                SystemTime::now(),
+            );
+            Ok(answer.content)
+        }
         None => {
-            error!("error while processing llm response: choices[0].message.content not found");
-            return Err((
+            error!("error while processing llm response: answer content not found");
+            Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(FeedbackErrorResponse {
                     code: 500,
                     message: "an error occurred while processing the llm response",
+                    rejected: None,
+                    budget: None,
                 }),
-            ));
+            ))
         }
-    };
+    }
+}
+
+fn completion_request<'a>(
+    config: &Config,
+    model: &'a str,
+    messages: &'a [Value],
+) -> Completion<'a> {
+    Completion {
+        model,
+        messages,
+        max_tokens: config.max_tokens,
+        format: if config.json_schema {
+            AnswerFormat::JsonSchema(answer_schema())
+        } else if config.structured_output {
+            AnswerFormat::JsonObject
+        } else {
+            AnswerFormat::Text
+        },
+    }
+}
+
+/// Schema of a structured answer, strict schemas require every field.
+fn answer_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "results": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "correct": {"type": "boolean"},
+                        "feedback": {"type": "string"},
+                        "hints": {"type": "array", "items": {"type": "string"}},
+                        "confidence": {"type": ["number", "null"]},
+                        "needs_review": {"type": "boolean"},
+                    },
+                    "required": ["correct", "feedback", "hints", "confidence", "needs_review"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+        "required": ["results"],
+        "additionalProperties": false,
+    })
+}
+
+fn is_admin(config: &Config, headers: &HeaderMap) -> bool {
+    let token = headers.get("X-Admin-Token").and_then(|h| h.to_str().ok());
+    matches!((&config.admin_token, token), (Some(expected), Some(token)) if expected == token)
+}
+
+#[utoipa::path(get, path = "/api/v1/stats", responses((status = OK, body = FeedbackStats)), description = "Get llm usage and error statistics")]
+pub async fn stats(state: State<AppState>) -> Json<FeedbackStats> {
+    Json(state.usage.stats())
+}
+
+#[utoipa::path(get, path = "/api/v1/version", responses((status = OK, body = VersionInfo)), description = "Get version and build information")]
+pub async fn version() -> Json<VersionInfo> {
+    Json(common::version_info!(API_VERSION, ENABLED_FEATURES))
+}
 
-    Ok(Json(vec![FeedbackResponse {
-        correct: false,
-        feedback: message.to_string(),
-    }]))
+#[utoipa::path(get, path = "/ping", responses((status = OK, body = PingResponse)), description = "Lightweight liveness check without touching any dependency")]
+pub async fn ping() -> Json<PingResponse> {
+    Json(PingResponse {
+        version: common::version_info!(API_VERSION, ENABLED_FEATURES),
+        uptime_secs: common::version::uptime().as_secs(),
+    })
 }
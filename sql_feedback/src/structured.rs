@@ -0,0 +1,101 @@
+use serde::Deserialize;
+
+/// Feedback of a llm asked to answer with a JSON object, see `STRUCTURED_OUTPUT`.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct StructuredFeedback {
+    /// Whether the submission solves the task according to the llm
+    #[serde(default)]
+    pub correct: bool,
+    pub feedback: String,
+    /// Short hints on what to do next
+    #[serde(default)]
+    pub hints: Vec<String>,
+    /// How certain the llm is that the feedback is correct, between 0 and 1
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    /// The llm abstained, e.g. because the task is ambiguous, and a human should review the
+    /// submission
+    #[serde(default)]
+    pub needs_review: bool,
+}
+
+/// Answer with one feedback per submission, in the order of the submissions.
+#[derive(Debug, Clone, Deserialize)]
+struct StructuredAnswer {
+    results: Vec<StructuredFeedback>,
+}
+
+impl StructuredFeedback {
+    /// Feedback of an answer which is not structured.
+    pub fn plain(feedback: &str) -> Self {
+        StructuredFeedback {
+            correct: false,
+            feedback: feedback.to_string(),
+            hints: vec![],
+            confidence: None,
+            needs_review: false,
+        }
+    }
+
+    /// Clamps the confidence, answers less confident than `abstain_below` need review.
+    fn normalise(mut self, abstain_below: Option<f64>) -> Self {
+        self.confidence = self
+            .confidence
+            .filter(|confidence| confidence.is_finite())
+            .map(|confidence| confidence.clamp(0.0, 1.0));
+        if let (Some(confidence), Some(threshold)) = (self.confidence, abstain_below) {
+            self.needs_review |= confidence < threshold;
+        }
+        self
+    }
+}
+
+/// The answer without a surrounding markdown code block.
+fn strip_code_block(answer: &str) -> &str {
+    answer
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+}
+
+/// Parses the answer of the llm, which may be wrapped in a markdown code block. An answer
+/// which is not a JSON object is taken as plain feedback without confidence. Answers less
+/// confident than `abstain_below` need review.
+pub fn parse_feedback(answer: &str, abstain_below: Option<f64>) -> StructuredFeedback {
+    serde_json::from_str::<StructuredFeedback>(strip_code_block(answer))
+        .unwrap_or_else(|_| StructuredFeedback::plain(answer))
+        .normalise(abstain_below)
+}
+
+/// Parses an answer with a `results` array holding one feedback per submission, a single
+/// feedback object is accepted for one submission. The error describes what is wrong with
+/// the answer, so the llm can be asked to correct it.
+pub fn parse_results(
+    answer: &str,
+    submissions: usize,
+    abstain_below: Option<f64>,
+) -> Result<Vec<StructuredFeedback>, String> {
+    let json = strip_code_block(answer);
+    let results = match serde_json::from_str::<StructuredAnswer>(json) {
+        Ok(answer) => answer.results,
+        Err(err) => match serde_json::from_str::<StructuredFeedback>(json) {
+            Ok(feedback) if submissions == 1 => vec![feedback],
+            _ => {
+                return Err(format!(
+                    "the answer is not a JSON object with a \"results\" array of feedback objects ({err})"
+                ));
+            }
+        },
+    };
+    if results.len() != submissions {
+        return Err(format!(
+            "expected {submissions} results, one per query, got {}",
+            results.len()
+        ));
+    }
+    Ok(results
+        .into_iter()
+        .map(|feedback| feedback.normalise(abstain_below))
+        .collect())
+}
@@ -0,0 +1,57 @@
+use common::stats::{ErrorRate, FeedbackStats};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Llm usage and feedback errors since the start of the service.
+#[derive(Debug, Default)]
+pub struct Usage {
+    llm_in_flight: AtomicUsize,
+    llm_requests: AtomicU64,
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+    recent_errors: ErrorRate,
+}
+
+impl Usage {
+    pub fn llm_request_started(&self) {
+        self.llm_in_flight.fetch_add(1, Ordering::Relaxed);
+        self.llm_requests.fetch_add(1, Ordering::Relaxed);
+        metrics::counter!("sql_feedback_llm_requests_total").increment(1);
+        metrics::gauge!("sql_feedback_llm_in_flight").increment(1);
+    }
+
+    pub fn llm_request_finished(&self) {
+        self.llm_in_flight.fetch_sub(1, Ordering::Relaxed);
+        metrics::gauge!("sql_feedback_llm_in_flight").decrement(1);
+    }
+
+    /// Adds the tokens of an llm answer.
+    pub fn record_tokens(&self, prompt_tokens: u64, completion_tokens: u64) {
+        self.prompt_tokens
+            .fetch_add(prompt_tokens, Ordering::Relaxed);
+        self.completion_tokens
+            .fetch_add(completion_tokens, Ordering::Relaxed);
+        metrics::counter!("sql_feedback_llm_tokens_total", "kind" => "prompt")
+            .increment(prompt_tokens);
+        metrics::counter!("sql_feedback_llm_tokens_total", "kind" => "completion")
+            .increment(completion_tokens);
+    }
+
+    pub fn record_feedback(&self, error: bool) {
+        self.recent_errors.record(error);
+        metrics::counter!(
+            "sql_feedback_feedback_total",
+            "outcome" => if error { "error" } else { "ok" }
+        )
+        .increment(1);
+    }
+
+    pub fn stats(&self) -> FeedbackStats {
+        FeedbackStats {
+            llm_in_flight: self.llm_in_flight.load(Ordering::Relaxed),
+            llm_requests: self.llm_requests.load(Ordering::Relaxed),
+            prompt_tokens: self.prompt_tokens.load(Ordering::Relaxed),
+            completion_tokens: self.completion_tokens.load(Ordering::Relaxed),
+            recent: self.recent_errors.recent(),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+#[path = "../src/cost.rs"]
+mod cost;
+
+use cost::{
+    BudgetExhausted, BudgetLimit, CostEstimate, CostGuard, CostLimit, CostRejection, DailyBudget,
+};
+use std::time::{Duration, UNIX_EPOCH};
+
+fn guard() -> CostGuard {
+    CostGuard {
+        max_prompt_chars: Some(100),
+        max_completion_tokens: Some(500),
+        max_tokens: None,
+        token_prices: Some((2.0, 8.0)),
+        max_cost: Some(0.005),
+    }
+}
+
+#[test]
+fn estimates_tokens_and_worst_case_cost() {
+    assert_eq!(
+        guard().estimate(&"x".repeat(10)),
+        CostEstimate {
+            prompt_chars: 10,
+            prompt_tokens: 3,
+            max_completion_tokens: Some(500),
+            cost: Some((3.0 * 2.0 + 500.0 * 8.0) / 1_000_000.0),
+        }
+    );
+}
+
+#[test]
+fn prompts_within_the_limits_pass() {
+    assert!(guard().check("SELECT * FROM t").is_ok());
+    assert!(CostGuard::default().check(&"x".repeat(100_000)).is_ok());
+}
+
+#[test]
+fn oversized_prompts_are_rejected_before_their_cost() {
+    let rejection = guard().check(&"x".repeat(101)).unwrap_err();
+    assert_eq!(rejection.limit, CostLimit::PromptSize);
+    assert_eq!(rejection.estimate.prompt_chars, 101);
+}
+
+#[test]
+fn expensive_requests_are_rejected() {
+    let guard = CostGuard {
+        max_prompt_chars: None,
+        max_cost: Some(0.01),
+        ..guard()
+    };
+    let prompt = "x".repeat(20_000);
+    let CostRejection { limit, estimate } = guard.check(&prompt).unwrap_err();
+    assert_eq!(limit, CostLimit::Cost);
+    assert_eq!(estimate.prompt_tokens, 5_000);
+    assert!(estimate.cost.unwrap() > 0.01);
+}
+
+#[test]
+fn requests_with_too_many_tokens_are_rejected() {
+    let guard = CostGuard {
+        max_prompt_chars: None,
+        max_tokens: Some(600),
+        max_cost: None,
+        ..guard()
+    };
+    assert!(guard.check(&"x".repeat(100)).is_ok());
+    let CostRejection { limit, estimate } = guard.check(&"x".repeat(1_000)).unwrap_err();
+    assert_eq!(limit, CostLimit::Tokens);
+    assert_eq!(estimate.max_tokens(), 750);
+}
+
+#[test]
+fn estimates_of_several_prompts_add_up() {
+    let estimate = guard().estimate("xxxx") + guard().estimate("xxxxxxxx");
+    assert_eq!(estimate.prompt_tokens, 3);
+    assert_eq!(estimate.max_completion_tokens, Some(1_000));
+    assert_eq!(estimate.max_tokens(), 1_003);
+}
+
+#[test]
+fn daily_budget_is_charged_and_reset_at_midnight() {
+    let budget = DailyBudget::new(Some(1_000), None, None);
+    let morning = UNIX_EPOCH + Duration::from_secs(10 * 86_400 + 8 * 3_600);
+    let estimate = guard().estimate("xxxx");
+    assert!(budget.check(estimate, morning).is_ok());
+    budget.record(400, 200, morning);
+    assert_eq!(
+        budget.check(estimate, morning).unwrap_err(),
+        BudgetExhausted {
+            limit: BudgetLimit::Tokens,
+            spent: 600.0,
+            budget: 1_000.0,
+            estimate,
+            retry_after_secs: 16 * 3_600,
+        }
+    );
+    assert!(
+        budget
+            .check(estimate, morning + Duration::from_secs(16 * 3_600))
+            .is_ok()
+    );
+}
+
+#[test]
+fn daily_cost_budget_is_charged_with_the_token_prices() {
+    let budget = DailyBudget::new(None, Some(0.011), Some((2.0, 8.0)));
+    let now = UNIX_EPOCH + Duration::from_secs(86_400);
+    let estimate = guard().estimate("xxxx");
+    budget.record(1_000, 500, now);
+    assert!(budget.check(estimate, now).is_ok());
+    budget.record(1_000, 500, now);
+    let exhausted = budget.check(estimate, now).unwrap_err();
+    assert_eq!(exhausted.limit, BudgetLimit::Cost);
+    assert!((exhausted.spent - 0.012).abs() < 1e-9);
+}
@@ -0,0 +1,140 @@
+#[path = "../src/llm.rs"]
+mod llm;
+
+use llm::{Answer, AnswerFormat, Completion, LlmClient, LlmProvider};
+use serde_json::{Value, json};
+
+fn completion(messages: &[Value], format: AnswerFormat) -> Completion<'_> {
+    Completion {
+        model: "model",
+        messages,
+        max_tokens: Some(100),
+        format,
+    }
+}
+
+fn request(client: &dyn LlmClient, completion: &Completion) -> reqwest::Request {
+    client.request(completion).build().unwrap()
+}
+
+fn body(request: &reqwest::Request) -> Value {
+    serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap()
+}
+
+fn header<'a>(request: &'a reqwest::Request, name: &str) -> &'a str {
+    request.headers()[name].to_str().unwrap()
+}
+
+#[test]
+fn openai_requests_chat_completions() {
+    let messages = [json!({"role": "user", "content": "prompt"})];
+    let client = LlmProvider::OpenAi.client("http://llm/v1/", Some("key"), "");
+    let request = request(&*client, &completion(&messages, AnswerFormat::JsonObject));
+    assert_eq!(request.url().as_str(), "http://llm/v1/chat/completions");
+    assert_eq!(header(&request, "authorization"), "Bearer key");
+    assert_eq!(
+        body(&request),
+        json!({
+            "model": "model",
+            "messages": messages,
+            "temperature": 0,
+            "max_tokens": 100,
+            "response_format": {"type": "json_object"},
+        })
+    );
+    assert_eq!(
+        client.answer(&json!({
+            "choices": [{"message": {"content": "feedback"}}],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 5},
+        })),
+        Some(Answer {
+            content: "feedback".to_string(),
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        })
+    );
+    assert_eq!(client.answer(&json!({"choices": []})), None);
+}
+
+#[test]
+fn azure_openai_requests_the_deployment() {
+    let messages = [json!({"role": "user", "content": "prompt"})];
+    let client = LlmProvider::AzureOpenAi.client("https://azure", Some("key"), "2024-10-21");
+    let schema = json!({"type": "object"});
+    let request = request(
+        &*client,
+        &completion(&messages, AnswerFormat::JsonSchema(schema.clone())),
+    );
+    assert_eq!(
+        request.url().as_str(),
+        "https://azure/openai/deployments/model/chat/completions?api-version=2024-10-21"
+    );
+    assert_eq!(header(&request, "api-key"), "key");
+    assert_eq!(
+        body(&request)["response_format"]["json_schema"]["schema"],
+        schema
+    );
+}
+
+#[test]
+fn ollama_requests_native_chat() {
+    let messages = [json!({"role": "user", "content": "prompt"})];
+    let client = LlmProvider::Ollama.client("http://ollama:11434", None, "");
+    let request = request(&*client, &completion(&messages, AnswerFormat::JsonObject));
+    assert_eq!(request.url().as_str(), "http://ollama:11434/api/chat");
+    assert!(request.headers().get("authorization").is_none());
+    assert_eq!(
+        body(&request),
+        json!({
+            "model": "model",
+            "messages": messages,
+            "stream": false,
+            "options": {"temperature": 0, "num_predict": 100},
+            "format": "json",
+        })
+    );
+    assert_eq!(
+        client.answer(&json!({
+            "message": {"role": "assistant", "content": "feedback"},
+            "prompt_eval_count": 10,
+            "eval_count": 5,
+        })),
+        Some(Answer {
+            content: "feedback".to_string(),
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        })
+    );
+}
+
+#[test]
+fn anthropic_requests_messages() {
+    let messages = [json!({"role": "user", "content": "prompt"})];
+    let client = LlmProvider::Anthropic.client("https://api.anthropic.com/v1", Some("key"), "");
+    let request = request(
+        &*client,
+        &Completion {
+            max_tokens: None,
+            ..completion(&messages, AnswerFormat::Text)
+        },
+    );
+    assert_eq!(
+        request.url().as_str(),
+        "https://api.anthropic.com/v1/messages"
+    );
+    assert_eq!(header(&request, "x-api-key"), "key");
+    assert_eq!(header(&request, "anthropic-version"), "2023-06-01");
+    assert_eq!(body(&request)["max_tokens"], 4096);
+    assert_eq!(
+        client.answer(&json!({
+            "content": [{"type": "text", "text": "feed"}, {"type": "text", "text": "back"}],
+            "usage": {"input_tokens": 10, "output_tokens": 5},
+        })),
+        Some(Answer {
+            content: "feedback".to_string(),
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        })
+    );
+    assert_eq!(client.answer(&json!({"content": []})), None);
+}
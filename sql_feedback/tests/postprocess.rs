@@ -0,0 +1,44 @@
+#[path = "../src/postprocess.rs"]
+mod postprocess;
+
+use postprocess::{PostProcessor, sanitise_markdown};
+
+fn postprocessor(rules: &str) -> PostProcessor {
+    let path = std::env::temp_dir().join(format!("postprocess-{}.json", std::process::id()));
+    std::fs::write(&path, rules).unwrap();
+    let postprocessor = PostProcessor::load(&path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    postprocessor
+}
+
+#[test]
+fn html_images_and_links_are_removed() {
+    assert_eq!(
+        sanitise_markdown(
+            "Use <b>JOIN</b><script>alert(1)</script> ![x](http://x/p.png) [docs](javascript:alert(1))"
+        ),
+        "Use JOIN x docs"
+    );
+}
+
+#[test]
+fn hints_are_sanitised_without_feedback_rules() {
+    let postprocessor = postprocessor(
+        r#"{
+            "replacements": [{"pattern": "SELECT \\*", "replacement": "SELECT with columns"}],
+            "max_length": 10,
+            "required_sections": [{"label": "Next step:", "default": "Try again."}]
+        }"#,
+    );
+    assert_eq!(
+        postprocessor.apply_hint(
+            " Avoid SELECT * <script>fetch('//evil')</script>[here](javascript:alert(1)) "
+        ),
+        "Avoid SELECT with columns here"
+    );
+    assert_eq!(postprocessor.apply_hint("<img src=x onerror=alert(1)>"), "");
+    assert_eq!(
+        postprocessor.apply("Avoid SELECT * please"),
+        "Avoid SELE…\n\nNext step: Try again."
+    );
+}
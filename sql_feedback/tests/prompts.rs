@@ -0,0 +1,48 @@
+//! Renders the prompt for every request in `tests/prompts/*.json` and compares it with the
+//! golden file next to it. Requests whose file name starts with `structured` are rendered with
+//! `STRUCTURED_OUTPUT`. Run with `UPDATE_SNAPSHOTS=1` to accept intended prompt changes.
+
+#[path = "../src/prompt.rs"]
+mod prompt;
+
+use prompt::{FeedbackRequest, render_prompt};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn prompts_match_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/prompts");
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut requests = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    requests.sort();
+    assert!(!requests.is_empty(), "no golden requests in {dir:?}");
+
+    let mut mismatches = Vec::new();
+    for path in requests {
+        let request: FeedbackRequest =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let structured = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("structured"));
+        let rendered = render_prompt(&request, structured).unwrap();
+        let golden = path.with_extension("txt");
+        if update {
+            fs::write(&golden, &rendered).unwrap();
+        } else if fs::read_to_string(&golden).ok().as_deref() != Some(rendered.as_str()) {
+            mismatches.push(format!(
+                "{}:\n--- rendered ---\n{rendered}\n--- golden ---\n{}",
+                golden.display(),
+                fs::read_to_string(&golden).unwrap_or_default()
+            ));
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "prompts differ from the golden files, rerun with UPDATE_SNAPSHOTS=1 if intended:\n{}",
+        mismatches.join("\n\n")
+    );
+}
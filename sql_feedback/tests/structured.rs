@@ -0,0 +1,75 @@
+#[path = "../src/structured.rs"]
+mod structured;
+
+use structured::{StructuredFeedback, parse_feedback, parse_results};
+
+#[test]
+fn json_answers_report_confidence_and_abstention() {
+    assert_eq!(
+        parse_feedback(
+            r#"{"feedback": "Join on the id.", "confidence": 0.9, "needs_review": false}"#,
+            None
+        ),
+        StructuredFeedback {
+            correct: false,
+            feedback: "Join on the id.".to_string(),
+            hints: vec![],
+            confidence: Some(0.9),
+            needs_review: false,
+        }
+    );
+    let abstained = parse_feedback(
+        "```json\n{\"feedback\": \"The task is ambiguous.\", \"needs_review\": true}\n```",
+        None,
+    );
+    assert_eq!(abstained.feedback, "The task is ambiguous.");
+    assert_eq!(abstained.confidence, None);
+    assert!(abstained.needs_review);
+}
+
+#[test]
+fn answers_below_the_threshold_need_review() {
+    let answer = r#"{"feedback": "Looks right.", "confidence": 0.4}"#;
+    assert!(!parse_feedback(answer, None).needs_review);
+    assert!(!parse_feedback(answer, Some(0.4)).needs_review);
+    assert!(parse_feedback(answer, Some(0.5)).needs_review);
+    let overconfident = parse_feedback(r#"{"feedback": "", "confidence": 7}"#, Some(0.5));
+    assert_eq!(overconfident.confidence, Some(1.0));
+    assert!(!overconfident.needs_review);
+}
+
+#[test]
+fn other_answers_are_plain_feedback() {
+    let answer = "Use a LEFT JOIN to keep students without courses.";
+    assert_eq!(
+        parse_feedback(answer, Some(0.5)),
+        StructuredFeedback::plain(answer)
+    );
+}
+
+#[test]
+fn results_are_parsed_per_submission() {
+    let answer = r#"{"results": [
+        {"correct": true, "feedback": "Correct.", "hints": [], "confidence": 0.95, "needs_review": false},
+        {"correct": false, "feedback": "Filter before 2000.", "hints": ["Use <"], "confidence": 0.3, "needs_review": false}
+    ]}"#;
+    let results = parse_results(answer, 2, Some(0.5)).unwrap();
+    assert!(results[0].correct && !results[0].needs_review);
+    assert_eq!(results[1].hints, vec!["Use <".to_string()]);
+    assert!(!results[1].correct && results[1].needs_review);
+
+    let single = r#"{"correct": true, "feedback": "Correct."}"#;
+    assert!(parse_results(single, 1, None).unwrap()[0].correct);
+}
+
+#[test]
+fn malformed_results_are_rejected() {
+    let answer = r#"{"results": [{"correct": true, "feedback": "Correct."}]}"#;
+    assert_eq!(
+        parse_results(answer, 2, None).unwrap_err(),
+        "expected 2 results, one per query, got 1"
+    );
+    assert!(parse_results(r#"{"correct": true, "feedback": "Correct."}"#, 2, None).is_err());
+    assert!(parse_results(r#"{"results": [{"correct": true}]}"#, 1, None).is_err());
+    assert!(parse_results("Looks good.", 1, None).is_err());
+}
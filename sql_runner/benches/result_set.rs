@@ -0,0 +1,112 @@
+//! Baseline of the result set operations comparisons are built from. Run
+//! `cargo bench --bench result_set -- --output-format bencher` for one line per benchmark,
+//! e.g. to track it in CI.
+
+#[path = "../src/db/types.rs"]
+#[allow(dead_code)]
+mod types;
+
+use chrono::{Duration, NaiveDate};
+use common::models::{ResultSet, SqlValue};
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use types::ResultSetExtension;
+
+/// Rows and columns of the benchmarked result sets.
+const SIZES: [(usize, usize); 4] = [(10, 5), (1_000, 10), (10_000, 20), (100_000, 50)];
+
+/// Result set with columns of every type and some `NULL`s, whose rows and columns are in
+/// no particular order. Equal sizes give equal result sets.
+fn result_set(rows: usize, columns: usize) -> ResultSet {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).expect("the date exists");
+    ResultSet {
+        columns: (0..columns)
+            .map(|column| format!("c{}", (column * 7919) % columns))
+            .collect(),
+        rows: (0..rows)
+            .map(|row| {
+                (0..columns)
+                    .map(|column| {
+                        // scrambles the rows so sorting has work to do
+                        let n = (row as i64 * 2_654_435_761 + column as i64) % 1_000_003;
+                        match (column % 6, n % 17) {
+                            (_, 0) => SqlValue::Null,
+                            (0, _) => SqlValue::Int(n),
+                            (1, _) => SqlValue::Text(format!("value {n}")),
+                            (2, _) => SqlValue::Float(n as f64 / 8.0),
+                            (3, _) => SqlValue::Date(epoch + Duration::days(n % 10_000)),
+                            (4, _) => SqlValue::Timestamp(
+                                epoch.and_time(Default::default()) + Duration::seconds(n),
+                            ),
+                            _ => SqlValue::Bool(n % 2 == 0),
+                        }
+                    })
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+fn benchmark(
+    c: &mut Criterion,
+    name: &str,
+    mut f: impl FnMut(&mut criterion::Bencher, &ResultSet),
+) {
+    let mut group = c.benchmark_group(name);
+    for (rows, columns) in SIZES {
+        if rows * columns >= 1_000_000 {
+            group.sample_size(10);
+        }
+        let result_set = result_set(rows, columns);
+        group.throughput(Throughput::Elements((rows * columns) as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{rows}x{columns}")),
+            &result_set,
+            &mut f,
+        );
+    }
+    group.finish();
+}
+
+fn sort_rows(c: &mut Criterion) {
+    benchmark(c, "sort_rows", |b, result_set| {
+        b.iter_batched(
+            || result_set.clone(),
+            |mut result_set| {
+                result_set.sort_rows();
+                result_set
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn sort_columns(c: &mut Criterion) {
+    benchmark(c, "sort_columns", |b, result_set| {
+        b.iter_batched(
+            || result_set.clone(),
+            |mut result_set| {
+                result_set.sort_columns();
+                result_set
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+/// Equal result sets, the worst case as every value is compared.
+fn equality(c: &mut Criterion) {
+    benchmark(c, "equality", |b, result_set| {
+        let other = result_set.clone();
+        b.iter(|| black_box(result_set) == black_box(&other))
+    });
+}
+
+fn fingerprint(c: &mut Criterion) {
+    benchmark(c, "fingerprint", |b, result_set| {
+        b.iter(|| black_box(result_set).fingerprint())
+    });
+}
+
+criterion_group!(benches, sort_rows, sort_columns, equality, fingerprint);
+criterion_main!(benches);
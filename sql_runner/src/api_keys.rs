@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Length of the window requests are counted in by [`KeyRateLimit`].
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Name of the API key a request was authenticated with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey(pub String);
+
+/// Accepted API keys, stored as blake3 hashes like the consumer tokens of the proxy so the
+/// configuration does not contain the keys themselves.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    /// Name of the key by its hex encoded hash
+    names: HashMap<String, String>,
+}
+
+impl ApiKeys {
+    /// Parses comma separated `name:hash` pairs.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut names = HashMap::new();
+        for entry in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (name, hash) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("`{entry}` is not of the form name:hash"))?;
+            let hash = hash.trim().to_lowercase();
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!(
+                    "the hash of key `{name}` is not a hex encoded blake3 hash"
+                ));
+            }
+            if names.insert(hash, name.trim().to_string()).is_some() {
+                return Err(format!("the key `{name}` is configured twice"));
+            }
+        }
+        if names.is_empty() {
+            return Err("no API key is configured".to_string());
+        }
+        Ok(ApiKeys { names })
+    }
+
+    /// Key the token belongs to, if any.
+    pub fn authenticate(&self, token: &str) -> Option<ApiKey> {
+        let hash = blake3::hash(token.as_bytes()).to_hex();
+        self.names.get(hash.as_str()).cloned().map(ApiKey)
+    }
+}
+
+/// Limits the requests of every API key per minute.
+#[derive(Debug)]
+pub struct KeyRateLimit {
+    requests_per_minute: u32,
+    /// Start of the current window and the requests in it by key name
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl KeyRateLimit {
+    pub fn new(requests_per_minute: u32) -> Self {
+        KeyRateLimit {
+            requests_per_minute,
+            windows: Default::default(),
+        }
+    }
+
+    /// Counts a request of the key, false if it exceeds the limit.
+    pub fn try_request(&self, key: &ApiKey, now: Instant) -> bool {
+        let mut windows = self.windows.lock().unwrap();
+        let (start, count) = windows.entry(key.0.clone()).or_insert((now, 0));
+        if now.duration_since(*start) >= RATE_WINDOW {
+            (*start, *count) = (now, 0);
+        }
+        if *count >= self.requests_per_minute {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
@@ -0,0 +1,510 @@
+use serde::Serialize;
+use sqlparser::ast::{
+    ColumnDef, ColumnOption, CreateTable, ForeignKeyConstraint, Ident, Statement, TableConstraint,
+};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlx::types::chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+/// Upper limit for the number of rows generated per table.
+pub const MAX_ROWS: usize = 10_000;
+
+/// Attempts to find a row with unused key values before the table is cut short.
+const MAX_ATTEMPTS: usize = 20;
+
+const FIRST_NAMES: &[&str] = &[
+    "Anna", "Ben", "Clara", "David", "Emma", "Felix", "Greta", "Hannah", "Jonas", "Lea", "Lukas",
+    "Mia", "Noah", "Paul", "Sophie", "Tim",
+];
+const LAST_NAMES: &[&str] = &[
+    "Becker",
+    "Fischer",
+    "Hoffmann",
+    "Klein",
+    "Koch",
+    "Meyer",
+    "Müller",
+    "Richter",
+    "Schmidt",
+    "Schneider",
+    "Schulz",
+    "Wagner",
+    "Weber",
+    "Wolf",
+];
+const CITIES: &[&str] = &[
+    "Berlin",
+    "Bremen",
+    "Dresden",
+    "Frankfurt",
+    "Gießen",
+    "Hamburg",
+    "Köln",
+    "Leipzig",
+    "München",
+    "Stuttgart",
+];
+const COUNTRIES: &[&str] = &[
+    "Austria",
+    "Belgium",
+    "Denmark",
+    "France",
+    "Germany",
+    "Italy",
+    "Netherlands",
+    "Poland",
+    "Spain",
+    "Switzerland",
+];
+const WORDS: &[&str] = &[
+    "alpha", "bright", "cedar", "delta", "ember", "forest", "granite", "harbor", "island",
+    "juniper", "lantern", "meadow", "nova", "orbit", "prairie", "river",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum DataGenError {
+    #[error("failed to parse schema: {0}")]
+    Parse(String),
+    #[error("schema does not contain any CREATE TABLE statement")]
+    NoTables,
+    #[error("at most {MAX_ROWS} rows can be generated per table")]
+    TooManyRows,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GeneratedTable {
+    pub name: String,
+    /// Less than requested if the key constraints of the table could not be satisfied
+    pub rows: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GeneratedData {
+    /// `INSERT` statements filling the tables of the schema
+    pub script: String,
+    pub tables: Vec<GeneratedTable>,
+}
+
+/// Deterministic pseudo random numbers (SplitMix64), stable across platforms and releases.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64, table: &str) -> Self {
+        let hash = blake3::hash(table.as_bytes());
+        let table = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        Rng(seed ^ table)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Number in `low..=high`.
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next() % (high - low + 1) as u64) as i64
+    }
+
+    fn pick<'a>(&mut self, values: &[&'a str]) -> &'a str {
+        values[self.next() as usize % values.len()]
+    }
+}
+
+/// Column of a table together with the information needed to fill it.
+struct Column {
+    name: String,
+    kind: String,
+    max_length: Option<usize>,
+    /// Precision and scale of `numeric` columns
+    precision: Option<(u32, u32)>,
+    unique: bool,
+}
+
+/// Columns referencing another table, `referred_columns` is empty for references to the
+/// primary key.
+struct ForeignKey {
+    columns: Vec<String>,
+    table: String,
+    referred_columns: Vec<String>,
+}
+
+impl ForeignKey {
+    fn new(foreign_key: &ForeignKeyConstraint, columns: &[Ident]) -> Self {
+        ForeignKey {
+            columns: columns.iter().map(Ident::to_string).collect(),
+            table: foreign_key.foreign_table.to_string().to_lowercase(),
+            referred_columns: foreign_key
+                .referred_columns
+                .iter()
+                .map(Ident::to_string)
+                .collect(),
+        }
+    }
+}
+
+struct Table {
+    name: String,
+    columns: Vec<Column>,
+    keys: Vec<Vec<String>>,
+    foreign_keys: Vec<ForeignKey>,
+}
+
+/// Generates `rows` rows for every table of the schema, honouring primary keys, unique
+/// constraints and foreign keys to tables defined earlier in the schema. Serial and generated
+/// columns are left to the database, check constraints are not evaluated.
+pub fn generate_data(schema: &str, rows: usize, seed: u64) -> Result<GeneratedData, DataGenError> {
+    if rows > MAX_ROWS {
+        return Err(DataGenError::TooManyRows);
+    }
+    let statements = Parser::parse_sql(&PostgreSqlDialect {}, schema)
+        .map_err(|err| DataGenError::Parse(err.to_string()))?;
+    let tables: Vec<Table> = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::CreateTable(create_table) => Some(table(create_table)),
+            _ => None,
+        })
+        .collect();
+    if tables.is_empty() {
+        return Err(DataGenError::NoTables);
+    }
+
+    let mut generated: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
+    let mut script = String::new();
+    let mut summary = Vec::new();
+    for table in &tables {
+        let mut rng = Rng::new(seed, &table.name);
+        let mut seen_keys: Vec<HashSet<Vec<String>>> = vec![HashSet::new(); table.keys.len()];
+        let mut table_rows = Vec::new();
+        'rows: for index in 0..rows {
+            for _ in 0..MAX_ATTEMPTS {
+                let Some(row) = generate_row(table, index, &generated, &mut rng) else {
+                    break 'rows;
+                };
+                let keys: Vec<Vec<String>> = table
+                    .keys
+                    .iter()
+                    .map(|key| key.iter().map(|column| row[column].clone()).collect())
+                    .collect();
+                if keys
+                    .iter()
+                    .zip(&seen_keys)
+                    .any(|(key, seen)| seen.contains(key))
+                {
+                    continue;
+                }
+                keys.into_iter()
+                    .zip(&mut seen_keys)
+                    .for_each(|(key, seen)| _ = seen.insert(key));
+                table_rows.push(row);
+                continue 'rows;
+            }
+            break;
+        }
+
+        if !table_rows.is_empty() {
+            script.push_str(&insert_statement(table, &table_rows));
+        }
+        summary.push(GeneratedTable {
+            name: table.name.clone(),
+            rows: table_rows.len(),
+        });
+        generated.insert(table.name.to_lowercase(), table_rows);
+    }
+    Ok(GeneratedData {
+        script,
+        tables: summary,
+    })
+}
+
+fn table(create_table: &CreateTable) -> Table {
+    let mut keys = Vec::new();
+    let mut foreign_keys = Vec::new();
+    for constraint in &create_table.constraints {
+        match constraint {
+            TableConstraint::PrimaryKey(key) => keys.push(
+                key.columns
+                    .iter()
+                    .map(|column| column.column.expr.to_string())
+                    .collect(),
+            ),
+            TableConstraint::Unique(key) => keys.push(
+                key.columns
+                    .iter()
+                    .map(|column| column.column.expr.to_string())
+                    .collect(),
+            ),
+            TableConstraint::ForeignKey(foreign_key) => {
+                foreign_keys.push(ForeignKey::new(foreign_key, &foreign_key.columns))
+            }
+            _ => {}
+        }
+    }
+
+    let mut columns = Vec::new();
+    for column_def in &create_table.columns {
+        let name = column_def.name.to_string();
+        let mut unique = false;
+        let mut generated = false;
+        for option in &column_def.options {
+            match &option.option {
+                ColumnOption::PrimaryKey(_) | ColumnOption::Unique(_) => unique = true,
+                ColumnOption::ForeignKey(foreign_key) => foreign_keys.push(ForeignKey::new(
+                    foreign_key,
+                    std::slice::from_ref(&column_def.name),
+                )),
+                ColumnOption::Generated { .. } => generated = true,
+                _ => {}
+            }
+        }
+        if unique {
+            keys.push(vec![name.clone()]);
+        }
+        if generated || is_serial(column_def) {
+            continue;
+        }
+        let (kind, max_length, precision) = column_kind(column_def);
+        columns.push(Column {
+            name,
+            kind,
+            max_length,
+            precision,
+            unique,
+        });
+    }
+    // keys on serial columns are filled by the database and always unique
+    keys.retain(|key| {
+        key.iter()
+            .all(|name| columns.iter().any(|column| column.name == *name))
+    });
+    for column in &mut columns {
+        column.unique |= keys.contains(&vec![column.name.clone()]);
+    }
+
+    Table {
+        name: create_table.name.to_string(),
+        columns,
+        keys,
+        foreign_keys,
+    }
+}
+
+fn is_serial(column: &ColumnDef) -> bool {
+    column
+        .data_type
+        .to_string()
+        .to_lowercase()
+        .ends_with("serial")
+}
+
+/// Lower case type name without arguments, maximum length, and precision and scale of the
+/// column type.
+fn column_kind(column: &ColumnDef) -> (String, Option<usize>, Option<(u32, u32)>) {
+    let data_type = column.data_type.to_string().to_lowercase();
+    let (kind, arguments) = match data_type.split_once('(') {
+        Some((kind, arguments)) => (kind.trim(), arguments.trim_end_matches(')')),
+        None => (data_type.as_str(), ""),
+    };
+    let mut arguments = arguments
+        .split(',')
+        .map(|argument| argument.trim().parse().ok());
+    let precision: Option<u32> = arguments.next().flatten();
+    // `numeric(p)` has a scale of 0
+    let scale = arguments.next().flatten().unwrap_or(0);
+    let (max_length, precision) = match kind {
+        "varchar" | "character varying" | "char" | "character" => {
+            (precision.map(|precision| precision as usize), None)
+        }
+        "numeric" | "decimal" => (None, precision.map(|precision| (precision, scale))),
+        _ => (None, None),
+    };
+    (kind.to_string(), max_length, precision)
+}
+
+fn generate_row(
+    table: &Table,
+    index: usize,
+    generated: &HashMap<String, Vec<HashMap<String, String>>>,
+    rng: &mut Rng,
+) -> Option<HashMap<String, String>> {
+    let mut row = HashMap::new();
+    for foreign_key in &table.foreign_keys {
+        let Some(referenced) = generated.get(&foreign_key.table) else {
+            continue;
+        };
+        if referenced.is_empty() {
+            return None;
+        }
+        let position = rng.range(0, referenced.len() as i64 - 1) as usize;
+        for (index, column) in foreign_key.columns.iter().enumerate() {
+            // serial keys are not part of the generated rows but numbered from 1
+            let value = foreign_key
+                .referred_columns
+                .get(index)
+                .and_then(|referred| referenced[position].get(referred))
+                .cloned()
+                .unwrap_or_else(|| (position + 1).to_string());
+            row.insert(column.clone(), value);
+        }
+    }
+    for column in &table.columns {
+        if !row.contains_key(&column.name) {
+            row.insert(column.name.clone(), value(column, index, rng));
+        }
+    }
+    Some(row)
+}
+
+/// SQL literal for the column, chosen by the type and the name of the column.
+fn value(column: &Column, index: usize, rng: &mut Rng) -> String {
+    let name = column.name.trim_matches('"').to_lowercase();
+    let number = index + 1;
+    match column.kind.as_str() {
+        "int" | "integer" | "int4" | "bigint" | "int8" | "smallint" | "int2" => {
+            if column.unique {
+                number.to_string()
+            } else if name == "age" || name.ends_with("_age") {
+                rng.range(18, 80).to_string()
+            } else if name.contains("year") {
+                rng.range(1990, 2025).to_string()
+            } else {
+                rng.range(0, 1000).to_string()
+            }
+        }
+        "numeric" | "decimal" | "real" | "float" | "float4" | "float8" | "double"
+        | "double precision" => {
+            let scale = column.precision.map_or(2, |(_, scale)| scale.min(6));
+            let unit = 10i64.pow(scale);
+            // 1 to 1000 in units of the scale, cut to the integer digits the precision allows
+            let mut units = rng.range(100, 100_000) * unit / 100;
+            if let Some((precision, declared_scale)) = column.precision {
+                let digits = precision.saturating_sub(declared_scale) + scale;
+                if digits < 18 {
+                    units %= 10i64.pow(digits);
+                }
+            }
+            match scale {
+                0 => units.to_string(),
+                _ => format!("{}.{:02$}", units / unit, units % unit, scale as usize),
+            }
+        }
+        "bool" | "boolean" => (rng.next() & 1 == 0).to_string(),
+        "date" => format!("'{}'", date(rng)),
+        "timestamp"
+        | "timestamptz"
+        | "timestamp with time zone"
+        | "timestamp without time zone" => format!("'{} {}'", date(rng), time(rng)),
+        "time" => format!("'{}'", time(rng)),
+        "uuid" => {
+            let (high, low) = (rng.next(), rng.next());
+            format!(
+                "'{:08x}-{:04x}-4{:03x}-8{:03x}-{:012x}'",
+                high >> 32,
+                (high >> 16) & 0xffff,
+                high & 0xfff,
+                low >> 52,
+                low & 0xffff_ffff_ffff
+            )
+        }
+        "text" | "varchar" | "character varying" | "char" | "character" => {
+            let text = text(
+                &name,
+                column.unique.then_some(number),
+                column.max_length,
+                rng,
+            );
+            format!("'{}'", text.replace('\'', "''"))
+        }
+        _ => "NULL".to_string(),
+    }
+}
+
+/// Text for a column of the name, ending in `number` for unique columns so it survives cutting
+/// the text to `max_length`.
+fn text(name: &str, number: Option<usize>, max_length: Option<usize>, rng: &mut Rng) -> String {
+    if name.contains("mail") {
+        let number = number.map(|number| number.to_string()).unwrap_or_default();
+        let local = format!("{}.{}", rng.pick(FIRST_NAMES), rng.pick(LAST_NAMES)).to_lowercase();
+        return fit(&local, &format!("{number}@example.org"), max_length);
+    }
+    let suffix = number
+        .map(|number| format!(" {number}"))
+        .unwrap_or_default();
+    let text = if name.contains("first") || name.contains("vorname") {
+        rng.pick(FIRST_NAMES).to_string()
+    } else if name.contains("last") || name.contains("surname") || name.contains("nachname") {
+        rng.pick(LAST_NAMES).to_string()
+    } else if name.contains("name") {
+        format!("{} {}", rng.pick(FIRST_NAMES), rng.pick(LAST_NAMES))
+    } else if name.contains("city") {
+        rng.pick(CITIES).to_string()
+    } else if name.contains("country") {
+        rng.pick(COUNTRIES).to_string()
+    } else if name.contains("phone") || name.contains("telefon") {
+        format!(
+            "+49 {} {}",
+            rng.range(100, 999),
+            rng.range(100_000, 999_999)
+        )
+    } else {
+        format!("{} {}", rng.pick(WORDS), rng.pick(WORDS))
+    };
+    fit(&text, &suffix, max_length)
+}
+
+/// `text` cut short so that it fits into `max_length` characters followed by `suffix`.
+fn fit(text: &str, suffix: &str, max_length: Option<usize>) -> String {
+    let Some(max_length) = max_length else {
+        return format!("{text}{suffix}");
+    };
+    let length = max_length.saturating_sub(suffix.chars().count());
+    let text: String = text.chars().take(length).collect();
+    format!("{text}{suffix}").chars().take(max_length).collect()
+}
+
+fn date(rng: &mut Rng) -> NaiveDate {
+    // 730120 is 2000-01-01, dates span 25 years
+    NaiveDate::from_num_days_from_ce_opt(730_120 + rng.range(0, 9130) as i32).unwrap()
+}
+
+fn time(rng: &mut Rng) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        rng.range(0, 23),
+        rng.range(0, 59),
+        rng.range(0, 59)
+    )
+}
+
+fn insert_statement(table: &Table, rows: &[HashMap<String, String>]) -> String {
+    // every column is filled by the database, which `VALUES` cannot express
+    if table.columns.is_empty() {
+        return format!("INSERT INTO {} DEFAULT VALUES;\n", table.name).repeat(rows.len());
+    }
+    let columns: Vec<&str> = table
+        .columns
+        .iter()
+        .map(|column| column.name.as_str())
+        .collect();
+    let values: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let values: Vec<&str> = columns
+                .iter()
+                .map(|column| row.get(*column).map_or("NULL", String::as_str))
+                .collect();
+            format!("    ({})", values.join(", "))
+        })
+        .collect();
+    format!(
+        "INSERT INTO {} ({}) VALUES\n{};\n",
+        table.name,
+        columns.join(", "),
+        values.join(",\n")
+    )
+}
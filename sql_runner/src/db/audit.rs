@@ -0,0 +1,113 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+tokio::task_local! {
+    /// Caller of the request currently handled, taken from the configured header.
+    pub static CALLER: Option<String>;
+    /// Opaque metadata the caller passed with the request currently handled.
+    pub static METADATA: Option<Value>;
+}
+
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    executed_at: u64,
+    environment_hash: &'a str,
+    query: &'a str,
+    duration_ms: u64,
+    rows: Option<usize>,
+    error: Option<String>,
+    caller: Option<String>,
+    metadata: Option<Value>,
+}
+
+#[derive(Debug)]
+struct AuditFile {
+    file: File,
+    size: u64,
+}
+
+/// Append-only JSONL file of executed queries, rotated to `<path>.1` ... `<path>.<max_files>`
+/// once it grows beyond `max_bytes`.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Mutex<AuditFile>,
+}
+
+impl AuditLog {
+    pub fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(AuditLog {
+            path,
+            max_bytes,
+            max_files,
+            file: Mutex::new(AuditFile { file, size }),
+        })
+    }
+
+    pub fn record(
+        &self,
+        environment_hash: &str,
+        query: &str,
+        duration: Duration,
+        rows: Option<usize>,
+        error: Option<String>,
+    ) {
+        let entry = AuditEntry {
+            executed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            environment_hash,
+            query,
+            duration_ms: duration.as_millis() as u64,
+            rows,
+            error,
+            caller: CALLER.try_with(Clone::clone).ok().flatten(),
+            metadata: METADATA.try_with(Clone::clone).ok().flatten(),
+        };
+        if let Err(err) = self.write(&entry) {
+            log::error!("failed to write audit log: {err}");
+        }
+    }
+
+    fn write(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap();
+        if file.size > 0 && file.size + line.len() as u64 > self.max_bytes {
+            self.rotate(&mut file)?;
+        }
+        file.file.write_all(&line)?;
+        file.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&self, file: &mut AuditFile) -> std::io::Result<()> {
+        let rotated = |index: usize| PathBuf::from(format!("{}.{index}", self.path.display()));
+        if self.max_files > 0 {
+            for index in (1..self.max_files).rev() {
+                let from = rotated(index);
+                if from.exists() {
+                    std::fs::rename(from, rotated(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+        file.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        file.size = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+tokio::task_local! {
+    /// Caller, task and user of the request currently handled, `None` for requests without a
+    /// task, which are not accounted.
+    pub static BUDGET_KEY: Option<String>;
+}
+
+/// Cumulative execution time allowed per caller, task and user, so the checker cannot be
+/// brute-forced with endless attempts.
+#[derive(Debug)]
+pub struct ExecutionBudget {
+    limit: Duration,
+    used: Mutex<HashMap<String, Duration>>,
+}
+
+impl ExecutionBudget {
+    pub fn new(limit: Duration) -> Self {
+        ExecutionBudget {
+            limit,
+            used: Default::default(),
+        }
+    }
+
+    /// Key of the current request, built from the forwarded headers.
+    pub fn key(
+        caller: Option<&str>,
+        task_id: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Option<String> {
+        task_id.map(|task_id| {
+            format!(
+                "{}\n{task_id}\n{}",
+                caller.unwrap_or_default(),
+                user_id.unwrap_or_default()
+            )
+        })
+    }
+
+    fn current_key() -> Option<String> {
+        BUDGET_KEY.try_with(Clone::clone).ok().flatten()
+    }
+
+    /// Whether the budget of the current request is used up.
+    pub fn exceeded(&self) -> bool {
+        Self::current_key().is_some_and(|key| {
+            self.used
+                .lock()
+                .unwrap()
+                .get(&key)
+                .is_some_and(|used| *used >= self.limit)
+        })
+    }
+
+    /// Adds execution time to the budget of the current request.
+    pub fn charge(&self, duration: Duration) {
+        if let Some(key) = Self::current_key() {
+            *self.used.lock().unwrap().entry(key).or_default() += duration;
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::db::progress::EnvironmentState;
+use crate::db::{DB, SqlExecutionError};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct CleanupReport {
+    /// Dropped environments, or the ones which would be dropped by a dry run
+    pub dropped: Vec<String>,
+    /// Environments which could not be dropped, e.g. because they were in use
+    pub failed: Vec<CleanupFailure>,
+    pub remaining: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CleanupFailure {
+    pub name: String,
+    pub error: String,
+}
+
+impl DB {
+    /// Drops the environments which are stale according to the cleanup policy. Environments
+    /// used while the cleanup runs are kept.
+    pub async fn cleanup_environments(&self) -> Result<CleanupReport, SqlExecutionError> {
+        let started = SystemTime::now();
+        let existing = self.environment_names().await?;
+        let stale = self.usage.stale(&existing, &self.cleanup_policy, started);
+        Ok(self
+            .drop_unused(stale, existing.len(), started, false)
+            .await)
+    }
+
+    /// Drops every environment the filter selects, e.g. at the end of a semester. With
+    /// `dry_run` nothing is dropped and the report lists the environments which would be.
+    /// Environments initialising or used while the drop runs are kept.
+    pub async fn drop_environments(
+        &self,
+        selected: impl Fn(&str) -> bool,
+        dry_run: bool,
+    ) -> Result<CleanupReport, SqlExecutionError> {
+        let started = SystemTime::now();
+        let existing = self.environment_names().await?;
+        let names = existing
+            .iter()
+            .filter(|name| selected(name))
+            .cloned()
+            .collect();
+        Ok(self
+            .drop_unused(names, existing.len(), started, dry_run)
+            .await)
+    }
+
+    async fn drop_unused(
+        &self,
+        names: Vec<String>,
+        existing: usize,
+        started: SystemTime,
+        dry_run: bool,
+    ) -> CleanupReport {
+        let mut report = CleanupReport::default();
+        for name in names {
+            // serialised with the creation of the environment
+            let environment_lock = self
+                .creation_locks
+                .lock()
+                .unwrap()
+                .entry(name.clone())
+                .or_default()
+                .clone();
+            let _environment_lock = environment_lock.lock().await;
+            let initialising =
+                self.init_progress
+                    .lock()
+                    .unwrap()
+                    .get(&name)
+                    .is_some_and(|progress| {
+                        matches!(
+                            progress.status().state,
+                            EnvironmentState::Queued | EnvironmentState::Initialising
+                        )
+                    });
+            if initialising || self.usage.used_since(&name, started) {
+                continue;
+            }
+            if dry_run {
+                report.dropped.push(name);
+                continue;
+            }
+            match self.drop_environment(&name).await {
+                Ok(()) => {
+                    self.usage.forget(&name);
+                    self.init_progress.lock().unwrap().remove(&name);
+                    self.creation_locks.lock().unwrap().remove(&name);
+                    report.dropped.push(name);
+                }
+                Err(err) => {
+                    warn!("Failed to drop environment {name}: {err}");
+                    report.failed.push(CleanupFailure {
+                        name,
+                        error: err.to_string(),
+                    });
+                }
+            }
+        }
+        report.remaining = existing - report.dropped.len();
+        report
+    }
+
+    /// Runs the cleanup every `interval` in the background.
+    pub fn spawn_cleanup(self: &Arc<Self>, interval: Duration) {
+        let db = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match db.cleanup_environments().await {
+                    Ok(report) if !report.dropped.is_empty() => info!(
+                        "Dropped {} stale environments, {} remaining",
+                        report.dropped.len(),
+                        report.remaining
+                    ),
+                    Ok(_) => {}
+                    Err(err) => error!("Failed to clean up environments: {err}"),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,45 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Outcome of checking the output column names of a submission against the required ones.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct ColumnNameCheck {
+    pub matches: bool,
+    pub mismatches: Vec<ColumnNameMismatch>,
+}
+
+/// Column at `position` whose name differs, `None` for a missing or surplus column.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct ColumnNameMismatch {
+    pub position: usize,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+/// Compares the column names position by position.
+pub fn check_column_names(
+    required: &[String],
+    actual: &[String],
+    case_sensitive: bool,
+) -> ColumnNameCheck {
+    let mismatches: Vec<ColumnNameMismatch> = (0..required.len().max(actual.len()))
+        .filter_map(|position| {
+            let expected = required.get(position);
+            let actual = actual.get(position);
+            let equal = match (expected, actual) {
+                (Some(expected), Some(actual)) if case_sensitive => expected == actual,
+                (Some(expected), Some(actual)) => expected.to_lowercase() == actual.to_lowercase(),
+                _ => false,
+            };
+            (!equal).then(|| ColumnNameMismatch {
+                position,
+                expected: expected.cloned(),
+                actual: actual.cloned(),
+            })
+        })
+        .collect();
+    ColumnNameCheck {
+        matches: mismatches.is_empty(),
+        mismatches,
+    }
+}
@@ -0,0 +1,310 @@
+use crate::db::column_names::{ColumnNameCheck, check_column_names};
+use crate::db::expectation::{ExpectedResultSet, as_number};
+use crate::db::normalise::{ColumnOverride, ValueNormaliser, normalise_columns, override_columns};
+use crate::db::result_diff::{ResultSetDiff, diff_result_sets};
+use crate::db::row_diff::{RowDiff, diff_by_key};
+use crate::db::types::{ResultSet, ResultSetExtension, SqlValue, TimeGranularity};
+use crate::db::{ColumnNormalisation, ComparisonMode, RowNormalisation};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A single step of a [`ComparisonSpec`], either normalising both result sets or checking
+/// that they match in some aspect.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(tag = "step", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ComparisonStep {
+    SortRows,
+    SortColumnsByName,
+    NumberColumnsByOrder,
+    /// Truncates the timestamps of both result sets, dates are unaffected
+    TruncateTimestamps {
+        granularity: TimeGranularity,
+    },
+    /// Parses text of both result sets spelling a timestamp or date, e.g. `2024-1-2`, as such,
+    /// so it equals the typed values
+    ParseDates,
+    /// Applies the normalisers in order to the values of both result sets in every column
+    /// whose name matches the case insensitive glob pattern, e.g. `*price*`. Names are
+    /// matched as they are at this step, so it belongs before steps renaming columns
+    NormaliseValues {
+        columns: String,
+        normalisers: Vec<ValueNormaliser>,
+    },
+    /// Settings of single columns, e.g. a free-form text column which is not graded, applied
+    /// to the columns as named at this step. Tolerances pair values of the same row, so it
+    /// belongs after sorting rows
+    OverrideColumns {
+        columns: Vec<ColumnOverride>,
+    },
+    /// Columns and rows are equal
+    MatchResultSet,
+    /// The number of rows is equal
+    MatchRowCount,
+    /// The first value of the first row is equal
+    MatchFirstValue,
+    /// Both result sets consist of a single value, regardless of the column name, and the
+    /// values are equal or, if numeric, differ by at most `tolerance`
+    MatchScalar {
+        #[serde(default = "get_default_tolerance")]
+        tolerance: f64,
+    },
+    /// The submission matches the expected result set, the solution is ignored
+    MatchExpected {
+        expected: ExpectedResultSet,
+    },
+    /// Rows with equal values in the key columns are paired and their other columns are
+    /// compared by name, reporting every differing cell
+    MatchByKey {
+        key_columns: Vec<String>,
+    },
+    /// The submission has exactly these output column names, checked before any
+    /// normalisation and reported apart from the equality of the values
+    RequireColumnNames {
+        columns: Vec<String>,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    /// A failing solution is compared with the submission instead of failing the comparison,
+    /// which matches if the submission fails with an error of the same SQLSTATE class, e.g.
+    /// `23` for integrity constraint violations
+    MatchFailure,
+}
+
+fn get_default_tolerance() -> f64 {
+    1e-9
+}
+
+impl ComparisonStep {
+    fn is_match(&self) -> bool {
+        matches!(
+            self,
+            ComparisonStep::MatchResultSet
+                | ComparisonStep::MatchRowCount
+                | ComparisonStep::MatchFirstValue
+                | ComparisonStep::MatchScalar { .. }
+                | ComparisonStep::MatchExpected { .. }
+                | ComparisonStep::MatchByKey { .. }
+        )
+    }
+}
+
+/// Steps applied in order to both result sets, which are equal if every match step succeeds.
+/// Without any match step the normalised result sets are compared as a whole.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ComparisonSpec(pub Vec<ComparisonStep>);
+
+impl ComparisonSpec {
+    /// Equivalent of the `row_normalisation`, `column_normalisation` and `comparison_mode`
+    /// request options.
+    pub fn from_legacy(
+        row_norm: RowNormalisation,
+        col_norm: ColumnNormalisation,
+        comparison: ComparisonMode,
+    ) -> Self {
+        let mut steps = Vec::new();
+        match col_norm {
+            ColumnNormalisation::NoNormalization => {}
+            ColumnNormalisation::SortColumnsByName => steps.push(ComparisonStep::SortColumnsByName),
+            ColumnNormalisation::NumberColumnsByOrder => {
+                steps.push(ComparisonStep::NumberColumnsByOrder)
+            }
+        }
+        if row_norm == RowNormalisation::SortRows {
+            steps.push(ComparisonStep::SortRows);
+        }
+        match comparison {
+            ComparisonMode::ResultSet => steps.push(ComparisonStep::MatchResultSet),
+            ComparisonMode::CountOnly => steps.push(ComparisonStep::MatchRowCount),
+            ComparisonMode::CountWithScalar => steps.extend([
+                ComparisonStep::MatchRowCount,
+                ComparisonStep::MatchFirstValue,
+            ]),
+            ComparisonMode::Scalar { tolerance } => {
+                steps.push(ComparisonStep::MatchScalar { tolerance })
+            }
+        }
+        ComparisonSpec(steps)
+    }
+
+    pub fn matches_failures(&self) -> bool {
+        self.0.contains(&ComparisonStep::MatchFailure)
+    }
+}
+
+/// Normalised result sets and their verdict.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub result_a: ResultSet,
+    pub result_b: ResultSet,
+    pub eq: bool,
+    /// Differences found by a [`ComparisonStep::MatchByKey`] step, `result_a` being expected
+    pub row_diff: Option<RowDiff>,
+    /// Result of a [`ComparisonStep::RequireColumnNames`] step for `result_b`
+    pub column_names: Option<ColumnNameCheck>,
+    /// Differences between the normalised result sets, `result_a` being expected
+    pub diff: ResultSetDiff,
+    /// Comparison of the errors if `result_a` failed, see [`ComparisonStep::MatchFailure`]
+    pub failure: Option<FailureComparison>,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureVerdict {
+    /// Both errors are of the same SQLSTATE class
+    Same,
+    Different,
+    /// Only the solution failed
+    SubmissionSucceeded,
+}
+
+/// Errors of a failing solution and the submission.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FailureComparison {
+    /// SQLSTATE of the error of the solution
+    pub expected_code: Option<String>,
+    /// SQLSTATE of the error of the submission, `null` if it did not fail
+    pub actual_code: Option<String>,
+    pub verdict: FailureVerdict,
+}
+
+impl FailureComparison {
+    /// `actual` is the error code of the submission, `None` if it did not fail. Errors
+    /// without code never match.
+    pub fn new(expected_code: Option<String>, actual: Option<Option<String>>) -> Self {
+        fn class(code: &Option<String>) -> Option<&str> {
+            code.as_deref().and_then(|code| code.get(..2))
+        }
+        let verdict = match &actual {
+            None => FailureVerdict::SubmissionSucceeded,
+            Some(actual_code)
+                if class(&expected_code).is_some()
+                    && class(&expected_code) == class(actual_code) =>
+            {
+                FailureVerdict::Same
+            }
+            Some(_) => FailureVerdict::Different,
+        };
+        FailureComparison {
+            expected_code,
+            actual_code: actual.flatten(),
+            verdict,
+        }
+    }
+}
+
+/// Comparison of a failed solution with the submission, equal if both failed the same way.
+pub fn compare_failures(failure: FailureComparison, result_b: ResultSet) -> Comparison {
+    let result_a = ResultSet {
+        columns: vec![],
+        rows: vec![],
+    };
+    let diff = diff_result_sets(&result_a, &result_b);
+    Comparison {
+        eq: failure.verdict == FailureVerdict::Same,
+        result_a,
+        result_b,
+        row_diff: None,
+        column_names: None,
+        diff,
+        failure: Some(failure),
+    }
+}
+
+/// Normalises both result sets and checks them for equality as described by the spec.
+pub fn compare_result_sets(
+    mut result_a: ResultSet,
+    mut result_b: ResultSet,
+    spec: &ComparisonSpec,
+) -> Comparison {
+    let mut eq = true;
+    let mut row_diff = None;
+    let column_names = spec.0.iter().find_map(|step| match step {
+        ComparisonStep::RequireColumnNames {
+            columns,
+            case_sensitive,
+        } => Some(check_column_names(
+            columns,
+            &result_b.columns,
+            *case_sensitive,
+        )),
+        _ => None,
+    });
+    for step in &spec.0 {
+        match step {
+            ComparisonStep::SortRows => {
+                result_a.sort_rows();
+                result_b.sort_rows();
+            }
+            ComparisonStep::SortColumnsByName => {
+                result_a.sort_columns();
+                result_b.sort_columns();
+            }
+            ComparisonStep::NumberColumnsByOrder => {
+                result_a.number_columns();
+                result_b.number_columns();
+            }
+            ComparisonStep::TruncateTimestamps { granularity } => {
+                result_a.truncate_timestamps(*granularity);
+                result_b.truncate_timestamps(*granularity);
+            }
+            ComparisonStep::ParseDates => {
+                result_a.parse_dates();
+                result_b.parse_dates();
+            }
+            ComparisonStep::NormaliseValues {
+                columns,
+                normalisers,
+            } => {
+                normalise_columns(&mut result_a, columns, normalisers);
+                normalise_columns(&mut result_b, columns, normalisers);
+            }
+            ComparisonStep::OverrideColumns { columns } => {
+                override_columns(&mut result_a, &mut result_b, columns)
+            }
+            ComparisonStep::MatchResultSet => eq &= result_a == result_b,
+            ComparisonStep::MatchRowCount => eq &= result_a.rows.len() == result_b.rows.len(),
+            ComparisonStep::MatchFirstValue => {
+                eq &= first_value(&result_a) == first_value(&result_b)
+            }
+            ComparisonStep::MatchScalar { tolerance } => {
+                eq &= match (result_a.scalar(), result_b.scalar()) {
+                    (Some(a), Some(b)) => scalars_match(a, b, *tolerance),
+                    _ => false,
+                }
+            }
+            ComparisonStep::MatchExpected { expected } => eq &= expected.matches(&result_b),
+            ComparisonStep::MatchByKey { key_columns } => {
+                let diff = diff_by_key(&result_a, &result_b, key_columns);
+                eq &= diff.is_empty();
+                row_diff = Some(diff);
+            }
+            ComparisonStep::RequireColumnNames { .. } | ComparisonStep::MatchFailure => {}
+        }
+    }
+    if !spec.0.iter().any(ComparisonStep::is_match) {
+        eq = result_a == result_b;
+    }
+    let diff = diff_result_sets(&result_a, &result_b);
+    Comparison {
+        result_a,
+        result_b,
+        eq,
+        row_diff,
+        column_names,
+        diff,
+        failure: None,
+    }
+}
+
+fn first_value(result_set: &ResultSet) -> Option<&SqlValue> {
+    result_set.rows.first().and_then(|row| row.first())
+}
+
+/// Numbers, including numeric text, match within the absolute tolerance, anything else
+/// has to be equal.
+fn scalars_match(a: &SqlValue, b: &SqlValue, tolerance: f64) -> bool {
+    match (as_number(a), as_number(b)) {
+        (Some(a), Some(b)) => (a - b).abs() <= tolerance,
+        _ => a == b,
+    }
+}
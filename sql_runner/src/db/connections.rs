@@ -0,0 +1,141 @@
+use crate::db::DatabaseType;
+use common::stats::ConnectionPoolStats;
+use sqlx::Pool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+#[derive(Debug)]
+struct Entry {
+    pool: Arc<Pool<DatabaseType>>,
+    last_used: Instant,
+}
+
+/// Connection pools by the user and the database or schema they connect to, keyed
+/// `user@name`. When the maximum count is reached the least recently used pool is closed,
+/// pools idle for longer than the idle timeout are closed on the next access.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    /// 0 if unlimited
+    max_pools: usize,
+    idle_timeout: Option<Duration>,
+    pools: Mutex<HashMap<String, Entry>>,
+    evicted: AtomicU64,
+    expired: AtomicU64,
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        ConnectionManager::new(0, None)
+    }
+}
+
+impl ConnectionManager {
+    pub fn new(max_pools: usize, idle_timeout: Option<Duration>) -> Self {
+        ConnectionManager {
+            max_pools,
+            idle_timeout,
+            pools: Default::default(),
+            evicted: Default::default(),
+            expired: Default::default(),
+        }
+    }
+
+    /// Cached pool of the key, otherwise the one connected by `connect`. The lock is held
+    /// while connecting, so concurrent requests for the same key share one pool.
+    pub async fn get_or_connect<E>(
+        &self,
+        key: String,
+        connect: impl Future<Output = Result<Pool<DatabaseType>, E>>,
+    ) -> Result<Arc<Pool<DatabaseType>>, E> {
+        let now = Instant::now();
+        let mut pools = self.pools.lock().await;
+        self.expire(&mut pools, now);
+        if let Some(entry) = pools.get_mut(&key) {
+            entry.last_used = now;
+            return Ok(entry.pool.clone());
+        }
+        let pool = Arc::new(connect.await?);
+        while self.max_pools > 0 && pools.len() >= self.max_pools {
+            let Some(oldest) = pools
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = pools.remove(&oldest) {
+                close(entry.pool);
+                self.evicted.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        pools.insert(
+            key,
+            Entry {
+                pool: pool.clone(),
+                last_used: now,
+            },
+        );
+        Ok(pool)
+    }
+
+    pub async fn contains(&self, key: &str) -> bool {
+        self.pools.lock().await.contains_key(key)
+    }
+
+    /// Removes the pools whose key matches, closing them is left to the caller.
+    pub async fn remove_matching(
+        &self,
+        predicate: impl Fn(&str) -> bool,
+    ) -> Vec<Arc<Pool<DatabaseType>>> {
+        let mut pools = self.pools.lock().await;
+        let keys: Vec<String> = pools.keys().filter(|key| predicate(key)).cloned().collect();
+        keys.iter()
+            .filter_map(|key| pools.remove(key))
+            .map(|entry| entry.pool)
+            .collect()
+    }
+
+    pub async fn remove_pool(&self, pool: &Arc<Pool<DatabaseType>>) {
+        self.pools
+            .lock()
+            .await
+            .retain(|_, entry| !Arc::ptr_eq(&entry.pool, pool));
+    }
+
+    pub async fn stats(&self) -> ConnectionPoolStats {
+        ConnectionPoolStats {
+            open: self.pools.lock().await.len(),
+            max: self.max_pools,
+            evicted: self.evicted.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+        }
+    }
+
+    fn expire(&self, pools: &mut HashMap<String, Entry>, now: Instant) {
+        let Some(idle_timeout) = self.idle_timeout else {
+            return;
+        };
+        let idle: Vec<String> = pools
+            .iter()
+            .filter(|(_, entry)| now.duration_since(entry.last_used) >= idle_timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in idle {
+            if let Some(entry) = pools.remove(&key) {
+                close(entry.pool);
+                self.expired.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Closes a pool in the background unless a request still uses it, whose reference is then
+/// the last one and closes the pool when dropped.
+fn close(pool: Arc<Pool<DatabaseType>>) {
+    if let Ok(pool) = Arc::try_unwrap(pool) {
+        tokio::spawn(async move { pool.close().await });
+    }
+}
@@ -0,0 +1,50 @@
+use common::models::SqlValue;
+use sqlx::postgres::PgRow;
+use sqlx::types::Decimal;
+use sqlx::types::chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use sqlx::{Column, Row, ValueRef};
+
+pub fn column_names(row: &PgRow) -> Vec<String> {
+    row.columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect()
+}
+
+/// Values of a row, the name of the first column whose value has no representation as error.
+pub fn decode_row(row: &PgRow) -> Result<Vec<SqlValue>, String> {
+    row.columns()
+        .iter()
+        .map(|column| {
+            let index = column.ordinal();
+            let undecodable = || column.name().to_string();
+            if row.try_get_raw(index).is_ok_and(|value| value.is_null()) {
+                Ok(SqlValue::Null)
+            } else if let Ok(str) = row.try_get::<String, _>(index) {
+                Ok(SqlValue::Text(str))
+            } else if let Ok(d) = row.try_get::<Decimal, _>(index) {
+                Ok(SqlValue::Float(d.try_into().map_err(|_| undecodable())?))
+            } else if let Ok(f) = row.try_get::<f64, _>(index) {
+                Ok(SqlValue::Float(f))
+            } else if let Ok(f) = row.try_get::<f32, _>(index) {
+                Ok(SqlValue::Float(f.into()))
+            } else if let Ok(i) = row.try_get::<i64, _>(index) {
+                Ok(SqlValue::Int(i))
+            } else if let Ok(i) = row.try_get::<i32, _>(index) {
+                Ok(SqlValue::Int(i.into()))
+            } else if let Ok(i) = row.try_get::<i16, _>(index) {
+                Ok(SqlValue::Int(i.into()))
+            } else if let Ok(b) = row.try_get::<bool, _>(index) {
+                Ok(SqlValue::Bool(b))
+            } else if let Ok(c) = row.try_get::<NaiveDateTime, _>(index) {
+                Ok(SqlValue::Timestamp(c))
+            } else if let Ok(c) = row.try_get::<DateTime<Utc>, _>(index) {
+                Ok(SqlValue::Timestamp(c.naive_utc()))
+            } else if let Ok(c) = row.try_get::<NaiveDate, _>(index) {
+                Ok(SqlValue::Date(c))
+            } else {
+                Err(undecodable())
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,176 @@
+use common::models::{ResultSet, SqlValue};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use utoipa::ToSchema;
+
+/// Result set an instructor expects, whose cells are either exact values or patterns.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedResultSet {
+    /// Checked against the column names if given
+    pub columns: Option<Vec<String>>,
+    pub rows: Vec<Vec<ExpectedCell>>,
+    /// Match rows regardless of their order
+    #[serde(default)]
+    pub any_order: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(untagged)]
+pub enum ExpectedCell {
+    Pattern(CellPattern),
+    Value(SqlValue),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(tag = "match", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CellPattern {
+    /// Accepts every value, e.g. in generated id columns
+    Any,
+    /// The whole value, as text, matches the regex
+    Regex {
+        #[schema(value_type = String)]
+        pattern: CellRegex,
+    },
+    /// A number, or numeric text, within the inclusive bounds
+    Range { min: Option<f64>, max: Option<f64> },
+}
+
+/// Regex anchored to match whole values, compiled when the expectation is deserialized.
+#[derive(Debug, Clone)]
+pub struct CellRegex(Regex);
+
+impl CellRegex {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(&format!("^(?:{pattern})$")).map(CellRegex)
+    }
+
+    fn pattern(&self) -> &str {
+        let anchored = self.0.as_str();
+        &anchored[4..anchored.len() - 2]
+    }
+}
+
+impl PartialEq for CellRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for CellRegex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.pattern())
+    }
+}
+
+impl<'de> Deserialize<'de> for CellRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        CellRegex::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+impl ExpectedCell {
+    pub fn matches(&self, value: &SqlValue) -> bool {
+        match self {
+            // dates and timestamps are text in JSON, so they are parsed to match typed values
+            ExpectedCell::Value(expected) => {
+                expected == value
+                    || matches!(value, SqlValue::Date(_) | SqlValue::Timestamp(_))
+                        && expected.clone().parse_date_text() == *value
+            }
+            ExpectedCell::Pattern(CellPattern::Any) => true,
+            ExpectedCell::Pattern(CellPattern::Regex { pattern }) => {
+                pattern.0.is_match(&value_text(value))
+            }
+            ExpectedCell::Pattern(CellPattern::Range { min, max }) => {
+                as_number(value).is_some_and(|number| {
+                    min.is_none_or(|min| number >= min) && max.is_none_or(|max| number <= max)
+                })
+            }
+        }
+    }
+}
+
+impl ExpectedResultSet {
+    /// Whether the result set has the expected columns and every row matches an expected
+    /// row, in order unless `any_order` is set.
+    pub fn matches(&self, result_set: &ResultSet) -> bool {
+        if self
+            .columns
+            .as_ref()
+            .is_some_and(|columns| *columns != result_set.columns)
+        {
+            return false;
+        }
+        if self.rows.len() != result_set.rows.len() {
+            return false;
+        }
+        if !self.any_order {
+            return self
+                .rows
+                .iter()
+                .zip(&result_set.rows)
+                .all(|(expected, row)| row_matches(expected, row));
+        }
+        // rows are assigned with augmenting paths, as patterns may accept several rows
+        let mut assigned: Vec<Option<usize>> = vec![None; result_set.rows.len()];
+        (0..self.rows.len()).all(|expected| {
+            let mut visited = vec![false; result_set.rows.len()];
+            self.assign(expected, result_set, &mut assigned, &mut visited)
+        })
+    }
+
+    fn assign(
+        &self,
+        expected: usize,
+        result_set: &ResultSet,
+        assigned: &mut Vec<Option<usize>>,
+        visited: &mut Vec<bool>,
+    ) -> bool {
+        for (index, row) in result_set.rows.iter().enumerate() {
+            if visited[index] || !row_matches(&self.rows[expected], row) {
+                continue;
+            }
+            visited[index] = true;
+            let free = match assigned[index] {
+                None => true,
+                Some(other) => self.assign(other, result_set, assigned, visited),
+            };
+            if free {
+                assigned[index] = Some(expected);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn row_matches(expected: &[ExpectedCell], row: &[SqlValue]) -> bool {
+    expected.len() == row.len()
+        && expected
+            .iter()
+            .zip(row)
+            .all(|(cell, value)| cell.matches(value))
+}
+
+fn value_text(value: &SqlValue) -> String {
+    match value {
+        SqlValue::Null => "NULL".to_string(),
+        SqlValue::Bool(value) => value.to_string(),
+        SqlValue::Int(value) => value.to_string(),
+        SqlValue::Float(value) => value.to_string(),
+        SqlValue::Date(value) => value.format("%Y-%m-%d").to_string(),
+        SqlValue::Timestamp(value) => value.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+        SqlValue::Text(value) => value.clone(),
+    }
+}
+
+pub fn as_number(value: &SqlValue) -> Option<f64> {
+    match value {
+        SqlValue::Int(i) => Some(*i as f64),
+        SqlValue::Float(f) => Some(*f),
+        SqlValue::Text(text) => text.trim().parse().ok(),
+        SqlValue::Null | SqlValue::Bool(_) | SqlValue::Date(_) | SqlValue::Timestamp(_) => None,
+    }
+}
@@ -0,0 +1,86 @@
+use serde::Serialize;
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+/// Distinct fingerprints counted per environment, further ones are dropped.
+const MAX_FINGERPRINTS_PER_ENVIRONMENT: usize = 1_000;
+
+/// Replaces literals.
+const PLACEHOLDER: &str = "?";
+
+/// Query with literals masked, comments removed, tokens separated by single spaces and unquoted
+/// words in lower case, so queries differing only in values or formatting share a fingerprint.
+pub fn query_fingerprint(query: &str) -> String {
+    let Ok(tokens) = Tokenizer::new(&PostgreSqlDialect {}, query).tokenize() else {
+        return query.split_whitespace().collect::<Vec<_>>().join(" ");
+    };
+    tokens
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Whitespace(_) | Token::SemiColon => None,
+            Token::Number(..)
+            | Token::SingleQuotedString(_)
+            | Token::EscapedStringLiteral(_)
+            | Token::NationalStringLiteral(_)
+            | Token::HexStringLiteral(_)
+            | Token::DollarQuotedString(_) => Some(PLACEHOLDER.to_string()),
+            Token::Word(word) if word.quote_style.is_some() => Some(Token::Word(word).to_string()),
+            Token::Word(word) => Some(word.value.to_lowercase()),
+            token => Some(token.to_string()),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FingerprintCount {
+    pub fingerprint: String,
+    pub count: u64,
+    /// Share of the counted queries executed in the environment
+    pub share: f64,
+}
+
+/// Counts executed queries by fingerprint for every environment database.
+#[derive(Debug, Default)]
+pub struct FingerprintStats {
+    counts: Mutex<HashMap<String, HashMap<String, u64>>>,
+}
+
+impl FingerprintStats {
+    pub fn record(&self, db_name: &str, query: &str) {
+        let fingerprint = query_fingerprint(query);
+        let mut counts = self.counts.lock().unwrap();
+        let environment = counts.entry(db_name.to_string()).or_default();
+        if let Some(count) = environment.get_mut(&fingerprint) {
+            *count += 1;
+        } else if environment.len() < MAX_FINGERPRINTS_PER_ENVIRONMENT {
+            environment.insert(fingerprint, 1);
+        }
+    }
+
+    /// Fingerprints of the environment, most frequent first.
+    pub fn get(&self, db_name: &str) -> Vec<FingerprintCount> {
+        let counts = self.counts.lock().unwrap();
+        let Some(environment) = counts.get(db_name) else {
+            return vec![];
+        };
+        let total: u64 = environment.values().sum();
+        let mut fingerprints: Vec<_> = environment
+            .iter()
+            .map(|(fingerprint, count)| FingerprintCount {
+                fingerprint: fingerprint.clone(),
+                count: *count,
+                share: *count as f64 / total as f64,
+            })
+            .collect();
+        fingerprints.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.fingerprint.cmp(&b.fingerprint))
+        });
+        fingerprints
+    }
+}
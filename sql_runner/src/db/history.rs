@@ -0,0 +1,83 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+#[derive(Debug, Copy, Clone, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryStatus {
+    Ok,
+    Error,
+    Timeout,
+}
+
+impl QueryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryStatus::Ok => "ok",
+            QueryStatus::Error => "error",
+            QueryStatus::Timeout => "timeout",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QueryHistoryEntry {
+    pub query_hash: String,
+    pub executed_at: u64,
+    pub duration_ms: u64,
+    pub status: QueryStatus,
+}
+
+/// Keeps the last `size` queries of every environment database in memory.
+#[derive(Debug)]
+pub struct QueryHistory {
+    size: usize,
+    entries: Mutex<HashMap<String, VecDeque<QueryHistoryEntry>>>,
+}
+
+impl QueryHistory {
+    pub fn new(size: usize) -> Self {
+        QueryHistory {
+            size,
+            entries: Default::default(),
+        }
+    }
+
+    pub async fn record(
+        &self,
+        db_name: &str,
+        query: &str,
+        duration: Duration,
+        status: QueryStatus,
+    ) {
+        if self.size == 0 {
+            return;
+        }
+        let entry = QueryHistoryEntry {
+            query_hash: blake3::hash(query.as_bytes()).to_hex().to_string(),
+            executed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            duration_ms: duration.as_millis() as u64,
+            status,
+        };
+        let mut entries = self.entries.lock().await;
+        let history = entries.entry(db_name.to_string()).or_default();
+        if history.len() >= self.size {
+            history.pop_front();
+        }
+        history.push_back(entry);
+    }
+
+    pub async fn get(&self, db_name: &str) -> Vec<QueryHistoryEntry> {
+        self.entries
+            .lock()
+            .await
+            .get(db_name)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,178 @@
+/// Part of an environment script, consecutive plain `INSERT` statements are grouped so they
+/// can be spread over several connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Statement(&'a str),
+    /// Target table and statement
+    Inserts(Vec<(String, &'a str)>),
+}
+
+/// Statements depending on session state, which is not shared between connections.
+const SESSION_KEYWORDS: &[&str] = &[
+    "begin",
+    "start",
+    "commit",
+    "end",
+    "rollback",
+    "abort",
+    "savepoint",
+    "release",
+    "prepare",
+    "set",
+    "reset",
+    "discard",
+    "listen",
+    "copy",
+];
+
+/// Groups the statements into segments, `None` if the script has to be executed on a single
+/// connection because it relies on transactions or session state.
+pub fn segments<'a>(statements: &[&'a str]) -> Option<Vec<Segment<'a>>> {
+    let mut segments: Vec<Segment<'a>> = Vec::new();
+    for statement in statements {
+        let statement = skip_leading_comments(statement);
+        let lower = statement.to_lowercase();
+        let keyword = first_keyword(&lower);
+        if SESSION_KEYWORDS.contains(&keyword)
+            || lower.contains("set_config")
+            || (keyword == "create" && (lower.contains(" temp ") || lower.contains(" temporary ")))
+        {
+            return None;
+        }
+        match insert_target(statement, &lower) {
+            Some(table) => match segments.last_mut() {
+                Some(Segment::Inserts(inserts)) => inserts.push((table, statement)),
+                _ => segments.push(Segment::Inserts(vec![(table, statement)])),
+            },
+            None => segments.push(Segment::Statement(statement)),
+        }
+    }
+    Some(segments)
+}
+
+/// Splits inserts into levels executed one after another. Every level holds one group of
+/// statements per table, in their original order, and the groups of a level only depend on
+/// tables of earlier levels through the given `(table, referenced table)` foreign keys.
+pub fn insert_levels<'a>(
+    inserts: &[(String, &'a str)],
+    foreign_keys: &[(String, String)],
+) -> Vec<Vec<Vec<&'a str>>> {
+    let mut tables: Vec<(&str, Vec<&'a str>)> = Vec::new();
+    for (table, statement) in inserts {
+        match tables.iter_mut().find(|(name, _)| name == table) {
+            Some((_, statements)) => statements.push(statement),
+            None => tables.push((table, vec![statement])),
+        }
+    }
+    let foreign_keys = foreign_keys
+        .iter()
+        .map(|(table, referenced)| {
+            (
+                normalise_identifier(table),
+                normalise_identifier(referenced),
+            )
+        })
+        .collect::<Vec<_>>();
+    let depends_on = |table: &str, other: &str| {
+        table != other
+            && foreign_keys
+                .iter()
+                .any(|(from, to)| from == table && to == other)
+    };
+
+    let mut levels = Vec::new();
+    while !tables.is_empty() {
+        let (ready, waiting): (Vec<_>, Vec<_>) = tables
+            .iter()
+            .cloned()
+            .partition(|(table, _)| !tables.iter().any(|(other, _)| depends_on(table, other)));
+        if ready.is_empty() {
+            // Cyclic foreign keys, keep the original order of the remaining statements
+            levels.push(vec![
+                inserts
+                    .iter()
+                    .filter(|(table, _)| waiting.iter().any(|(name, _)| name == table))
+                    .map(|(_, statement)| *statement)
+                    .collect(),
+            ]);
+            break;
+        }
+        levels.push(
+            ready
+                .into_iter()
+                .map(|(_, statements)| statements)
+                .collect(),
+        );
+        tables = waiting;
+    }
+    levels
+}
+
+fn skip_leading_comments(mut statement: &str) -> &str {
+    loop {
+        statement = statement.trim_start();
+        if let Some(rest) = statement.strip_prefix("--") {
+            statement = rest.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(rest) = statement.strip_prefix("/*") {
+            statement = rest.split_once("*/").map_or("", |(_, rest)| rest);
+        } else {
+            return statement;
+        }
+    }
+}
+
+fn first_keyword(statement: &str) -> &str {
+    statement
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .find(|word| !word.is_empty())
+        .unwrap_or_default()
+}
+
+/// Table of a plain `INSERT INTO table ... VALUES` statement, which does not read other tables.
+fn insert_target(statement: &str, lower: &str) -> Option<String> {
+    if lower.contains("select") {
+        return None;
+    }
+    let rest = strip_keyword(statement, "insert")?;
+    let rest = strip_keyword(rest, "into")?;
+    let mut end = 0;
+    let mut quoted = false;
+    for (index, c) in rest.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if !quoted && !is_identifier_char(c) => break,
+            _ => {}
+        }
+        end = index + c.len_utf8();
+    }
+    let table = normalise_identifier(&rest[..end]);
+    (!table.is_empty()).then_some(table)
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || c == '.'
+}
+
+fn strip_keyword<'a>(statement: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = statement.get(keyword.len()..)?;
+    (statement[..keyword.len()].eq_ignore_ascii_case(keyword)
+        && rest.starts_with(char::is_whitespace))
+    .then(|| rest.trim_start())
+}
+
+/// Unqualified table name, lowercased unless quoted.
+pub fn normalise_identifier(identifier: &str) -> String {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in identifier.trim().chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '.' if !quoted => parts.push(std::mem::take(&mut current)),
+            c if quoted => current.push(c),
+            c => current.extend(c.to_lowercase()),
+        }
+    }
+    parts.push(current);
+    parts.pop().unwrap_or_default()
+}
@@ -9,7 +9,7 @@ pub const TABLES: &str = "SELECT c.table_name as name,
 FROM information_schema.columns as c
 JOIN information_schema.tables as t
   ON c.table_name = t.table_name AND c.table_schema = t.table_schema
-WHERE c.table_schema = 'public' AND t.table_type != 'VIEW'
+WHERE c.table_schema = current_schema() AND t.table_type != 'VIEW'
 GROUP BY c.table_name, t.table_type;";
 
 pub const CONSTRAINTS: &str = "SELECT constrains.table_name as table,
@@ -25,19 +25,19 @@ FROM (
     SELECT tc.table_name, kcu.column_name, kcu.constraint_name, tc.constraint_type, NULL as check_clause
     FROM information_schema.KEY_COLUMN_USAGE as kcu
     JOIN information_schema.table_constraints as tc ON tc.constraint_name = kcu.constraint_name
-    WHERE tc.table_schema = 'public'
+    WHERE tc.table_schema = current_schema()
     UNION
     SELECT tc.table_name, SUBSTRING(cc.check_clause from '(?:^|(?:\\.\\s))(\\w+)'), tc.constraint_name, tc.constraint_type, cc.check_clause
     FROM information_schema.table_constraints as tc
     JOIN information_schema.check_constraints as cc ON cc.constraint_name = tc.constraint_name
         AND constraint_type = 'CHECK'
-    WHERE tc.table_schema = 'public'
+    WHERE tc.table_schema = current_schema()
 ) as constrains
 GROUP BY constrains.table_name;";
 
 pub const VIEWS: &str = "SELECT table_name as table, view_definition as definition
 FROM information_schema.views
-WHERE table_schema = 'public';";
+WHERE table_schema = current_schema();";
 
 pub const ROUTINES: &str = "SELECT DISTINCT ON (oid)
        routine_name as name,
@@ -46,7 +46,7 @@ pub const ROUTINES: &str = "SELECT DISTINCT ON (oid)
        pg_catalog.pg_get_function_identity_arguments(p.oid) AS parameters
 FROM information_schema.routines i
 JOIN pg_catalog.pg_proc p ON i.routine_name = p.proname
-WHERE routine_schema = 'public';";
+WHERE routine_schema = current_schema();";
 
 pub const TRIGGERS: &str = "SELECT trigger_name as name,
        event_object_table as objectTable,
@@ -55,5 +55,15 @@ pub const TRIGGERS: &str = "SELECT trigger_name as name,
        action_orientation as orientation,
        action_timing as timing
 FROM information_schema.triggers
-WHERE trigger_schema = 'public'
+WHERE trigger_schema = current_schema()
 GROUP BY trigger_name, action_statement, action_orientation, action_timing, event_object_table;";
+
+/// Foreign keys between tables of the current schema, named like `init_plan` normalises the
+/// targets of inserts: unqualified and quoted only if necessary.
+pub const FOREIGN_KEYS: &str = "SELECT quote_ident(child.relname), quote_ident(parent.relname)
+FROM pg_constraint AS con
+JOIN pg_class AS child ON child.oid = con.conrelid
+JOIN pg_class AS parent ON parent.oid = con.confrelid
+WHERE con.contype = 'f'
+  AND con.connamespace = current_schema()::regnamespace
+  AND parent.relnamespace = current_schema()::regnamespace;";
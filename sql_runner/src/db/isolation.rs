@@ -0,0 +1,352 @@
+use crate::db::progress::InitProgress;
+use crate::db::sandbox::writer_name;
+use crate::db::script::split_statements;
+use crate::db::{DB, DatabaseType, GRADER_VERSION, SqlExecutionError};
+use log::debug;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Executor, Pool};
+use std::sync::Arc;
+
+/// How the environments are isolated from each other.
+#[derive(Debug, Clone)]
+pub enum IsolationStrategy {
+    /// Every environment gets its own database and a read-only user, requires the root user
+    /// to be allowed to create databases and roles.
+    DatabasePerEnvironment,
+    /// Every environment gets its own schema in the database of the root connection. Queries
+    /// are executed by a single pre-existing role which is granted read access to each schema,
+    /// for managed services where creating databases or roles is not permitted.
+    ///
+    /// The role can read every environment schema, so environments are only separated by the
+    /// `search_path` of the connection.
+    SchemaPerEnvironment { role: String, role_password: String },
+}
+
+impl DB {
+    pub(super) async fn environment_exists(&self, name: &str) -> Result<bool, SqlExecutionError> {
+        let query = match self.isolation {
+            IsolationStrategy::DatabasePerEnvironment => {
+                "SELECT 1 FROM pg_database WHERE datname = $1"
+            }
+            IsolationStrategy::SchemaPerEnvironment { .. } => {
+                "SELECT 1 FROM pg_namespace WHERE nspname = $1"
+            }
+        };
+        Ok(sqlx::query(query)
+            .bind(name)
+            .fetch_optional(&self.root_connection)
+            .await?
+            .is_some())
+    }
+
+    /// Names of all environments, recognised by their hash names.
+    pub(super) async fn environment_names(&self) -> Result<Vec<String>, SqlExecutionError> {
+        let query = match self.isolation {
+            IsolationStrategy::DatabasePerEnvironment => {
+                "SELECT datname FROM pg_database WHERE datname ~ '^[0-9a-f]{63}$'"
+            }
+            IsolationStrategy::SchemaPerEnvironment { .. } => {
+                "SELECT nspname FROM pg_namespace WHERE nspname ~ '^[0-9a-f]{63}$'"
+            }
+        };
+        Ok(sqlx::query_scalar(query)
+            .fetch_all(&self.root_connection)
+            .await?)
+    }
+
+    /// Names of the environments created by another grader version, including those created
+    /// before the version was recorded.
+    pub async fn outdated_environment_names(&self) -> Result<Vec<String>, SqlExecutionError> {
+        let query = match self.isolation {
+            IsolationStrategy::DatabasePerEnvironment => {
+                "SELECT datname FROM pg_database WHERE datname ~ '^[0-9a-f]{63}$' \
+                 AND shobj_description(oid, 'pg_database') IS DISTINCT FROM $1"
+            }
+            IsolationStrategy::SchemaPerEnvironment { .. } => {
+                "SELECT nspname FROM pg_namespace WHERE nspname ~ '^[0-9a-f]{63}$' \
+                 AND obj_description(oid, 'pg_namespace') IS DISTINCT FROM $1"
+            }
+        };
+        Ok(sqlx::query_scalar(query)
+            .bind(grader_version_comment())
+            .fetch_all(&self.root_connection)
+            .await?)
+    }
+
+    /// Stores the grader version as comment of the database or schema.
+    // Name must be trusted as queries used to comment don't support bind
+    async fn record_grader_version(&self, name: &str) -> Result<(), SqlExecutionError> {
+        let object = match self.isolation {
+            IsolationStrategy::DatabasePerEnvironment => "DATABASE",
+            IsolationStrategy::SchemaPerEnvironment { .. } => "SCHEMA",
+        };
+        self.root_connection
+            .execute(
+                format!(
+                    "COMMENT ON {object} \"{name}\" IS '{}';",
+                    grader_version_comment()
+                )
+                .as_str(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Drops the environment after closing the cached connections to it. Fails if a
+    /// connection which is not cached, e.g. of a running initialisation, still uses it.
+    // Name must be trusted as queries used to drop the environment don't support bind
+    pub(super) async fn drop_environment(&self, name: &str) -> Result<(), SqlExecutionError> {
+        let suffix = format!("@{name}");
+        let pools = self
+            .connections
+            .remove_matching(|key| key.ends_with(&suffix))
+            .await;
+        for pool in pools {
+            pool.close().await;
+        }
+        match &self.isolation {
+            IsolationStrategy::DatabasePerEnvironment => {
+                debug!("Dropping database {name}");
+                self.root_connection
+                    .execute(format!("DROP DATABASE IF EXISTS \"{name}\";").as_str())
+                    .await?;
+                self.drop_template(name).await?;
+                self.root_connection
+                    .execute(format!("DROP USER IF EXISTS \"{name}\";").as_str())
+                    .await?;
+                self.root_connection
+                    .execute(format!("DROP USER IF EXISTS \"{}\";", writer_name(name)).as_str())
+                    .await?;
+            }
+            IsolationStrategy::SchemaPerEnvironment { .. } => {
+                debug!("Dropping schema {name}");
+                self.root_connection
+                    .execute(format!("DROP SCHEMA IF EXISTS \"{name}\" CASCADE;").as_str())
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Connection used to execute queries in an existing environment.
+    pub(super) async fn environment_connection(
+        &self,
+        name: &str,
+        password_hash: &str,
+    ) -> Result<Arc<Pool<DatabaseType>>, SqlExecutionError> {
+        let (key, options) = self.environment_options(name, password_hash);
+        self.get_connection(key, options).await
+    }
+
+    /// Pool key and options of the connection used to execute queries in an environment.
+    fn environment_options(&self, name: &str, password_hash: &str) -> (String, PgConnectOptions) {
+        match &self.isolation {
+            IsolationStrategy::DatabasePerEnvironment => (
+                format!("{name}@{name}"),
+                self.connect_options
+                    .clone()
+                    .username(name)
+                    .database(name)
+                    .password(password_hash),
+            ),
+            IsolationStrategy::SchemaPerEnvironment {
+                role,
+                role_password,
+            } => (
+                format!("{role}@{name}"),
+                self.connect_options
+                    .clone()
+                    .username(role)
+                    .password(role_password)
+                    .options([("search_path", name)]),
+            ),
+        }
+    }
+
+    pub(super) async fn create_environment(
+        &self,
+        environment: &str,
+        name: &str,
+        password_hash: &str,
+    ) -> Result<Arc<Pool<DatabaseType>>, SqlExecutionError> {
+        let environment_lock = self
+            .creation_locks
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+        let _environment_lock = environment_lock.lock().await;
+        if !self.environment_exists(name).await? {
+            let progress = Arc::new(InitProgress::new(split_statements(environment).len()));
+            self.init_progress
+                .lock()
+                .unwrap()
+                .insert(name.to_string(), progress.clone());
+            let _permit = self
+                .creation_semaphore
+                .acquire()
+                .await
+                .expect("creation semaphore is never closed");
+            progress.start();
+            let result = match &self.isolation {
+                IsolationStrategy::DatabasePerEnvironment => {
+                    self.create_database(environment, name, password_hash, &progress)
+                        .await
+                }
+                IsolationStrategy::SchemaPerEnvironment { role, .. } => {
+                    self.create_schema(environment, name, role, &progress).await
+                }
+            };
+            let result = match result {
+                Ok(()) => self.record_grader_version(name).await,
+                err => err,
+            };
+            match &result {
+                Ok(()) => {
+                    self.init_progress.lock().unwrap().remove(name);
+                    self.creation_locks.lock().unwrap().remove(name);
+                }
+                Err(err) => progress.fail(err.to_string()),
+            }
+            result?;
+        }
+        self.environment_connection(name, password_hash).await
+    }
+
+    async fn create_database(
+        &self,
+        environment: &str,
+        name: &str,
+        password_hash: &str,
+        progress: &InitProgress,
+    ) -> Result<(), SqlExecutionError> {
+        if self.template_databases {
+            return self
+                .create_database_from_template(environment, name, password_hash, progress)
+                .await;
+        }
+        if !self.claim_pooled_database(name, password_hash).await? {
+            debug!("Creating database {name}");
+            self.create_database_and_user(name, password_hash).await?;
+        }
+
+        debug!("Initialising database {name}");
+        let (key, options) = self.environment_options(name, password_hash);
+        let conn = self.get_connection(key, options.clone()).await?;
+        self.init_environment(&conn, options, environment, progress)
+            .await?;
+
+        debug!("Updating permission for database {name}");
+        let options = self.connect_options.clone().database(name);
+        let root_conn = self
+            .get_connection(format!("{}@{name}", self.db_root_username), options)
+            .await?;
+        self.make_database_readonly(&*root_conn, name).await
+    }
+
+    // Name and password must be trusted as queries used to create database
+    async fn create_database_and_user(
+        &self,
+        name: &str,
+        password: &str,
+    ) -> Result<(), SqlExecutionError> {
+        self.root_connection
+            .execute(format!("CREATE DATABASE \"{name}\";").as_str())
+            .await?;
+        self.root_connection
+            .execute(
+                format!("CREATE USER \"{name}\" WITH ENCRYPTED PASSWORD '{password}';").as_str(),
+            )
+            .await?;
+        self.root_connection
+            .execute(format!("ALTER DATABASE \"{name}\" OWNER TO \"{name}\";").as_str())
+            .await?;
+
+        Ok(())
+    }
+
+    // Name must be trusted as queries used to change permission don't support bind
+    pub(super) async fn make_database_readonly<
+        'c,
+        E: Executor<'c, Database = DatabaseType> + Copy,
+    >(
+        &self,
+        root_conn: E,
+        name: &str,
+    ) -> Result<(), SqlExecutionError> {
+        root_conn
+            .execute(
+                format!(
+                    "REASSIGN OWNED BY \"{name}\" TO \"{}\";",
+                    self.db_root_username
+                )
+                .as_str(),
+            )
+            .await?;
+        root_conn
+            .execute(
+                format!(
+                    "ALTER DATABASE \"{name}\" OWNER TO \"{}\";",
+                    self.db_root_username
+                )
+                .as_str(),
+            )
+            .await?;
+        root_conn
+            .execute(format!("GRANT CONNECT ON DATABASE \"{name}\" TO \"{name}\";").as_str())
+            .await?;
+        root_conn
+            .execute(format!("GRANT USAGE ON SCHEMA public TO \"{name}\";").as_str())
+            .await?;
+        root_conn
+            .execute(format!("GRANT SELECT ON ALL TABLES IN SCHEMA public TO \"{name}\";").as_str())
+            .await?;
+        Ok(())
+    }
+
+    // Name and role must be trusted as queries used to create the schema don't support bind
+    async fn create_schema(
+        &self,
+        environment: &str,
+        name: &str,
+        role: &str,
+        progress: &InitProgress,
+    ) -> Result<(), SqlExecutionError> {
+        debug!("Creating schema {name}");
+        self.root_connection
+            .execute(format!("CREATE SCHEMA \"{name}\";").as_str())
+            .await?;
+
+        // A dedicated pool, so the search_path of the shared root connection stays untouched
+        debug!("Initialising schema {name}");
+        let options = self
+            .connect_options
+            .clone()
+            .options([("search_path", name)]);
+        let root_conn = PgPoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.clone())
+            .await?;
+        let init = self
+            .init_environment(&root_conn, options, environment, progress)
+            .await;
+        root_conn.close().await;
+        init?;
+
+        debug!("Updating permission for schema {name}");
+        self.root_connection
+            .execute(format!("GRANT USAGE ON SCHEMA \"{name}\" TO \"{role}\";").as_str())
+            .await?;
+        self.root_connection
+            .execute(
+                format!("GRANT SELECT ON ALL TABLES IN SCHEMA \"{name}\" TO \"{role}\";").as_str(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+fn grader_version_comment() -> String {
+    format!("grader_version={GRADER_VERSION}")
+}
@@ -1,86 +1,295 @@
+pub mod audit;
+pub mod budget;
+pub mod cleanup;
+pub mod column_names;
+pub mod comparison;
+pub mod connections;
+pub mod decode;
+pub mod expectation;
+pub mod fingerprint;
+pub mod history;
+pub mod init_plan;
 mod introspect;
+pub mod isolation;
+pub mod normalise;
+pub mod plan;
+pub mod progress;
+pub mod result_cache;
+pub mod result_diff;
+pub mod row_diff;
+pub mod sandbox;
+pub mod script;
+pub mod seed;
+mod template;
 pub mod types;
+pub mod usage;
+pub mod warm_pool;
 
-use crate::db::types::{DatabaseInfo, ResultSet, ResultSetExtension, SqlValue};
-use futures::{StreamExt, TryStreamExt};
-use log::{debug, error};
+use crate::db::audit::{AuditLog, CALLER, METADATA};
+use crate::db::budget::{BUDGET_KEY, ExecutionBudget};
+use crate::db::comparison::{
+    Comparison, ComparisonSpec, FailureComparison, compare_failures, compare_result_sets,
+};
+use crate::db::connections::ConnectionManager;
+use crate::db::decode::{column_names, decode_row};
+use crate::db::fingerprint::{FingerprintCount, FingerprintStats};
+use crate::db::history::{QueryHistory, QueryHistoryEntry, QueryStatus};
+use crate::db::isolation::IsolationStrategy;
+use crate::db::plan::{QueryPlan, parse_plan};
+use crate::db::progress::{EnvironmentState, EnvironmentStatus, InitProgress};
+use crate::db::result_cache::{ResultCache, cache_key};
+use crate::db::sandbox::WriteMode;
+use crate::db::script::split_statements;
+use crate::db::seed::{SeedStore, parse_seed_copy};
+use crate::db::types::{DatabaseInfo, ResultSet, SqlValue};
+use crate::db::usage::{CleanupPolicy, EnvironmentUsage};
+use crate::db::warm_pool::WarmPool;
+use crate::deny::DenyRules;
+use bytes::Bytes;
+use common::stats::{ConnectionPoolStats, ErrorRate, RecentErrors};
+use futures::channel::mpsc;
+use futures::future::try_join_all;
+use futures::stream::BoxStream;
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
-use sqlx::postgres::{PgPoolOptions, PgRow};
-use sqlx::types::Decimal;
-use sqlx::{Column, Executor, FromRow, Pool, Postgres, Row};
-use std::cell::OnceCell;
+use sqlparser::ast::Statement;
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlx::postgres::{PgConnectOptions, PgPoolCopyExt, PgPoolOptions, PgRow};
+use sqlx::{Column, Executor, FromRow, Pool, Postgres};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
 use utoipa::ToSchema;
 
 type DatabaseType = Postgres;
 type RowType = PgRow;
 
+/// Rows buffered for a streamed query before it waits for the client to catch up.
+const STREAM_BUFFER_ROWS: usize = 64;
+
+/// Part of a streamed query result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowEvent {
+    /// Column names, first and also for results without rows
+    Columns(Vec<String>),
+    Row(Vec<SqlValue>),
+    /// Number of rows, after the last one
+    End {
+        rows: usize,
+    },
+}
+
 #[derive(Debug)]
 pub struct DB {
     root_connection: Pool<DatabaseType>,
-    connections: Mutex<HashMap<String, Arc<Pool<DatabaseType>>>>,
+    connections: ConnectionManager,
     password_hash_key: [u8; 32],
-    db_host: String,
+    connect_options: PgConnectOptions,
     db_root_username: String,
-    db_root_password: String,
     max_rows_in_result_set: usize,
     statement_timeout: u64,
-    create_db_mutex: Mutex<()>,
+    /// Serialises the creation of each environment
+    creation_locks: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    creation_semaphore: Semaphore,
+    timeouts: AtomicU64,
+    history: QueryHistory,
+    fingerprints: FingerprintStats,
+    recent_errors: ErrorRate,
+    isolation: IsolationStrategy,
+    deny_rules: DenyRules,
+    audit: Option<AuditLog>,
+    init_progress: std::sync::Mutex<HashMap<String, Arc<InitProgress>>>,
+    init_concurrency: usize,
+    seed_store: Option<SeedStore>,
+    budget: Option<ExecutionBudget>,
+    usage: EnvironmentUsage,
+    cleanup_policy: CleanupPolicy,
+    warm_pool: WarmPool,
+    result_cache: Option<ResultCache>,
+    template_databases: bool,
 }
 
 impl DB {
     pub async fn connect(
-        db_host: String,
-        db_root_username: String,
-        db_root_password: String,
+        connect_options: PgConnectOptions,
         password_hash_key: [u8; 32],
         max_rows_in_result_set: usize,
         statement_timeout: u64,
+        query_history_size: usize,
+        isolation: IsolationStrategy,
+        deny_rules: DenyRules,
     ) -> Result<Self, SqlExecutionError> {
         Ok(DB {
             root_connection: PgPoolOptions::new()
-                .connect(&format!(
-                    "postgresql://{}:{}@{}",
-                    db_root_username, db_root_password, db_host
-                ))
+                .connect_with(connect_options.clone())
                 .await?,
             connections: Default::default(),
             password_hash_key,
-            db_host,
-            db_root_username,
-            db_root_password,
+            db_root_username: connect_options.get_username().to_string(),
+            connect_options,
             max_rows_in_result_set,
             statement_timeout,
-            create_db_mutex: Default::default(),
+            creation_locks: Default::default(),
+            creation_semaphore: Semaphore::new(1),
+            timeouts: Default::default(),
+            history: QueryHistory::new(query_history_size),
+            fingerprints: Default::default(),
+            recent_errors: Default::default(),
+            isolation,
+            deny_rules,
+            audit: None,
+            init_progress: Default::default(),
+            init_concurrency: 1,
+            seed_store: None,
+            budget: None,
+            usage: Default::default(),
+            cleanup_policy: Default::default(),
+            warm_pool: Default::default(),
+            result_cache: None,
+            template_databases: false,
         })
     }
 
+    /// Executes the inserts of environment scripts on up to `init_concurrency` connections.
+    pub fn with_init_concurrency(self, init_concurrency: usize) -> Self {
+        DB {
+            init_concurrency,
+            ..self
+        }
+    }
+
+    /// Creates and initialises up to `creation_concurrency` environments at the same time,
+    /// further ones are queued.
+    pub fn with_creation_concurrency(self, creation_concurrency: usize) -> Self {
+        DB {
+            creation_semaphore: Semaphore::new(creation_concurrency.max(1)),
+            ..self
+        }
+    }
+
+    /// Resolves `COPY ... FROM 'seed:<id>'` statements of environment scripts from the store.
+    pub fn with_seed_store(self, seed_store: SeedStore) -> Self {
+        DB {
+            seed_store: Some(seed_store),
+            ..self
+        }
+    }
+
+    /// Limits the cumulative execution time of every caller, task and user.
+    pub fn with_execution_budget(self, budget: ExecutionBudget) -> Self {
+        DB {
+            budget: Some(budget),
+            ..self
+        }
+    }
+
+    /// Number of blank databases kept ready for new environments, see [`DB::spawn_warm_pool`].
+    pub fn with_warm_pool_size(self, size: usize) -> Self {
+        DB {
+            warm_pool: WarmPool::new(size),
+            ..self
+        }
+    }
+
+    /// Caches the result sets of solution queries, see [`DB::execute_cached`].
+    pub fn with_result_cache(self, result_cache: ResultCache) -> Self {
+        DB {
+            result_cache: Some(result_cache),
+            ..self
+        }
+    }
+
+    /// Initialises every environment once in a template database and creates the environment
+    /// and the copies of [`WriteMode::copy`] from it. Requires database isolation.
+    pub fn with_template_databases(self, template_databases: bool) -> Self {
+        DB {
+            template_databases,
+            ..self
+        }
+    }
+
+    /// Keeps at most `max_pools` connection pools open, 0 for no limit, and closes those
+    /// unused for `idle_timeout`.
+    pub fn with_connection_limits(self, max_pools: usize, idle_timeout: Option<Duration>) -> Self {
+        DB {
+            connections: ConnectionManager::new(max_pools, idle_timeout),
+            ..self
+        }
+    }
+
+    /// Drops environments according to the policy when cleaning up.
+    pub fn with_cleanup_policy(self, cleanup_policy: CleanupPolicy) -> Self {
+        DB {
+            cleanup_policy,
+            ..self
+        }
+    }
+
+    /// Stores seed data to be referenced by environment scripts and returns its id.
+    pub async fn store_seed_data(&self, data: &[u8]) -> Result<String, SqlExecutionError> {
+        let store = self.seed_store.as_ref().ok_or_else(|| {
+            SqlExecutionError::SeedData("no seed data store is configured".to_string())
+        })?;
+        store
+            .store(data)
+            .await
+            .map_err(|err| SqlExecutionError::SeedData(err.to_string()))
+    }
+
+    /// Records every query in the audit log.
+    pub fn with_audit_log(self, audit: AuditLog) -> Self {
+        DB {
+            audit: Some(audit),
+            ..self
+        }
+    }
+
     pub async fn execute(
         &self,
         environment: &str,
         query: &str,
         include_database_info: bool,
     ) -> Result<(ResultSet, Option<DatabaseInfo>), SqlExecutionError> {
-        let environment_hash = blake3::hash(environment.as_bytes()).to_hex().to_string();
+        let environment_hash = environment_hash(environment);
         let db_name = &environment_hash[..63];
-        let password_hash =
-            blake3::keyed_hash(&self.password_hash_key, environment_hash.as_bytes())
-                .to_hex()
-                .to_string();
-        let db_exists = self.db_exists(db_name).await?;
-
-        let conn = if !db_exists {
-            self.create_db(environment, db_name, &password_hash).await?
-        } else {
-            self.get_connection(db_name, db_name, &password_hash)
-                .await?
-        };
+        if let Some(rule) = self.deny_rules.check(query) {
+            let err = SqlExecutionError::Denied(rule.to_string());
+            self.audit(db_name, query, Duration::ZERO, Err(&err));
+            return Err(err);
+        }
+        self.check_budget()?;
+        let conn = self
+            .connect_environment(environment, &environment_hash)
+            .await?;
 
         debug!("Executing query in {db_name}");
-        let result_set = self.extract(&*conn, query).await?;
+        let start = Instant::now();
+        let mut result = self.extract(&*conn, query).await;
+        let conn = match &result {
+            Err(err) if err.is_connection_error() => {
+                warn!("Lost connection to {db_name}, reconnecting: {err}");
+                self.forget_connection(&conn).await;
+                let conn = self
+                    .connect_environment(environment, &environment_hash)
+                    .await?;
+                result = self.extract(&*conn, query).await;
+                conn
+            }
+            _ => conn,
+        };
+        self.record_query(
+            db_name,
+            query,
+            start.elapsed(),
+            result.as_ref().map(|result_set| result_set.rows.len()),
+        )
+        .await;
+        let result_set = result?;
         let database_info = if include_database_info {
             Some(self.get_database_information(&*conn).await?)
         } else {
@@ -89,156 +298,433 @@ impl DB {
         Ok((result_set, database_info))
     }
 
-    async fn db_exists(&self, db_name: &str) -> Result<bool, SqlExecutionError> {
-        Ok(sqlx::query("SELECT 1 FROM pg_database WHERE datname = $1")
-            .bind(db_name)
-            .fetch_optional(&self.root_connection)
-            .await?
-            .is_some())
-    }
-
-    async fn create_db(
+    /// Executes the query unless its result set is cached. Only read-only queries are cached,
+    /// cache hits are neither recorded in the history nor the audit log.
+    pub async fn execute_cached(
         &self,
         environment: &str,
-        db_name: &str,
-        password_hash: &str,
-    ) -> Result<Arc<Pool<DatabaseType>>, SqlExecutionError> {
-        let _create_db_lock = self.create_db_mutex.lock().await;
-        let db_exists = self.db_exists(db_name).await?;
+        query: &str,
+    ) -> Result<ResultSet, SqlExecutionError> {
+        let cached = self.result_cache.as_ref().and_then(|cache| {
+            cache_key(&environment_hash(environment), query).map(|key| (cache, key))
+        });
+        let Some((cache, key)) = cached else {
+            return Ok(self.execute(environment, query, false).await?.0);
+        };
+        if let Some(result_set) = cache.get(&key, Instant::now()) {
+            debug!("Serving cached result set of query {key}");
+            return Ok(result_set);
+        }
+        let (result_set, _) = self.execute(environment, query, false).await?;
+        cache.insert(key, &result_set, Instant::now());
+        Ok(result_set)
+    }
 
-        if !db_exists {
-            debug!("Creating database {db_name}");
-            self.create_database_and_user(db_name, password_hash)
-                .await?;
+    /// Streams the rows of the query while they arrive from Postgres, the column names first
+    /// and the number of rows last. Errors after the stream started end it.
+    ///
+    /// Postgres sends rows whenever its output buffer of a few kilobytes fills, so small rows
+    /// of a slow query may still arrive together.
+    pub async fn execute_stream(
+        self: &Arc<Self>,
+        environment: &str,
+        query: &str,
+    ) -> Result<BoxStream<'static, Result<RowEvent, SqlExecutionError>>, SqlExecutionError> {
+        let environment_hash = environment_hash(environment);
+        let db_name = environment_hash[..63].to_string();
+        if let Some(rule) = self.deny_rules.check(query) {
+            let err = SqlExecutionError::Denied(rule.to_string());
+            self.audit(&db_name, query, Duration::ZERO, Err(&err));
+            return Err(err);
         }
+        self.check_budget()?;
+        let conn = self
+            .connect_environment(environment, &environment_hash)
+            .await?;
 
-        let conn = self.get_connection(db_name, db_name, password_hash).await?;
+        debug!("Streaming query in {db_name}");
+        let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER_ROWS);
+        let (db, query) = (Arc::clone(self), query.to_string());
+        let stream = async move {
+            let start = Instant::now();
+            let result = async {
+                // the header is also sent for results without rows
+                let describe = (&*conn)
+                    .describe(&query)
+                    .await
+                    .map_err(SqlExecutionError::from_execute)?;
+                let columns = describe
+                    .columns()
+                    .iter()
+                    .map(|column| column.name().to_string())
+                    .collect();
+                // the client went away, dropping the rows cancels the query
+                if sender.send(Ok(RowEvent::Columns(columns))).await.is_err() {
+                    return Ok(0);
+                }
+                let mut rows = sqlx::query(&query)
+                    .fetch(&*conn)
+                    .take(db.max_rows_in_result_set);
+                let mut count = 0;
+                while let Some(row) = rows.next().await {
+                    let row = row.map_err(SqlExecutionError::from_execute)?;
+                    let values = decode_row(&row).map_err(SqlExecutionError::ColumnDecodeError)?;
+                    if sender.send(Ok(RowEvent::Row(values))).await.is_err() {
+                        break;
+                    }
+                    count += 1;
+                }
+                Ok(count)
+            }
+            .await;
+            db.record_query(&db_name, &query, start.elapsed(), result.as_ref().copied())
+                .await;
+            let _ = sender.send(result.map(|rows| RowEvent::End { rows })).await;
+        };
+        tokio::spawn(request_scope().run(stream));
+        Ok(receiver.boxed())
+    }
 
-        if !db_exists {
-            debug!("Initialising database {db_name}");
-            self.init_environment(&*conn, environment).await?;
-            debug!("Updating permission for database {db_name}");
-            let root_conn = self
-                .get_connection(db_name, &self.db_root_username, &self.db_root_password)
-                .await?;
-            self.make_database_readonly(&*root_conn, db_name).await?;
+    /// Streams the result of the query in the Postgres binary COPY format, without applying
+    /// the row limit. The execution is charged and recorded once the stream ends.
+    pub async fn copy_out(
+        self: &Arc<Self>,
+        environment: &str,
+        query: &str,
+    ) -> Result<BoxStream<'static, Result<Bytes, std::io::Error>>, SqlExecutionError> {
+        let environment_hash = environment_hash(environment);
+        let db_name = &environment_hash[..63];
+        let statements = split_statements(query);
+        let statement = match (self.deny_rules.check(query), statements.as_slice()) {
+            (Some(rule), _) => Err(SqlExecutionError::Denied(rule.to_string())),
+            // the query is pasted into `COPY`, which must not be able to end it early
+            (None, [statement]) if !is_single_query(statement) => {
+                Err(SqlExecutionError::Denied("single_query".to_string()))
+            }
+            (None, [statement]) => Ok(statement),
+            (None, _) => Err(SqlExecutionError::Denied("single_statement".to_string())),
+        };
+        let statement = statement.inspect_err(|err| {
+            self.audit(db_name, query, Duration::ZERO, Err(err));
+        })?;
+        self.check_budget()?;
+        let conn = self
+            .connect_environment(environment, &environment_hash)
+            .await?;
+
+        debug!("Copying query result in {db_name}");
+        let start = Instant::now();
+        // a trailing line comment of the query must not hide the closing parenthesis
+        let copy = format!("COPY ({statement}\n) TO STDOUT WITH (FORMAT binary)");
+        let stream = match conn.copy_out_raw(&copy).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                let err = SqlExecutionError::from_execute(err);
+                self.record_copy(db_name, query, start.elapsed(), Some(&err))
+                    .await;
+                return Err(err);
+            }
+        };
+        let mut finish = CopyFinish {
+            db: Arc::clone(self),
+            db_name: db_name.to_string(),
+            query: query.to_string(),
+            start,
+            error: None,
+            scope: request_scope(),
+        };
+        Ok(stream
+            .map(move |chunk| {
+                chunk.map_err(|err| {
+                    let message = err.to_string();
+                    finish.error = Some(SqlExecutionError::from_execute(err));
+                    std::io::Error::other(message)
+                })
+            })
+            .boxed())
+    }
+
+    /// Records a `COPY` like [`DB::record_query`], whose number of rows is unknown.
+    async fn record_copy(
+        &self,
+        db_name: &str,
+        query: &str,
+        duration: Duration,
+        error: Option<&SqlExecutionError>,
+    ) {
+        self.record_statistics(db_name, query, duration, error)
+            .await;
+        if let Some(audit) = &self.audit {
+            audit.record(
+                db_name,
+                query,
+                duration,
+                None,
+                error.map(ToString::to_string),
+            );
         }
+    }
 
-        Ok(conn)
+    /// Plan of the query, which is executed if `analyze` is set. The statement timeout and
+    /// the execution budget apply as to any other query.
+    pub async fn explain(
+        &self,
+        environment: &str,
+        query: &str,
+        analyze: bool,
+    ) -> Result<QueryPlan, SqlExecutionError> {
+        let environment_hash = environment_hash(environment);
+        let db_name = &environment_hash[..63];
+        let explain = match (
+            self.deny_rules.check(query),
+            split_statements(query).as_slice(),
+        ) {
+            (Some(rule), _) => Err(SqlExecutionError::Denied(rule.to_string())),
+            (None, [query]) => Ok(format!(
+                "EXPLAIN (FORMAT JSON, ANALYZE {analyze}, BUFFERS {analyze}) {query}"
+            )),
+            (None, _) => Err(SqlExecutionError::Denied("single_statement".to_string())),
+        };
+        let explain = match explain {
+            Ok(explain) => explain,
+            Err(err) => {
+                self.audit(db_name, query, Duration::ZERO, Err(&err));
+                return Err(err);
+            }
+        };
+        self.check_budget()?;
+        let conn = self
+            .connect_environment(environment, &environment_hash)
+            .await?;
+
+        debug!("Explaining query in {db_name}");
+        let start = Instant::now();
+        let result = sqlx::query_scalar::<_, serde_json::Value>(&explain)
+            .fetch_one(&*conn)
+            .await
+            .map_err(SqlExecutionError::from_execute);
+        self.record_query(
+            db_name,
+            &explain,
+            start.elapsed(),
+            result.as_ref().map(|_| 1),
+        )
+        .await;
+        parse_plan(&result?)
+            .ok_or_else(|| SqlExecutionError::ColumnDecodeError("QUERY PLAN".to_string()))
     }
 
-    pub async fn compare(
+    fn check_budget(&self) -> Result<(), SqlExecutionError> {
+        match &self.budget {
+            Some(budget) if budget.exceeded() => Err(SqlExecutionError::BudgetExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// Connection to the environment, which is created if it does not exist yet.
+    async fn connect_environment(
         &self,
         environment: &str,
-        query_a: &str,
-        query_b: &str,
-        row_norm: RowNormalisation,
-        col_norm: ColumnNormalisation,
-    ) -> Result<(ResultSet, ResultSet, bool), SqlExecutionError> {
-        let (mut result_a, _) = self.execute(environment, query_a, false).await?;
-        let (mut result_b, _) = self.execute(environment, query_b, false).await?;
-
-        if col_norm == ColumnNormalisation::NumberColumnsByOrder {
-            result_a.number_columns();
-            result_b.number_columns();
-        } else if col_norm == ColumnNormalisation::SortColumnsByName {
-            result_a.sort_columns();
-            result_b.sort_columns();
+        environment_hash: &str,
+    ) -> Result<Arc<Pool<DatabaseType>>, SqlExecutionError> {
+        let db_name = &environment_hash[..63];
+        self.usage.touch(db_name);
+        let password_hash =
+            blake3::keyed_hash(&self.password_hash_key, environment_hash.as_bytes())
+                .to_hex()
+                .to_string();
+        if !self.environment_exists(db_name).await? {
+            self.create_environment(environment, db_name, &password_hash)
+                .await
+        } else {
+            self.environment_connection(db_name, &password_hash).await
         }
-        if row_norm == RowNormalisation::SortRows {
-            result_a.sort_rows();
-            result_b.sort_rows();
+    }
+
+    /// Creates the environment if it does not exist yet.
+    pub async fn prepare_environment(&self, environment: &str) -> Result<(), SqlExecutionError> {
+        let environment_hash = environment_hash(environment);
+        let db_name = &environment_hash[..63];
+        self.usage.touch(db_name);
+        if !self.environment_exists(db_name).await? {
+            let password_hash =
+                blake3::keyed_hash(&self.password_hash_key, environment_hash.as_bytes())
+                    .to_hex()
+                    .to_string();
+            self.create_environment(environment, db_name, &password_hash)
+                .await?;
         }
+        Ok(())
+    }
 
-        let eq = result_a == result_b;
-        Ok((result_a, result_b, eq))
+    /// Progress of a running or failed initialisation of the environment, if any.
+    pub fn init_progress(&self, environment: &str) -> Option<EnvironmentStatus> {
+        self.init_progress
+            .lock()
+            .unwrap()
+            .get(environment_name(environment).as_str())
+            .map(|progress| progress.status())
     }
 
-    // Name and password must be trusted as queries used to create database
-    async fn create_database_and_user(
+    /// Number of environments in the state, which must be `Queued` or `Initialising`.
+    pub fn init_count(&self, state: EnvironmentState) -> usize {
+        self.init_progress
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|progress| progress.status().state == state)
+            .count()
+    }
+
+    pub async fn environment_status(
         &self,
         name: &str,
-        password: &str,
-    ) -> Result<(), SqlExecutionError> {
-        self.root_connection
-            .execute(format!("CREATE DATABASE \"{name}\";").as_str())
-            .await?;
-        self.root_connection
-            .execute(
-                format!("CREATE USER \"{name}\" WITH ENCRYPTED PASSWORD '{password}';").as_str(),
-            )
-            .await?;
-        self.root_connection
-            .execute(format!("ALTER DATABASE \"{name}\" OWNER TO \"{name}\";").as_str())
-            .await?;
+    ) -> Result<EnvironmentStatus, SqlExecutionError> {
+        if let Some(progress) = self.init_progress.lock().unwrap().get(name) {
+            return Ok(progress.status());
+        }
+        Ok(EnvironmentStatus::new(
+            if self.environment_exists(name).await? {
+                EnvironmentState::Ready
+            } else {
+                EnvironmentState::Missing
+            },
+        ))
+    }
 
-        Ok(())
+    /// Charges the execution budget and records the query in the history, the statistics and
+    /// the audit log.
+    async fn record_query(
+        &self,
+        db_name: &str,
+        query: &str,
+        duration: Duration,
+        result: Result<usize, &SqlExecutionError>,
+    ) {
+        self.record_statistics(db_name, query, duration, result.err())
+            .await;
+        self.audit(db_name, query, duration, result);
     }
 
-    // Name must be trusted as queries used to change permission don't support bind
-    async fn make_database_readonly<'c, E: Executor<'c, Database = DatabaseType> + Copy>(
+    /// Charges the execution budget and records the query in the history and the statistics.
+    async fn record_statistics(
         &self,
-        root_conn: E,
-        name: &str,
-    ) -> Result<(), SqlExecutionError> {
-        root_conn
-            .execute(
-                format!(
-                    "REASSIGN OWNED BY \"{name}\" TO \"{}\";",
-                    self.db_root_username
-                )
-                .as_str(),
-            )
-            .await?;
-        root_conn
-            .execute(
-                format!(
-                    "ALTER DATABASE \"{name}\" OWNER TO \"{}\";",
-                    self.db_root_username
-                )
-                .as_str(),
-            )
-            .await?;
-        root_conn
-            .execute(format!("GRANT CONNECT ON DATABASE \"{name}\" TO \"{name}\";").as_str())
-            .await?;
-        root_conn
-            .execute(format!("GRANT USAGE ON SCHEMA public TO \"{name}\";").as_str())
-            .await?;
-        root_conn
-            .execute(format!("GRANT SELECT ON ALL TABLES IN SCHEMA public TO \"{name}\";").as_str())
-            .await?;
-        Ok(())
+        db_name: &str,
+        query: &str,
+        duration: Duration,
+        error: Option<&SqlExecutionError>,
+    ) {
+        if let Some(budget) = &self.budget {
+            budget.charge(duration);
+        }
+        let status = match error {
+            None => QueryStatus::Ok,
+            Some(SqlExecutionError::Timeout(_)) => {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+                warn!("Query in {db_name} exceeded the statement timeout");
+                QueryStatus::Timeout
+            }
+            Some(_) => QueryStatus::Error,
+        };
+        metrics::counter!(
+            "sql_runner_queries_total",
+            "environment" => db_name.to_string(),
+            "status" => status.as_str()
+        )
+        .increment(1);
+        metrics::histogram!(
+            "sql_runner_query_duration_seconds",
+            "environment" => db_name.to_string()
+        )
+        .record(duration.as_secs_f64());
+        self.history.record(db_name, query, duration, status).await;
+        self.fingerprints.record(db_name, query);
+        self.recent_errors.record(status != QueryStatus::Ok);
     }
 
-    async fn get_connection(
+    fn audit(
         &self,
-        db: &str,
-        username: &str,
-        password_hash: &str,
-    ) -> Result<Arc<Pool<DatabaseType>>, SqlExecutionError> {
-        let mut connections = self.connections.lock().await;
-        let mut connection_option = connections.get(&format!("{username}@{db}"));
-        let connection = match connection_option {
-            None => {
-                let pool = PgPoolOptions::new()
-                    .max_connections(1)
-                    .connect(&format!(
-                        "postgresql://{}:{}@{}/{}",
-                        username, password_hash, self.db_host, db
-                    ))
-                    .await?;
-                pool.execute(
-                    format!("SET statement_timeout to {}", self.statement_timeout).as_str(),
-                )
-                .await?;
-                connections.insert(db.to_string(), Arc::new(pool));
-                connection_option = connections.get(db);
-                connection_option.unwrap()
+        db_name: &str,
+        query: &str,
+        duration: Duration,
+        result: Result<usize, &SqlExecutionError>,
+    ) {
+        if let Some(audit) = &self.audit {
+            let (rows, error) = match result {
+                Ok(rows) => (Some(rows), None),
+                Err(err) => (None, Some(err.to_string())),
+            };
+            audit.record(db_name, query, duration, rows, error);
+        }
+    }
+
+    pub async fn compare(
+        &self,
+        environment: &str,
+        query_a: &str,
+        query_b: &str,
+        spec: &ComparisonSpec,
+        write_mode: Option<&WriteMode>,
+    ) -> Result<Comparison, SqlExecutionError> {
+        let result_a = match write_mode {
+            Some(write_mode) => {
+                self.execute_sandboxed(environment, query_a, write_mode)
+                    .await
+            }
+            None => self.execute_cached(environment, query_a).await,
+        };
+        let result_a = match result_a {
+            Err(SqlExecutionError::Execute(err)) if spec.matches_failures() => Err(err),
+            result_a => Ok(result_a?),
+        };
+        let result_b = match write_mode {
+            Some(write_mode) => {
+                self.execute_sandboxed(environment, query_b, write_mode)
+                    .await
             }
-            Some(option) => option,
+            None => self
+                .execute(environment, query_b, false)
+                .await
+                .map(|(result_set, _)| result_set),
         };
-        Ok(connection.clone())
+        match (result_a, result_b) {
+            (Ok(result_a), result_b) => Ok(compare_result_sets(result_a, result_b?, spec)),
+            (Err(err_a), Ok(result_b)) => Ok(compare_failures(
+                FailureComparison::new(sqlstate(&err_a), None),
+                result_b,
+            )),
+            (Err(err_a), Err(SqlExecutionError::Execute(err_b))) => Ok(compare_failures(
+                FailureComparison::new(sqlstate(&err_a), Some(sqlstate(&err_b))),
+                ResultSet {
+                    columns: vec![],
+                    rows: vec![],
+                },
+            )),
+            (Err(_), Err(err_b)) => Err(err_b),
+        }
+    }
+
+    // Pools are cached by key, which must identify user and database or schema of the options
+    async fn get_connection(
+        &self,
+        key: String,
+        options: PgConnectOptions,
+    ) -> Result<Arc<Pool<DatabaseType>>, SqlExecutionError> {
+        // the timeout is part of the options to apply to connections replacing broken ones
+        let connect = PgPoolOptions::new()
+            .max_connections(1)
+            .test_before_acquire(true)
+            .connect_with(
+                options.options([("statement_timeout", self.statement_timeout.to_string())]),
+            );
+        Ok(self.connections.get_or_connect(key, connect).await?)
+    }
+
+    /// Closes a cached pool whose connection broke, e.g. because Postgres restarted, so the
+    /// next request connects again.
+    async fn forget_connection(&self, pool: &Arc<Pool<DatabaseType>>) {
+        self.connections.remove_pool(pool).await;
+        pool.close().await;
     }
 
     async fn extract<'c, E: Executor<'c, Database = DatabaseType>>(
@@ -251,65 +737,136 @@ impl DB {
             .take(self.max_rows_in_result_set)
             .try_collect::<Vec<PgRow>>()
             .await
-            .map_err(SqlExecutionError::Execute)?;
-        let mut cell: OnceCell<ResultSet> = OnceCell::new();
-        let row_len = rows.len();
-        for row in rows {
-            cell.get_or_init(|| ResultSet {
-                columns: row
-                    .columns()
-                    .iter()
-                    .map(|column| column.name().to_string())
-                    .collect(),
-                rows: Vec::with_capacity(row_len),
-            });
-            let cell_ref = cell.get_mut().unwrap();
-            let mut row_set = Vec::with_capacity(row.columns().len());
-            for column in row.columns() {
-                if let Ok(str) = row.try_get::<String, _>(column.name()) {
-                    row_set.push(SqlValue::Text(str))
-                } else if let Ok(d) = row.try_get::<Decimal, _>(column.name()) {
-                    row_set.push(SqlValue::Float(d.try_into().map_err(|_| {
-                        SqlExecutionError::ColumnDecodeError(column.name().to_string())
-                    })?))
-                } else if let Ok(f) = row.try_get::<f64, _>(column.name()) {
-                    row_set.push(SqlValue::Float(f))
-                } else if let Ok(f) = row.try_get::<f32, _>(column.name()) {
-                    row_set.push(SqlValue::Float(f.into()))
-                } else if let Ok(i) = row.try_get::<i64, _>(column.name()) {
-                    row_set.push(SqlValue::Int(i))
-                } else if let Ok(i) = row.try_get::<i32, _>(column.name()) {
-                    row_set.push(SqlValue::Int(i.into()))
-                } else if let Ok(b) = row.try_get::<bool, _>(column.name()) {
-                    row_set.push(SqlValue::Bool(b))
-                } else if let Ok(c) = row.try_get::<sqlx::types::chrono::NaiveDateTime, _>(column.name()) {
-                    row_set.push(SqlValue::Text(c.to_string()))
-                } else if let Ok(c) = row.try_get::<sqlx::types::chrono::NaiveDate, _>(column.name()) {
-                    row_set.push(SqlValue::Text(c.to_string()))
-                } else {
-                    return Err(SqlExecutionError::ColumnDecodeError(
-                        column.name().to_string(),
-                    ));
+            .map_err(SqlExecutionError::from_execute)?;
+        Ok(ResultSet {
+            columns: rows.first().map(column_names).unwrap_or_default(),
+            rows: rows
+                .iter()
+                .map(decode_row)
+                .collect::<Result<_, _>>()
+                .map_err(SqlExecutionError::ColumnDecodeError)?,
+        })
+    }
+
+    async fn init_environment(
+        &self,
+        conn: &Pool<DatabaseType>,
+        options: PgConnectOptions,
+        environment: &str,
+        progress: &InitProgress,
+    ) -> Result<(), SqlExecutionError> {
+        let statements = split_statements(environment);
+        let segments = match self.init_concurrency {
+            0 | 1 => None,
+            _ => init_plan::segments(&statements),
+        };
+        if statements
+            .iter()
+            .any(|statement| parse_seed_copy(statement).is_some())
+        {
+            return self
+                .init_environment_with_seed_data(conn, &statements, progress)
+                .await;
+        }
+        let Some(segments) = segments else {
+            let mut results = conn.execute_many(environment);
+            while let Some(r) = results.next().await {
+                if let Err(err) = r {
+                    return Err(SqlExecutionError::Init(err));
                 }
+                progress.statement_executed();
             }
-            cell_ref.rows.push(row_set);
-        }
+            return Ok(());
+        };
 
-        Ok(cell.into_inner().unwrap_or_else(|| ResultSet {
-            columns: vec![],
-            rows: vec![],
-        }))
+        let pool = PgPoolOptions::new()
+            .max_connections(self.init_concurrency as u32)
+            .connect_with(
+                options.options([("statement_timeout", self.statement_timeout.to_string())]),
+            )
+            .await?;
+        let result = self
+            .init_environment_concurrently(conn, &pool, segments, progress)
+            .await;
+        pool.close().await;
+        result
     }
 
-    async fn init_environment<'c, E: Executor<'c, Database = DatabaseType>>(
+    /// Executes statements one by one, streaming the data of seed `COPY` statements.
+    async fn init_environment_with_seed_data(
         &self,
-        conn: E,
-        environment: &str,
+        conn: &Pool<DatabaseType>,
+        statements: &[&str],
+        progress: &InitProgress,
+    ) -> Result<(), SqlExecutionError> {
+        for statement in statements {
+            match parse_seed_copy(statement) {
+                Some(copy) => {
+                    let data = match &self.seed_store {
+                        Some(store) => store.load(&copy.source).await,
+                        None => None,
+                    }
+                    .ok_or_else(|| {
+                        SqlExecutionError::SeedData(format!(
+                            "seed data of `{}` is not available",
+                            copy.statement
+                        ))
+                    })?;
+                    let mut copy_in = conn
+                        .copy_in_raw(&copy.statement)
+                        .await
+                        .map_err(SqlExecutionError::Init)?;
+                    copy_in.send(data).await.map_err(SqlExecutionError::Init)?;
+                    copy_in.finish().await.map_err(SqlExecutionError::Init)?;
+                }
+                None => {
+                    sqlx::raw_sql(statement)
+                        .execute(conn)
+                        .await
+                        .map_err(SqlExecutionError::Init)?;
+                }
+            }
+            progress.statement_executed();
+        }
+        Ok(())
+    }
+
+    /// Executes statements in order on `conn`, except for consecutive inserts, which are
+    /// spread over `pool` with one connection per table and the tables they reference first.
+    async fn init_environment_concurrently(
+        &self,
+        conn: &Pool<DatabaseType>,
+        pool: &Pool<DatabaseType>,
+        segments: Vec<init_plan::Segment<'_>>,
+        progress: &InitProgress,
     ) -> Result<(), SqlExecutionError> {
-        let mut results = conn.execute_many(environment);
-        while let Some(r) = results.next().await {
-            if let Err(err) = r {
-                return Err(SqlExecutionError::Init(err));
+        for segment in segments {
+            match segment {
+                init_plan::Segment::Statement(statement) => {
+                    sqlx::raw_sql(statement)
+                        .execute(conn)
+                        .await
+                        .map_err(SqlExecutionError::Init)?;
+                    progress.statement_executed();
+                }
+                init_plan::Segment::Inserts(inserts) => {
+                    let foreign_keys: Vec<(String, String)> =
+                        sqlx::query_as(introspect::FOREIGN_KEYS)
+                            .fetch_all(conn)
+                            .await?;
+                    for level in init_plan::insert_levels(&inserts, &foreign_keys) {
+                        try_join_all(level.into_iter().map(|group| async move {
+                            let script = group.join(";\n");
+                            let mut results = pool.execute_many(sqlx::raw_sql(&script));
+                            while let Some(r) = results.next().await {
+                                r.map_err(SqlExecutionError::Init)?;
+                                progress.statement_executed();
+                            }
+                            Ok::<(), SqlExecutionError>(())
+                        }))
+                        .await?;
+                    }
+                }
             }
         }
         Ok(())
@@ -339,6 +896,110 @@ impl DB {
     ) -> Result<Vec<T>, SqlExecutionError> {
         Ok(sqlx::query_as(query).fetch_all(conn).await?)
     }
+
+    /// Most recent queries executed in the given environment database, oldest first.
+    pub async fn query_history(&self, db_name: &str) -> Vec<QueryHistoryEntry> {
+        self.history.get(db_name).await
+    }
+
+    /// Normalised queries executed in the given environment database, most frequent first.
+    pub fn query_fingerprints(&self, db_name: &str) -> Vec<FingerprintCount> {
+        self.fingerprints.get(db_name)
+    }
+
+    pub async fn connection_stats(&self) -> ConnectionPoolStats {
+        self.connections.stats().await
+    }
+
+    pub fn recent_errors(&self) -> RecentErrors {
+        self.recent_errors.recent()
+    }
+
+    /// Number of queries cancelled by the statement timeout since startup.
+    pub fn timeout_count(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether the query is a single `SELECT`, `VALUES` or `WITH` query and nothing else.
+/// Task-locals of the request currently handled, which tasks spawned for it and streams
+/// outliving the handler don't inherit.
+#[derive(Default)]
+struct RequestScope {
+    budget_key: Option<String>,
+    caller: Option<String>,
+    metadata: Option<serde_json::Value>,
+}
+
+fn request_scope() -> RequestScope {
+    RequestScope {
+        budget_key: BUDGET_KEY.try_with(Clone::clone).ok().flatten(),
+        caller: CALLER.try_with(Clone::clone).ok().flatten(),
+        metadata: METADATA.try_with(Clone::clone).ok().flatten(),
+    }
+}
+
+impl RequestScope {
+    async fn run<F: Future>(self, future: F) -> F::Output {
+        let future = METADATA.scope(self.metadata, future);
+        BUDGET_KEY
+            .scope(self.budget_key, CALLER.scope(self.caller, future))
+            .await
+    }
+}
+
+/// Records a streamed `COPY` in the scope of its request once the stream is dropped, having
+/// ended or been abandoned by the client.
+struct CopyFinish {
+    db: Arc<DB>,
+    db_name: String,
+    query: String,
+    start: Instant,
+    error: Option<SqlExecutionError>,
+    scope: RequestScope,
+}
+
+impl Drop for CopyFinish {
+    fn drop(&mut self) {
+        let db = Arc::clone(&self.db);
+        let (db_name, query) = (std::mem::take(&mut self.db_name), std::mem::take(&mut self.query));
+        let (duration, error) = (self.start.elapsed(), self.error.take());
+        let record = async move {
+            db.record_copy(&db_name, &query, duration, error.as_ref())
+                .await
+        };
+        tokio::spawn(std::mem::take(&mut self.scope).run(record));
+    }
+}
+
+fn is_single_query(query: &str) -> bool {
+    matches!(
+        Parser::parse_sql(&PostgreSqlDialect {}, query).as_deref(),
+        Ok([Statement::Query(_)])
+    )
+}
+
+fn sqlstate(err: &sqlx::Error) -> Option<String> {
+    err.as_database_error()
+        .and_then(|err| err.code())
+        .map(|code| code.to_string())
+}
+
+/// Version of the extraction, normalisation and comparison semantics, increased whenever a
+/// change may grade queries in an existing environment differently. It is part of the
+/// environment hash, so environments are initialised anew after it changed.
+pub const GRADER_VERSION: u32 = 3;
+
+fn environment_hash(environment: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&GRADER_VERSION.to_le_bytes());
+    hasher.update(environment.as_bytes());
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Name of the database or schema of the environment.
+pub fn environment_name(environment: &str) -> String {
+    environment_hash(environment)[..63].to_string()
 }
 
 #[derive(Error, Debug)]
@@ -347,10 +1008,58 @@ pub enum SqlExecutionError {
     Init(sqlx::Error),
     #[error("error while executing supplied query: {0}")]
     Execute(sqlx::Error),
+    #[error("supplied query exceeded the statement timeout: {0}")]
+    Timeout(sqlx::Error),
     #[error("an sql error occurred: {0}")]
     Other(#[from] sqlx::Error),
     #[error("failed to determine column type of `{0}`")]
     ColumnDecodeError(String),
+    #[error("supplied query is not allowed by rule `{0}`")]
+    Denied(String),
+    #[error("failed to load seed data: {0}")]
+    SeedData(String),
+    #[error("execution budget of the task is exhausted")]
+    BudgetExceeded,
+    #[error("{0}")]
+    Unsupported(&'static str),
+}
+
+const QUERY_CANCELED: &str = "57014";
+
+/// Postgres error classes of broken connections and server shutdowns.
+const CONNECTION_ERROR_CLASSES: &[&str] = &["08", "57P"];
+
+impl SqlExecutionError {
+    /// Whether the connection broke instead of the query failing.
+    pub fn is_connection_error(&self) -> bool {
+        let (SqlExecutionError::Execute(err)
+        | SqlExecutionError::Other(err)
+        | SqlExecutionError::Init(err)) = self
+        else {
+            return false;
+        };
+        match err {
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::Protocol(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => true,
+            sqlx::Error::Database(err) => err.code().is_some_and(|code| {
+                CONNECTION_ERROR_CLASSES
+                    .iter()
+                    .any(|class| code.starts_with(class))
+            }),
+            _ => false,
+        }
+    }
+
+    fn from_execute(err: sqlx::Error) -> Self {
+        match err.as_database_error().and_then(|e| e.code()) {
+            Some(code) if code == QUERY_CANCELED => SqlExecutionError::Timeout(err),
+            _ => SqlExecutionError::Execute(err),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, ToSchema)]
@@ -365,3 +1074,25 @@ pub enum ColumnNormalisation {
     SortColumnsByName,
     NumberColumnsByOrder,
 }
+
+/// What has to match for two result sets to be considered equal, superseded by
+/// [`ComparisonSpec`].
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum ComparisonMode {
+    /// Columns and rows
+    ResultSet,
+    /// Only the number of rows
+    CountOnly,
+    /// The number of rows and the first value of the first row
+    CountWithScalar,
+    /// Both result sets consist of a single value, regardless of the column name, and the
+    /// values are equal or, if numeric, differ by at most `tolerance`
+    Scalar {
+        #[serde(default = "get_default_tolerance")]
+        tolerance: f64,
+    },
+}
+
+fn get_default_tolerance() -> f64 {
+    1e-9
+}
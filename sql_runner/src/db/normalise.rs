@@ -0,0 +1,242 @@
+use chrono::{Datelike, NaiveDate};
+use common::models::{ResultSet, SqlValue, parse_date, parse_timestamp};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥', '₹', '₽', '¢'];
+
+/// Normaliser of single values, values it does not apply to are left unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ValueNormaliser {
+    /// Removes leading and trailing whitespace of text
+    Trim,
+    Lowercase,
+    /// Text which is an amount, e.g. `€ 1.234,50` or `1,234.50 USD`, becomes a number without
+    /// currency and thousands separators, other text is unchanged. Integers become floats
+    StripCurrency,
+    /// Rounds numbers, including numeric text, to this many decimal places. Numbers become
+    /// floats, so integers and decimals compare equal
+    Round {
+        decimals: u32,
+    },
+    /// Truncates dates and timestamps, including text spelling one, to the start of the unit
+    TruncateDate {
+        unit: DateUnit,
+    },
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DateUnit {
+    Day,
+    Month,
+    Year,
+}
+
+impl ValueNormaliser {
+    fn apply(&self, value: &mut SqlValue) {
+        match (self, &*value) {
+            (ValueNormaliser::Trim, SqlValue::Text(text)) => {
+                *value = SqlValue::Text(text.trim().to_string())
+            }
+            (ValueNormaliser::Lowercase, SqlValue::Text(text)) => {
+                *value = SqlValue::Text(text.to_lowercase())
+            }
+            (ValueNormaliser::StripCurrency, SqlValue::Int(number)) => {
+                *value = SqlValue::Float(*number as f64)
+            }
+            (ValueNormaliser::StripCurrency, SqlValue::Text(text)) => *value = strip_currency(text),
+            (ValueNormaliser::Round { decimals }, _) => {
+                if let Some(number) = as_number(value) {
+                    let factor = 10f64.powi((*decimals).min(15) as i32);
+                    *value = SqlValue::Float((number * factor).round() / factor);
+                }
+            }
+            (ValueNormaliser::TruncateDate { unit }, _) => {
+                let date = match value {
+                    SqlValue::Date(date) => Some(*date),
+                    SqlValue::Timestamp(timestamp) => Some(timestamp.date()),
+                    SqlValue::Text(text) => parse_timestamp(text)
+                        .map(|timestamp| timestamp.date())
+                        .or_else(|| parse_date(text)),
+                    _ => None,
+                };
+                if let Some(date) = date {
+                    *value = SqlValue::Date(truncate_date(date, *unit));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Numbers and numeric text.
+fn as_number(value: &SqlValue) -> Option<f64> {
+    match value {
+        SqlValue::Int(number) => Some(*number as f64),
+        SqlValue::Float(number) => Some(*number),
+        SqlValue::Text(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn strip_currency(text: &str) -> SqlValue {
+    let stripped: String = text
+        .trim()
+        .trim_matches(|c: char| c.is_ascii_uppercase() || c.is_whitespace())
+        .chars()
+        .filter(|c| !CURRENCY_SYMBOLS.contains(c) && !c.is_whitespace() && *c != '\'')
+        .collect();
+    // with both separators the last one is decimal, a single dot is decimal and a single
+    // comma unless three digits follow, like in `1,000`
+    let count = |separator: char| stripped.matches(separator).count();
+    let decimal = match (stripped.rfind('.'), stripped.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(dot.max(comma)),
+        (Some(dot), None) => (count('.') == 1).then_some(dot),
+        (None, Some(comma)) => {
+            (count(',') == 1 && stripped.len() - comma - 1 != 3).then_some(comma)
+        }
+        (None, None) => None,
+    };
+    let number: String = stripped
+        .char_indices()
+        .filter_map(|(index, c)| match c {
+            '.' | ',' if Some(index) == decimal => Some('.'),
+            '.' | ',' => None,
+            c => Some(c),
+        })
+        .collect();
+    match number.parse() {
+        Ok(number) => SqlValue::Float(number),
+        Err(_) => SqlValue::Text(text.to_string()),
+    }
+}
+
+fn truncate_date(date: NaiveDate, unit: DateUnit) -> NaiveDate {
+    match unit {
+        DateUnit::Day => Some(date),
+        DateUnit::Month => date.with_day(1),
+        DateUnit::Year => date.with_ordinal(1),
+    }
+    .unwrap_or(date)
+}
+
+/// Case insensitive glob match, `*` matches any text and `?` a single character.
+fn column_matches(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, _) => name.is_empty(),
+        (Some('*'), _) => {
+            column_matches(&pattern[1..], name)
+                || !name.is_empty() && column_matches(pattern, &name[1..])
+        }
+        (Some('?'), Some(_)) => column_matches(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) => {
+            p.to_lowercase().eq(n.to_lowercase()) && column_matches(&pattern[1..], &name[1..])
+        }
+        (Some(_), None) => false,
+    }
+}
+
+/// Indices of the columns whose name matches the glob pattern.
+fn matching_columns(result_set: &ResultSet, pattern: &str) -> Vec<usize> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    result_set
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| column_matches(&pattern, &name.chars().collect::<Vec<_>>()))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Applies the normalisers in order to the values of every column whose name matches the
+/// glob pattern, e.g. `*price*`.
+pub fn normalise_columns(
+    result_set: &mut ResultSet,
+    pattern: &str,
+    normalisers: &[ValueNormaliser],
+) {
+    let columns = matching_columns(result_set, pattern);
+    for row in &mut result_set.rows {
+        for index in &columns {
+            if let Some(value) = row.get_mut(*index) {
+                for normaliser in normalisers {
+                    normaliser.apply(value);
+                }
+            }
+        }
+    }
+}
+
+/// How the columns whose name matches the glob pattern are compared.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnOverride {
+    pub column: String,
+    /// The column is removed from both result sets and not graded at all
+    #[serde(default)]
+    pub ignore: bool,
+    /// Numbers differing by at most this much are equal
+    pub tolerance: Option<f64>,
+    /// Text is compared ignoring case
+    #[serde(default)]
+    pub case_insensitive: bool,
+}
+
+/// Applies the overrides to the expected result set `a` and the actual `b`. A number of `b`
+/// within the tolerance of the number in the same row and column of `a` is replaced by it,
+/// so rows should already be in a comparable order.
+pub fn override_columns(a: &mut ResultSet, b: &mut ResultSet, overrides: &[ColumnOverride]) {
+    for column_override in overrides {
+        let columns_a = matching_columns(a, &column_override.column);
+        let columns_b = matching_columns(b, &column_override.column);
+        if column_override.case_insensitive {
+            normalise_columns(a, &column_override.column, &[ValueNormaliser::Lowercase]);
+            normalise_columns(b, &column_override.column, &[ValueNormaliser::Lowercase]);
+        }
+        if let Some(tolerance) = column_override.tolerance {
+            for index_a in &columns_a {
+                let Some(index_b) = b
+                    .columns
+                    .iter()
+                    .position(|name| *name == a.columns[*index_a])
+                else {
+                    continue;
+                };
+                for (row_a, row_b) in a.rows.iter().zip(&mut b.rows) {
+                    let (Some(expected), Some(actual)) =
+                        (row_a.get(*index_a), row_b.get_mut(index_b))
+                    else {
+                        continue;
+                    };
+                    let within = match (as_number(expected), as_number(actual)) {
+                        (Some(x), Some(y)) => (x - y).abs() <= tolerance,
+                        _ => false,
+                    };
+                    if within {
+                        *actual = expected.clone();
+                    }
+                }
+            }
+        }
+        if column_override.ignore {
+            remove_columns(a, &columns_a);
+            remove_columns(b, &columns_b);
+        }
+    }
+}
+
+fn remove_columns(result_set: &mut ResultSet, columns: &[usize]) {
+    let keep = |index: &usize| !columns.contains(index);
+    result_set.columns = (0..result_set.columns.len())
+        .filter(keep)
+        .map(|index| result_set.columns[index].clone())
+        .collect();
+    for row in &mut result_set.rows {
+        *row = (0..row.len())
+            .filter(keep)
+            .map(|index| row[index].clone())
+            .collect();
+    }
+}
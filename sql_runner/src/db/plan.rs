@@ -0,0 +1,95 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Keys of a plan node which are returned as fields of [`PlanNode`] instead of `details`.
+const NODE_FIELDS: &[&str] = &[
+    "Node Type",
+    "Relation Name",
+    "Startup Cost",
+    "Total Cost",
+    "Plan Rows",
+    "Plan Width",
+    "Actual Startup Time",
+    "Actual Total Time",
+    "Actual Rows",
+    "Actual Loops",
+    "Plans",
+];
+
+/// Plan of a query as reported by `EXPLAIN (FORMAT JSON)`.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct QueryPlan {
+    pub plan: PlanNode,
+    pub planning_time_ms: Option<f64>,
+    /// Only known if the query was executed by `EXPLAIN ANALYZE`
+    pub execution_time_ms: Option<f64>,
+}
+
+/// Node of the plan tree. Costs are estimates in the arbitrary units of the planner, the
+/// actual values are only known if the query was executed.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct PlanNode {
+    pub node_type: String,
+    pub relation_name: Option<String>,
+    pub startup_cost: f64,
+    pub total_cost: f64,
+    pub plan_rows: f64,
+    pub plan_width: f64,
+    pub actual_startup_time_ms: Option<f64>,
+    pub actual_total_time_ms: Option<f64>,
+    pub actual_rows: Option<f64>,
+    pub actual_loops: Option<f64>,
+    /// Any other property of the node as named by Postgres, e.g. `Filter` or `Index Name`
+    pub details: BTreeMap<String, Value>,
+    #[schema(no_recursion)]
+    pub children: Vec<PlanNode>,
+}
+
+/// Parses the single row returned by `EXPLAIN (FORMAT JSON)`, None if it is not a plan.
+pub fn parse_plan(explain: &Value) -> Option<QueryPlan> {
+    let explain = explain.as_array()?.first()?.as_object()?;
+    Some(QueryPlan {
+        plan: parse_node(explain.get("Plan")?)?,
+        planning_time_ms: number(explain, "Planning Time"),
+        execution_time_ms: number(explain, "Execution Time"),
+    })
+}
+
+fn parse_node(node: &Value) -> Option<PlanNode> {
+    let node = node.as_object()?;
+    let children = match node.get("Plans") {
+        Some(children) => children
+            .as_array()?
+            .iter()
+            .map(parse_node)
+            .collect::<Option<_>>()?,
+        None => vec![],
+    };
+    Some(PlanNode {
+        node_type: node.get("Node Type")?.as_str()?.to_string(),
+        relation_name: node
+            .get("Relation Name")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        startup_cost: number(node, "Startup Cost")?,
+        total_cost: number(node, "Total Cost")?,
+        plan_rows: number(node, "Plan Rows")?,
+        plan_width: number(node, "Plan Width")?,
+        actual_startup_time_ms: number(node, "Actual Startup Time"),
+        actual_total_time_ms: number(node, "Actual Total Time"),
+        actual_rows: number(node, "Actual Rows"),
+        actual_loops: number(node, "Actual Loops"),
+        details: node
+            .iter()
+            .filter(|(key, _)| !NODE_FIELDS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        children,
+    })
+}
+
+fn number(object: &Map<String, Value>, key: &str) -> Option<f64> {
+    object.get(key).and_then(Value::as_f64)
+}
@@ -0,0 +1,85 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use utoipa::ToSchema;
+
+/// Progress of an environment initialisation, kept after a failure to report the error.
+#[derive(Debug)]
+pub struct InitProgress {
+    started: AtomicBool,
+    executed: AtomicUsize,
+    total: usize,
+    error: Mutex<Option<String>>,
+}
+
+impl InitProgress {
+    pub fn new(total: usize) -> Self {
+        InitProgress {
+            started: AtomicBool::new(false),
+            executed: AtomicUsize::new(0),
+            total,
+            error: Mutex::new(None),
+        }
+    }
+
+    /// Marks the end of waiting for a free creation slot.
+    pub fn start(&self) {
+        self.started.store(true, Ordering::Relaxed);
+    }
+
+    pub fn statement_executed(&self) {
+        self.executed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn fail(&self, error: String) {
+        *self.error.lock().unwrap() = Some(error);
+    }
+
+    pub fn status(&self) -> EnvironmentStatus {
+        let error = self.error.lock().unwrap().clone();
+        EnvironmentStatus {
+            state: if error.is_some() {
+                EnvironmentState::Failed
+            } else if self.started.load(Ordering::Relaxed) {
+                EnvironmentState::Initialising
+            } else {
+                EnvironmentState::Queued
+            },
+            executed: Some(self.executed.load(Ordering::Relaxed).min(self.total)),
+            total: Some(self.total),
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvironmentState {
+    Missing,
+    /// Waiting for other environments to be created first
+    Queued,
+    Initialising,
+    Failed,
+    Ready,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EnvironmentStatus {
+    pub state: EnvironmentState,
+    /// Statements of the environment executed so far, while initialising
+    pub executed: Option<usize>,
+    /// Statements in the environment, while initialising
+    pub total: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl EnvironmentStatus {
+    pub fn new(state: EnvironmentState) -> Self {
+        EnvironmentStatus {
+            state,
+            executed: None,
+            total: None,
+            error: None,
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use common::models::ResultSet;
+use sqlparser::ast::Statement;
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Functions whose result changes between executions, queries calling them are not cached.
+const VOLATILE_FUNCTIONS: &[&str] = &[
+    "clock_timestamp",
+    "current_date",
+    "current_time",
+    "current_timestamp",
+    "gen_random_uuid",
+    "localtime",
+    "localtimestamp",
+    "nextval",
+    "now",
+    "random",
+    "statement_timestamp",
+    "timeofday",
+    "txid_current",
+];
+
+/// Key of the query in the environment, `None` unless it is a single `SELECT` without
+/// volatile functions. Queries differing only in formatting, comments and the case of
+/// unquoted words share a key, unlike fingerprints literals are kept.
+pub fn cache_key(environment_hash: &str, query: &str) -> Option<String> {
+    match Parser::parse_sql(&PostgreSqlDialect {}, query)
+        .ok()?
+        .as_slice()
+    {
+        [Statement::Query(_)] => {}
+        _ => return None,
+    }
+    let mut normalized = Vec::new();
+    for token in Tokenizer::new(&PostgreSqlDialect {}, query)
+        .tokenize()
+        .ok()?
+    {
+        match token {
+            Token::Whitespace(_) | Token::SemiColon => {}
+            Token::Word(word) if word.quote_style.is_some() => {
+                normalized.push(Token::Word(word).to_string())
+            }
+            Token::Word(word) => {
+                let word = word.value.to_lowercase();
+                if VOLATILE_FUNCTIONS.contains(&word.as_str()) {
+                    return None;
+                }
+                normalized.push(word);
+            }
+            token => normalized.push(token.to_string()),
+        }
+    }
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(environment_hash.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(normalized.join(" ").as_bytes());
+    Some(hasher.finalize().to_hex().to_string())
+}
+
+#[derive(Debug)]
+struct Entry {
+    result_set: ResultSet,
+    stored_at: Instant,
+    /// Position in [`CacheState::recency`]
+    used: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<String, Entry>,
+    /// Keys by the last use of their entry, least recently used first
+    recency: BTreeMap<u64, String>,
+    uses: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, key: &str) {
+        self.uses += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            entry.used = self.uses;
+            self.recency.insert(self.uses, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+/// Result sets of read-only queries by environment and query, so solutions compared with many
+/// submissions are executed once. Entries expire after the TTL and the least recently used
+/// one is evicted when the cache is full.
+#[derive(Debug)]
+pub struct ResultCache {
+    capacity: usize,
+    ttl: Duration,
+    /// Result sets with more rows are not cached
+    max_rows: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize, ttl: Duration, max_rows: usize) -> Self {
+        ResultCache {
+            capacity,
+            ttl,
+            max_rows,
+            state: Default::default(),
+        }
+    }
+
+    pub fn get(&self, key: &str, now: Instant) -> Option<ResultSet> {
+        let mut state = self.state.lock().unwrap();
+        let stored_at = state.entries.get(key)?.stored_at;
+        if now.duration_since(stored_at) >= self.ttl {
+            state.remove(key);
+            return None;
+        }
+        state.touch(key);
+        state.entries.get(key).map(|entry| entry.result_set.clone())
+    }
+
+    pub fn insert(&self, key: String, result_set: &ResultSet, now: Instant) {
+        if self.capacity == 0 || result_set.rows.len() > self.max_rows {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.remove(&key);
+        while state.entries.len() >= self.capacity {
+            let Some((_, oldest)) = state.recency.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+        state.uses += 1;
+        let used = state.uses;
+        state.recency.insert(used, key.clone());
+        state.entries.insert(
+            key,
+            Entry {
+                result_set: result_set.clone(),
+                stored_at: now,
+                used,
+            },
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use common::models::{ResultSet, SqlValue};
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Differences between an expected and an actual result set. Rows are compared by the
+/// columns both have, regardless of their order.
+#[derive(Debug, Clone, Default, Serialize, ToSchema, PartialEq)]
+pub struct ResultSetDiff {
+    /// Expected columns the actual result set lacks
+    pub missing_columns: Vec<String>,
+    /// Actual columns which are not expected
+    pub extra_columns: Vec<String>,
+    /// Columns of both result sets whose values have different types
+    pub column_types: Vec<ColumnTypeDiff>,
+    /// Expected rows without an equal actual row
+    pub missing_rows: Vec<Vec<SqlValue>>,
+    /// Actual rows without an equal expected row
+    pub extra_rows: Vec<Vec<SqlValue>>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct ColumnTypeDiff {
+    pub column: String,
+    pub expected: ValueType,
+    pub actual: ValueType,
+}
+
+/// Type of the non-null values of a column.
+#[derive(Debug, Copy, Clone, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    Bool,
+    Int,
+    Float,
+    Date,
+    Timestamp,
+    Text,
+}
+
+pub fn diff_result_sets(expected: &ResultSet, actual: &ResultSet) -> ResultSetDiff {
+    let shared: Vec<(&String, usize, usize)> = expected
+        .columns
+        .iter()
+        .enumerate()
+        .filter_map(|(index, column)| {
+            let actual_index = actual.columns.iter().position(|c| c == column)?;
+            Some((column, index, actual_index))
+        })
+        .collect();
+    let column_types = shared
+        .iter()
+        .filter_map(|(column, index, actual_index)| {
+            match (
+                column_type(expected, *index),
+                column_type(actual, *actual_index),
+            ) {
+                (Some(expected), Some(actual)) if expected != actual => Some(ColumnTypeDiff {
+                    column: column.to_string(),
+                    expected,
+                    actual,
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+
+    let expected_indices: Vec<usize> = shared.iter().map(|(_, index, _)| *index).collect();
+    let actual_indices: Vec<usize> = shared.iter().map(|(_, _, index)| *index).collect();
+    let mut unmatched: HashMap<String, usize> = HashMap::new();
+    for row in &actual.rows {
+        *unmatched.entry(row_key(row, &actual_indices)).or_default() += 1;
+    }
+    let mut missing_rows = Vec::new();
+    for row in &expected.rows {
+        match unmatched.get_mut(&row_key(row, &expected_indices)) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => missing_rows.push(row.clone()),
+        }
+    }
+    let mut extra_rows = Vec::new();
+    for row in &actual.rows {
+        match unmatched.get_mut(&row_key(row, &actual_indices)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                extra_rows.push(row.clone());
+            }
+            _ => {}
+        }
+    }
+
+    ResultSetDiff {
+        missing_columns: absent(&expected.columns, &actual.columns),
+        extra_columns: absent(&actual.columns, &expected.columns),
+        column_types,
+        missing_rows,
+        extra_rows,
+    }
+}
+
+/// Values of the columns at the indices, as a hashable key.
+fn row_key(row: &[SqlValue], indices: &[usize]) -> String {
+    let values: Vec<&SqlValue> = indices.iter().map(|index| &row[*index]).collect();
+    serde_json::to_string(&values).unwrap_or_default()
+}
+
+fn column_type(result_set: &ResultSet, index: usize) -> Option<ValueType> {
+    result_set
+        .rows
+        .iter()
+        .find_map(|row| value_type(&row[index]))
+}
+
+fn value_type(value: &SqlValue) -> Option<ValueType> {
+    match value {
+        SqlValue::Null => None,
+        SqlValue::Bool(_) => Some(ValueType::Bool),
+        SqlValue::Int(_) => Some(ValueType::Int),
+        SqlValue::Float(_) => Some(ValueType::Float),
+        SqlValue::Date(_) => Some(ValueType::Date),
+        SqlValue::Timestamp(_) => Some(ValueType::Timestamp),
+        SqlValue::Text(_) => Some(ValueType::Text),
+    }
+}
+
+fn absent(columns: &[String], other: &[String]) -> Vec<String> {
+    columns
+        .iter()
+        .filter(|column| !other.contains(column))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,136 @@
+use common::models::{ResultSet, SqlValue};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use utoipa::ToSchema;
+
+/// Differences between an expected and an actual result set whose rows are matched by the
+/// values of the key columns.
+#[derive(Debug, Clone, Default, Serialize, ToSchema, PartialEq)]
+pub struct RowDiff {
+    pub key_columns: Vec<String>,
+    /// Expected columns the actual result set lacks, including key columns
+    pub missing_columns: Vec<String>,
+    /// Actual columns which are not expected
+    pub unexpected_columns: Vec<String>,
+    /// Keys of expected rows without an actual row
+    pub missing_rows: Vec<Vec<SqlValue>>,
+    /// Keys of actual rows without an expected row
+    pub unexpected_rows: Vec<Vec<SqlValue>>,
+    pub changed_rows: Vec<ChangedRow>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct ChangedRow {
+    pub key: Vec<SqlValue>,
+    pub cells: Vec<CellDiff>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct CellDiff {
+    pub column: String,
+    pub expected: SqlValue,
+    pub actual: SqlValue,
+}
+
+impl RowDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missing_columns.is_empty()
+            && self.unexpected_columns.is_empty()
+            && self.missing_rows.is_empty()
+            && self.unexpected_rows.is_empty()
+            && self.changed_rows.is_empty()
+    }
+}
+
+/// Matches the rows of both result sets by their key and compares the non-key columns by
+/// name. Rows sharing a key are matched in order of appearance.
+pub fn diff_by_key(expected: &ResultSet, actual: &ResultSet, key_columns: &[String]) -> RowDiff {
+    let mut diff = RowDiff {
+        key_columns: key_columns.to_vec(),
+        missing_columns: absent(&expected.columns, &actual.columns),
+        unexpected_columns: absent(&actual.columns, &expected.columns),
+        ..Default::default()
+    };
+    for key in key_columns {
+        if !expected.columns.contains(key) && !diff.missing_columns.contains(key) {
+            diff.missing_columns.push(key.clone());
+        }
+    }
+    let (Some(expected_key), Some(actual_key)) = (
+        indices(&expected.columns, key_columns),
+        indices(&actual.columns, key_columns),
+    ) else {
+        return diff;
+    };
+    let compared: Vec<(&String, usize, usize)> = expected
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| !key_columns.contains(column))
+        .filter_map(|(index, column)| {
+            let actual_index = actual.columns.iter().position(|c| c == column)?;
+            Some((column, index, actual_index))
+        })
+        .collect();
+
+    let mut actual_rows: HashMap<String, VecDeque<&Vec<SqlValue>>> = HashMap::new();
+    let mut actual_order = Vec::new();
+    for row in &actual.rows {
+        let key = key_of(row, &actual_key);
+        let hash = key_hash(&key);
+        if !actual_rows.contains_key(&hash) {
+            actual_order.push((hash.clone(), key));
+        }
+        actual_rows.entry(hash).or_default().push_back(row);
+    }
+    for row in &expected.rows {
+        let key = key_of(row, &expected_key);
+        let Some(actual_row) = actual_rows
+            .get_mut(&key_hash(&key))
+            .and_then(VecDeque::pop_front)
+        else {
+            diff.missing_rows.push(key);
+            continue;
+        };
+        let cells: Vec<CellDiff> = compared
+            .iter()
+            .filter(|(_, index, actual_index)| row[*index] != actual_row[*actual_index])
+            .map(|(column, index, actual_index)| CellDiff {
+                column: column.to_string(),
+                expected: row[*index].clone(),
+                actual: actual_row[*actual_index].clone(),
+            })
+            .collect();
+        if !cells.is_empty() {
+            diff.changed_rows.push(ChangedRow { key, cells });
+        }
+    }
+    for (hash, key) in actual_order {
+        let left = actual_rows.get(&hash).map_or(0, VecDeque::len);
+        diff.unexpected_rows.extend(std::iter::repeat_n(key, left));
+    }
+    diff
+}
+
+fn absent(columns: &[String], other: &[String]) -> Vec<String> {
+    columns
+        .iter()
+        .filter(|column| !other.contains(column))
+        .cloned()
+        .collect()
+}
+
+fn indices(columns: &[String], key_columns: &[String]) -> Option<Vec<usize>> {
+    key_columns
+        .iter()
+        .map(|key| columns.iter().position(|column| column == key))
+        .collect()
+}
+
+fn key_of(row: &[SqlValue], key: &[usize]) -> Vec<SqlValue> {
+    key.iter().map(|index| row[*index].clone()).collect()
+}
+
+fn key_hash(key: &[SqlValue]) -> String {
+    serde_json::to_string(key).unwrap_or_default()
+}
@@ -0,0 +1,209 @@
+use crate::db::isolation::IsolationStrategy;
+use crate::db::types::ResultSet;
+use crate::db::{DB, DatabaseType, SqlExecutionError, environment_hash};
+use log::{debug, warn};
+use serde::Deserialize;
+use sqlparser::ast::Statement;
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlx::{Executor, Pool};
+use std::sync::Arc;
+use std::time::Instant;
+use utoipa::ToSchema;
+
+/// Sequences of the environment with their current value, `None` if never called.
+const SEQUENCES: &str = "SELECT format('%I.%I', schemaname, sequencename), last_value \
+    FROM pg_sequences WHERE schemaname = 'public'";
+
+/// Executes a query with write access in a transaction which is rolled back, so INSERT,
+/// UPDATE, DELETE and DDL exercises can be graded without changing the environment. New
+/// objects can be created, those of the environment script can't be altered or dropped.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct WriteMode {
+    /// Executed after the query in the same transaction, its result set is returned instead
+    /// of the query's, e.g. to inspect the tables the query changed
+    pub check_query: Option<String>,
+    /// Execute in a copy of the environment instead of a transaction, so the tables of the
+    /// environment script can be altered and dropped and statements which can't run in a
+    /// transaction are allowed. Requires template databases
+    #[serde(default)]
+    pub copy: bool,
+}
+
+/// Whether the query begins, ends or partially rolls back a transaction, which would let it
+/// escape the sandbox. Queries which can't be parsed are checked after execution.
+fn controls_transaction(query: &str) -> bool {
+    Parser::parse_sql(&PostgreSqlDialect {}, query).is_ok_and(|statements| {
+        statements.iter().any(|statement| {
+            matches!(
+                statement,
+                Statement::StartTransaction { .. }
+                    | Statement::Commit { .. }
+                    | Statement::Rollback { .. }
+                    | Statement::Savepoint { .. }
+                    | Statement::ReleaseSavepoint { .. }
+            )
+        })
+    })
+}
+
+/// Role with write access to the environment database, its name differs from the
+/// environment's so the cleanup does not mistake it for one.
+pub(super) fn writer_name(name: &str) -> String {
+    format!("w{}", &name[..62])
+}
+
+impl DB {
+    /// Executes the query as the writer of the environment in a transaction which is rolled
+    /// back, followed by the check query of the write mode. Sequences are reset afterwards, so
+    /// every execution sees the same generated ids. Requires database isolation.
+    pub async fn execute_sandboxed(
+        &self,
+        environment: &str,
+        query: &str,
+        write_mode: &WriteMode,
+    ) -> Result<ResultSet, SqlExecutionError> {
+        if !matches!(self.isolation, IsolationStrategy::DatabasePerEnvironment) {
+            return Err(SqlExecutionError::Unsupported(
+                "write mode requires database isolation",
+            ));
+        }
+        if write_mode.copy && !self.template_databases {
+            return Err(SqlExecutionError::Unsupported(
+                "write mode copies require template databases",
+            ));
+        }
+        let environment_hash = environment_hash(environment);
+        let db_name = &environment_hash[..63];
+        for query in [Some(query), write_mode.check_query.as_deref()]
+            .into_iter()
+            .flatten()
+        {
+            let rule = self.deny_rules.check(query).or_else(|| {
+                (!write_mode.copy && controls_transaction(query)).then_some("transaction_control")
+            });
+            if let Some(rule) = rule {
+                let err = SqlExecutionError::Denied(rule.to_string());
+                self.audit(db_name, query, Default::default(), Err(&err));
+                return Err(err);
+            }
+        }
+        self.check_budget()?;
+        if write_mode.copy {
+            return self
+                .execute_in_copy(environment, &environment_hash, query, write_mode)
+                .await;
+        }
+        self.connect_environment(environment, &environment_hash)
+            .await?;
+        let conn = self.writer_connection(db_name).await?;
+
+        debug!("Executing sandboxed query in {db_name}");
+        let sequences: Vec<(String, Option<i64>)> =
+            sqlx::query_as(SEQUENCES).fetch_all(&*conn).await?;
+        let mut transaction = conn.begin().await?;
+        let (transaction_id,): (i64,) = sqlx::query_as("SELECT txid_current()")
+            .fetch_one(&mut *transaction)
+            .await?;
+        let start = Instant::now();
+        let mut result = self.extract(&mut *transaction, query).await;
+        self.record_query(
+            db_name,
+            query,
+            start.elapsed(),
+            result.as_ref().map(|result_set| result_set.rows.len()),
+        )
+        .await;
+        if result.is_ok() {
+            // statements the parser missed may have ended the transaction and with it the
+            // sandbox, its id differs in any later one
+            let (open,): (Option<bool>,) = sqlx::query_as("SELECT txid_current_if_assigned() = $1")
+                .bind(transaction_id)
+                .fetch_one(&mut *transaction)
+                .await?;
+            if open != Some(true) {
+                warn!("Sandboxed query in {db_name} ended its transaction");
+                result = Err(SqlExecutionError::Denied("transaction_control".to_string()));
+            }
+        }
+        if let (Ok(_), Some(check_query)) = (&result, &write_mode.check_query) {
+            let start = Instant::now();
+            result = self.extract(&mut *transaction, check_query).await;
+            self.record_query(
+                db_name,
+                check_query,
+                start.elapsed(),
+                result.as_ref().map(|result_set| result_set.rows.len()),
+            )
+            .await;
+        }
+        transaction.rollback().await?;
+        for (sequence, last_value) in sequences {
+            let reset = match last_value {
+                Some(value) => sqlx::query("SELECT setval($1::regclass, $2, true)")
+                    .bind(sequence)
+                    .bind(value),
+                None => sqlx::query(
+                    "SELECT setval($1::regclass, start_value, false) FROM pg_sequences \
+                    WHERE format('%I.%I', schemaname, sequencename) = $1",
+                )
+                .bind(sequence),
+            };
+            reset.execute(&*conn).await?;
+        }
+        result
+    }
+
+    /// Connection of the writer of the environment, which is created on first use.
+    // Name must be trusted as queries used to create the role don't support bind
+    async fn writer_connection(
+        &self,
+        name: &str,
+    ) -> Result<Arc<Pool<DatabaseType>>, SqlExecutionError> {
+        let writer = writer_name(name);
+        let key = format!("{writer}@{name}");
+        let password = blake3::keyed_hash(&self.password_hash_key, writer.as_bytes())
+            .to_hex()
+            .to_string();
+        let options = self
+            .connect_options
+            .clone()
+            .username(&writer)
+            .database(name)
+            .password(&password);
+        if self.connections.contains(&key).await {
+            return self.get_connection(key, options).await;
+        }
+
+        debug!("Granting write access to {name} to {writer}");
+        self.root_connection
+            .execute(
+                format!(
+                    "DO $$ BEGIN CREATE ROLE \"{writer}\" LOGIN PASSWORD '{password}'; \
+                    EXCEPTION WHEN duplicate_object THEN NULL; END $$;"
+                )
+                .as_str(),
+            )
+            .await?;
+        let root_conn = self
+            .get_connection(
+                format!("{}@{name}", self.db_root_username),
+                self.connect_options.clone().database(name),
+            )
+            .await?;
+        for grant in [
+            format!("GRANT CONNECT ON DATABASE \"{name}\" TO \"{writer}\";"),
+            format!("GRANT USAGE, CREATE ON SCHEMA public TO \"{writer}\";"),
+            format!(
+                "GRANT SELECT, INSERT, UPDATE, DELETE, TRUNCATE, REFERENCES, TRIGGER \
+                ON ALL TABLES IN SCHEMA public TO \"{writer}\";"
+            ),
+            format!(
+                "GRANT USAGE, SELECT, UPDATE ON ALL SEQUENCES IN SCHEMA public TO \"{writer}\";"
+            ),
+        ] {
+            root_conn.execute(grant.as_str()).await?;
+        }
+        self.get_connection(key, options).await
+    }
+}
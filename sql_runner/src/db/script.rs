@@ -0,0 +1,80 @@
+/// Splits an sql script into its statements, respecting quoted identifiers, string and
+/// dollar-quoted literals and comments. Empty statements are skipped.
+pub fn split_statements(script: &str) -> Vec<&str> {
+    let bytes = script.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' => i = skip_quoted(bytes, i, bytes[i]),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = script[i..]
+                    .find('\n')
+                    .map_or(bytes.len(), |end| i + end + 1)
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = script[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2)
+            }
+            b'$' => i = skip_dollar_quoted(script, i),
+            b';' => {
+                push_statement(&mut statements, &script[start..i]);
+                i += 1;
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    push_statement(&mut statements, &script[start..]);
+    statements
+}
+
+fn push_statement<'a>(statements: &mut Vec<&'a str>, statement: &'a str) {
+    if !strip_comments(statement).trim().is_empty() {
+        statements.push(statement.trim());
+    }
+}
+
+fn strip_comments(statement: &str) -> String {
+    statement
+        .lines()
+        .map(|line| line.split("--").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Index after the closing quote, a doubled quote is an escaped quote.
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Index after the closing tag of a `$tag$ ... $tag$` literal, or after the `$` if it does
+/// not start one, e.g. for positional parameters.
+fn skip_dollar_quoted(script: &str, start: usize) -> usize {
+    let rest = &script[start + 1..];
+    let Some(tag_end) = rest.find('$') else {
+        return start + 1;
+    };
+    let tag = &rest[..tag_end];
+    if !tag.chars().all(|c| c.is_alphanumeric() || c == '_') || tag.starts_with(char::is_numeric) {
+        return start + 1;
+    }
+    let delimiter = &script[start..start + tag_end + 2];
+    let body_start = start + delimiter.len();
+    script[body_start..]
+        .find(delimiter)
+        .map_or(script.len(), |end| body_start + end + delimiter.len())
+}
@@ -0,0 +1,82 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use regex::Regex;
+use std::io;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+/// `COPY target FROM 'seed:<id>' ...` or `COPY target FROM 'base64:<data>' ...` in an
+/// environment script, loaded through the COPY protocol instead of reading a server file.
+static SEED_COPY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)^\s*copy\s+(.+?)\s+from\s+'(seed|base64):([^']*)'(.*)$").unwrap()
+});
+
+/// Where the data of a seed `COPY` statement comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeedSource<'a> {
+    /// Data uploaded to the seed store before
+    Stored(&'a str),
+    /// Data embedded as base64 in the statement
+    Inline(&'a str),
+}
+
+/// A `COPY` statement of an environment script loading seed data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeedCopy<'a> {
+    /// The statement reading the data from `STDIN`
+    pub statement: String,
+    pub source: SeedSource<'a>,
+}
+
+/// Parses a seed `COPY` statement, `None` for any other statement.
+pub fn parse_seed_copy(statement: &str) -> Option<SeedCopy<'_>> {
+    let captures = SEED_COPY.captures(statement)?;
+    let reference = captures.get(3)?.as_str();
+    let source = match &captures[2].to_lowercase()[..] {
+        "seed" => SeedSource::Stored(reference),
+        _ => SeedSource::Inline(reference),
+    };
+    Some(SeedCopy {
+        statement: format!("COPY {} FROM STDIN{}", &captures[1], &captures[4]),
+        source,
+    })
+}
+
+/// Seed data stored on disk by content hash, so environment scripts referencing it change
+/// whenever the data changes.
+#[derive(Debug)]
+pub struct SeedStore {
+    dir: PathBuf,
+}
+
+impl SeedStore {
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(SeedStore { dir })
+    }
+
+    /// Stores the data and returns its id.
+    pub async fn store(&self, data: &[u8]) -> io::Result<String> {
+        let id = blake3::hash(data).to_hex().to_string();
+        let path = self.dir.join(&id);
+        if !tokio::fs::try_exists(&path).await? {
+            let partial = self.dir.join(format!("{id}.partial"));
+            tokio::fs::write(&partial, data).await?;
+            tokio::fs::rename(&partial, &path).await?;
+        }
+        Ok(id)
+    }
+
+    /// Data of the given source, `None` if it is not stored or not valid base64.
+    pub async fn load(&self, source: &SeedSource<'_>) -> Option<Vec<u8>> {
+        match source {
+            SeedSource::Stored(id) if is_id(id) => tokio::fs::read(self.dir.join(id)).await.ok(),
+            SeedSource::Stored(_) => None,
+            SeedSource::Inline(data) => STANDARD.decode(data.trim()).ok(),
+        }
+    }
+}
+
+fn is_id(id: &str) -> bool {
+    id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
@@ -0,0 +1,203 @@
+use crate::db::progress::InitProgress;
+use crate::db::sandbox::WriteMode;
+use crate::db::types::ResultSet;
+use crate::db::{DB, SqlExecutionError};
+use log::debug;
+use sqlx::Executor;
+use sqlx::postgres::PgPoolOptions;
+use std::time::{Instant, SystemTime};
+
+/// Database the environment is copied from, its name differs from the environment's so the
+/// cleanup does not mistake it for one.
+pub(super) fn template_name(name: &str) -> String {
+    format!("t{}", &name[..62])
+}
+
+/// Prefix of the per-use copies of the environment.
+fn copy_prefix(name: &str) -> String {
+    format!("c{}_", &name[..50])
+}
+
+impl DB {
+    /// Initialises the environment in its template database, unless that exists already, and
+    /// creates the environment as a copy of it. The template is owned by the root user and
+    /// does not allow connections, so it can be copied at any time.
+    // Name and password must be trusted as queries used to create databases don't support bind
+    pub(super) async fn create_database_from_template(
+        &self,
+        environment: &str,
+        name: &str,
+        password_hash: &str,
+        progress: &InitProgress,
+    ) -> Result<(), SqlExecutionError> {
+        let template = template_name(name);
+        if !self.database_exists(&template).await? {
+            debug!("Creating template database {template}");
+            self.root_connection
+                .execute(
+                    format!("CREATE USER \"{name}\" WITH ENCRYPTED PASSWORD '{password_hash}';")
+                        .as_str(),
+                )
+                .await?;
+            self.root_connection
+                .execute(format!("CREATE DATABASE \"{template}\" OWNER \"{name}\";").as_str())
+                .await?;
+
+            // A dedicated pool, as no connection to the template may remain while copying it
+            debug!("Initialising template database {template}");
+            let options = self
+                .connect_options
+                .clone()
+                .username(name)
+                .database(&template)
+                .password(password_hash);
+            let conn = PgPoolOptions::new()
+                .max_connections(1)
+                .connect_with(options.clone())
+                .await?;
+            let init = self
+                .init_environment(&conn, options, environment, progress)
+                .await;
+            conn.close().await;
+            if let Err(err) = init {
+                self.root_connection
+                    .execute(format!("DROP DATABASE IF EXISTS \"{template}\";").as_str())
+                    .await?;
+                self.root_connection
+                    .execute(format!("DROP USER IF EXISTS \"{name}\";").as_str())
+                    .await?;
+                return Err(err);
+            }
+            self.root_connection
+                .execute(
+                    format!(
+                        "ALTER DATABASE \"{template}\" OWNER TO \"{}\";",
+                        self.db_root_username
+                    )
+                    .as_str(),
+                )
+                .await?;
+            self.root_connection
+                .execute(
+                    format!("ALTER DATABASE \"{template}\" WITH ALLOW_CONNECTIONS false;").as_str(),
+                )
+                .await?;
+        }
+
+        debug!("Creating database {name} from {template}");
+        self.root_connection
+            .execute(format!("CREATE DATABASE \"{name}\" TEMPLATE \"{template}\";").as_str())
+            .await?;
+        debug!("Updating permission for database {name}");
+        let options = self.connect_options.clone().database(name);
+        let root_conn = self
+            .get_connection(format!("{}@{name}", self.db_root_username), options)
+            .await?;
+        self.make_database_readonly(&*root_conn, name).await
+    }
+
+    /// Executes the query, followed by the check query of the write mode, in a copy of the
+    /// environment created from its template, which is dropped afterwards. The copy is owned
+    /// by the user of the environment, so the tables of the environment script can be altered
+    /// and dropped as well.
+    // Name must be trusted as queries used to copy the database don't support bind
+    pub(super) async fn execute_in_copy(
+        &self,
+        environment: &str,
+        environment_hash: &str,
+        query: &str,
+        write_mode: &WriteMode,
+    ) -> Result<ResultSet, SqlExecutionError> {
+        let db_name = &environment_hash[..63];
+        self.connect_environment(environment, environment_hash)
+            .await?;
+        let password_hash =
+            blake3::keyed_hash(&self.password_hash_key, environment_hash.as_bytes())
+                .to_hex()
+                .to_string();
+        let seed = format!("{:?}{}", SystemTime::now(), std::process::id());
+        let copy = format!(
+            "{}{}",
+            copy_prefix(db_name),
+            &blake3::hash(seed.as_bytes()).to_hex()[..12]
+        );
+
+        debug!("Creating copy {copy} of {db_name}");
+        self.root_connection
+            .execute(
+                format!(
+                    "CREATE DATABASE \"{copy}\" OWNER \"{db_name}\" TEMPLATE \"{}\";",
+                    template_name(db_name)
+                )
+                .as_str(),
+            )
+            .await?;
+        let options = self
+            .connect_options
+            .clone()
+            .username(db_name)
+            .database(&copy)
+            .password(&password_hash)
+            .options([("statement_timeout", self.statement_timeout.to_string())]);
+        let result = async {
+            let conn = PgPoolOptions::new()
+                .max_connections(1)
+                .connect_with(options)
+                .await?;
+            let start = Instant::now();
+            let mut result = self.extract(&conn, query).await;
+            self.record_query(
+                db_name,
+                query,
+                start.elapsed(),
+                result.as_ref().map(|result_set| result_set.rows.len()),
+            )
+            .await;
+            if let (Ok(_), Some(check_query)) = (&result, &write_mode.check_query) {
+                let start = Instant::now();
+                result = self.extract(&conn, check_query).await;
+                self.record_query(
+                    db_name,
+                    check_query,
+                    start.elapsed(),
+                    result.as_ref().map(|result_set| result_set.rows.len()),
+                )
+                .await;
+            }
+            conn.close().await;
+            result
+        }
+        .await;
+        self.root_connection
+            .execute(format!("DROP DATABASE IF EXISTS \"{copy}\" WITH (FORCE);").as_str())
+            .await?;
+        result
+    }
+
+    /// Drops the template of the environment and copies left over from failed executions.
+    // Name must be trusted as queries used to drop databases don't support bind
+    pub(super) async fn drop_template(&self, name: &str) -> Result<(), SqlExecutionError> {
+        let copies: Vec<String> =
+            sqlx::query_scalar("SELECT datname FROM pg_database WHERE starts_with(datname, $1)")
+                .bind(copy_prefix(name))
+                .fetch_all(&self.root_connection)
+                .await?;
+        for copy in copies {
+            self.root_connection
+                .execute(format!("DROP DATABASE IF EXISTS \"{copy}\" WITH (FORCE);").as_str())
+                .await?;
+        }
+        self.root_connection
+            .execute(format!("DROP DATABASE IF EXISTS \"{}\";", template_name(name)).as_str())
+            .await?;
+        Ok(())
+    }
+
+    async fn database_exists(&self, name: &str) -> Result<bool, SqlExecutionError> {
+        Ok(sqlx::query("SELECT 1 FROM pg_database WHERE datname = $1")
+            .bind(name)
+            .fetch_optional(&self.root_connection)
+            .await?
+            .is_some())
+    }
+}
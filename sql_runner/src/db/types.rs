@@ -1,18 +1,34 @@
 pub use common::models::{ResultSet, SqlValue};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use sqlx::types::chrono::DateTime;
 use utoipa::ToSchema;
 
 pub trait ResultSetExtension {
     fn sort_columns(&mut self);
     fn number_columns(&mut self);
     fn sort_rows(&mut self);
+    fn fingerprint(&self) -> ResultSetFingerprint;
+    fn scalar(&self) -> Option<&SqlValue>;
+    fn round_floats(&mut self, significant_digits: u32);
+    fn truncate_timestamps(&mut self, granularity: TimeGranularity);
+    fn parse_dates(&mut self);
+}
+
+/// Precision timestamps are compared with.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeGranularity {
+    /// Timestamps become dates
+    Date,
+    /// Fractions of seconds are dropped
+    Second,
 }
 
 impl ResultSetExtension for ResultSet {
     fn sort_columns(&mut self) {
         let mut indexed_columns = self.columns.iter().enumerate().collect::<Vec<_>>();
-        indexed_columns.sort_by(|(_, column_a), (_, column_b)| column_a.cmp(column_b));
+        indexed_columns.sort_by_key(|(_, column)| *column);
         let new_rows = self
             .rows
             .iter()
@@ -39,6 +55,85 @@ impl ResultSetExtension for ResultSet {
     fn sort_rows(&mut self) {
         self.rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
     }
+
+    fn fingerprint(&self) -> ResultSetFingerprint {
+        let columns = serde_json::to_vec(&self.columns).unwrap_or_default();
+        let mut row_hashes = self
+            .rows
+            .iter()
+            .map(|row| blake3::hash(&serde_json::to_vec(row).unwrap_or_default()))
+            .collect::<Vec<_>>();
+
+        let mut ordered = blake3::Hasher::new();
+        ordered.update(&columns);
+        for row_hash in &row_hashes {
+            ordered.update(row_hash.as_bytes());
+        }
+
+        row_hashes.sort_by_key(|row_hash| *row_hash.as_bytes());
+        let mut unordered = blake3::Hasher::new();
+        unordered.update(&columns);
+        for row_hash in &row_hashes {
+            unordered.update(row_hash.as_bytes());
+        }
+
+        ResultSetFingerprint {
+            ordered: ordered.finalize().to_hex().to_string(),
+            unordered: unordered.finalize().to_hex().to_string(),
+        }
+    }
+
+    /// The only value of a result set with a single row and column.
+    fn scalar(&self) -> Option<&SqlValue> {
+        match self.rows.as_slice() {
+            [row] if row.len() == 1 => row.first(),
+            _ => None,
+        }
+    }
+
+    /// Rounds every float to the given number of significant digits, only meant for output
+    /// as comparisons use the exact values.
+    fn round_floats(&mut self, significant_digits: u32) {
+        let precision = significant_digits.max(1) as usize - 1;
+        for value in self.rows.iter_mut().flatten() {
+            match value {
+                SqlValue::Float(f) if f.is_finite() => {
+                    *f = format!("{f:.precision$e}").parse().unwrap_or(*f)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn truncate_timestamps(&mut self, granularity: TimeGranularity) {
+        for value in self.rows.iter_mut().flatten() {
+            if let SqlValue::Timestamp(timestamp) = value {
+                *value = match granularity {
+                    TimeGranularity::Date => SqlValue::Date(timestamp.date()),
+                    TimeGranularity::Second => SqlValue::Timestamp(
+                        DateTime::from_timestamp(timestamp.and_utc().timestamp(), 0)
+                            .map_or(*timestamp, |truncated| truncated.naive_utc()),
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Parses text spelling a timestamp or date, e.g. of `to_char`, so it equals typed values.
+    fn parse_dates(&mut self) {
+        for value in self.rows.iter_mut().flatten() {
+            if let SqlValue::Text(_) = value {
+                *value = std::mem::replace(value, SqlValue::Null).parse_date_text();
+            }
+        }
+    }
+}
+
+/// Hashes of a result set: `ordered` changes with the row order, `unordered` only with the rows.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq, Eq)]
+pub struct ResultSetFingerprint {
+    pub ordered: String,
+    pub unordered: String,
 }
 
 #[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
@@ -50,6 +145,25 @@ pub struct DatabaseInfo {
     pub triggers: Vec<TriggerDatabaseInfo>,
 }
 
+impl DatabaseInfo {
+    /// Removes view, routine and trigger definitions and check clauses, which may reveal
+    /// solution logic, keeping names and structure.
+    pub fn redact(&mut self) {
+        for constraint in self.constraints.iter_mut().flat_map(|c| c.json.iter_mut()) {
+            constraint.check_clause = None;
+        }
+        for view in &mut self.views {
+            view.definition = None;
+        }
+        for routine in &mut self.routines {
+            routine.definition = None;
+        }
+        for trigger in &mut self.triggers {
+            trigger.statement = None;
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct TableColumnInfo {
     pub name: String,
@@ -89,7 +203,7 @@ pub struct ConstraintsDatabaseInfo {
 pub struct ViewDatabaseInfo {
     #[serde(rename = "table")]
     pub table_name: String,
-    pub definition: String,
+    pub definition: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
@@ -107,7 +221,7 @@ pub struct TriggerDatabaseInfo {
     #[serde(rename = "objectTable")]
     pub object_table: String,
     pub json: Vec<String>,
-    pub statement: String,
+    pub statement: Option<String>,
     pub orientation: String,
     pub timing: String,
 }
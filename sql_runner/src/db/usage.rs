@@ -0,0 +1,72 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Which environments the cleanup drops, nothing is dropped by default.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CleanupPolicy {
+    /// Environments unused for longer are dropped
+    pub ttl: Option<Duration>,
+    /// The least recently used environments beyond this count are dropped
+    pub max_environments: Option<usize>,
+}
+
+/// Last use of every environment. Environments created before the start of the runner count
+/// as used when the cleanup first sees them.
+#[derive(Debug, Default)]
+pub struct EnvironmentUsage(Mutex<HashMap<String, SystemTime>>);
+
+impl EnvironmentUsage {
+    pub fn touch(&self, name: &str) {
+        self.touch_at(name, SystemTime::now());
+    }
+
+    pub fn touch_at(&self, name: &str, at: SystemTime) {
+        self.0.lock().unwrap().insert(name.to_string(), at);
+    }
+
+    pub fn forget(&self, name: &str) {
+        self.0.lock().unwrap().remove(name);
+    }
+
+    /// Whether the environment was used after `at`.
+    pub fn used_since(&self, name: &str, at: SystemTime) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .get(name)
+            .is_some_and(|used| *used > at)
+    }
+
+    /// Existing environments to drop according to the policy, least recently used first.
+    pub fn stale(
+        &self,
+        existing: &[String],
+        policy: &CleanupPolicy,
+        now: SystemTime,
+    ) -> Vec<String> {
+        let mut last_used = self.0.lock().unwrap();
+        let existing_names: HashSet<&String> = existing.iter().collect();
+        last_used.retain(|name, _| existing_names.contains(name));
+        for name in existing {
+            last_used.entry(name.clone()).or_insert(now);
+        }
+
+        let mut environments: Vec<(&String, &SystemTime)> = last_used.iter().collect();
+        environments.sort_by_key(|(name, used)| (**used, *name));
+        let beyond_max = policy
+            .max_environments
+            .map_or(0, |max| environments.len().saturating_sub(max));
+        environments
+            .into_iter()
+            .enumerate()
+            .filter(|(index, (_, used))| {
+                *index < beyond_max
+                    || policy
+                        .ttl
+                        .is_some_and(|ttl| now.duration_since(**used).unwrap_or_default() > ttl)
+            })
+            .map(|(_, (name, _))| name.clone())
+            .collect()
+    }
+}
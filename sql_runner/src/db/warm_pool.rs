@@ -0,0 +1,135 @@
+use crate::db::isolation::IsolationStrategy;
+use crate::db::{DB, SqlExecutionError};
+use log::{debug, error, info, warn};
+use sqlx::Executor;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Notify;
+
+/// Pause before the next refill after creating a pooled database failed.
+const REFILL_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Blank databases, each owned by a user of the same name, created ahead of time so an
+/// environment only has to claim one instead of waiting for `CREATE DATABASE`. Their names
+/// don't look like environment names, so the cleanup leaves them alone.
+#[derive(Debug, Default)]
+pub struct WarmPool {
+    size: usize,
+    available: std::sync::Mutex<VecDeque<String>>,
+    refill: Notify,
+}
+
+impl WarmPool {
+    pub fn new(size: usize) -> Self {
+        WarmPool {
+            size,
+            ..Default::default()
+        }
+    }
+
+    pub fn available(&self) -> usize {
+        self.available.lock().unwrap().len()
+    }
+}
+
+impl DB {
+    /// Keeps the warm pool filled in the background. Pooled databases left over from an
+    /// earlier run are adopted, the pool is only used with database isolation.
+    pub fn spawn_warm_pool(self: &Arc<Self>) {
+        if self.warm_pool.size == 0
+            || !matches!(self.isolation, IsolationStrategy::DatabasePerEnvironment)
+        {
+            return;
+        }
+        let db = Arc::clone(self);
+        tokio::spawn(async move {
+            match db.pooled_database_names().await {
+                Ok(names) => {
+                    if !names.is_empty() {
+                        info!("Adopted {} pooled databases", names.len());
+                    }
+                    db.warm_pool.available.lock().unwrap().extend(names);
+                }
+                Err(err) => error!("Failed to list pooled databases: {err}"),
+            }
+            loop {
+                while db.warm_pool.available() < db.warm_pool.size {
+                    match db.create_pooled_database().await {
+                        Ok(name) => db.warm_pool.available.lock().unwrap().push_back(name),
+                        Err(err) => {
+                            error!("Failed to create pooled database: {err}");
+                            tokio::time::sleep(REFILL_RETRY_DELAY).await;
+                        }
+                    }
+                }
+                db.warm_pool.refill.notified().await;
+            }
+        });
+    }
+
+    /// Renames a pooled database and its user to the environment and sets the password of
+    /// the user. Returns false if the pool is empty.
+    // Name and password must be trusted as queries used to rename don't support bind
+    pub(super) async fn claim_pooled_database(
+        &self,
+        name: &str,
+        password: &str,
+    ) -> Result<bool, SqlExecutionError> {
+        loop {
+            let Some(pooled) = self.warm_pool.available.lock().unwrap().pop_front() else {
+                return Ok(false);
+            };
+            self.warm_pool.refill.notify_one();
+            // fails if another replica adopted and claimed the same database
+            if let Err(err) = self
+                .root_connection
+                .execute(format!("ALTER DATABASE \"{pooled}\" RENAME TO \"{name}\";").as_str())
+                .await
+            {
+                warn!("Failed to claim pooled database {pooled}: {err}");
+                continue;
+            }
+            debug!("Claimed pooled database {pooled} for {name}");
+            self.root_connection
+                .execute(format!("ALTER USER \"{pooled}\" RENAME TO \"{name}\";").as_str())
+                .await?;
+            self.root_connection
+                .execute(
+                    format!("ALTER USER \"{name}\" WITH ENCRYPTED PASSWORD '{password}';").as_str(),
+                )
+                .await?;
+            return Ok(true);
+        }
+    }
+
+    /// The user is created first, so a pooled database always has its user.
+    async fn create_pooled_database(&self) -> Result<String, SqlExecutionError> {
+        let seed = format!("{:?}{}", SystemTime::now(), std::process::id());
+        let name = format!("pool_{}", &blake3::hash(seed.as_bytes()).to_hex()[..32]);
+        debug!("Creating pooled database {name}");
+        self.root_connection
+            .execute(format!("CREATE USER \"{name}\";").as_str())
+            .await?;
+        if let Err(err) = self
+            .root_connection
+            .execute(format!("CREATE DATABASE \"{name}\" OWNER \"{name}\";").as_str())
+            .await
+        {
+            self.root_connection
+                .execute(format!("DROP USER IF EXISTS \"{name}\";").as_str())
+                .await?;
+            return Err(err.into());
+        }
+        Ok(name)
+    }
+
+    async fn pooled_database_names(&self) -> Result<Vec<String>, SqlExecutionError> {
+        Ok(sqlx::query_scalar(
+            "SELECT datname FROM pg_database JOIN pg_roles ON rolname = datname \
+             WHERE datname ~ '^pool_[0-9a-f]{32}$' ORDER BY datname",
+        )
+        .fetch_all(&self.root_connection)
+        .await?)
+    }
+}
@@ -0,0 +1,78 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Rules used if no rule file is configured, covering access to cluster wide catalogs,
+/// server files and other databases.
+const DEFAULT_RULES: &[(&str, &str)] = &[
+    ("pg_catalog", r"(?i)\bpg_catalog\b"),
+    (
+        "system_catalog",
+        r"(?i)\bpg_(authid|shadow|roles|user|database|stat_activity|settings|hba_file_rules)\b",
+    ),
+    (
+        "server_files",
+        r"(?i)\bpg_(read_file|read_binary_file|ls_dir|stat_file|ls_logdir|ls_waldir)\s*\(",
+    ),
+    ("large_objects", r"(?i)\blo_(import|export)\s*\("),
+    ("dblink", r"(?i)\bdblink\w*\s*\("),
+    ("copy_program", r"(?is)\bcopy\b.*\bprogram\b"),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+struct DenyRuleConfig {
+    name: String,
+    pattern: String,
+}
+
+#[derive(Debug, Clone)]
+struct DenyRule {
+    name: String,
+    pattern: Regex,
+}
+
+/// Regex rules checked against every query before it is executed.
+#[derive(Debug, Clone)]
+pub struct DenyRules {
+    rules: Vec<DenyRule>,
+}
+
+impl DenyRules {
+    /// Loads a JSON list of `{"name": ..., "pattern": ...}` rules, replacing the default rules.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let rules: Vec<DenyRuleConfig> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(DenyRules {
+            rules: rules
+                .into_iter()
+                .map(|rule| {
+                    Ok(DenyRule {
+                        pattern: Regex::new(&rule.pattern)?,
+                        name: rule.name,
+                    })
+                })
+                .collect::<Result<_, regex::Error>>()?,
+        })
+    }
+
+    /// Name of the first rule matching the query.
+    pub fn check(&self, query: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(query))
+            .map(|rule| rule.name.as_str())
+    }
+}
+
+impl Default for DenyRules {
+    fn default() -> Self {
+        DenyRules {
+            rules: DEFAULT_RULES
+                .iter()
+                .map(|(name, pattern)| DenyRule {
+                    name: name.to_string(),
+                    pattern: Regex::new(pattern).unwrap(),
+                })
+                .collect(),
+        }
+    }
+}
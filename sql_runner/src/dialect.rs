@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use sqlparser::dialect::{Dialect, MySqlDialect, SQLiteDialect};
+use sqlparser::keywords::Keyword;
+use sqlparser::tokenizer::{Token, Tokenizer, Whitespace};
+use utoipa::ToSchema;
+
+/// Dialect queries are written in, anything but `postgres` is translated before execution.
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SqlDialect {
+    #[default]
+    Postgres,
+    Mysql,
+    Sqlite,
+}
+
+/// Query rewritten for Postgres and the constructs which were translated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub query: String,
+    pub translated: Vec<&'static str>,
+}
+
+/// Functions with a Postgres equivalent taking the same arguments.
+const FUNCTIONS: &[(&str, &str)] = &[("IFNULL", "COALESCE"), ("RAND", "RANDOM")];
+
+/// Rewrites common MySQL and SQLite idioms into Postgres syntax: quoted identifiers, double
+/// quoted MySQL strings, `LIMIT offset, count`, `#` comments and functions from
+/// [`FUNCTIONS`]. Queries which cannot be tokenized are returned unchanged.
+pub fn translate(query: &str, dialect: SqlDialect) -> Translation {
+    let tokens = match dialect {
+        SqlDialect::Postgres => None,
+        SqlDialect::Mysql => tokenize(&MySqlDialect {}, query),
+        SqlDialect::Sqlite => tokenize(&SQLiteDialect {}, query),
+    };
+    let Some(tokens) = tokens else {
+        return Translation {
+            query: query.to_string(),
+            translated: vec![],
+        };
+    };
+
+    let mut translated = Vec::new();
+    let mut flag = |construct: &'static str| {
+        if !translated.contains(&construct) {
+            translated.push(construct);
+        }
+    };
+    let mut rendered = String::with_capacity(query.len());
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = &tokens[index];
+        match token {
+            Token::Word(word) if word.keyword == Keyword::LIMIT => {
+                if let Some((offset, count, end)) = limit_with_offset(&tokens, index) {
+                    flag("LIMIT offset, count");
+                    rendered.push_str(&format!("LIMIT {count} OFFSET {offset}"));
+                    index = end;
+                    continue;
+                }
+                rendered.push_str(&word.value);
+            }
+            Token::Word(word) => match word.quote_style {
+                Some('"') => rendered.push_str(&quote_identifier(&word.value)),
+                Some(_) => {
+                    flag("quoted identifier");
+                    rendered.push_str(&quote_identifier(&word.value));
+                }
+                None => match function_replacement(&tokens, index) {
+                    Some(replacement) => {
+                        flag(replacement.0);
+                        rendered.push_str(replacement.1);
+                    }
+                    None => rendered.push_str(&word.value),
+                },
+            },
+            Token::SingleQuotedString(value) => rendered.push_str(&quote_string(value)),
+            Token::DoubleQuotedString(value) => {
+                flag("double quoted string");
+                rendered.push_str(&quote_string(value));
+            }
+            Token::Whitespace(Whitespace::SingleLineComment { comment, prefix }) => {
+                if prefix != "--" {
+                    flag("# comment");
+                }
+                rendered.push_str("--");
+                rendered.push_str(comment);
+            }
+            token => rendered.push_str(&token.to_string()),
+        }
+        index += 1;
+    }
+    Translation {
+        query: rendered,
+        translated,
+    }
+}
+
+/// Solution and submission of a comparison translated to Postgres, the solution is written in
+/// `solution_dialect` if given, else in the dialect of the submission.
+pub fn translate_comparison(
+    solution: &str,
+    submission: &str,
+    dialect: SqlDialect,
+    solution_dialect: Option<SqlDialect>,
+) -> (Translation, Translation) {
+    (
+        translate(solution, solution_dialect.unwrap_or(dialect)),
+        translate(submission, dialect),
+    )
+}
+
+fn tokenize(dialect: &dyn Dialect, query: &str) -> Option<Vec<Token>> {
+    Tokenizer::new(dialect, query).tokenize().ok()
+}
+
+/// Offset, count and the index after `LIMIT offset, count` starting at `index`.
+fn limit_with_offset(tokens: &[Token], index: usize) -> Option<(&str, &str, usize)> {
+    let mut significant = tokens
+        .iter()
+        .enumerate()
+        .skip(index + 1)
+        .filter(|(_, token)| !matches!(token, Token::Whitespace(_)));
+    match (
+        significant.next()?,
+        significant.next()?,
+        significant.next()?,
+    ) {
+        ((_, Token::Number(offset, _)), (_, Token::Comma), (end, Token::Number(count, _))) => {
+            Some((offset, count, end + 1))
+        }
+        _ => None,
+    }
+}
+
+/// Postgres function replacing the MySQL or SQLite function called at `index`.
+fn function_replacement(tokens: &[Token], index: usize) -> Option<(&'static str, &'static str)> {
+    let Token::Word(word) = &tokens[index] else {
+        return None;
+    };
+    let is_call = tokens[index + 1..]
+        .iter()
+        .find(|token| !matches!(token, Token::Whitespace(_)))
+        .is_some_and(|token| *token == Token::LParen);
+    FUNCTIONS
+        .iter()
+        .find(|(name, _)| is_call && word.value.eq_ignore_ascii_case(name))
+        .map(|(name, replacement)| (*name, *replacement))
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
@@ -0,0 +1,101 @@
+use serde::Deserialize;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+/// Environment of a request, either a complete script or registered fragments.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum Environment {
+    Script(String),
+    /// Names of fragments, e.g. base schema, dataset and task specific views, concatenated in
+    /// order. Updating a fragment changes every environment including it.
+    Fragments(Vec<String>),
+}
+
+#[derive(Error, Debug)]
+pub enum FragmentError {
+    #[error("invalid fragment name `{0}`")]
+    InvalidName(String),
+    #[error("unknown fragment `{0}`")]
+    NotFound(String),
+    #[error("failed to access fragment: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Named environment fragments stored as files.
+#[derive(Debug)]
+pub struct FragmentStore {
+    dir: PathBuf,
+}
+
+impl FragmentStore {
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(FragmentStore { dir })
+    }
+
+    pub async fn put(&self, name: &str, script: &str) -> Result<(), FragmentError> {
+        let path = self.path(name)?;
+        let partial = self.dir.join(format!(".{name}.partial"));
+        tokio::fs::write(&partial, script).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, name: &str) -> Result<String, FragmentError> {
+        match tokio::fs::read_to_string(self.path(name)?).await {
+            Ok(script) => Ok(script),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                Err(FragmentError::NotFound(name.to_string()))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn list(&self) -> Result<Vec<String>, FragmentError> {
+        let mut names = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(name) = entry
+                .file_name()
+                .to_str()
+                .filter(|name| is_valid_name(name))
+            {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// The script of the environment, fragments are separated by a statement delimiter.
+    pub async fn resolve(&self, environment: &Environment) -> Result<String, FragmentError> {
+        match environment {
+            Environment::Script(script) => Ok(script.clone()),
+            Environment::Fragments(names) => {
+                let mut scripts = Vec::with_capacity(names.len());
+                for name in names {
+                    scripts.push(self.get(name).await?);
+                }
+                Ok(scripts.join(";\n"))
+            }
+        }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, FragmentError> {
+        if !is_valid_name(name) {
+            return Err(FragmentError::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(name))
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
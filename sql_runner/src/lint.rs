@@ -0,0 +1,109 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+struct Rule {
+    name: &'static str,
+    pattern: &'static str,
+    message: &'static str,
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        name: "random",
+        pattern: "random(",
+        message: "random() produces different data for every environment initialisation",
+    },
+    Rule {
+        name: "uuid",
+        pattern: "gen_random_uuid(",
+        message: "generated UUIDs differ for every environment initialisation",
+    },
+    Rule {
+        name: "uuid",
+        pattern: "uuid_generate_v4(",
+        message: "generated UUIDs differ for every environment initialisation",
+    },
+    Rule {
+        name: "current_time",
+        pattern: "now(",
+        message: "now() depends on the time the environment was initialised",
+    },
+    Rule {
+        name: "current_time",
+        pattern: "current_timestamp",
+        message: "current_timestamp depends on the time the environment was initialised",
+    },
+    Rule {
+        name: "current_time",
+        pattern: "current_date",
+        message: "current_date depends on the time the environment was initialised",
+    },
+    Rule {
+        name: "current_time",
+        pattern: "clock_timestamp(",
+        message: "clock_timestamp() depends on the time the environment was initialised",
+    },
+    Rule {
+        name: "current_time",
+        pattern: "localtimestamp",
+        message: "localtimestamp depends on the time the environment was initialised",
+    },
+];
+
+const SEQUENCE_COLUMNS: &[&str] = &[
+    "serial",
+    "generated always as identity",
+    "generated by default as identity",
+];
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LintWarning {
+    /// 1-based line in the environment script
+    pub line: usize,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+/// Flags constructs in an environment script which make the seeded data, and therefore the
+/// grading, non-deterministic.
+pub fn lint_environment(environment: &str) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let mut uses_sequences = false;
+    let mut conflict_lines = Vec::new();
+
+    for (index, line) in environment.lines().enumerate() {
+        let code = line.split("--").next().unwrap_or_default().to_lowercase();
+        for rule in RULES {
+            if code.contains(rule.pattern) {
+                warnings.push(LintWarning {
+                    line: index + 1,
+                    rule: rule.name,
+                    message: rule.message.to_string(),
+                });
+            }
+        }
+        if SEQUENCE_COLUMNS
+            .iter()
+            .any(|pattern| code.contains(pattern))
+        {
+            uses_sequences = true;
+        }
+        if code.contains("on conflict") {
+            conflict_lines.push(index + 1);
+        }
+    }
+
+    if uses_sequences {
+        warnings.extend(conflict_lines.into_iter().map(|line| {
+            LintWarning {
+                line,
+                rule: "serial_gap",
+                message:
+                    "skipped inserts still consume sequence values, leaving gaps in generated ids"
+                        .to_string(),
+            }
+        }));
+    }
+
+    warnings
+}
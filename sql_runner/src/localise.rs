@@ -0,0 +1,54 @@
+use crate::db::SqlExecutionError;
+use common::i18n::Language;
+
+tokio::task_local! {
+    /// Language of the errors of the request currently handled, negotiated from its
+    /// `Accept-Language` header.
+    pub static LANGUAGE: Language;
+}
+
+/// Language of the request currently handled, English outside of requests.
+pub fn current_language() -> Language {
+    LANGUAGE.try_with(|language| *language).unwrap_or_default()
+}
+
+/// Message of an error shown to students, in English unless it is translated.
+pub fn localise_error(err: &SqlExecutionError, language: Language) -> String {
+    let translated = match err {
+        SqlExecutionError::Init(e) => {
+            language.translate("error-init", &[("message", &postgres_message(e, language))])
+        }
+        SqlExecutionError::Execute(e) => language.translate(
+            "error-query",
+            &[("message", &postgres_message(e, language))],
+        ),
+        SqlExecutionError::Timeout(_) => language.translate("error-timeout", &[]),
+        SqlExecutionError::Denied(rule) => language.translate("error-denied", &[("rule", rule)]),
+        SqlExecutionError::SeedData(message) => {
+            language.translate("error-seed-data", &[("message", message)])
+        }
+        SqlExecutionError::BudgetExceeded => language.translate("error-budget", &[]),
+        _ => None,
+    };
+    translated.unwrap_or_else(|| err.to_string())
+}
+
+/// Message of a Postgres error translated by its SQLSTATE, the original message if there is
+/// no translation.
+fn postgres_message(err: &sqlx::Error, language: Language) -> String {
+    let sqlx::Error::Database(database_error) = err else {
+        return err.to_string();
+    };
+    let message = database_error.message();
+    // Postgres quotes the names of columns, tables, constraints and offending input
+    let args: Vec<_> = message
+        .split('"')
+        .nth(1)
+        .map(|name| ("name", name))
+        .into_iter()
+        .collect();
+    database_error
+        .code()
+        .and_then(|code| language.translate(&format!("pg-{code}"), &args))
+        .unwrap_or_else(|| message.to_string())
+}
@@ -1,19 +1,51 @@
+mod api_keys;
+mod datagen;
 mod db;
+mod deny;
+mod dialect;
+mod fragments;
+mod lint;
+mod localise;
+#[cfg(feature = "playground")]
+mod playground;
 mod routes;
 
+use crate::api_keys::{ApiKeys, KeyRateLimit};
 use crate::db::DB;
+use crate::db::audit::AuditLog;
+use crate::db::budget::ExecutionBudget;
+use crate::db::isolation::IsolationStrategy;
+use crate::db::result_cache::ResultCache;
+use crate::db::seed::SeedStore;
+use crate::db::usage::CleanupPolicy;
+use crate::deny::DenyRules;
+use crate::fragments::FragmentStore;
+use anyhow::anyhow;
+use axum::extract::DefaultBodyLimit;
+use axum::middleware;
+use common::config::{ConfigError, ConfigValidator};
 use env_logger::Env;
 use log::{error, info};
 use serde::de::Error as SerdeError;
 use serde::{Deserialize, Deserializer};
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 use utoipa_redoc::Redoc;
 use utoipa_redoc::Servable;
 
+/// Cargo features this binary was built with.
+pub const ENABLED_FEATURES: &[&str] = &[
+    #[cfg(feature = "playground")]
+    "playground",
+];
+
 fn get_default_port() -> u16 {
     8080
 }
@@ -26,15 +58,102 @@ fn get_default_statement_timeout() -> u64 {
     10000
 }
 
+fn get_default_query_history_size() -> usize {
+    0
+}
+
+fn get_default_batch_concurrency() -> usize {
+    4
+}
+
+fn get_default_isolation() -> String {
+    "database".to_string()
+}
+
+fn get_default_init_concurrency() -> usize {
+    1
+}
+
+fn get_default_audit_log_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn get_default_audit_log_max_files() -> usize {
+    5
+}
+
+fn get_default_task_id_header() -> String {
+    "X-Task-Id".to_string()
+}
+
+fn get_default_user_id_header() -> String {
+    "X-User-Id".to_string()
+}
+
+fn get_default_seed_data_dir() -> String {
+    std::env::temp_dir()
+        .join("sql_runner_seed_data")
+        .to_string_lossy()
+        .to_string()
+}
+
+fn get_default_fragment_dir() -> String {
+    std::env::temp_dir()
+        .join("sql_runner_fragments")
+        .to_string_lossy()
+        .to_string()
+}
+
+fn get_default_max_seed_data_bytes() -> usize {
+    1024 * 1024 * 1024
+}
+
+fn get_default_max_concurrent_environment_creations() -> usize {
+    2
+}
+
+fn get_default_environment_cleanup_interval_secs() -> u64 {
+    3600
+}
+
+fn get_default_warm_pool_size() -> usize {
+    0
+}
+
+fn get_default_result_cache_size() -> usize {
+    0
+}
+
+fn get_default_result_cache_ttl_secs() -> u64 {
+    300
+}
+
+fn get_default_result_cache_max_rows() -> usize {
+    10_000
+}
+
+fn get_default_max_connection_pools() -> usize {
+    50
+}
+
+fn get_default_connection_pool_idle_secs() -> u64 {
+    600
+}
+
+fn get_default_audit_caller_header() -> String {
+    "X-Caller".to_string()
+}
+
 pub fn hex_to_bytes32<'de, D>(deserializer: D) -> Result<[u8; 32], D::Error>
 where
     D: Deserializer<'de>,
 {
     let s: String = Deserialize::deserialize(deserializer)?;
-    let bytes = hex::decode(s).map_err(SerdeError::custom)?;
+    let bytes = hex::decode(s)
+        .map_err(|err| SerdeError::custom(format!("PASSWORD_HASH_KEY is not valid hex: {err}")))?;
     if bytes.len() != 32 {
         return Err(SerdeError::custom(format!(
-            "Expected 32 bytes, got {}",
+            "PASSWORD_HASH_KEY must be 32 bytes (64 hex characters), got {} bytes",
             bytes.len()
         )));
     }
@@ -47,47 +166,380 @@ where
 struct Config {
     #[serde(default = "get_default_port")]
     port: u16,
-    db_password: String,
-    db_username: String,
-    db_host: String,
+    /// Full connection url of the root user, alternative to the `db_*` options
+    database_url: Option<String>,
+    db_password: Option<String>,
+    db_username: Option<String>,
+    /// Hostname, or directory of the unix socket if starting with `/`
+    db_host: Option<String>,
+    db_port: Option<u16>,
+    /// One of `disable`, `allow`, `prefer`, `require`, `verify-ca` or `verify-full`
+    db_ssl_mode: Option<String>,
+    /// CA certificate used to verify the server with `verify-ca` and `verify-full`
+    db_ssl_root_cert: Option<String>,
     #[serde(deserialize_with = "hex_to_bytes32")]
     password_hash_key: [u8; 32],
     #[serde(default = "get_default_max_rows_in_result_set")]
     max_rows_in_result_set: usize,
     #[serde(default = "get_default_statement_timeout")]
     statement_timeout: u64,
+    #[serde(default = "get_default_query_history_size")]
+    query_history_size: usize,
+    #[serde(default = "get_default_batch_concurrency")]
+    batch_concurrency: usize,
+    /// Either `database` (one database and user per environment) or `schema` (one schema per
+    /// environment in the configured database, queried by `isolation_role`)
+    #[serde(default = "get_default_isolation")]
+    isolation: String,
+    /// Pre-existing role used to execute queries with `schema` isolation
+    isolation_role: Option<String>,
+    isolation_role_password: Option<String>,
+    /// JSON file with `{"name": ..., "pattern": ...}` rules rejecting matching queries,
+    /// replacing the default rules
+    query_deny_rules: Option<String>,
+    /// Append-only JSONL file every executed query is logged to
+    audit_log: Option<String>,
+    #[serde(default = "get_default_audit_log_max_bytes")]
+    audit_log_max_bytes: u64,
+    /// Number of rotated audit log files kept
+    #[serde(default = "get_default_audit_log_max_files")]
+    audit_log_max_files: usize,
+    /// Request header identifying the caller in the audit log
+    #[serde(default = "get_default_audit_caller_header")]
+    audit_caller_header: String,
+    /// Connections used to execute the inserts of environment scripts, `1` executes the
+    /// script sequentially
+    #[serde(default = "get_default_init_concurrency")]
+    init_concurrency: usize,
+    /// Environments created and initialised at the same time, further ones are queued
+    #[serde(default = "get_default_max_concurrent_environment_creations")]
+    max_concurrent_environment_creations: usize,
+    /// Directory uploaded seed data is stored in
+    #[serde(default = "get_default_seed_data_dir")]
+    seed_data_dir: String,
+    /// Maximum size of a seed data upload
+    #[serde(default = "get_default_max_seed_data_bytes")]
+    max_seed_data_bytes: usize,
+    /// Directory registered environment fragments are stored in
+    #[serde(default = "get_default_fragment_dir")]
+    fragment_dir: String,
+    /// Round floats in returned result sets to this many significant digits by default
+    float_significant_digits: Option<u32>,
+    /// Cumulative execution time in milliseconds allowed per caller, task and user, requests
+    /// without the task header are not limited
+    execution_budget_ms: Option<u64>,
+    /// Request header identifying the task for the execution budget
+    #[serde(default = "get_default_task_id_header")]
+    task_id_header: String,
+    /// Request header identifying the user for the execution budget
+    #[serde(default = "get_default_user_id_header")]
+    user_id_header: String,
+    /// Environments unused for this many seconds are dropped by the cleanup
+    environment_ttl_secs: Option<u64>,
+    /// The least recently used environments beyond this count are dropped by the cleanup
+    max_environments: Option<usize>,
+    /// Seconds between background cleanups, which only run if `environment_ttl_secs` or
+    /// `max_environments` is set
+    #[serde(default = "get_default_environment_cleanup_interval_secs")]
+    environment_cleanup_interval_secs: u64,
+    /// Blank databases kept ready for new environments, so their creation only has to run
+    /// the init script. Requires `database` isolation
+    #[serde(default = "get_default_warm_pool_size")]
+    warm_pool_size: usize,
+    /// Initialise every environment once in a template database and create it, and the
+    /// per-use copies of write mode, with `CREATE DATABASE ... TEMPLATE`. Requires `database`
+    /// isolation, pooled databases are not used
+    #[serde(default)]
+    template_databases: bool,
+    /// Connection pools to environments kept open, each holding one connection, the least
+    /// recently used one is closed to make room. 0 for no limit
+    #[serde(default = "get_default_max_connection_pools")]
+    max_connection_pools: usize,
+    /// Seconds after which an unused connection pool is closed
+    #[serde(default = "get_default_connection_pool_idle_secs")]
+    connection_pool_idle_secs: u64,
+    /// Result sets of solution queries kept in memory, so comparing many submissions with
+    /// the same solution executes it once. 0 disables the cache
+    #[serde(default = "get_default_result_cache_size")]
+    result_cache_size: usize,
+    /// Seconds a cached result set is served before the query is executed again
+    #[serde(default = "get_default_result_cache_ttl_secs")]
+    result_cache_ttl_secs: u64,
+    /// Result sets with more rows are not cached
+    #[serde(default = "get_default_result_cache_max_rows")]
+    result_cache_max_rows: usize,
+    /// Allow `/api/v1/explain` to execute queries with `EXPLAIN ANALYZE`
+    #[serde(default)]
+    allow_explain_analyze: bool,
+    /// Comma separated `name:hash` pairs of the API keys accepted as bearer token, hashed with
+    /// blake3 and hex encoded. Every route except `/ping` and `/redoc` requires a key if set
+    api_keys: Option<String>,
+    /// Requests allowed per API key and minute
+    api_key_requests_per_minute: Option<u32>,
+}
+
+impl Config {
+    /// Problems envy cannot detect, reported together before exiting.
+    fn validate(&self) -> Result<(), ConfigError> {
+        ConfigValidator::new()
+            .port("PORT", self.port)
+            .check(
+                self.database_url.is_some() || self.db_host.is_some(),
+                || "either DATABASE_URL or DB_HOST must be set".to_string(),
+            )
+            .parses(
+                "DATABASE_URL",
+                self.database_url.as_deref().map(PgConnectOptions::from_str),
+            )
+            .parses(
+                "DB_SSL_MODE",
+                self.db_ssl_mode.as_deref().map(PgSslMode::from_str),
+            )
+            .check(
+                matches!(self.isolation.as_str(), "database" | "schema"),
+                || {
+                    format!(
+                        "ISOLATION must be `database` or `schema`, got `{}`",
+                        self.isolation
+                    )
+                },
+            )
+            .check(
+                self.isolation != "schema"
+                    || (self.isolation_role.is_some() && self.isolation_role_password.is_some()),
+                || {
+                    "schema isolation requires ISOLATION_ROLE and ISOLATION_ROLE_PASSWORD"
+                        .to_string()
+                },
+            )
+            .positive("MAX_ROWS_IN_RESULT_SET", self.max_rows_in_result_set as u64)
+            .positive("BATCH_CONCURRENCY", self.batch_concurrency as u64)
+            .positive(
+                "MAX_CONCURRENT_ENVIRONMENT_CREATIONS",
+                self.max_concurrent_environment_creations as u64,
+            )
+            .positive("CONNECTION_POOL_IDLE_SECS", self.connection_pool_idle_secs)
+            .positive(
+                "ENVIRONMENT_CLEANUP_INTERVAL_SECS",
+                self.environment_cleanup_interval_secs,
+            )
+            .parses("API_KEYS", self.api_keys.as_deref().map(ApiKeys::parse))
+            .requires(
+                (
+                    "API_KEY_REQUESTS_PER_MINUTE",
+                    self.api_key_requests_per_minute.is_some(),
+                ),
+                ("API_KEYS", self.api_keys.is_some()),
+            )
+            .positive(
+                "API_KEY_REQUESTS_PER_MINUTE",
+                self.api_key_requests_per_minute.unwrap_or(1).into(),
+            )
+            .requires(
+                ("RESULT_CACHE_SIZE", self.result_cache_size > 0),
+                (
+                    "a positive RESULT_CACHE_TTL_SECS",
+                    self.result_cache_ttl_secs > 0,
+                ),
+            )
+            .check(
+                self.warm_pool_size == 0 || self.isolation == "database",
+                || "WARM_POOL_SIZE requires database isolation".to_string(),
+            )
+            .check(
+                !self.template_databases || self.isolation == "database",
+                || "TEMPLATE_DATABASES requires database isolation".to_string(),
+            )
+            .check(!self.template_databases || self.warm_pool_size == 0, || {
+                "WARM_POOL_SIZE can't be combined with TEMPLATE_DATABASES".to_string()
+            })
+            .check(
+                self.float_significant_digits
+                    .is_none_or(|digits| (1..=17).contains(&digits)),
+                || "FLOAT_SIGNIFICANT_DIGITS must be between 1 and 17".to_string(),
+            )
+            .check(
+                self.query_deny_rules
+                    .as_deref()
+                    .is_none_or(|path| Path::new(path).is_file()),
+                || "QUERY_DENY_RULES does not point to a file".to_string(),
+            )
+            .finish()
+    }
 }
 
 #[derive(Debug, Clone)]
 struct AppState {
     db: Arc<DB>,
+    batch_concurrency: usize,
+    audit_caller_header: String,
+    fragments: Arc<FragmentStore>,
+    float_significant_digits: Option<u32>,
+    task_id_header: String,
+    user_id_header: String,
+    allow_explain_analyze: bool,
+    api_keys: Option<Arc<ApiKeys>>,
+    key_rate_limit: Option<Arc<KeyRateLimit>>,
 }
 
 #[derive(OpenApi)]
 #[openapi(info(description = "API for comparing result sets"))]
 struct ApiDoc;
 
+fn connect_options(config: &Config) -> Result<PgConnectOptions, anyhow::Error> {
+    let mut options = match &config.database_url {
+        Some(url) => PgConnectOptions::from_str(url)?,
+        None if config.db_host.is_some() => PgConnectOptions::new(),
+        None => return Err(anyhow!("either DATABASE_URL or DB_HOST must be set")),
+    };
+    if let Some(host) = &config.db_host {
+        options = options.host(host);
+    }
+    if let Some(port) = config.db_port {
+        options = options.port(port);
+    }
+    if let Some(username) = &config.db_username {
+        options = options.username(username);
+    }
+    if let Some(password) = &config.db_password {
+        options = options.password(password);
+    }
+    if let Some(ssl_mode) = &config.db_ssl_mode {
+        options = options.ssl_mode(PgSslMode::from_str(ssl_mode)?);
+    }
+    if let Some(root_cert) = &config.db_ssl_root_cert {
+        options = options.ssl_root_cert(root_cert);
+    }
+    Ok(options)
+}
+
+fn isolation_strategy(config: &Config) -> Result<IsolationStrategy, anyhow::Error> {
+    match config.isolation.as_str() {
+        "database" => Ok(IsolationStrategy::DatabasePerEnvironment),
+        "schema" => match (&config.isolation_role, &config.isolation_role_password) {
+            (Some(role), Some(role_password)) => Ok(IsolationStrategy::SchemaPerEnvironment {
+                role: role.clone(),
+                role_password: role_password.clone(),
+            }),
+            _ => Err(anyhow!(
+                "schema isolation requires ISOLATION_ROLE and ISOLATION_ROLE_PASSWORD"
+            )),
+        },
+        other => Err(anyhow!(
+            "unknown isolation `{other}`, expected `database` or `schema`"
+        )),
+    }
+}
+
 async fn run() -> Result<(), anyhow::Error> {
+    common::version::mark_started();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    let config = envy::from_env::<Config>()?;
+    let config = common::config::from_env::<Config>()?;
+    config.validate()?;
+    let metrics = common::metrics::install_recorder();
 
+    let mut db = DB::connect(
+        connect_options(&config)?,
+        config.password_hash_key,
+        config.max_rows_in_result_set,
+        config.statement_timeout,
+        config.query_history_size,
+        isolation_strategy(&config)?,
+        match &config.query_deny_rules {
+            Some(path) => DenyRules::load(Path::new(path))?,
+            None => DenyRules::default(),
+        },
+    )
+    .await?
+    .with_init_concurrency(config.init_concurrency)
+    .with_creation_concurrency(config.max_concurrent_environment_creations)
+    .with_connection_limits(
+        config.max_connection_pools,
+        Some(Duration::from_secs(config.connection_pool_idle_secs)),
+    )
+    .with_seed_store(SeedStore::open(PathBuf::from(&config.seed_data_dir))?);
+    if let Some(path) = &config.audit_log {
+        db = db.with_audit_log(AuditLog::open(
+            PathBuf::from(path),
+            config.audit_log_max_bytes,
+            config.audit_log_max_files,
+        )?);
+    }
+    if let Some(budget_ms) = config.execution_budget_ms {
+        db = db.with_execution_budget(ExecutionBudget::new(Duration::from_millis(budget_ms)));
+    }
+    if config.result_cache_size > 0 {
+        db = db.with_result_cache(ResultCache::new(
+            config.result_cache_size,
+            Duration::from_secs(config.result_cache_ttl_secs),
+            config.result_cache_max_rows,
+        ));
+    }
+    let cleanup_policy = CleanupPolicy {
+        ttl: config.environment_ttl_secs.map(Duration::from_secs),
+        max_environments: config.max_environments,
+    };
     let db = Arc::new(
-        DB::connect(
-            config.db_host.clone(),
-            config.db_username.clone(),
-            config.db_password.clone(),
-            config.password_hash_key,
-            config.max_rows_in_result_set,
-            config.statement_timeout,
-        )
-        .await?,
+        db.with_cleanup_policy(cleanup_policy)
+            .with_warm_pool_size(config.warm_pool_size)
+            .with_template_databases(config.template_databases),
     );
+    db.spawn_warm_pool();
+    if cleanup_policy.ttl.is_some() || cleanup_policy.max_environments.is_some() {
+        db.spawn_cleanup(Duration::from_secs(
+            config.environment_cleanup_interval_secs,
+        ));
+    }
+    let state = AppState {
+        db,
+        batch_concurrency: config.batch_concurrency.max(1),
+        audit_caller_header: config.audit_caller_header.clone(),
+        fragments: Arc::new(FragmentStore::open(PathBuf::from(&config.fragment_dir))?),
+        float_significant_digits: config.float_significant_digits,
+        task_id_header: config.task_id_header.clone(),
+        user_id_header: config.user_id_header.clone(),
+        allow_explain_analyze: config.allow_explain_analyze,
+        api_keys: match &config.api_keys {
+            Some(spec) => Some(Arc::new(ApiKeys::parse(spec).map_err(|err| anyhow!(err))?)),
+            None => None,
+        },
+        key_rate_limit: config
+            .api_key_requests_per_minute
+            .map(|limit| Arc::new(KeyRateLimit::new(limit))),
+    };
 
     let (router, api) = OpenApiRouter::with_openapi(ApiDoc::openapi())
         .routes(routes!(routes::run))
+        .routes(routes!(routes::batch_run))
+        .routes(routes!(routes::run_stream))
+        .routes(routes!(routes::copy_out))
+        .routes(routes!(routes::explain))
         .routes(routes!(routes::compare_result_set))
         .routes(routes!(routes::batch_compare_result_sets))
+        .routes(routes!(routes::batch_compare_submissions))
+        .routes(routes!(routes::compare_parts))
+        .routes(routes!(routes::validate_environment))
+        .routes(routes!(routes::prepare_environment))
+        .routes(routes!(routes::generate_sample_data))
+        .routes(routes!(routes::render))
+        .routes(routes!(routes::environment_status))
+        .routes(routes!(routes::put_fragment, routes::get_fragment))
+        .routes(routes!(routes::list_fragments))
+        .routes(routes!(routes::stats))
+        .routes(routes!(routes::query_fingerprints))
+        .routes(routes!(routes::version))
+        .routes(routes!(routes::ping))
+        .routes(routes!(routes::query_history))
+        .routes(routes!(routes::cleanup_environments))
+        .routes(routes!(routes::drop_environments))
+        .merge(
+            OpenApiRouter::new()
+                .routes(routes!(routes::upload_seed_data))
+                .layer(DefaultBodyLimit::max(config.max_seed_data_bytes)),
+        )
         .split_for_parts();
+    #[cfg(feature = "playground")]
+    let router = router.route("/playground", axum::routing::get(playground::playground));
 
     info!("Starting on port {}", config.port);
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.port)).await?;
@@ -95,7 +547,17 @@ async fn run() -> Result<(), anyhow::Error> {
         listener,
         router
             .merge(Redoc::with_url("/redoc", api))
-            .with_state(AppState { db }),
+            .merge(common::metrics::router(metrics))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                routes::request_context,
+            ))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                routes::authenticate,
+            ))
+            .layer(middleware::from_fn(common::metrics::track_requests))
+            .with_state(state),
     )
     .await?;
 
@@ -0,0 +1,6 @@
+use axum::response::Html;
+
+/// Single page to run and compare queries against an environment while authoring tasks.
+pub async fn playground() -> Html<&'static str> {
+    Html(include_str!("../playground/index.html"))
+}
@@ -1,24 +1,175 @@
-use crate::AppState;
-use crate::db::types::ResultSet;
-use crate::db::{ColumnNormalisation, RowNormalisation, SqlExecutionError};
+use crate::api_keys::ApiKey;
+use crate::datagen::{GeneratedData, generate_data};
+use crate::db::audit::{CALLER, METADATA};
+use crate::db::budget::{BUDGET_KEY, ExecutionBudget};
+use crate::db::cleanup::CleanupReport;
+use crate::db::column_names::ColumnNameCheck;
+use crate::db::comparison::{ComparisonSpec, FailureComparison, compare_result_sets};
+use crate::db::fingerprint::FingerprintCount;
+use crate::db::history::QueryHistoryEntry;
+use crate::db::plan::QueryPlan;
+use crate::db::progress::{EnvironmentState, EnvironmentStatus};
+use crate::db::result_diff::ResultSetDiff;
+use crate::db::row_diff::RowDiff;
+use crate::db::sandbox::WriteMode;
+use crate::db::types::{
+    DatabaseInfo, ResultSet, ResultSetExtension, ResultSetFingerprint, SqlValue,
+};
+use crate::db::{
+    ColumnNormalisation, ComparisonMode, RowEvent, RowNormalisation, SqlExecutionError,
+    environment_name,
+};
+use crate::dialect::{SqlDialect, translate, translate_comparison};
+use crate::fragments::{Environment, FragmentError};
+use crate::lint::{LintWarning, lint_environment};
+use crate::localise::{LANGUAGE, current_language, localise_error};
+use crate::{AppState, ENABLED_FEATURES};
 use axum::Json;
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::body::Body;
+use axum::extract::{Multipart, Path, Query, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use common::i18n::Language;
+use common::pagination::{Page, PageParams};
+use common::render::{RenderOptions, TableFormat, render_result_set};
+use common::stats::RunnerStats;
+use common::version::{PingResponse, RUNNER_API_VERSION, VersionInfo};
 use futures::future::join_all;
+use futures::{StreamExt, TryStreamExt, stream};
 use log::error;
 use serde::{Deserialize, Serialize};
-use tokio::sync::OnceCell;
+use serde_json::Value;
+use std::convert::Infallible;
+use std::time::Instant;
 use utoipa::ToSchema;
 
+/// Requires one of the configured API keys as bearer token, except for the liveness check
+/// and the API documentation. The key is available to later middleware as [`ApiKey`].
+pub async fn authenticate(state: State<AppState>, mut request: Request, next: Next) -> Response {
+    let Some(api_keys) = &state.api_keys else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path();
+    if path == "/ping" || path.starts_with("/redoc") {
+        return next.run(request).await;
+    }
+    let key = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| api_keys.authenticate(token));
+    let Some(key) = key else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    if state
+        .key_rate_limit
+        .as_ref()
+        .is_some_and(|limit| !limit.try_request(&key, Instant::now()))
+    {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+    request.extensions_mut().insert(key);
+    next.run(request).await
+}
+
+/// Makes the forwarded caller, task and user headers available to the audit log and the
+/// execution budget of the queries run by the request. Without caller header the name of the
+/// API key is the caller. Errors are localised to the `Accept-Language` header.
+pub async fn request_context(state: State<AppState>, request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let caller = header(&state.audit_caller_header).or_else(|| {
+        request
+            .extensions()
+            .get::<ApiKey>()
+            .map(|key| key.0.clone())
+    });
+    let budget_key = ExecutionBudget::key(
+        caller.as_deref(),
+        header(&state.task_id_header).as_deref(),
+        header(&state.user_id_header).as_deref(),
+    );
+    let language = Language::negotiate(None, header(header::ACCEPT_LANGUAGE.as_str()).as_deref());
+    LANGUAGE
+        .scope(
+            language,
+            BUDGET_KEY.scope(budget_key, CALLER.scope(caller, next.run(request))),
+        )
+        .await
+}
+
 #[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct RunRequest {
-    pub environment: String,
+    pub environment: Environment,
     pub query: String,
+    #[serde(default = "get_default_include_fingerprint")]
+    pub include_fingerprint: bool,
+    /// Return the tables, constraints, views, routines and triggers of the environment
+    #[serde(default = "get_default_include_database_info")]
+    pub include_database_info: bool,
+    /// Hide definitions and check clauses in the returned database info
+    #[serde(default = "get_default_redact_database_info")]
+    pub redact_database_info: bool,
+    /// Wait for a running initialisation of the environment instead of returning 202
+    #[serde(default = "get_default_wait_for_init")]
+    pub wait_for_init: bool,
+    /// Round floats in returned result sets to this many significant digits, overriding the
+    /// server default
+    pub float_significant_digits: Option<u32>,
+    /// Dialect the query is written in, it is translated to Postgres before execution
+    #[serde(default)]
+    pub dialect: SqlDialect,
+    /// Opaque object, e.g. course, assignment and attempt, which is ignored by the execution
+    /// but recorded in the audit log and echoed in the response
+    pub metadata: Option<Value>,
+    /// Execute the query with write access in a transaction which is rolled back, requires
+    /// database isolation. Database info is not returned in this mode
+    pub write_mode: Option<WriteMode>,
+    /// Language of the error, e.g. `de`, instead of the one of the `Accept-Language` header
+    pub language: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct RunResponse {
     pub result_set: ResultSet,
+    pub fingerprint: Option<ResultSetFingerprint>,
+    pub database_info: Option<DatabaseInfo>,
+    /// Constructs of the query which were translated to Postgres
+    pub translated: Vec<&'static str>,
+    /// Metadata of the request
+    pub metadata: Option<Value>,
+}
+
+impl RunResponse {
+    fn new(
+        result_set: ResultSet,
+        include_fingerprint: bool,
+        float_significant_digits: Option<u32>,
+    ) -> Self {
+        RunResponse {
+            fingerprint: include_fingerprint.then(|| result_set.fingerprint()),
+            result_set: round_floats(result_set, float_significant_digits),
+            database_info: None,
+            translated: vec![],
+            metadata: None,
+        }
+    }
+}
+
+/// Rounds floats for the response, fingerprints and comparisons use the exact values.
+fn round_floats(mut result_set: ResultSet, significant_digits: Option<u32>) -> ResultSet {
+    if let Some(significant_digits) = significant_digits {
+        result_set.round_floats(significant_digits);
+    }
+    result_set
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
@@ -34,33 +185,410 @@ pub async fn run(
     state: State<AppState>,
     body: Json<RunRequest>,
 ) -> Result<Json<RunResponse>, GenerateErrorResponse> {
-    let (rs, _) = state
-        .db
-        .execute(&body.environment, &body.query, false)
+    let environment = resolve_environment(&state, &body.environment).await?;
+    check_initialising(&state, &environment, body.wait_for_init)?;
+    let translation = translate(&body.query, body.dialect);
+    let execution = async {
+        match &body.write_mode {
+            Some(write_mode) => state
+                .db
+                .execute_sandboxed(&environment, &translation.query, write_mode)
+                .await
+                .map(|rs| (rs, None)),
+            None => {
+                state
+                    .db
+                    .execute(&environment, &translation.query, body.include_database_info)
+                    .await
+            }
+        }
+    };
+    let (rs, mut database_info) = METADATA
+        .scope(body.metadata.clone(), execution)
         .await
         .map_err(|err| {
             error!("Error while handling run request: {err}");
+            localised_err_to_response(err, body.language.as_deref())
+        })?;
+    if body.redact_database_info {
+        database_info.iter_mut().for_each(DatabaseInfo::redact);
+    }
+    Ok(Json(RunResponse {
+        database_info,
+        translated: translation.translated,
+        metadata: body.metadata.clone(),
+        ..RunResponse::new(
+            rs,
+            body.include_fingerprint,
+            body.float_significant_digits
+                .or(state.float_significant_digits),
+        )
+    }))
+}
+
+/// Answers 202 while the environment is initialising, unless the request waits for it.
+fn check_initialising(
+    state: &AppState,
+    environment: &str,
+    wait_for_init: bool,
+) -> Result<(), GenerateErrorResponse> {
+    let initialising = state.db.init_progress(environment).filter(|status| {
+        matches!(
+            status.state,
+            EnvironmentState::Queued | EnvironmentState::Initialising
+        )
+    });
+    if let (false, Some(status)) = (wait_for_init, initialising) {
+        return Err((
+            StatusCode::ACCEPTED,
+            Json(RunError {
+                location: "initialising",
+                error: match status.state {
+                    EnvironmentState::Queued => {
+                        "environment is being prepared, waiting for a free slot".to_string()
+                    }
+                    _ => format!(
+                        "environment is initialising, executed {} of {} statements",
+                        status.executed.unwrap_or_default(),
+                        status.total.unwrap_or_default()
+                    ),
+                },
+            }),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BatchRunRequest {
+    pub environment: Environment,
+    pub queries: Vec<String>,
+    /// Wait for a running initialisation of the environment instead of returning 202
+    #[serde(default = "get_default_wait_for_init")]
+    pub wait_for_init: bool,
+    /// Round floats in returned result sets to this many significant digits, overriding the
+    /// server default
+    pub float_significant_digits: Option<u32>,
+    /// Dialect the queries are written in, they are translated to Postgres before execution
+    #[serde(default)]
+    pub dialect: SqlDialect,
+    /// Opaque object which is recorded in the audit log for every query
+    pub metadata: Option<Value>,
+    /// Language of the errors, e.g. `de`, instead of the one of the `Accept-Language` header
+    pub language: Option<String>,
+}
+
+/// Result of one query of a batch, shaped like the response of `/run`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum BatchRunResult {
+    Success { result_set: ResultSet },
+    Error(RunError),
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BatchRunResponse {
+    /// One result per query, in the order of the queries
+    pub results: Vec<BatchRunResult>,
+}
+
+#[utoipa::path(post, path = "/api/v1/batch_run", request_body = BatchRunRequest, responses((status = OK, body = BatchRunResponse), (status = UNPROCESSABLE_ENTITY), (status = INTERNAL_SERVER_ERROR)), description = "Execute many queries in one environment")]
+pub async fn batch_run(
+    state: State<AppState>,
+    body: Json<BatchRunRequest>,
+) -> Result<Json<BatchRunResponse>, GenerateErrorResponse> {
+    let environment = resolve_environment(&state, &body.environment).await?;
+    check_initialising(&state, &environment, body.wait_for_init)?;
+    let float_significant_digits = body
+        .float_significant_digits
+        .or(state.float_significant_digits);
+    let (state, body, environment) = (&state, &body, &environment);
+    let results = stream::iter(body.queries.clone())
+        .map(|query| async move {
+            let translation = translate(&query, body.dialect);
+            let execution = state.db.execute(environment, &translation.query, false);
+            match METADATA.scope(body.metadata.clone(), execution).await {
+                Ok((result_set, _)) => Ok(BatchRunResult::Success {
+                    result_set: round_floats(result_set, float_significant_digits),
+                }),
+                Err(err) => match localised_err_to_response(err, body.language.as_deref()) {
+                    (StatusCode::OK, Json(error)) => Ok(BatchRunResult::Error(error)),
+                    response => {
+                        error!(
+                            "Error while handling batch_run request: {}",
+                            response.1.error
+                        );
+                        Err(response)
+                    }
+                },
+            }
+        })
+        .buffered(state.batch_concurrency)
+        .try_collect()
+        .await?;
+    Ok(Json(BatchRunResponse { results }))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct RunStreamRequest {
+    pub environment: Environment,
+    pub query: String,
+    /// Wait for a running initialisation of the environment instead of returning 202
+    #[serde(default = "get_default_wait_for_init")]
+    pub wait_for_init: bool,
+    /// Round floats in returned rows to this many significant digits, overriding the server
+    /// default
+    pub float_significant_digits: Option<u32>,
+    /// Dialect the query is written in, it is translated to Postgres before execution
+    #[serde(default)]
+    pub dialect: SqlDialect,
+}
+
+/// Line of a streamed run, a stream without `end` or `error` line was cut off. As server-sent
+/// events, the variant is the event name and its content the data.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStreamLine {
+    /// Column names, before the first row
+    Columns(Vec<String>),
+    Row(Vec<SqlValue>),
+    /// Number of rows, after the last one
+    End {
+        rows: usize,
+    },
+    /// Error of the query after the first row, earlier ones are answered like `/run`
+    Error(RunError),
+}
+
+impl RunStreamLine {
+    fn to_event(&self) -> Event {
+        let event = Event::default();
+        match self {
+            RunStreamLine::Columns(columns) => event.event("columns").json_data(columns),
+            RunStreamLine::Row(row) => event.event("row").json_data(row),
+            RunStreamLine::End { rows } => event.event("end").json_data(rows),
+            RunStreamLine::Error(error) => event.event("error").json_data(error),
+        }
+        .expect("events are serialisable")
+    }
+}
+
+#[utoipa::path(post, path = "/api/v1/run/stream", request_body = RunStreamRequest, responses((status = OK, content((RunStreamLine = "application/x-ndjson"), (RunStreamLine = "text/event-stream")), description = "One JSON line or server-sent event per column header, row and the end of the result"), (status = UNPROCESSABLE_ENTITY, body = RunError), (status = INTERNAL_SERVER_ERROR)), description = "Execute query in environment and stream the rows as newline delimited JSON while they arrive, or as server-sent events if accepted")]
+pub async fn run_stream(
+    state: State<AppState>,
+    headers: HeaderMap,
+    body: Json<RunStreamRequest>,
+) -> Result<Response, GenerateErrorResponse> {
+    let environment = resolve_environment(&state, &body.environment).await?;
+    check_initialising(&state, &environment, body.wait_for_init)?;
+    let translation = translate(&body.query, body.dialect);
+    let on_error = |err| {
+        error!("Error while handling run_stream request: {err}");
+        err_to_response(err)
+    };
+    let mut events = state
+        .db
+        .execute_stream(&environment, &translation.query)
+        .await
+        .map_err(on_error)?;
+    // waiting for the first event answers errors like syntax errors with a status code
+    let first = events.next().await.transpose().map_err(on_error)?;
+    let significant_digits = body
+        .float_significant_digits
+        .or(state.float_significant_digits);
+    let lines = stream::iter(first.map(Ok))
+        .chain(events)
+        .map(move |event| match event {
+            Ok(RowEvent::Columns(columns)) => RunStreamLine::Columns(columns),
+            Ok(RowEvent::Row(row)) => RunStreamLine::Row(round_row(row, significant_digits)),
+            Ok(RowEvent::End { rows }) => RunStreamLine::End { rows },
+            Err(err) => RunStreamLine::Error(on_error(err).1.0),
+        });
+    let accepts_events = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"));
+    if accepts_events {
+        let events = lines.map(|line| Ok::<_, Infallible>(line.to_event()));
+        return Ok(Sse::new(events).into_response());
+    }
+    let lines = lines.map(|line| {
+        let mut line = serde_json::to_vec(&line).expect("lines are serialisable");
+        line.push(b'\n');
+        Ok::<_, Infallible>(line)
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+fn round_row(row: Vec<SqlValue>, significant_digits: Option<u32>) -> Vec<SqlValue> {
+    let result_set = ResultSet {
+        columns: vec![],
+        rows: vec![row],
+    };
+    let mut rows = round_floats(result_set, significant_digits).rows;
+    rows.pop().unwrap_or_default()
+}
+
+async fn resolve_environment(
+    state: &AppState,
+    environment: &Environment,
+) -> Result<String, GenerateErrorResponse> {
+    state
+        .fragments
+        .resolve(environment)
+        .await
+        .map_err(fragment_err_to_response)
+}
+
+fn fragment_err_to_response(err: FragmentError) -> GenerateErrorResponse {
+    match err {
+        FragmentError::Io(e) => {
+            error!("internal error: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(RunError {
+                    location: "other",
+                    error: "an internal error occurred".to_string(),
+                }),
+            )
+        }
+        e => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(RunError {
+                location: "environment",
+                error: e.to_string(),
+            }),
+        ),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ExplainRequest {
+    pub environment: Environment,
+    pub query: String,
+    /// Execute the query to report actual times and row counts, only if enabled by the
+    /// server
+    #[serde(default)]
+    pub analyze: bool,
+    /// Dialect of the query, it is translated to Postgres before it is explained
+    #[serde(default)]
+    pub dialect: SqlDialect,
+}
+
+#[utoipa::path(post, path = "/api/v1/explain", request_body = ExplainRequest, responses((status = OK, body = QueryPlan), (status = UNPROCESSABLE_ENTITY, body = RunError), (status = INTERNAL_SERVER_ERROR)), description = "Get the plan tree of a query and, if analyzed, its execution statistics")]
+pub async fn explain(
+    state: State<AppState>,
+    body: Json<ExplainRequest>,
+) -> Result<Json<QueryPlan>, GenerateErrorResponse> {
+    if body.analyze && !state.allow_explain_analyze {
+        return Err(err_to_response(SqlExecutionError::Denied(
+            "explain_analyze".to_string(),
+        )));
+    }
+    let environment = resolve_environment(&state, &body.environment).await?;
+    let translation = translate(&body.query, body.dialect);
+    state
+        .db
+        .explain(&environment, &translation.query, body.analyze)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            error!("Error while handling explain request: {err}");
             err_to_response(err)
+        })
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CopyRequest {
+    pub environment: Environment,
+    pub query: String,
+}
+
+#[utoipa::path(post, path = "/api/v1/run/copy", request_body = CopyRequest, responses((status = OK, content_type = "application/octet-stream", description = "Result in the Postgres binary COPY format"), (status = UNPROCESSABLE_ENTITY, body = RunError), (status = INTERNAL_SERVER_ERROR)), description = "Stream the full result of a query in the Postgres binary COPY format")]
+pub async fn copy_out(
+    state: State<AppState>,
+    body: Json<CopyRequest>,
+) -> Result<Response, GenerateErrorResponse> {
+    let environment = resolve_environment(&state, &body.environment).await?;
+    let stream = state
+        .db
+        .copy_out(&environment, &body.query)
+        .await
+        .map_err(|err| {
+            error!("Error while handling copy_out request: {err}");
+            match err_to_response(err) {
+                (StatusCode::OK, error) => (StatusCode::UNPROCESSABLE_ENTITY, error),
+                response => response,
+            }
         })?;
-    Ok(Json(RunResponse { result_set: rs }))
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Error response in the requested language if supported, otherwise in the one of the
+/// `Accept-Language` header.
+fn localised_err_to_response(
+    err: SqlExecutionError,
+    language: Option<&str>,
+) -> GenerateErrorResponse {
+    match language.and_then(Language::from_tag) {
+        Some(language) => LANGUAGE.sync_scope(language, || err_to_response(err)),
+        None => err_to_response(err),
+    }
 }
 
 fn err_to_response(err: SqlExecutionError) -> GenerateErrorResponse {
+    let language = current_language();
     match err {
-        SqlExecutionError::Init(e) => (
+        e @ (SqlExecutionError::Init(_) | SqlExecutionError::SeedData(_)) => (
             StatusCode::OK,
             Json(RunError {
                 location: "init",
-                error: e.to_string(),
+                error: localise_error(&e, language),
             }),
         ),
-        SqlExecutionError::Execute(e) => (
+        e @ SqlExecutionError::Execute(_) => (
             StatusCode::OK,
             Json(RunError {
                 location: "query",
+                error: localise_error(&e, language),
+            }),
+        ),
+        e @ SqlExecutionError::Timeout(_) => (
+            StatusCode::OK,
+            Json(RunError {
+                location: "timeout",
+                error: localise_error(&e, language),
+            }),
+        ),
+        e @ SqlExecutionError::BudgetExceeded => (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(RunError {
+                location: "budget",
+                error: localise_error(&e, language),
+            }),
+        ),
+        e @ SqlExecutionError::Unsupported(_) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(RunError {
+                location: "request",
                 error: e.to_string(),
             }),
         ),
+        e @ SqlExecutionError::Denied(_) => (
+            StatusCode::OK,
+            Json(RunError {
+                location: "denied",
+                error: localise_error(&e, language),
+            }),
+        ),
         e => {
             error!("internal error: {e}");
             (
@@ -76,13 +604,30 @@ fn err_to_response(err: SqlExecutionError) -> GenerateErrorResponse {
 
 #[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct CompareRequest {
-    pub environment: String,
+    pub environment: Environment,
     pub solution: String,
     pub submission: String,
     #[serde(default = "get_default_row_normalisation")]
     row_normalisation: RowNormalisation,
     #[serde(default = "get_default_column_normalisation")]
     column_normalisation: ColumnNormalisation,
+    #[serde(default = "get_default_comparison_mode")]
+    comparison_mode: ComparisonMode,
+    /// Replaces `row_normalisation`, `column_normalisation` and `comparison_mode` if given
+    comparison: Option<ComparisonSpec>,
+    #[serde(default = "get_default_include_fingerprint")]
+    include_fingerprint: bool,
+    /// Round floats in returned result sets to this many significant digits, overriding the
+    /// server default
+    float_significant_digits: Option<u32>,
+    /// Dialect of the submission, it is translated to Postgres before execution
+    #[serde(default)]
+    dialect: SqlDialect,
+    /// Dialect of the solution, the one of the submission if not given
+    solution_dialect: Option<SqlDialect>,
+    /// Execute solution and submission with write access in transactions which are rolled
+    /// back, comparing the results of the check query if one is given
+    write_mode: Option<WriteMode>,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
@@ -90,6 +635,15 @@ pub struct CompareResponse {
     pub solution: RunResponse,
     pub submission: RunResponse,
     pub equal: bool,
+    /// Per row differences, if the comparison matches rows by key columns
+    pub row_diff: Option<RowDiff>,
+    /// Whether the submission has the required column names, independent of `equal`
+    pub column_names: Option<ColumnNameCheck>,
+    /// Differences of the submission to the solution after normalisation
+    pub diff: ResultSetDiff,
+    /// Errors of the solution and the submission if the solution failed, requires a
+    /// `match_failure` comparison step
+    pub failure: Option<FailureComparison>,
 }
 
 #[utoipa::path(post, path = "/api/v1/compare", request_body = CompareRequest, responses((status = OK, body = CompareResponse), (status = UNPROCESSABLE_ENTITY), (status = INTERNAL_SERVER_ERROR)), description = "Compare sql result sets")]
@@ -97,14 +651,29 @@ pub async fn compare_result_set(
     state: State<AppState>,
     body: Json<CompareRequest>,
 ) -> Result<Json<CompareResponse>, GenerateErrorResponse> {
-    let (a, b, eq) = state
+    let float_significant_digits = body
+        .float_significant_digits
+        .or(state.float_significant_digits);
+    let environment = resolve_environment(&state, &body.environment).await?;
+    let (solution, submission) = translate_comparison(
+        &body.solution,
+        &body.submission,
+        body.dialect,
+        body.solution_dialect,
+    );
+    let comparison = state
         .db
         .compare(
-            &body.environment,
-            &body.solution,
-            &body.submission,
-            body.row_normalisation,
-            body.column_normalisation,
+            &environment,
+            &solution.query,
+            &submission.query,
+            &comparison_spec(
+                &body.comparison,
+                body.row_normalisation,
+                body.column_normalisation,
+                body.comparison_mode,
+            ),
+            body.write_mode.as_ref(),
         )
         .await
         .map_err(|err| {
@@ -112,12 +681,40 @@ pub async fn compare_result_set(
             err_to_response(err)
         })?;
     Ok(Json(CompareResponse {
-        solution: RunResponse { result_set: a },
-        submission: RunResponse { result_set: b },
-        equal: eq,
+        solution: RunResponse {
+            translated: solution.translated,
+            ..RunResponse::new(
+                comparison.result_a,
+                body.include_fingerprint,
+                float_significant_digits,
+            )
+        },
+        submission: RunResponse {
+            translated: submission.translated,
+            ..RunResponse::new(
+                comparison.result_b,
+                body.include_fingerprint,
+                float_significant_digits,
+            )
+        },
+        equal: comparison.eq,
+        row_diff: comparison.row_diff,
+        column_names: comparison.column_names,
+        diff: comparison.diff,
+        failure: comparison.failure,
     }))
 }
 
+fn comparison_spec(
+    spec: &Option<ComparisonSpec>,
+    row_norm: RowNormalisation,
+    col_norm: ColumnNormalisation,
+    comparison: ComparisonMode,
+) -> ComparisonSpec {
+    spec.clone()
+        .unwrap_or_else(|| ComparisonSpec::from_legacy(row_norm, col_norm, comparison))
+}
+
 fn get_default_row_normalisation() -> RowNormalisation {
     RowNormalisation::NoNormalization
 }
@@ -126,10 +723,34 @@ fn get_default_column_normalisation() -> ColumnNormalisation {
     ColumnNormalisation::NumberColumnsByOrder
 }
 
+fn get_default_comparison_mode() -> ComparisonMode {
+    ComparisonMode::ResultSet
+}
+
 fn get_default_return_result_set() -> bool {
     false
 }
 
+fn get_default_include_fingerprint() -> bool {
+    false
+}
+
+fn get_default_hidden() -> bool {
+    false
+}
+
+fn get_default_wait_for_init() -> bool {
+    true
+}
+
+fn get_default_include_database_info() -> bool {
+    false
+}
+
+fn get_default_redact_database_info() -> bool {
+    false
+}
+
 #[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct Solution {
     query: String,
@@ -137,27 +758,73 @@ pub struct Solution {
     row_normalisation: RowNormalisation,
     #[serde(default = "get_default_column_normalisation")]
     column_normalisation: ColumnNormalisation,
+    #[serde(default = "get_default_comparison_mode")]
+    comparison_mode: ComparisonMode,
+    /// Replaces `row_normalisation`, `column_normalisation` and `comparison_mode` if given
+    comparison: Option<ComparisonSpec>,
     #[serde(default = "get_default_return_result_set")]
     return_result_set: bool,
+    /// Only return whether the submission matches, never the result set or fingerprint
+    #[serde(default = "get_default_hidden")]
+    hidden: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct BatchCompareRequest {
-    pub environment: String,
+    pub environment: Environment,
     pub solutions: Vec<Solution>,
     pub submission: String,
+    #[serde(default = "get_default_include_fingerprint")]
+    pub include_fingerprint: bool,
+    /// Round floats in returned result sets to this many significant digits, overriding the
+    /// server default
+    pub float_significant_digits: Option<u32>,
+    /// Compare the solutions one after another and skip the rest once one matches
+    #[serde(default)]
+    pub stop_on_first_match: bool,
+    /// Compare the solutions one after another and skip the rest once one fails, e.g.
+    /// because the submission does not execute
+    #[serde(default)]
+    pub stop_on_first_error: bool,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct SolutionResponse {
     pub eq: bool,
     pub result_set: Option<ResultSet>,
+    pub fingerprint: Option<ResultSetFingerprint>,
+    /// Per row differences to the solution, never given for hidden solutions
+    pub row_diff: Option<RowDiff>,
+    /// Index of the error in `errors` if the comparison failed
+    pub error: Option<usize>,
+    /// Whether the submission has the required column names, independent of `eq`
+    pub column_names: Option<ColumnNameCheck>,
+    /// Differences to the solution after normalisation, never given for hidden solutions
+    pub diff: Option<ResultSetDiff>,
+    /// Errors of the solution and the submission if the solution failed, requires a
+    /// `match_failure` comparison step
+    pub failure: Option<FailureComparison>,
+    /// Not compared because an earlier solution matched or failed
+    pub skipped: bool,
+}
+
+/// Error reported once for all solutions whose comparison failed with it, e.g. because the
+/// submission has a syntax error.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BatchError {
+    pub location: &'static str,
+    pub error: String,
+    /// Indices of the affected solutions
+    pub solutions: Vec<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct BatchCompareResponse {
     pub solutions: Vec<SolutionResponse>,
     pub submission_result_set: Option<ResultSet>,
+    pub submission_fingerprint: Option<ResultSetFingerprint>,
+    /// Distinct errors of the failed comparisons
+    pub errors: Vec<BatchError>,
 }
 
 #[utoipa::path(post, path = "/api/v1/batch_compare", request_body = BatchCompareRequest, responses((status = OK, body = BatchCompareResponse), (status = UNPROCESSABLE_ENTITY), (status = INTERNAL_SERVER_ERROR)), description = "Batch compare SQL resulsets")]
@@ -165,45 +832,754 @@ pub async fn batch_compare_result_sets(
     state: State<AppState>,
     body: Json<BatchCompareRequest>,
 ) -> Result<Json<BatchCompareResponse>, GenerateErrorResponse> {
-    let mut submission_result_set: OnceCell<ResultSet> = OnceCell::new();
-    let solutions = join_all(body.solutions.iter().map(
-        |Solution {
-             query,
-             row_normalisation,
-             column_normalisation,
-             return_result_set,
-         }| async {
-            state
+    let float_significant_digits = body
+        .float_significant_digits
+        .or(state.float_significant_digits);
+    let environment = resolve_environment(&state, &body.environment).await?;
+    let (state, body, environment) = (&state, &body, &environment);
+    let compare = |index: usize| async move {
+        let solution = &body.solutions[index];
+        state
+            .db
+            .compare(
+                environment,
+                &solution.query,
+                &body.submission,
+                &comparison_spec(
+                    &solution.comparison,
+                    solution.row_normalisation,
+                    solution.column_normalisation,
+                    solution.comparison_mode,
+                ),
+                None,
+            )
+            .await
+            .map_err(|err| {
+                error!("Error while handling compare_result_set request: {err}");
+                err_to_response(err)
+            })
+    };
+    let comparisons = if body.stop_on_first_match || body.stop_on_first_error {
+        let mut comparisons = Vec::with_capacity(body.solutions.len());
+        for index in 0..body.solutions.len() {
+            let comparison = compare(index).await;
+            let stop = match &comparison {
+                Ok(comparison) => body.stop_on_first_match && comparison.eq,
+                Err(_) => body.stop_on_first_error,
+            };
+            comparisons.push(comparison);
+            if stop {
+                break;
+            }
+        }
+        comparisons
+    } else {
+        join_all((0..body.solutions.len()).map(compare)).await
+    };
+
+    let mut submission_result_set = None;
+    let mut errors: Vec<BatchError> = Vec::new();
+    let mut solutions = Vec::with_capacity(comparisons.len());
+    for (index, (comparison, solution)) in comparisons.into_iter().zip(&body.solutions).enumerate()
+    {
+        let comparison = match comparison {
+            Ok(comparison) => comparison,
+            Err((StatusCode::OK, Json(error))) => {
+                let position = errors
+                    .iter()
+                    .position(|known| {
+                        known.location == error.location && known.error == error.error
+                    })
+                    .unwrap_or_else(|| {
+                        errors.push(BatchError {
+                            location: error.location,
+                            error: error.error,
+                            solutions: vec![],
+                        });
+                        errors.len() - 1
+                    });
+                errors[position].solutions.push(index);
+                solutions.push(SolutionResponse {
+                    eq: false,
+                    result_set: None,
+                    fingerprint: None,
+                    row_diff: None,
+                    error: Some(position),
+                    column_names: None,
+                    diff: None,
+                    failure: None,
+                    skipped: false,
+                });
+                continue;
+            }
+            Err(response) => return Err(response),
+        };
+        let hidden = solution.hidden;
+        solutions.push(SolutionResponse {
+            fingerprint: (body.include_fingerprint && !hidden)
+                .then(|| comparison.result_a.fingerprint()),
+            result_set: if solution.return_result_set && !hidden {
+                Some(round_floats(comparison.result_a, float_significant_digits))
+            } else {
+                None
+            },
+            eq: comparison.eq,
+            row_diff: comparison.row_diff.filter(|_| !hidden),
+            error: None,
+            column_names: comparison.column_names,
+            diff: (!hidden).then_some(comparison.diff),
+            failure: comparison.failure,
+            skipped: false,
+        });
+        submission_result_set.get_or_insert(comparison.result_b);
+    }
+    solutions.resize_with(body.solutions.len(), || SolutionResponse {
+        eq: false,
+        result_set: None,
+        fingerprint: None,
+        row_diff: None,
+        error: None,
+        column_names: None,
+        diff: None,
+        failure: None,
+        skipped: true,
+    });
+
+    Ok(Json(BatchCompareResponse {
+        solutions,
+        submission_fingerprint: submission_result_set
+            .as_ref()
+            .filter(|_| body.include_fingerprint)
+            .map(|rs| rs.fingerprint()),
+        submission_result_set: submission_result_set
+            .map(|rs| round_floats(rs, float_significant_digits)),
+        errors,
+    }))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BatchCompareSubmissionsRequest {
+    pub environment: Environment,
+    pub solution: String,
+    pub submissions: Vec<String>,
+    #[serde(default = "get_default_row_normalisation")]
+    row_normalisation: RowNormalisation,
+    #[serde(default = "get_default_column_normalisation")]
+    column_normalisation: ColumnNormalisation,
+    #[serde(default = "get_default_comparison_mode")]
+    comparison_mode: ComparisonMode,
+    /// Replaces `row_normalisation`, `column_normalisation` and `comparison_mode` if given
+    comparison: Option<ComparisonSpec>,
+    #[serde(default = "get_default_return_result_set")]
+    return_result_set: bool,
+    /// Round floats in returned result sets to this many significant digits, overriding the
+    /// server default
+    float_significant_digits: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SubmissionResponse {
+    pub eq: bool,
+    pub result_set: Option<ResultSet>,
+    pub error: Option<RunError>,
+    /// Per row differences to the solution, if the comparison matches rows by key columns
+    pub row_diff: Option<RowDiff>,
+    /// Whether the submission has the required column names, independent of `eq`
+    pub column_names: Option<ColumnNameCheck>,
+    /// Differences to the solution after normalisation, if the submission executed
+    pub diff: Option<ResultSetDiff>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BatchCompareSubmissionsResponse {
+    pub submissions: Vec<SubmissionResponse>,
+    pub solution_result_set: Option<ResultSet>,
+}
+
+#[utoipa::path(post, path = "/api/v1/batch_compare_submissions", request_body = BatchCompareSubmissionsRequest, responses((status = OK, body = BatchCompareSubmissionsResponse), (status = UNPROCESSABLE_ENTITY), (status = INTERNAL_SERVER_ERROR)), description = "Compare many submissions against one solution")]
+pub async fn batch_compare_submissions(
+    state: State<AppState>,
+    body: Json<BatchCompareSubmissionsRequest>,
+) -> Result<Json<BatchCompareSubmissionsResponse>, GenerateErrorResponse> {
+    let float_significant_digits = body
+        .float_significant_digits
+        .or(state.float_significant_digits);
+    let environment = resolve_environment(&state, &body.environment).await?;
+    let solution_result_set = state
+        .db
+        .execute_cached(&environment, &body.solution)
+        .await
+        .map_err(|err| {
+            error!("Error while handling batch_compare_submissions request: {err}");
+            err_to_response(err)
+        })?;
+
+    let spec = comparison_spec(
+        &body.comparison,
+        body.row_normalisation,
+        body.column_normalisation,
+        body.comparison_mode,
+    );
+    let (state, body, environment, solution_result_set, spec) =
+        (&state, &body, &environment, &solution_result_set, &spec);
+    let submissions = stream::iter(body.submissions.clone())
+        .map(|submission| async move {
+            match state.db.execute(environment, &submission, false).await {
+                Ok((result_set, _)) => {
+                    let comparison =
+                        compare_result_sets(solution_result_set.clone(), result_set, spec);
+                    Ok(SubmissionResponse {
+                        eq: comparison.eq,
+                        result_set: body
+                            .return_result_set
+                            .then(|| round_floats(comparison.result_b, float_significant_digits)),
+                        error: None,
+                        row_diff: comparison.row_diff,
+                        column_names: comparison.column_names,
+                        diff: Some(comparison.diff),
+                    })
+                }
+                Err(err) => match err_to_response(err) {
+                    (StatusCode::OK, Json(error)) => Ok(SubmissionResponse {
+                        eq: false,
+                        result_set: None,
+                        error: Some(error),
+                        row_diff: None,
+                        column_names: None,
+                        diff: None,
+                    }),
+                    response => Err(response),
+                },
+            }
+        })
+        .buffered(state.batch_concurrency)
+        .try_collect::<Vec<SubmissionResponse>>()
+        .await?;
+
+    Ok(Json(BatchCompareSubmissionsResponse {
+        submissions,
+        solution_result_set: body
+            .return_result_set
+            .then(|| round_floats(solution_result_set.clone(), float_significant_digits)),
+    }))
+}
+
+/// One part of a multi-part task, e.g. part b, whose submission is compared with its own
+/// solution.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct TaskPart {
+    /// Label of the part, unique within the request
+    pub name: String,
+    pub solution: String,
+    /// `null` if the part was not answered
+    pub submission: Option<String>,
+    /// Replaces the comparison of the request for this part
+    comparison: Option<ComparisonSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ComparePartsRequest {
+    pub environment: Environment,
+    pub parts: Vec<TaskPart>,
+    /// Comparison of the parts without their own, the default comparison if not given
+    comparison: Option<ComparisonSpec>,
+    #[serde(default = "get_default_return_result_set")]
+    return_result_set: bool,
+    /// Round floats in returned result sets to this many significant digits, overriding the
+    /// server default
+    float_significant_digits: Option<u32>,
+    /// Dialect of the submissions, they are translated to Postgres before execution
+    #[serde(default)]
+    dialect: SqlDialect,
+    /// Dialect of the solutions, the one of the submissions if not given
+    solution_dialect: Option<SqlDialect>,
+    /// Execute every part with write access in a transaction which is rolled back
+    write_mode: Option<WriteMode>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PartResponse {
+    pub name: String,
+    pub eq: bool,
+    /// The part was not answered and is not compared
+    pub missing: bool,
+    /// Result set of the submission
+    pub result_set: Option<ResultSet>,
+    pub error: Option<RunError>,
+    /// Per row differences to the solution, if the comparison matches rows by key columns
+    pub row_diff: Option<RowDiff>,
+    /// Whether the submission has the required column names, independent of `eq`
+    pub column_names: Option<ColumnNameCheck>,
+    /// Differences to the solution after normalisation, if both executed
+    pub diff: Option<ResultSetDiff>,
+    /// Errors of the solution and the submission if the solution failed, requires a
+    /// `match_failure` comparison step
+    pub failure: Option<FailureComparison>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ComparePartsResponse {
+    /// Comparison of every part in the order of the request
+    pub parts: Vec<PartResponse>,
+    /// Whether every part matches its solution
+    pub eq: bool,
+}
+
+#[utoipa::path(post, path = "/api/v1/compare_parts", request_body = ComparePartsRequest, responses((status = OK, body = ComparePartsResponse), (status = UNPROCESSABLE_ENTITY), (status = INTERNAL_SERVER_ERROR)), description = "Compare the named parts of a multi-part submission with their solutions")]
+pub async fn compare_parts(
+    state: State<AppState>,
+    body: Json<ComparePartsRequest>,
+) -> Result<Json<ComparePartsResponse>, GenerateErrorResponse> {
+    for (index, part) in body.parts.iter().enumerate() {
+        if body.parts[..index]
+            .iter()
+            .any(|earlier| earlier.name == part.name)
+        {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(RunError {
+                    location: "request",
+                    error: format!("part `{}` is given more than once", part.name),
+                }),
+            ));
+        }
+    }
+    let float_significant_digits = body
+        .float_significant_digits
+        .or(state.float_significant_digits);
+    let environment = resolve_environment(&state, &body.environment).await?;
+    let (state, body, environment) = (&state, &body, &environment);
+    let parts = stream::iter(0..body.parts.len())
+        .map(|index| async move {
+            let part = &body.parts[index];
+            let missing = PartResponse {
+                name: part.name.clone(),
+                eq: false,
+                missing: true,
+                result_set: None,
+                error: None,
+                row_diff: None,
+                column_names: None,
+                diff: None,
+                failure: None,
+            };
+            let Some(submission) = &part.submission else {
+                return Ok(missing);
+            };
+            let (solution, submission) = translate_comparison(
+                &part.solution,
+                submission,
+                body.dialect,
+                body.solution_dialect,
+            );
+            let spec = part
+                .comparison
+                .clone()
+                .or_else(|| body.comparison.clone())
+                .unwrap_or_else(|| {
+                    ComparisonSpec::from_legacy(
+                        get_default_row_normalisation(),
+                        get_default_column_normalisation(),
+                        get_default_comparison_mode(),
+                    )
+                });
+            match state
                 .db
                 .compare(
-                    &body.environment,
-                    query,
-                    &body.submission,
-                    *row_normalisation,
-                    *column_normalisation,
+                    environment,
+                    &solution.query,
+                    &submission.query,
+                    &spec,
+                    body.write_mode.as_ref(),
                 )
                 .await
-                .map_err(|err| {
-                    error!("Error while handling compare_result_set request: {err}");
-                    err_to_response(err)
-                })
-                .inspect(|(a, _, _)| {
-                    if !submission_result_set.initialized() {
-                        let _ = submission_result_set.set(a.clone());
-                    }
-                })
-                .map(|(_, b, eq)| SolutionResponse {
-                    result_set: if *return_result_set { Some(b) } else { None },
-                    eq,
-                })
-        },
-    ))
-    .await
-    .into_iter()
-    .collect::<Result<Vec<SolutionResponse>, GenerateErrorResponse>>()?;
+            {
+                Ok(comparison) => Ok(PartResponse {
+                    eq: comparison.eq,
+                    missing: false,
+                    result_set: body
+                        .return_result_set
+                        .then(|| round_floats(comparison.result_b, float_significant_digits)),
+                    row_diff: comparison.row_diff,
+                    column_names: comparison.column_names,
+                    diff: Some(comparison.diff),
+                    failure: comparison.failure,
+                    ..missing
+                }),
+                Err(err) => match err_to_response(err) {
+                    (StatusCode::OK, Json(error)) => Ok(PartResponse {
+                        missing: false,
+                        error: Some(error),
+                        ..missing
+                    }),
+                    response => Err(response),
+                },
+            }
+        })
+        .buffered(state.batch_concurrency)
+        .try_collect::<Vec<PartResponse>>()
+        .await?;
 
-    Ok(Json(BatchCompareResponse {
-        solutions,
-        submission_result_set: submission_result_set.take(),
+    Ok(Json(ComparePartsResponse {
+        eq: parts.iter().all(|part| part.eq),
+        parts,
+    }))
+}
+
+#[utoipa::path(get, path = "/api/v1/stats", responses((status = OK, body = RunnerStats)), description = "Get execution statistics")]
+pub async fn stats(state: State<AppState>) -> Json<RunnerStats> {
+    Json(RunnerStats {
+        timeouts: state.db.timeout_count(),
+        queued_environments: state.db.init_count(EnvironmentState::Queued),
+        initialising_environments: state.db.init_count(EnvironmentState::Initialising),
+        recent: state.db.recent_errors(),
+        connection_pools: state.db.connection_stats().await,
+    })
+}
+
+#[utoipa::path(get, path = "/api/v1/admin/history/{database}", params(("database" = String, Path, description = "Name of the environment database"), PageParams), responses((status = OK, body = Page<QueryHistoryEntry>)), description = "Get the most recent queries executed in an environment, sortable by `executed_at` or `duration_ms` and filtered by status")]
+pub async fn query_history(
+    state: State<AppState>,
+    Path(database): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Json<Page<QueryHistoryEntry>> {
+    let mut entries = state.db.query_history(&database).await;
+    entries.retain(|entry| params.matches(entry.status.as_str()));
+    if let Some(sort) = params.sort() {
+        match sort.field {
+            "executed_at" => entries.sort_by_key(|entry| entry.executed_at),
+            "duration_ms" => entries.sort_by_key(|entry| entry.duration_ms),
+            _ => {}
+        }
+        if sort.descending {
+            entries.reverse();
+        }
+    }
+    Json(params.paginate(entries))
+}
+
+#[utoipa::path(post, path = "/api/v1/admin/cleanup", responses((status = OK, body = CleanupReport), (status = INTERNAL_SERVER_ERROR, body = RunError)), description = "Drop the environments unused for longer than the configured TTL and the least recently used ones beyond the configured maximum count")]
+pub async fn cleanup_environments(
+    state: State<AppState>,
+) -> Result<Json<CleanupReport>, GenerateErrorResponse> {
+    state
+        .db
+        .cleanup_environments()
+        .await
+        .map(Json)
+        .map_err(|err| {
+            error!("Error while cleaning up environments: {err}");
+            err_to_response(err)
+        })
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct DropEnvironmentsRequest {
+    /// Environments to drop by name, e.g. as listed by a dry run
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// Drop every environment whose name starts with this, an empty prefix selects all
+    pub prefix: Option<String>,
+    /// Drop every environment created by another grader version, whose results may differ
+    /// from the current one
+    #[serde(default)]
+    pub outdated: bool,
+    /// Only list the environments which would be dropped
+    #[serde(default = "get_default_dry_run")]
+    pub dry_run: bool,
+}
+
+fn get_default_dry_run() -> bool {
+    true
+}
+
+#[utoipa::path(post, path = "/api/v1/admin/drop", request_body = DropEnvironmentsRequest, responses((status = OK, body = CleanupReport), (status = UNPROCESSABLE_ENTITY, body = RunError), (status = INTERNAL_SERVER_ERROR, body = RunError)), description = "Drop all environments with one of the names or the prefix, or created by another grader version, except those in use. Only lists them unless dry_run is false")]
+pub async fn drop_environments(
+    state: State<AppState>,
+    body: Json<DropEnvironmentsRequest>,
+) -> Result<Json<CleanupReport>, GenerateErrorResponse> {
+    if body.names.is_empty() && body.prefix.is_none() && !body.outdated {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(RunError {
+                location: "other",
+                error: "either names, prefix or outdated must be given".to_string(),
+            }),
+        ));
+    }
+    let on_error = |err| {
+        error!("Error while dropping environments: {err}");
+        err_to_response(err)
+    };
+    let outdated = match body.outdated {
+        true => state
+            .db
+            .outdated_environment_names()
+            .await
+            .map_err(on_error)?,
+        false => vec![],
+    };
+    let selected = |name: &str| {
+        body.names.iter().any(|selected| selected == name)
+            || outdated.iter().any(|outdated| outdated == name)
+            || body
+                .prefix
+                .as_ref()
+                .is_some_and(|prefix| name.starts_with(prefix.as_str()))
+    };
+    state
+        .db
+        .drop_environments(selected, body.dry_run)
+        .await
+        .map(Json)
+        .map_err(on_error)
+}
+
+#[utoipa::path(get, path = "/api/v1/stats/fingerprints/{database}", params(("database" = String, Path, description = "Name of the environment database"), PageParams), responses((status = OK, body = Page<FingerprintCount>)), description = "Get how often queries of each normalised shape were executed in an environment, most frequent first and filtered by fingerprint")]
+pub async fn query_fingerprints(
+    state: State<AppState>,
+    Path(database): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Json<Page<FingerprintCount>> {
+    let mut fingerprints = state.db.query_fingerprints(&database);
+    fingerprints.retain(|fingerprint| params.matches(&fingerprint.fingerprint));
+    Json(params.paginate(fingerprints))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ValidateEnvironmentRequest {
+    pub environment: Environment,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ValidateEnvironmentResponse {
+    pub warnings: Vec<LintWarning>,
+}
+
+#[utoipa::path(post, path = "/api/v1/environments/validate", request_body = ValidateEnvironmentRequest, responses((status = OK, body = ValidateEnvironmentResponse), (status = UNPROCESSABLE_ENTITY)), description = "Check an environment for constructs that make grading non-deterministic")]
+pub async fn validate_environment(
+    state: State<AppState>,
+    body: Json<ValidateEnvironmentRequest>,
+) -> Result<Json<ValidateEnvironmentResponse>, GenerateErrorResponse> {
+    let environment = resolve_environment(&state, &body.environment).await?;
+    Ok(Json(ValidateEnvironmentResponse {
+        warnings: lint_environment(&environment),
     }))
 }
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct GenerateDataRequest {
+    /// `CREATE TABLE` statements of the tables to fill, other statements are ignored
+    pub schema: String,
+    #[serde(default = "get_default_generated_rows")]
+    pub rows: usize,
+    /// The same schema, rows and seed always produce the same script
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn get_default_generated_rows() -> usize {
+    20
+}
+
+#[utoipa::path(post, path = "/api/v1/generate_data", request_body = GenerateDataRequest, responses((status = OK, body = GeneratedData), (status = UNPROCESSABLE_ENTITY)), description = "Generate a deterministic seed script with sample data for a schema")]
+pub async fn generate_sample_data(
+    body: Json<GenerateDataRequest>,
+) -> Result<Json<GeneratedData>, GenerateErrorResponse> {
+    generate_data(&body.schema, body.rows, body.seed)
+        .map(Json)
+        .map_err(|err| {
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(RunError {
+                    location: "schema",
+                    error: err.to_string(),
+                }),
+            )
+        })
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct PrepareEnvironmentRequest {
+    pub environment: Environment,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PrepareEnvironmentResponse {
+    pub name: String,
+    pub status_url: String,
+    pub status: EnvironmentStatus,
+}
+
+#[utoipa::path(post, path = "/api/v1/environments", request_body = PrepareEnvironmentRequest, responses((status = OK, body = PrepareEnvironmentResponse), (status = ACCEPTED, body = PrepareEnvironmentResponse), (status = UNPROCESSABLE_ENTITY), (status = INTERNAL_SERVER_ERROR)), description = "Initialise an environment in the background")]
+pub async fn prepare_environment(
+    state: State<AppState>,
+    body: Json<PrepareEnvironmentRequest>,
+) -> Result<(StatusCode, Json<PrepareEnvironmentResponse>), GenerateErrorResponse> {
+    let environment = resolve_environment(&state, &body.environment).await?;
+    let name = environment_name(&environment);
+    let status = state.db.environment_status(&name).await.map_err(|err| {
+        error!("Error while handling prepare_environment request: {err}");
+        err_to_response(err)
+    })?;
+    let code = match status.state {
+        EnvironmentState::Ready => StatusCode::OK,
+        EnvironmentState::Queued | EnvironmentState::Initialising => StatusCode::ACCEPTED,
+        EnvironmentState::Missing | EnvironmentState::Failed => {
+            let db = state.db.clone();
+            tokio::spawn(async move {
+                if let Err(err) = db.prepare_environment(&environment).await {
+                    error!("Error while initialising environment: {err}");
+                }
+            });
+            StatusCode::ACCEPTED
+        }
+    };
+    Ok((
+        code,
+        Json(PrepareEnvironmentResponse {
+            status_url: format!("/api/v1/environments/{name}"),
+            name,
+            status,
+        }),
+    ))
+}
+
+#[utoipa::path(get, path = "/api/v1/environments/{name}", params(("name" = String, Path, description = "Name of the environment database")), responses((status = OK, body = EnvironmentStatus), (status = INTERNAL_SERVER_ERROR)), description = "Get the initialisation status of an environment")]
+pub async fn environment_status(
+    state: State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<EnvironmentStatus>, StatusCode> {
+    state
+        .db
+        .environment_status(&name)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            error!("Error while handling environment_status request: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SeedDataResponse {
+    pub id: String,
+    /// Source to use in `COPY table FROM '<reference>' WITH (FORMAT csv)` statements of
+    /// environment scripts
+    pub reference: String,
+}
+
+#[utoipa::path(post, path = "/api/v1/seed_data", request_body(content_type = "multipart/form-data", description = "CSV data in the `file` field"), responses((status = OK, body = SeedDataResponse), (status = BAD_REQUEST), (status = INTERNAL_SERVER_ERROR)), description = "Upload seed data to be loaded into environments via COPY")]
+pub async fn upload_seed_data(
+    state: State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<SeedDataResponse>, StatusCode> {
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let data = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+        let id = state.db.store_seed_data(&data).await.map_err(|err| {
+            error!("Error while handling upload_seed_data request: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        return Ok(Json(SeedDataResponse {
+            reference: format!("seed:{id}"),
+            id,
+        }));
+    }
+    Err(StatusCode::BAD_REQUEST)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FragmentRequest {
+    pub script: String,
+}
+
+#[utoipa::path(put, path = "/api/v1/fragments/{name}", params(("name" = String, Path, description = "Name of the fragment")), request_body = FragmentRequest, responses((status = NO_CONTENT), (status = BAD_REQUEST), (status = INTERNAL_SERVER_ERROR)), description = "Register or update an environment fragment")]
+pub async fn put_fragment(
+    state: State<AppState>,
+    Path(name): Path<String>,
+    body: Json<FragmentRequest>,
+) -> StatusCode {
+    match state.fragments.put(&name, &body.script).await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(FragmentError::InvalidName(_)) => StatusCode::BAD_REQUEST,
+        Err(err) => {
+            error!("Error while handling put_fragment request: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[utoipa::path(get, path = "/api/v1/fragments/{name}", params(("name" = String, Path, description = "Name of the fragment")), responses((status = OK, body = FragmentRequest), (status = NOT_FOUND), (status = INTERNAL_SERVER_ERROR)), description = "Get an environment fragment")]
+pub async fn get_fragment(
+    state: State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<FragmentRequest>, StatusCode> {
+    match state.fragments.get(&name).await {
+        Ok(script) => Ok(Json(FragmentRequest { script })),
+        Err(FragmentError::InvalidName(_) | FragmentError::NotFound(_)) => {
+            Err(StatusCode::NOT_FOUND)
+        }
+        Err(err) => {
+            error!("Error while handling get_fragment request: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[utoipa::path(get, path = "/api/v1/fragments", params(PageParams), responses((status = OK, body = Page<String>), (status = INTERNAL_SERVER_ERROR)), description = "List the names of environment fragments, sorted by `name`")]
+pub async fn list_fragments(
+    state: State<AppState>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<String>>, StatusCode> {
+    let mut names = state.fragments.list().await.map_err(|err| {
+        error!("Error while handling list_fragments request: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    names.retain(|name| params.matches(name));
+    if params.sort().is_some_and(|sort| sort.descending) {
+        names.reverse();
+    }
+    Ok(Json(params.paginate(names)))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct RenderRequest {
+    pub result_set: ResultSet,
+    #[serde(default)]
+    pub format: TableFormat,
+    #[serde(flatten)]
+    pub options: RenderOptions,
+}
+
+#[utoipa::path(post, path = "/api/v1/render", request_body = RenderRequest, responses((status = OK, body = String, content_type = "text/markdown"), (status = OK, body = String, content_type = "text/html")), description = "Render a result set as markdown or HTML table, the same way feedback prompts show it")]
+pub async fn render(body: Json<RenderRequest>) -> Response {
+    let content_type = match body.format {
+        TableFormat::Markdown => "text/markdown; charset=utf-8",
+        TableFormat::Html => "text/html; charset=utf-8",
+    };
+    (
+        [(header::CONTENT_TYPE, content_type)],
+        render_result_set(&body.result_set, body.format, &body.options),
+    )
+        .into_response()
+}
+
+#[utoipa::path(get, path = "/api/v1/version", responses((status = OK, body = VersionInfo)), description = "Get version and build information")]
+pub async fn version() -> Json<VersionInfo> {
+    Json(common::version_info!(RUNNER_API_VERSION, ENABLED_FEATURES))
+}
+
+#[utoipa::path(get, path = "/ping", responses((status = OK, body = PingResponse)), description = "Lightweight liveness check without touching any dependency")]
+pub async fn ping() -> Json<PingResponse> {
+    Json(PingResponse {
+        version: common::version_info!(RUNNER_API_VERSION, ENABLED_FEATURES),
+        uptime_secs: common::version::uptime().as_secs(),
+    })
+}
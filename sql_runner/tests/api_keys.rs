@@ -0,0 +1,49 @@
+#[path = "../src/api_keys.rs"]
+mod api_keys;
+
+use api_keys::{ApiKey, ApiKeys, KeyRateLimit};
+use std::time::{Duration, Instant};
+
+fn hash(key: &str) -> String {
+    blake3::hash(key.as_bytes()).to_hex().to_string()
+}
+
+#[test]
+fn tokens_are_authenticated_by_their_hash() {
+    let keys = ApiKeys::parse(&format!(
+        "proxy:{}, grader : {}",
+        hash("secret"),
+        hash("other")
+    ))
+    .unwrap();
+    assert_eq!(
+        keys.authenticate("secret"),
+        Some(ApiKey("proxy".to_string()))
+    );
+    assert_eq!(
+        keys.authenticate("other"),
+        Some(ApiKey("grader".to_string()))
+    );
+    assert_eq!(keys.authenticate(&hash("secret")), None);
+}
+
+#[test]
+fn invalid_key_lists_are_rejected() {
+    assert!(ApiKeys::parse("").is_err());
+    assert!(ApiKeys::parse("proxy").is_err());
+    assert!(ApiKeys::parse("proxy:abc").is_err());
+    let duplicate = format!("a:{},b:{}", hash("key"), hash("key"));
+    assert!(ApiKeys::parse(&duplicate).is_err());
+}
+
+#[test]
+fn requests_are_limited_per_key_and_minute() {
+    let limit = KeyRateLimit::new(2);
+    let (a, b) = (ApiKey("a".to_string()), ApiKey("b".to_string()));
+    let start = Instant::now();
+    assert!(limit.try_request(&a, start));
+    assert!(limit.try_request(&a, start + Duration::from_secs(1)));
+    assert!(!limit.try_request(&a, start + Duration::from_secs(2)));
+    assert!(limit.try_request(&b, start + Duration::from_secs(2)));
+    assert!(limit.try_request(&a, start + Duration::from_secs(60)));
+}
@@ -0,0 +1,43 @@
+#[path = "../src/db/column_names.rs"]
+mod column_names;
+
+use column_names::{ColumnNameMismatch, check_column_names};
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn names_match_ignoring_case_by_default() {
+    let required = names(&["CustomerName", "total"]);
+    assert!(check_column_names(&required, &names(&["customername", "TOTAL"]), false).matches);
+
+    let check = check_column_names(&required, &names(&["customername", "total"]), true);
+    assert_eq!(
+        check.mismatches,
+        vec![ColumnNameMismatch {
+            position: 0,
+            expected: Some("CustomerName".to_string()),
+            actual: Some("customername".to_string()),
+        }]
+    );
+}
+
+#[test]
+fn missing_and_surplus_columns_are_reported_by_position() {
+    let required = names(&["name", "total"]);
+    let check = check_column_names(&required, &names(&["name"]), false);
+    assert!(!check.matches);
+    assert_eq!(check.mismatches[0].position, 1);
+    assert_eq!(check.mismatches[0].actual, None);
+
+    let check = check_column_names(&required, &names(&["name", "total", "count"]), false);
+    assert_eq!(
+        check.mismatches,
+        vec![ColumnNameMismatch {
+            position: 2,
+            expected: None,
+            actual: Some("count".to_string()),
+        }]
+    );
+}
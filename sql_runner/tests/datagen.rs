@@ -0,0 +1,112 @@
+#[path = "../src/datagen.rs"]
+mod datagen;
+
+use datagen::{DataGenError, MAX_ROWS, generate_data};
+
+const SCHEMA: &str = "
+CREATE TABLE customer (
+    id integer PRIMARY KEY,
+    name varchar(40) NOT NULL,
+    email text UNIQUE,
+    city text,
+    birthday date
+);
+CREATE TABLE product (
+    id serial PRIMARY KEY,
+    title text,
+    price numeric(8, 2)
+);
+CREATE TABLE purchase (
+    customer_id integer REFERENCES customer (id),
+    product_id integer REFERENCES product,
+    quantity integer,
+    PRIMARY KEY (customer_id, product_id)
+);
+";
+
+#[test]
+fn same_seed_produces_same_script() {
+    let first = generate_data(SCHEMA, 10, 7).unwrap();
+    let second = generate_data(SCHEMA, 10, 7).unwrap();
+    let other = generate_data(SCHEMA, 10, 8).unwrap();
+    assert_eq!(first.script, second.script);
+    assert_ne!(first.script, other.script);
+}
+
+#[test]
+fn tables_are_filled_in_schema_order() {
+    let data = generate_data(SCHEMA, 5, 1).unwrap();
+    let tables: Vec<_> = data
+        .tables
+        .iter()
+        .map(|table| (table.name.as_str(), table.rows))
+        .collect();
+    assert_eq!(tables, [("customer", 5), ("product", 5), ("purchase", 5)]);
+    assert!(
+        data.script
+            .starts_with("INSERT INTO customer (id, name, email, city, birthday)")
+    );
+    assert!(data.script.contains("INSERT INTO product (title, price)"));
+    assert!(data.script.contains("    (1, '"));
+}
+
+#[test]
+fn composite_keys_stay_unique() {
+    let data = generate_data(SCHEMA, 2, 3).unwrap();
+    let purchase = data.script.split("INSERT INTO purchase").nth(1).unwrap();
+    let rows: Vec<_> = purchase.lines().skip(1).collect();
+    assert_eq!(rows.len(), 2);
+    assert_ne!(rows[0][..9], rows[1][..9]);
+}
+
+#[test]
+fn invalid_schemas_are_rejected() {
+    assert!(matches!(
+        generate_data("SELECT 1", 1, 0),
+        Err(DataGenError::NoTables)
+    ));
+    assert!(matches!(
+        generate_data("CREATE TABLE (", 1, 0),
+        Err(DataGenError::Parse(_))
+    ));
+    assert!(matches!(
+        generate_data(SCHEMA, MAX_ROWS + 1, 0),
+        Err(DataGenError::TooManyRows)
+    ));
+}
+
+#[test]
+fn values_fit_their_columns() {
+    let schema =
+        "CREATE TABLE item (code varchar(6) UNIQUE, share numeric(3, 2), score numeric(2));";
+    let data = generate_data(schema, 20, 5).unwrap();
+    let mut codes = Vec::new();
+    for row in data.script.lines().skip(1) {
+        let row = row
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches([',', ';', ')']);
+        let values: Vec<&str> = row.split(", ").collect();
+        let code = values[0].trim_matches('\'');
+        assert!(code.chars().count() <= 6, "{code}");
+        codes.push(code.to_string());
+        let share: f64 = values[1].parse().unwrap();
+        assert!(share < 10.0 && values[1].len() == 4, "{}", values[1]);
+        assert!(values[2].parse::<u32>().unwrap() < 100, "{}", values[2]);
+    }
+    assert_eq!(codes.len(), 20);
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), 20);
+}
+
+#[test]
+fn tables_of_database_filled_columns_use_default_values() {
+    let schema = "CREATE TABLE counter (id serial PRIMARY KEY, doubled integer GENERATED ALWAYS AS (id * 2) STORED);";
+    let data = generate_data(schema, 3, 0).unwrap();
+    assert_eq!(data.tables[0].rows, 3);
+    assert_eq!(
+        data.script,
+        "INSERT INTO counter DEFAULT VALUES;\n".repeat(3)
+    );
+}
@@ -0,0 +1,89 @@
+#[path = "../src/dialect.rs"]
+mod dialect;
+
+use dialect::{SqlDialect, translate, translate_comparison};
+
+#[test]
+fn postgres_queries_are_unchanged() {
+    let query = "SELECT `a` FROM t LIMIT 1, 2";
+    let translation = translate(query, SqlDialect::Postgres);
+    assert_eq!(translation.query, query);
+    assert!(translation.translated.is_empty());
+}
+
+#[test]
+fn mysql_idioms_are_translated() {
+    let translation = translate(
+        "SELECT `name`, IFNULL(note, \"none\") FROM `order` # newest first\nORDER BY id DESC LIMIT 10, 5",
+        SqlDialect::Mysql,
+    );
+    assert_eq!(
+        translation.query,
+        "SELECT \"name\", COALESCE(note, 'none') FROM \"order\" -- newest first\nORDER BY id DESC LIMIT 5 OFFSET 10"
+    );
+    assert_eq!(
+        translation.translated,
+        [
+            "quoted identifier",
+            "IFNULL",
+            "double quoted string",
+            "# comment",
+            "LIMIT offset, count"
+        ]
+    );
+}
+
+#[test]
+fn string_literals_are_requoted_for_postgres() {
+    let translation = translate("SELECT 'it\\'s', ifnull FROM t", SqlDialect::Mysql);
+    assert_eq!(translation.query, "SELECT 'it''s', ifnull FROM t");
+    assert!(translation.translated.is_empty());
+}
+
+#[test]
+fn sqlite_identifiers_are_translated() {
+    let translation = translate(
+        "SELECT [first name] FROM people LIMIT 3",
+        SqlDialect::Sqlite,
+    );
+    assert_eq!(
+        translation.query,
+        "SELECT \"first name\" FROM people LIMIT 3"
+    );
+    assert_eq!(translation.translated, ["quoted identifier"]);
+}
+
+#[test]
+fn comparisons_translate_both_sides() {
+    let (solution, submission) = translate_comparison(
+        "SELECT `name` FROM people LIMIT 2, 3",
+        "SELECT name FROM `people` LIMIT 2, 3",
+        SqlDialect::Mysql,
+        None,
+    );
+    assert_eq!(
+        solution.query,
+        "SELECT \"name\" FROM people LIMIT 3 OFFSET 2"
+    );
+    assert_eq!(
+        solution.translated,
+        ["quoted identifier", "LIMIT offset, count"]
+    );
+    assert_eq!(
+        submission.query,
+        "SELECT name FROM \"people\" LIMIT 3 OFFSET 2"
+    );
+}
+
+#[test]
+fn solutions_may_use_their_own_dialect() {
+    let (solution, submission) = translate_comparison(
+        "SELECT \"first name\" FROM people LIMIT 3 OFFSET 2",
+        "SELECT [first name] FROM people LIMIT 3 OFFSET 2",
+        SqlDialect::Sqlite,
+        Some(SqlDialect::Postgres),
+    );
+    assert_eq!(solution.query, submission.query);
+    assert!(solution.translated.is_empty());
+    assert_eq!(submission.translated, ["quoted identifier"]);
+}
@@ -0,0 +1,118 @@
+#[path = "../src/db/expectation.rs"]
+mod expectation;
+
+use common::models::{ResultSet, SqlValue};
+use expectation::ExpectedResultSet;
+use serde_json::json;
+
+fn result_set(rows: Vec<Vec<SqlValue>>) -> ResultSet {
+    ResultSet {
+        columns: vec!["id".to_string(), "name".to_string(), "price".to_string()],
+        rows,
+    }
+}
+
+fn expected(value: serde_json::Value) -> ExpectedResultSet {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn wildcards_accept_generated_values_and_rest_is_strict() {
+    let expectation = expected(json!({
+        "columns": ["id", "name", "price"],
+        "rows": [
+            [{"match": "any"}, "Tea", {"match": "range", "min": 1.0, "max": 2.0}],
+            [{"match": "any"}, {"match": "regex", "pattern": "Coff?ee"}, 3.5],
+        ]
+    }));
+    let mut rows = vec![
+        vec![
+            SqlValue::Int(17),
+            SqlValue::Text("Tea".into()),
+            SqlValue::Float(1.5),
+        ],
+        vec![
+            SqlValue::Int(42),
+            SqlValue::Text("Coffee".into()),
+            SqlValue::Float(3.5),
+        ],
+    ];
+    assert!(expectation.matches(&result_set(rows.clone())));
+
+    rows[0][1] = SqlValue::Text("Green Tea".into());
+    assert!(!expectation.matches(&result_set(rows.clone())));
+    rows[0][1] = SqlValue::Text("Tea".into());
+    rows[0][2] = SqlValue::Float(2.5);
+    assert!(!expectation.matches(&result_set(rows.clone())));
+    rows[0][2] = SqlValue::Float(1.5);
+    rows[1][1] = SqlValue::Text("Coffee beans".into());
+    assert!(!expectation.matches(&result_set(rows)));
+}
+
+#[test]
+fn columns_and_row_count_are_checked() {
+    let expectation = expected(json!({
+        "columns": ["id", "title", "price"],
+        "rows": [[{"match": "any"}, {"match": "any"}, {"match": "any"}]]
+    }));
+    let rows = vec![vec![
+        SqlValue::Int(1),
+        SqlValue::Text("Tea".into()),
+        SqlValue::Float(1.5),
+    ]];
+    assert!(!expectation.matches(&result_set(rows.clone())));
+
+    let expectation =
+        expected(json!({"rows": [[{"match": "any"}, {"match": "any"}, {"match": "any"}]]}));
+    assert!(expectation.matches(&result_set(rows.clone())));
+    assert!(!expectation.matches(&result_set([rows.clone(), rows].concat())));
+}
+
+#[test]
+fn rows_match_in_any_order_when_requested() {
+    let rows = vec![
+        vec![
+            SqlValue::Int(1),
+            SqlValue::Text("Tea".into()),
+            SqlValue::Int(2),
+        ],
+        vec![
+            SqlValue::Int(2),
+            SqlValue::Text("Coffee".into()),
+            SqlValue::Int(3),
+        ],
+    ];
+    let value = json!({
+        "rows": [
+            [{"match": "any"}, {"match": "any"}, {"match": "any"}],
+            [{"match": "any"}, "Tea", 2],
+        ]
+    });
+    assert!(!expected(value.clone()).matches(&result_set(rows.clone())));
+
+    let mut value = value;
+    value["any_order"] = json!(true);
+    assert!(expected(value).matches(&result_set(rows)));
+}
+
+#[test]
+fn invalid_regex_is_rejected() {
+    let result = serde_json::from_value::<ExpectedResultSet>(
+        json!({"rows": [[{"match": "regex", "pattern": "("}]]}),
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn expected_text_matches_dates_it_spells() {
+    let expectation = expected(json!({
+        "rows": [[1, "2024-1-2", 3.5]]
+    }));
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+    let row = |value| vec![SqlValue::Int(1), value, SqlValue::Float(3.5)];
+    assert!(expectation.matches(&result_set(vec![row(SqlValue::Date(date))])));
+    assert!(!expectation.matches(&result_set(vec![row(SqlValue::Date(
+        date.succ_opt().unwrap()
+    ))])));
+    assert!(!expectation.matches(&result_set(vec![row(SqlValue::Text("2024-01-02".into()))])));
+}
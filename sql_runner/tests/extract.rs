@@ -0,0 +1,255 @@
+//! Extraction of random tables from the Postgres at `TEST_DATABASE_URL`, skipped without it.
+
+#[path = "../src/db/decode.rs"]
+mod decode;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use common::models::{ResultSet, SqlValue};
+use decode::{column_names, decode_row};
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::{Executor, Row};
+use std::sync::LazyLock;
+use tokio::runtime::Runtime;
+
+/// Postgres types with a representation as `SqlValue`.
+#[derive(Debug, Copy, Clone)]
+enum ColumnType {
+    Bool,
+    Int2,
+    Int4,
+    Int8,
+    Float4,
+    Float8,
+    Numeric,
+    Text,
+    Varchar,
+    Date,
+    Timestamp,
+    Timestamptz,
+}
+
+const COLUMN_TYPES: [ColumnType; 12] = [
+    ColumnType::Bool,
+    ColumnType::Int2,
+    ColumnType::Int4,
+    ColumnType::Int8,
+    ColumnType::Float4,
+    ColumnType::Float8,
+    ColumnType::Numeric,
+    ColumnType::Text,
+    ColumnType::Varchar,
+    ColumnType::Date,
+    ColumnType::Timestamp,
+    ColumnType::Timestamptz,
+];
+
+impl ColumnType {
+    fn sql(self) -> &'static str {
+        match self {
+            ColumnType::Bool => "boolean",
+            ColumnType::Int2 => "smallint",
+            ColumnType::Int4 => "integer",
+            ColumnType::Int8 => "bigint",
+            ColumnType::Float4 => "real",
+            ColumnType::Float8 => "double precision",
+            ColumnType::Numeric => "numeric",
+            ColumnType::Text => "text",
+            ColumnType::Varchar => "varchar",
+            ColumnType::Date => "date",
+            ColumnType::Timestamp => "timestamp",
+            ColumnType::Timestamptz => "timestamptz",
+        }
+    }
+
+    /// Literals of the type with the value they are extracted as. Floats are finite and
+    /// dates have four digit years, as JSON has no representation of the others.
+    fn values(self) -> BoxedStrategy<(String, SqlValue)> {
+        let epoch = NaiveDate::from_ymd_opt(1, 1, 1).expect("the date exists");
+        let dates = (0i64..3_652_059).prop_map(move |days| epoch + Duration::days(days));
+        let timestamps = (dates.clone(), 0i64..86_400_000_000).prop_map(|(date, micros)| {
+            date.and_time(Default::default()) + Duration::microseconds(micros)
+        });
+        match self {
+            ColumnType::Bool => any::<bool>()
+                .prop_map(|b| (b.to_string(), SqlValue::Bool(b)))
+                .boxed(),
+            ColumnType::Int2 => any::<i16>()
+                .prop_map(|i| (i.to_string(), SqlValue::Int(i.into())))
+                .boxed(),
+            ColumnType::Int4 => any::<i32>()
+                .prop_map(|i| (i.to_string(), SqlValue::Int(i.into())))
+                .boxed(),
+            ColumnType::Int8 => any::<i64>()
+                .prop_map(|i| (i.to_string(), SqlValue::Int(i)))
+                .boxed(),
+            ColumnType::Float4 => any::<f32>()
+                .prop_filter("finite", |f| f.is_finite())
+                .prop_map(|f| (format!("{f:?}"), SqlValue::Float(f.into())))
+                .boxed(),
+            ColumnType::Float8 => any::<f64>()
+                .prop_filter("finite", |f| f.is_finite())
+                .prop_map(|f| (format!("{f:?}"), SqlValue::Float(f)))
+                .boxed(),
+            ColumnType::Numeric => (any::<i32>(), 0u32..8)
+                .prop_map(|(mantissa, scale)| {
+                    let literal = format!("{mantissa}e-{scale}");
+                    let value = literal.parse().expect("literals are numbers");
+                    (literal, SqlValue::Float(value))
+                })
+                .boxed(),
+            ColumnType::Text => "[^\0]{0,20}"
+                .prop_map(|text| (quote(&text), SqlValue::Text(text)))
+                .boxed(),
+            ColumnType::Varchar => "[^\0]{0,20}"
+                .prop_map(|text| (quote(&text), SqlValue::Text(text)))
+                .boxed(),
+            ColumnType::Date => dates
+                .prop_map(|date| (quote(&date.to_string()), SqlValue::Date(date)))
+                .boxed(),
+            ColumnType::Timestamp => timestamps
+                .prop_map(|timestamp| (quote(&iso(timestamp)), SqlValue::Timestamp(timestamp)))
+                .boxed(),
+            ColumnType::Timestamptz => (timestamps, -14i64..=14)
+                .prop_map(|(timestamp, offset)| {
+                    let literal = format!("{}{offset:+03}", iso(timestamp));
+                    let utc = timestamp - Duration::hours(offset);
+                    (quote(&literal), SqlValue::Timestamp(utc))
+                })
+                .boxed(),
+        }
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn iso(timestamp: NaiveDateTime) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%S%.6f").to_string()
+}
+
+/// Column types and rows of literals or `NULL` with the values they are extracted as.
+type Table = (Vec<ColumnType>, Vec<Vec<(String, SqlValue)>>);
+
+fn tables() -> impl Strategy<Value = Table> {
+    prop::collection::vec(prop::sample::select(&COLUMN_TYPES[..]), 1..=6).prop_flat_map(|types| {
+        let row: Vec<_> = types
+            .iter()
+            .map(|column_type| {
+                prop_oneof![
+                    1 => Just(("NULL".to_string(), SqlValue::Null)),
+                    5 => column_type.values(),
+                ]
+            })
+            .collect();
+        (Just(types), prop::collection::vec(row, 0..=5))
+    })
+}
+
+fn failed(err: sqlx::Error) -> TestCaseError {
+    TestCaseError::fail(err.to_string())
+}
+
+/// Creates the table in a transaction which is rolled back, selecting every column twice to
+/// extract columns of the same name.
+async fn extract(pool: &PgPool, (types, rows): &Table) -> Result<ResultSet, TestCaseError> {
+    let mut transaction = pool.begin().await.map_err(failed)?;
+    let columns: Vec<String> = types
+        .iter()
+        .enumerate()
+        .map(|(index, column_type)| format!("\"c {index}\" {}", column_type.sql()))
+        .collect();
+    transaction
+        .execute(format!("CREATE TEMP TABLE fuzz ({})", columns.join(", ")).as_str())
+        .await
+        .map_err(failed)?;
+    for row in rows {
+        let literals: Vec<&str> = row.iter().map(|(literal, _)| literal.as_str()).collect();
+        transaction
+            .execute(format!("INSERT INTO fuzz VALUES ({})", literals.join(", ")).as_str())
+            .await
+            .map_err(failed)?;
+    }
+    // the types of the table differ between cases, so the statement is not cached
+    let selected = sqlx::query("SELECT fuzz.*, fuzz.* FROM fuzz")
+        .persistent(false)
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(failed)?;
+    transaction.rollback().await.map_err(failed)?;
+    Ok(ResultSet {
+        columns: selected.first().map(column_names).unwrap_or_default(),
+        rows: selected
+            .iter()
+            .map(decode_row)
+            .collect::<Result<_, _>>()
+            .map_err(|column| TestCaseError::fail(format!("`{column}` is not decoded")))?,
+    })
+}
+
+/// Runtime and pool of the test database, `None` if there is none.
+static DATABASE: LazyLock<Option<(Runtime, PgPool)>> = LazyLock::new(|| {
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("skipped, TEST_DATABASE_URL is not set");
+        return None;
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let pool = runtime
+        .block_on(PgPoolOptions::new().max_connections(1).connect(&url))
+        .unwrap();
+    Some((runtime, pool))
+});
+
+proptest! {
+    #[test]
+    fn extraction_is_lossless(table in tables()) {
+        let Some((runtime, pool)) = &*DATABASE else {
+            return Ok(());
+        };
+        let extracted = runtime.block_on(extract(pool, &table))?;
+        let (types, rows) = &table;
+        if !rows.is_empty() {
+            let names: Vec<String> = (0..types.len()).map(|i| format!("c {i}")).collect();
+            prop_assert_eq!(&extracted.columns, &[names.clone(), names].concat());
+        }
+        let expected: Vec<Vec<SqlValue>> = rows
+            .iter()
+            .map(|row| {
+                let values: Vec<SqlValue> = row.iter().map(|(_, value)| value.clone()).collect();
+                [values.clone(), values].concat()
+            })
+            .collect();
+        prop_assert_eq!(&extracted.rows, &expected);
+
+        // dates and timestamps are read back as the text they are serialised as
+        let serialised = serde_json::to_string(&extracted).unwrap();
+        let parsed = serde_json::from_str::<ResultSet>(&serialised).unwrap();
+        prop_assert_eq!(serde_json::to_string(&parsed).unwrap(), serialised);
+    }
+}
+
+#[test]
+fn unsupported_types_are_not_decoded() {
+    let Some((runtime, pool)) = &*DATABASE else {
+        return;
+    };
+    for literal in [
+        "'a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11'::uuid",
+        r#"'{"a": 1}'::json"#,
+        "'1 day'::interval",
+        "'\\x00'::bytea",
+        "ARRAY[1, 2]",
+        "'12:00'::time",
+    ] {
+        let row = runtime
+            .block_on(sqlx::query(&format!("SELECT 1 AS id, {literal} AS value")).fetch_one(pool))
+            .unwrap();
+        assert_eq!(row.len(), 2);
+        assert_eq!(decode_row(&row), Err("value".to_string()), "{literal}");
+    }
+}
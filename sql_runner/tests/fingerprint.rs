@@ -0,0 +1,43 @@
+#[path = "../src/db/fingerprint.rs"]
+mod fingerprint;
+
+use fingerprint::{FingerprintStats, query_fingerprint};
+
+#[test]
+fn literals_and_formatting_are_normalised() {
+    let a =
+        query_fingerprint("select name from  Customer\nwhere id = 42 and city = 'Gießen'; -- mine");
+    let b = query_fingerprint("SELECT name FROM customer WHERE id = 7 AND city = 'Berlin'");
+    assert_eq!(a, b);
+    assert_eq!(a, "select name from customer where id = ? and city = ?");
+}
+
+#[test]
+fn quoted_identifiers_are_kept() {
+    assert_eq!(
+        query_fingerprint("SELECT \"Name\" FROM t LIMIT 3"),
+        "select \"Name\" from t limit ?"
+    );
+}
+
+#[test]
+fn stats_count_fingerprints_per_environment() {
+    let stats = FingerprintStats::default();
+    stats.record(
+        "env",
+        "SELECT * FROM a LEFT JOIN b ON a.id = b.id WHERE a.x = 1",
+    );
+    stats.record(
+        "env",
+        "select * from a left join b on a.id = b.id where a.x = 2",
+    );
+    stats.record("env", "SELECT * FROM a JOIN b ON a.id = b.id");
+    stats.record("other", "SELECT 1");
+
+    let fingerprints = stats.get("env");
+    assert_eq!(fingerprints.len(), 2);
+    assert_eq!(fingerprints[0].count, 2);
+    assert!(fingerprints[0].fingerprint.contains("left join"));
+    assert!((fingerprints[0].share - 2.0 / 3.0).abs() < f64::EPSILON);
+    assert!(stats.get("missing").is_empty());
+}
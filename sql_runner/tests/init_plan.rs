@@ -0,0 +1,146 @@
+//! Checks that concurrent environment initialisation ends in the same state as executing the
+//! script sequentially: every table receives the same inserts in the same order, and rows of
+//! referenced tables are inserted before the rows referencing them.
+
+#[path = "../src/db/init_plan.rs"]
+mod init_plan;
+#[path = "../src/db/script.rs"]
+mod script;
+
+use init_plan::{Segment, insert_levels, segments};
+use script::split_statements;
+use std::collections::BTreeMap;
+
+const SCRIPT: &str = r#"
+CREATE TABLE customer (id SERIAL PRIMARY KEY, name TEXT);
+CREATE TABLE product (id SERIAL PRIMARY KEY, name TEXT, note TEXT);
+CREATE TABLE "Order" (id SERIAL PRIMARY KEY, customer_id INT REFERENCES customer(id), product_id INT REFERENCES product(id));
+-- seed data; the semicolon in this comment is not a separator
+INSERT INTO customer (name) VALUES ('Ada');
+INSERT INTO product (name, note) VALUES ('Tea', 'contains ; semicolon');
+INSERT INTO "Order" (customer_id, product_id) VALUES (1, 1);
+INSERT INTO customer (name) VALUES ('Grace');
+INSERT INTO public.product (name, note) VALUES ('Cake', $$it's $tagged$ text;$$);
+INSERT INTO "Order" (customer_id, product_id) VALUES (2, 2);
+CREATE VIEW order_count AS SELECT count(*) FROM "Order";
+INSERT INTO customer (name) SELECT 'copy of ' || name FROM customer;
+"#;
+
+/// As returned by `introspect::FOREIGN_KEYS`, unqualified and quoted only if necessary.
+const FOREIGN_KEYS: &[(&str, &str)] = &[("\"Order\"", "customer"), ("\"Order\"", "product")];
+
+/// Inserts per table in execution order when running the script sequentially.
+fn sequential_state(statements: &[&str]) -> BTreeMap<String, Vec<String>> {
+    let mut state: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for statement in statements {
+        if let Some(Segment::Inserts(inserts)) = segments(&[statement]).unwrap().pop() {
+            for (table, statement) in inserts {
+                state.entry(table).or_default().push(statement.to_string());
+            }
+        }
+    }
+    state
+}
+
+#[test]
+fn splits_statements_outside_literals_and_comments() {
+    let statements = split_statements(SCRIPT);
+    assert_eq!(statements.len(), 11);
+    assert!(statements[4].ends_with("'contains ; semicolon')"));
+    assert!(statements[7].ends_with("$$it's $tagged$ text;$$)"));
+}
+
+#[test]
+fn groups_consecutive_plain_inserts() {
+    let statements = split_statements(SCRIPT);
+    let segments = segments(&statements).unwrap();
+    let kinds = segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Statement(_) => "statement".to_string(),
+            Segment::Inserts(inserts) => format!("inserts({})", inserts.len()),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            "statement",
+            "statement",
+            "statement",
+            "inserts(6)",
+            "statement",
+            "statement"
+        ]
+    );
+}
+
+#[test]
+fn concurrent_plan_reaches_sequential_state() {
+    let statements = split_statements(SCRIPT);
+    let foreign_keys = FOREIGN_KEYS
+        .iter()
+        .map(|(table, referenced)| (table.to_string(), referenced.to_string()))
+        .collect::<Vec<_>>();
+
+    let mut state: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut level_of_table = BTreeMap::new();
+    let mut level_index = 0;
+    for segment in segments(&statements).unwrap() {
+        let Segment::Inserts(inserts) = segment else {
+            continue;
+        };
+        for level in insert_levels(&inserts, &foreign_keys) {
+            for group in level {
+                for statement in group {
+                    let (table, _) = inserts
+                        .iter()
+                        .find(|(_, candidate)| *candidate == statement)
+                        .unwrap();
+                    state
+                        .entry(table.clone())
+                        .or_default()
+                        .push(statement.to_string());
+                    level_of_table.insert(table.clone(), level_index);
+                }
+            }
+            level_index += 1;
+        }
+    }
+
+    assert_eq!(state, sequential_state(&statements));
+    assert!(level_of_table["customer"] < level_of_table["Order"]);
+    assert!(level_of_table["product"] < level_of_table["Order"]);
+    assert_eq!(level_of_table["customer"], level_of_table["product"]);
+}
+
+#[test]
+fn cyclic_foreign_keys_keep_original_order() {
+    let inserts = vec![
+        ("a".to_string(), "INSERT INTO a VALUES (1)"),
+        ("b".to_string(), "INSERT INTO b VALUES (1)"),
+        ("a".to_string(), "INSERT INTO a VALUES (2)"),
+    ];
+    let foreign_keys = vec![
+        ("a".to_string(), "b".to_string()),
+        ("b".to_string(), "a".to_string()),
+    ];
+    assert_eq!(
+        insert_levels(&inserts, &foreign_keys),
+        vec![vec![vec![
+            "INSERT INTO a VALUES (1)",
+            "INSERT INTO b VALUES (1)",
+            "INSERT INTO a VALUES (2)"
+        ]]]
+    );
+}
+
+#[test]
+fn session_dependent_scripts_run_sequentially() {
+    for script in [
+        "BEGIN; INSERT INTO a VALUES (1); COMMIT;",
+        "SET search_path TO other; INSERT INTO a VALUES (1);",
+        "CREATE TEMP TABLE a (id INT); INSERT INTO a VALUES (1);",
+    ] {
+        assert_eq!(segments(&split_statements(script)), None, "{script}");
+    }
+}
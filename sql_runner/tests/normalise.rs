@@ -0,0 +1,197 @@
+#[path = "../src/db/normalise.rs"]
+mod normalise;
+
+use chrono::NaiveDate;
+use common::models::{ResultSet, SqlValue};
+use normalise::{ColumnOverride, DateUnit, ValueNormaliser, normalise_columns, override_columns};
+use proptest::prelude::*;
+
+fn result_set(columns: &[&str], rows: Vec<Vec<SqlValue>>) -> ResultSet {
+    ResultSet {
+        columns: columns.iter().map(|column| column.to_string()).collect(),
+        rows,
+    }
+}
+
+fn text(value: &str) -> SqlValue {
+    SqlValue::Text(value.to_string())
+}
+
+#[test]
+fn normalisers_only_apply_to_matching_columns() {
+    let mut normalised = result_set(
+        &["Name", "unit_price", "total_price"],
+        vec![vec![text("  Tea "), text(" Tea "), text("€ 3,50")]],
+    );
+    normalise_columns(
+        &mut normalised,
+        "*PRICE",
+        &[ValueNormaliser::Trim, ValueNormaliser::StripCurrency],
+    );
+    normalise_columns(
+        &mut normalised,
+        "nam?",
+        &[ValueNormaliser::Trim, ValueNormaliser::Lowercase],
+    );
+    assert_eq!(
+        normalised.rows[0],
+        vec![text("tea"), text("Tea"), SqlValue::Float(3.5)]
+    );
+}
+
+#[test]
+fn amounts_lose_currency_and_thousands_separators() {
+    let mut amounts = result_set(
+        &["amount"],
+        ["$1,234.50", "1.234,50 EUR", "1,000", "12.345", "£7", "n/a"]
+            .iter()
+            .map(|amount| vec![text(amount)])
+            .chain([vec![SqlValue::Int(7)]])
+            .collect(),
+    );
+    normalise_columns(&mut amounts, "*", &[ValueNormaliser::StripCurrency]);
+    assert_eq!(
+        amounts.rows.concat(),
+        vec![
+            SqlValue::Float(1234.5),
+            SqlValue::Float(1234.5),
+            SqlValue::Float(1000.0),
+            SqlValue::Float(12.345),
+            SqlValue::Float(7.0),
+            text("n/a"),
+            SqlValue::Float(7.0),
+        ]
+    );
+}
+
+#[test]
+fn numbers_are_rounded_and_dates_truncated() {
+    let mut normalised = result_set(
+        &["average", "day"],
+        vec![
+            vec![SqlValue::Float(2.3456), text("2024-03-17T10:30:00")],
+            vec![
+                text("2.35"),
+                SqlValue::Date(NaiveDate::from_ymd_opt(2024, 3, 2).unwrap()),
+            ],
+        ],
+    );
+    normalise_columns(
+        &mut normalised,
+        "average",
+        &[ValueNormaliser::Round { decimals: 2 }],
+    );
+    normalise_columns(
+        &mut normalised,
+        "day",
+        &[ValueNormaliser::TruncateDate {
+            unit: DateUnit::Month,
+        }],
+    );
+    let march = SqlValue::Date(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+    assert_eq!(
+        normalised.rows[0],
+        vec![SqlValue::Float(2.35), march.clone()]
+    );
+    assert_eq!(normalised.rows[1], vec![SqlValue::Float(2.35), march]);
+}
+
+fn column_override(column: &str) -> ColumnOverride {
+    ColumnOverride {
+        column: column.to_string(),
+        ignore: false,
+        tolerance: None,
+        case_insensitive: false,
+    }
+}
+
+#[test]
+fn overridden_columns_are_ignored_or_compared_leniently() {
+    let mut expected = result_set(
+        &["id", "comment", "average", "city"],
+        vec![
+            vec![
+                SqlValue::Int(1),
+                text("fine"),
+                SqlValue::Float(2.5),
+                text("Giessen"),
+            ],
+            vec![
+                SqlValue::Int(2),
+                text("ok"),
+                SqlValue::Float(4.0),
+                text("Marburg"),
+            ],
+        ],
+    );
+    let mut actual = result_set(
+        &["id", "city", "average", "comment"],
+        vec![
+            vec![
+                SqlValue::Int(1),
+                text("GIESSEN"),
+                SqlValue::Float(2.5004),
+                text("meh"),
+            ],
+            vec![
+                SqlValue::Int(2),
+                text("marburg"),
+                SqlValue::Float(4.1),
+                text(""),
+            ],
+        ],
+    );
+    override_columns(
+        &mut expected,
+        &mut actual,
+        &[
+            ColumnOverride {
+                ignore: true,
+                ..column_override("comment")
+            },
+            ColumnOverride {
+                tolerance: Some(0.001),
+                ..column_override("average")
+            },
+            ColumnOverride {
+                case_insensitive: true,
+                ..column_override("city")
+            },
+        ],
+    );
+    assert_eq!(expected.columns, vec!["id", "average", "city"]);
+    assert_eq!(actual.columns, vec!["id", "city", "average"]);
+    assert_eq!(
+        actual.rows,
+        vec![
+            vec![SqlValue::Int(1), text("giessen"), SqlValue::Float(2.5)],
+            vec![SqlValue::Int(2), text("marburg"), SqlValue::Float(4.1)],
+        ]
+    );
+    assert_eq!(expected.rows[1][2], text("marburg"));
+}
+
+fn any_normaliser() -> impl Strategy<Value = ValueNormaliser> {
+    prop_oneof![
+        Just(ValueNormaliser::Trim),
+        Just(ValueNormaliser::Lowercase),
+        Just(ValueNormaliser::StripCurrency),
+        (0u32..4).prop_map(|decimals| ValueNormaliser::Round { decimals }),
+        prop::sample::select(&[DateUnit::Day, DateUnit::Month, DateUnit::Year][..])
+            .prop_map(|unit| ValueNormaliser::TruncateDate { unit }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn normalisers_are_idempotent(
+        result_set in any::<ResultSet>(),
+        normaliser in any_normaliser(),
+    ) {
+        let mut once = result_set;
+        normalise_columns(&mut once, "*", std::slice::from_ref(&normaliser));
+        let mut twice = once.clone();
+        normalise_columns(&mut twice, "*", &[normaliser]);
+        prop_assert_eq!(once, twice);
+    }
+}
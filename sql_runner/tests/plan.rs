@@ -0,0 +1,90 @@
+#[path = "../src/db/plan.rs"]
+mod plan;
+
+use plan::{PlanNode, QueryPlan, parse_plan};
+use serde_json::json;
+
+#[test]
+fn nested_plans_become_children_and_unknown_keys_details() {
+    let explain = json!([{
+        "Plan": {
+            "Node Type": "Hash Join",
+            "Startup Cost": 41.67,
+            "Total Cost": 83.89,
+            "Plan Rows": 2538,
+            "Plan Width": 36,
+            "Hash Cond": "(s.a = t.a)",
+            "Plans": [{
+                "Node Type": "Seq Scan",
+                "Relation Name": "s",
+                "Startup Cost": 0.0,
+                "Total Cost": 35.5,
+                "Plan Rows": 2550,
+                "Plan Width": 4,
+            }],
+        },
+    }]);
+    let QueryPlan {
+        plan,
+        planning_time_ms,
+        execution_time_ms,
+    } = parse_plan(&explain).unwrap();
+    assert_eq!(plan.node_type, "Hash Join");
+    assert_eq!(plan.relation_name, None);
+    assert_eq!(plan.total_cost, 83.89);
+    assert_eq!(plan.details["Hash Cond"], json!("(s.a = t.a)"));
+    assert!(!plan.details.contains_key("Plans"));
+    let [scan] = plan.children.as_slice() else {
+        panic!("expected a single child, got {:?}", plan.children);
+    };
+    assert_eq!(scan.relation_name.as_deref(), Some("s"));
+    assert!(scan.children.is_empty());
+    assert_eq!((planning_time_ms, execution_time_ms), (None, None));
+}
+
+#[test]
+fn analyzed_plans_report_actual_values_and_times() {
+    let explain = json!([{
+        "Plan": {
+            "Node Type": "Seq Scan",
+            "Relation Name": "t",
+            "Startup Cost": 0.0,
+            "Total Cost": 25.88,
+            "Plan Rows": 423,
+            "Plan Width": 0,
+            "Actual Startup Time": 0.022,
+            "Actual Total Time": 0.137,
+            "Actual Rows": 99,
+            "Actual Loops": 1,
+        },
+        "Planning Time": 0.401,
+        "Execution Time": 0.241,
+    }]);
+    let plan = parse_plan(&explain).unwrap();
+    let PlanNode {
+        actual_startup_time_ms,
+        actual_total_time_ms,
+        actual_rows,
+        actual_loops,
+        startup_cost,
+        plan_rows,
+        plan_width,
+        ..
+    } = plan.plan;
+    assert_eq!(actual_startup_time_ms, Some(0.022));
+    assert_eq!(actual_total_time_ms, Some(0.137));
+    assert_eq!(actual_rows, Some(99.0));
+    assert_eq!(actual_loops, Some(1.0));
+    assert_eq!((startup_cost, plan_rows, plan_width), (0.0, 423.0, 0.0));
+    assert_eq!(plan.planning_time_ms, Some(0.401));
+    assert_eq!(plan.execution_time_ms, Some(0.241));
+}
+
+#[test]
+fn output_which_is_not_a_plan_is_rejected() {
+    assert_eq!(parse_plan(&json!([])), None);
+    assert_eq!(
+        parse_plan(&json!([{"Plan": {"Node Type": "Result"}}])),
+        None
+    );
+}
@@ -0,0 +1,69 @@
+#[path = "../src/db/result_cache.rs"]
+mod result_cache;
+
+use common::models::{ResultSet, SqlValue};
+use result_cache::{ResultCache, cache_key};
+use std::time::{Duration, Instant};
+
+fn result_set(value: i64) -> ResultSet {
+    ResultSet {
+        columns: vec!["n".to_string()],
+        rows: vec![vec![SqlValue::Int(value)]],
+    }
+}
+
+#[test]
+fn formatting_is_normalised_but_literals_and_environments_are_kept() {
+    let key = cache_key("env", "select n from t where n = 1").unwrap();
+    assert_eq!(
+        cache_key("env", "SELECT n\n  FROM T -- solution\nWHERE n = 1;"),
+        Some(key.clone())
+    );
+    assert_ne!(
+        cache_key("env", "select n from t where n = 2"),
+        Some(key.clone())
+    );
+    assert_ne!(cache_key("other", "select n from t where n = 1"), Some(key));
+    assert_ne!(
+        cache_key("env", r#"select n from "T""#),
+        cache_key("env", "select n from t")
+    );
+}
+
+#[test]
+fn only_deterministic_single_queries_are_cached() {
+    assert_eq!(cache_key("env", "delete from t"), None);
+    assert_eq!(cache_key("env", "select 1; select 2"), None);
+    assert_eq!(cache_key("env", "select random()"), None);
+    assert_eq!(cache_key("env", "select NOW()"), None);
+    assert_eq!(cache_key("env", "not sql"), None);
+}
+
+#[test]
+fn entries_expire_and_the_least_recently_used_is_evicted() {
+    let cache = ResultCache::new(2, Duration::from_secs(60), 10);
+    let now = Instant::now();
+    cache.insert("a".to_string(), &result_set(1), now);
+    cache.insert("b".to_string(), &result_set(2), now);
+    assert_eq!(cache.get("a", now), Some(result_set(1)));
+    cache.insert("c".to_string(), &result_set(3), now);
+    assert_eq!(cache.get("b", now), None);
+    assert_eq!(cache.get("a", now), Some(result_set(1)));
+    assert_eq!(cache.get("c", now + Duration::from_secs(60)), None);
+    assert_eq!(
+        cache.get("a", now + Duration::from_secs(59)),
+        Some(result_set(1))
+    );
+}
+
+#[test]
+fn large_result_sets_are_not_cached() {
+    let cache = ResultCache::new(2, Duration::from_secs(60), 1);
+    let now = Instant::now();
+    let large = ResultSet {
+        columns: vec!["n".to_string()],
+        rows: vec![vec![SqlValue::Int(1)], vec![SqlValue::Int(2)]],
+    };
+    cache.insert("large".to_string(), &large, now);
+    assert_eq!(cache.get("large", now), None);
+}
@@ -0,0 +1,117 @@
+#[path = "../src/db/result_diff.rs"]
+mod result_diff;
+
+use common::models::{ResultSet, SqlValue};
+use common::testing::{ValueKind, result_set_with};
+use proptest::prelude::*;
+use result_diff::{ColumnTypeDiff, ResultSetDiff, ValueType, diff_result_sets};
+
+fn result_set(columns: &[&str], rows: Vec<Vec<SqlValue>>) -> ResultSet {
+    ResultSet {
+        columns: columns.iter().map(|column| column.to_string()).collect(),
+        rows,
+    }
+}
+
+fn row(id: i64, name: &str) -> Vec<SqlValue> {
+    vec![SqlValue::Int(id), SqlValue::Text(name.to_string())]
+}
+
+#[test]
+fn equal_result_sets_in_any_order_have_no_diff() {
+    let expected = result_set(&["id", "name"], vec![row(1, "Tea"), row(2, "Coffee")]);
+    let actual = result_set(&["id", "name"], vec![row(2, "Coffee"), row(1, "Tea")]);
+    let diff = diff_result_sets(&expected, &actual);
+    assert_eq!(diff, ResultSetDiff::default());
+}
+
+#[test]
+fn unmatched_rows_are_missing_or_extra_counting_duplicates() {
+    let expected = result_set(
+        &["id", "name"],
+        vec![row(1, "Tea"), row(1, "Tea"), row(2, "Coffee")],
+    );
+    let actual = result_set(&["id", "name"], vec![row(1, "Tea"), row(3, "Water")]);
+    let diff = diff_result_sets(&expected, &actual);
+    assert_eq!(diff.missing_rows, vec![row(1, "Tea"), row(2, "Coffee")]);
+    assert_eq!(diff.extra_rows, vec![row(3, "Water")]);
+}
+
+#[test]
+fn rows_are_compared_by_the_shared_columns() {
+    let expected = result_set(&["id", "name"], vec![row(1, "Tea")]);
+    let actual = result_set(
+        &["price", "id"],
+        vec![vec![SqlValue::Float(1.5), SqlValue::Int(1)]],
+    );
+    let diff = diff_result_sets(&expected, &actual);
+    assert_eq!(diff.missing_columns, vec!["name".to_string()]);
+    assert_eq!(diff.extra_columns, vec!["price".to_string()]);
+    assert!(diff.missing_rows.is_empty());
+    assert!(diff.extra_rows.is_empty());
+}
+
+#[test]
+fn column_types_are_those_of_the_first_non_null_value() {
+    let expected = result_set(
+        &["id", "price"],
+        vec![
+            vec![SqlValue::Int(1), SqlValue::Null],
+            vec![SqlValue::Int(2), SqlValue::Float(2.0)],
+        ],
+    );
+    let actual = result_set(
+        &["id", "price"],
+        vec![
+            vec![SqlValue::Int(1), SqlValue::Null],
+            vec![SqlValue::Int(2), SqlValue::Int(2)],
+        ],
+    );
+    let diff = diff_result_sets(&expected, &actual);
+    assert_eq!(
+        diff.column_types,
+        vec![ColumnTypeDiff {
+            column: "price".to_string(),
+            expected: ValueType::Float,
+            actual: ValueType::Int,
+        }]
+    );
+    assert_eq!(diff.missing_rows.len(), 1);
+    assert_eq!(diff.extra_rows.len(), 1);
+}
+
+/// Values of a column of the rows, sorted, as which rows with equal shared values are
+/// reported is up to their order.
+fn column_values(rows: &[Vec<SqlValue>], index: usize) -> Vec<SqlValue> {
+    let mut values: Vec<SqlValue> = rows.iter().map(|row| row[index].clone()).collect();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    values
+}
+
+proptest! {
+    #[test]
+    fn result_sets_do_not_differ_from_themselves(result_set in any::<ResultSet>()) {
+        prop_assert_eq!(diff_result_sets(&result_set, &result_set), ResultSetDiff::default());
+    }
+
+    #[test]
+    fn diffs_are_symmetric(
+        (a, b) in (
+            result_set_with(&[("id", ValueKind::Int), ("name", ValueKind::Text)], 6),
+            result_set_with(&[("name", ValueKind::Text), ("price", ValueKind::Float)], 6),
+        )
+    ) {
+        let forward = diff_result_sets(&a, &b);
+        let backward = diff_result_sets(&b, &a);
+        prop_assert_eq!(&forward.missing_columns, &backward.extra_columns);
+        prop_assert_eq!(&forward.extra_columns, &backward.missing_columns);
+        prop_assert_eq!(
+            column_values(&forward.missing_rows, 1),
+            column_values(&backward.extra_rows, 1)
+        );
+        prop_assert_eq!(
+            column_values(&forward.extra_rows, 0),
+            column_values(&backward.missing_rows, 0)
+        );
+    }
+}
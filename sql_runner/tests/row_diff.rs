@@ -0,0 +1,104 @@
+#[path = "../src/db/row_diff.rs"]
+mod row_diff;
+
+use common::models::{ResultSet, SqlValue};
+use row_diff::{CellDiff, ChangedRow, diff_by_key};
+
+fn result_set(columns: &[&str], rows: Vec<Vec<SqlValue>>) -> ResultSet {
+    ResultSet {
+        columns: columns.iter().map(|column| column.to_string()).collect(),
+        rows,
+    }
+}
+
+fn row(id: i64, name: &str, price: f64) -> Vec<SqlValue> {
+    vec![
+        SqlValue::Int(id),
+        SqlValue::Text(name.to_string()),
+        SqlValue::Float(price),
+    ]
+}
+
+fn key() -> Vec<String> {
+    vec!["id".to_string()]
+}
+
+#[test]
+fn rows_are_matched_by_key_regardless_of_order() {
+    let expected = result_set(
+        &["id", "name", "price"],
+        vec![row(1, "Tea", 1.5), row(2, "Coffee", 3.5)],
+    );
+    let actual = result_set(
+        &["price", "id", "name"],
+        vec![
+            vec![
+                SqlValue::Float(3.5),
+                SqlValue::Int(2),
+                SqlValue::Text("Coffee".into()),
+            ],
+            vec![
+                SqlValue::Float(1.5),
+                SqlValue::Int(1),
+                SqlValue::Text("Tea".into()),
+            ],
+        ],
+    );
+    assert!(diff_by_key(&expected, &actual, &key()).is_empty());
+}
+
+#[test]
+fn differences_are_reported_per_cell_and_row() {
+    let expected = result_set(
+        &["id", "name", "price"],
+        vec![
+            row(1, "Tea", 1.5),
+            row(2, "Coffee", 3.5),
+            row(3, "Juice", 2.0),
+        ],
+    );
+    let actual = result_set(
+        &["id", "name", "price"],
+        vec![
+            row(1, "Tea", 1.0),
+            row(2, "Coffee", 3.5),
+            row(4, "Water", 0.5),
+        ],
+    );
+    let diff = diff_by_key(&expected, &actual, &key());
+    assert_eq!(
+        diff.changed_rows,
+        vec![ChangedRow {
+            key: vec![SqlValue::Int(1)],
+            cells: vec![CellDiff {
+                column: "price".to_string(),
+                expected: SqlValue::Float(1.5),
+                actual: SqlValue::Float(1.0),
+            }],
+        }]
+    );
+    assert_eq!(diff.missing_rows, vec![vec![SqlValue::Int(3)]]);
+    assert_eq!(diff.unexpected_rows, vec![vec![SqlValue::Int(4)]]);
+    assert!(!diff.is_empty());
+}
+
+#[test]
+fn columns_and_duplicate_keys_are_reported() {
+    let expected = result_set(
+        &["id", "name", "price"],
+        vec![row(1, "Tea", 1.5), row(1, "Tea", 1.5)],
+    );
+    let actual = result_set(
+        &["id", "name", "stock"],
+        vec![row(1, "Tea", 7.0), row(1, "Tea", 7.0), row(1, "Tea", 7.0)],
+    );
+    let diff = diff_by_key(&expected, &actual, &key());
+    assert_eq!(diff.missing_columns, vec!["price".to_string()]);
+    assert_eq!(diff.unexpected_columns, vec!["stock".to_string()]);
+    assert!(diff.changed_rows.is_empty());
+    assert_eq!(diff.unexpected_rows, vec![vec![SqlValue::Int(1)]]);
+
+    let diff = diff_by_key(&expected, &expected, &["sku".to_string()]);
+    assert_eq!(diff.missing_columns, vec!["sku".to_string()]);
+    assert!(diff.changed_rows.is_empty() && diff.missing_rows.is_empty());
+}
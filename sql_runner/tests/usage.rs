@@ -0,0 +1,82 @@
+#[path = "../src/db/usage.rs"]
+mod usage;
+
+use std::time::{Duration, SystemTime};
+use usage::{CleanupPolicy, EnvironmentUsage};
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+fn minutes_ago(now: SystemTime, minutes: u64) -> SystemTime {
+    now - Duration::from_secs(minutes * 60)
+}
+
+#[test]
+fn environments_unused_for_longer_than_the_ttl_are_stale() {
+    let now = SystemTime::now();
+    let usage = EnvironmentUsage::default();
+    usage.touch_at("old", minutes_ago(now, 90));
+    usage.touch_at("recent", minutes_ago(now, 10));
+    let policy = CleanupPolicy {
+        ttl: Some(Duration::from_secs(60 * 60)),
+        max_environments: None,
+    };
+    assert_eq!(
+        usage.stale(&names(&["old", "recent"]), &policy, now),
+        names(&["old"])
+    );
+}
+
+#[test]
+fn least_recently_used_environments_beyond_the_maximum_are_stale() {
+    let now = SystemTime::now();
+    let usage = EnvironmentUsage::default();
+    usage.touch_at("a", minutes_ago(now, 3));
+    usage.touch_at("b", minutes_ago(now, 1));
+    usage.touch_at("c", minutes_ago(now, 2));
+    let policy = CleanupPolicy {
+        ttl: None,
+        max_environments: Some(1),
+    };
+    assert_eq!(
+        usage.stale(&names(&["a", "b", "c"]), &policy, now),
+        names(&["a", "c"])
+    );
+}
+
+#[test]
+fn untracked_environments_count_as_used_when_first_seen() {
+    let now = SystemTime::now();
+    let usage = EnvironmentUsage::default();
+    usage.touch_at("dropped elsewhere", minutes_ago(now, 90));
+    let policy = CleanupPolicy {
+        ttl: Some(Duration::from_secs(60)),
+        max_environments: None,
+    };
+    assert!(usage.stale(&names(&["existing"]), &policy, now).is_empty());
+    assert!(!usage.used_since("dropped elsewhere", minutes_ago(now, 100)));
+    assert_eq!(
+        usage.stale(
+            &names(&["existing"]),
+            &policy,
+            now + Duration::from_secs(120)
+        ),
+        names(&["existing"])
+    );
+    assert!(
+        usage
+            .stale(&names(&["existing"]), &CleanupPolicy::default(), now)
+            .is_empty()
+    );
+}
+
+#[test]
+fn used_environments_are_tracked_until_forgotten() {
+    let start = SystemTime::now() - Duration::from_secs(1);
+    let usage = EnvironmentUsage::default();
+    usage.touch("env");
+    assert!(usage.used_since("env", start));
+    usage.forget("env");
+    assert!(!usage.used_since("env", start));
+}